@@ -0,0 +1,101 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Explicit selection of the adapter to render with.
+
+use clap::ValueEnum;
+use vello::util::RenderContext;
+
+use crate::Result;
+use wgpu::{AdapterInfo, Backends, PowerPreference, RequestAdapterOptions};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl Backend {
+    pub fn to_wgpu(self) -> Backends {
+        match self {
+            Backend::Vulkan => Backends::VULKAN,
+            Backend::Dx12 => Backends::DX12,
+            Backend::Metal => Backends::METAL,
+            Backend::Gl => Backends::GL,
+        }
+    }
+}
+
+/// Formats the identifying details of an adapter on a single line.
+pub fn describe(info: &AdapterInfo) -> String {
+    format!(
+        "{} (vendor {:#06x}, device {:#06x}, {:?}, {:?}, driver {} {})",
+        info.name,
+        info.vendor,
+        info.device,
+        info.device_type,
+        info.backend,
+        info.driver,
+        info.driver_info
+    )
+}
+
+/// Prints every adapter which the context can use.
+pub fn list(context: &RenderContext) {
+    for adapter in context.enumerate_adapters() {
+        println!("{}", describe(&adapter.get_info()));
+    }
+}
+
+/// Creates a device from the first adapter whose name contains `name`, ignoring case.
+pub async fn device_matching(context: &mut RenderContext, name: &str) -> Result<usize> {
+    let needle = name.to_lowercase();
+    let adapters = context.enumerate_adapters();
+    let mut candidates = Vec::new();
+    for adapter in adapters {
+        let info = adapter.get_info();
+        if info.name.to_lowercase().contains(&needle) {
+            return context.device_from_adapter(adapter).await.map_err(|error| {
+                format!("failed to create a device for {}: {error}", describe(&info)).into()
+            });
+        }
+        candidates.push(describe(&info));
+    }
+    Err(format!(
+        "no adapter matching {name:?}, candidates are:\n{}",
+        candidates.join("\n")
+    )
+    .into())
+}
+
+/// Creates a device from the fallback adapter, which is implemented in software.
+pub async fn software_device(context: &mut RenderContext) -> Result<usize> {
+    let adapter = context
+        .instance
+        .request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        })
+        .await
+        .ok_or("no software adapter found, try --list-adapters")?;
+    let info = adapter.get_info();
+    context.device_from_adapter(adapter).await.map_err(|error| {
+        format!("failed to create a device for {}: {error}", describe(&info)).into()
+    })
+}
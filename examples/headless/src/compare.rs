@@ -0,0 +1,178 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Comparison of rendered images against reference images.
+
+use std::{fs::File, path::Path};
+
+use crate::Result;
+
+/// Amplification applied to the per pixel difference in the heatmap, so that
+/// small differences are still visible.
+const HEATMAP_GAIN: u32 = 8;
+
+/// An RGBA8 image with tightly packed rows.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Loads a png file, converting it to RGBA8.
+    pub fn load_png(path: &Path) -> Result<Self> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        data.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data,
+            png::ColorType::Rgb => data
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            png::ColorType::Grayscale => data.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err("indexed png was not expanded by the decoder".into());
+            }
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+/// Result of comparing two images of the same size.
+pub struct Comparison {
+    /// Mean absolute difference over every channel of every pixel, scaled
+    /// so that 0.0 is identical and 1.0 is maximally different.
+    pub score: f64,
+    /// Visualisation of the per pixel difference, as an RGBA8 image.
+    pub heatmap: Image,
+}
+
+/// Compares `actual` against `expected`, returning an error if the
+/// dimensions differ.
+pub fn compare(expected: &Image, actual: &Image) -> Result<Comparison> {
+    if expected.width != actual.width || expected.height != actual.height {
+        return Err(format!(
+            "image size {}x{} doesn't match reference size {}x{}",
+            actual.width, actual.height, expected.width, expected.height
+        )
+        .into());
+    }
+    let mut total = 0u64;
+    let mut heatmap = Vec::with_capacity(expected.pixels.len());
+    for (a, b) in expected
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        let mut max_diff = 0;
+        for (x, y) in a.iter().zip(b) {
+            let diff = x.abs_diff(*y);
+            total += diff as u64;
+            max_diff = max_diff.max(diff);
+        }
+        let heat = (max_diff as u32 * HEATMAP_GAIN).min(255) as u8;
+        heatmap.extend([heat, 0, 0, 255]);
+    }
+    let score = total as f64 / (expected.pixels.len().max(1) as f64 * 255.0);
+    Ok(Comparison {
+        score,
+        heatmap: Image {
+            width: expected.width,
+            height: expected.height,
+            pixels: heatmap,
+        },
+    })
+}
+
+/// Roughness of an image at the boundaries between tiles, compared with elsewhere.
+pub struct Seams {
+    /// Mean second difference along rows, as `(boundary, other)`. `boundary` is the
+    /// largest mean over the columns on either side of a tile boundary, and `other`
+    /// the largest over the columns at any other position within a tile.
+    pub columns: (f64, f64),
+    /// Mean second difference along columns, likewise for rows.
+    pub rows: (f64, f64),
+}
+
+impl Seams {
+    /// Returns true if the boundaries are rougher than anywhere else in a tile by more
+    /// than antialiasing accounts for, which shows up as lines every tile.
+    pub fn found(&self) -> bool {
+        let is_seam = |(boundary, other): (f64, f64)| boundary > other * 1.25 + 1e-3;
+        is_seam(self.columns) || is_seam(self.rows)
+    }
+}
+
+/// Measures how much the pixels next to the boundaries of tiles of `tile_size`
+/// pixels differ from their neighbors.
+///
+/// A seam is a line of pixels which are lighter or darker than those on either side,
+/// so each pixel is compared with the mean of its neighbors. Edges of shapes which
+/// cross the boundaries at many positions within a pixel are as rough at every
+/// position within a tile, so only the boundaries stand out.
+pub fn seams(image: &Image, tile_size: u32) -> Seams {
+    Seams {
+        columns: seams_along(image, tile_size, true),
+        rows: seams_along(image, tile_size, false),
+    }
+}
+
+fn seams_along(image: &Image, tile_size: u32, columns: bool) -> (f64, f64) {
+    let (len, across) = if columns {
+        (image.width, image.height)
+    } else {
+        (image.height, image.width)
+    };
+    let channel = |along: u32, at: u32, c: usize| {
+        let (x, y) = if columns { (along, at) } else { (at, along) };
+        image.pixels[(y * image.width + x) as usize * 4 + c] as f64
+    };
+    let period = tile_size as usize;
+    let mut sums = vec![0.0; period];
+    let mut counts = vec![0u64; period];
+    for i in 1..len.saturating_sub(1) {
+        let phase = i as usize % period;
+        for j in 0..across {
+            let deviation = (0..4)
+                .map(|c| {
+                    (2.0 * channel(i, j, c) - channel(i - 1, j, c) - channel(i + 1, j, c)).abs()
+                })
+                .fold(0.0, f64::max);
+            sums[phase] += deviation / (2.0 * 255.0);
+        }
+        counts[phase] += across as u64;
+    }
+    let means = sums
+        .iter()
+        .zip(&counts)
+        .map(|(sum, count)| sum / (*count).max(1) as f64)
+        .collect::<Vec<_>>();
+    let boundary = means[0].max(means[period - 1]);
+    let other = means[1..period - 1].iter().copied().fold(0.0, f64::max);
+    (boundary, other)
+}
@@ -0,0 +1,412 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+mod adapters;
+mod compare;
+mod output;
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, ValueEnum};
+use scenes::{ExampleScene, SceneConfig, SceneParams, SceneSet, SimpleText};
+use vello::{util::RenderContext, ImageParams, Renderer, RendererOptions, Scene, SceneBuilder};
+
+use adapters::Backend;
+use compare::Image;
+use output::{Alpha, Format, Output};
+
+/// Errors from vello, and from writing and comparing images.
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser, Debug)]
+#[command(about, long_about = None)]
+struct Args {
+    /// Which scene to render, either by index or by name. Generated scenes take their
+    /// parameters after the name, separated by colons, such as `many_shapes:50000:7`
+    #[arg(long, default_value = "0")]
+    scene: String,
+    /// Render a scene which was saved with `--save-scene`, instead of `--scene`
+    #[arg(long)]
+    load_scene: Option<PathBuf>,
+    /// Save the encoded scene of the last frame to this file
+    #[arg(long)]
+    save_scene: Option<PathBuf>,
+    /// Width of the output image
+    #[arg(long, default_value_t = 1024)]
+    width: u32,
+    /// Height of the output image
+    #[arg(long, default_value_t = 1024)]
+    height: u32,
+    /// Directory to write the output files into
+    #[arg(long, default_value = "outputs")]
+    out_directory: PathBuf,
+    /// Number of frames to render. Frame `n` is rendered at time `n / fps`
+    #[arg(long, default_value_t = 1)]
+    frames: u32,
+    /// Frame rate used to advance the scene clock between frames
+    #[arg(long, default_value_t = 60.0)]
+    fps: f64,
+    /// Format of the output files
+    #[arg(long, value_enum, default_value_t = Format::Png)]
+    format: Format,
+    /// Alpha representation for the png and raw formats
+    #[arg(long, value_enum, default_value_t = Alpha::Straight)]
+    alpha: Alpha,
+    /// Reference png to compare the rendered scene against, instead of writing it to the
+    /// output directory. On mismatch, the rendered image and a difference heatmap are
+    /// written beside the reference
+    #[arg(long)]
+    compare: Option<PathBuf>,
+    /// Largest mean absolute channel difference, between 0 and 1, accepted by `--compare`
+    #[arg(long, default_value_t = 0.005)]
+    threshold: f64,
+    /// Render with the first adapter whose name contains this, ignoring case
+    #[arg(long)]
+    adapter: Option<String>,
+    /// Only consider adapters from this backend
+    #[arg(long, value_enum)]
+    backend: Option<Backend>,
+    /// Print the available adapters and exit
+    #[arg(long)]
+    list_adapters: bool,
+    /// Render with a software adapter, such as lavapipe, SwiftShader or WARP, so that
+    /// results don't depend on the GPU of the machine. Also enabled by setting the
+    /// `VELLO_SOFTWARE` environment variable to `1`
+    #[arg(long)]
+    software: bool,
+    /// Write every intermediate buffer of the first frame into this directory, and
+    /// check the structure of the tiles they describe
+    #[arg(long)]
+    capture: Option<PathBuf>,
+    /// Render with deterministic coverage, so that repeated renders on the same adapter
+    /// are identical. Always enabled by `--compare`
+    #[arg(long)]
+    deterministic: bool,
+    /// Render the first frame this many times and report whether the images differ.
+    /// Differences are an error with `--deterministic`
+    #[arg(long)]
+    check_determinism: Option<u32>,
+    /// Size of the tiles the image is divided into, for comparing the render times of
+    /// the two sizes on a scene
+    #[arg(long, value_enum, default_value_t = TileSize::Large)]
+    tile_size: TileSize,
+    /// Embed a description of the adapter, options and scene each frame was rendered
+    /// with into the png files, including those written when `--compare` fails
+    #[arg(long)]
+    embed_metadata: bool,
+    /// Print the description embedded into a png file by `--embed-metadata` and exit
+    #[arg(long)]
+    print_metadata: Option<PathBuf>,
+    /// Check the first frame for lines of lighter or darker pixels along the tile
+    /// boundaries, such as in the `tile_seams` scene. With `--tile-size auto`, the
+    /// boundaries of small tiles are checked, which include those of large tiles
+    #[arg(long)]
+    check_seams: bool,
+}
+
+/// Size of the tiles the image is divided into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum TileSize {
+    /// 16 by 16 pixel tiles
+    Large,
+    /// 8 by 8 pixel tiles
+    Small,
+    /// Chosen from the average area of the paths of each frame
+    Auto,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    if let Some(path) = &args.print_metadata {
+        match output::read_png_metadata(path)? {
+            Some(metadata) => println!("{}", output::pretty_json(&metadata)),
+            None => return Err(format!("{} has no vello metadata", path.display()).into()),
+        }
+        return Ok(());
+    }
+    if args.compare.is_some() && args.frames != 1 {
+        return Err("--compare can only be used with a single frame".into());
+    }
+    let passed = pollster::block_on(render(args))?;
+    if !passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Renders the requested frames, returning whether the comparison against the
+/// reference image passed, if there was one.
+async fn render(args: Args) -> Result<bool> {
+    let mut scenes = SceneSet::test_scenes();
+    let scene_ix = match &args.load_scene {
+        Some(path) => {
+            scenes.scenes.push(load_scene(path)?);
+            scenes.scenes.len() - 1
+        }
+        None => scenes.select(&args.scene)?,
+    };
+    let example_scene = &mut scenes.scenes[scene_ix];
+    if args.frames > 1 && !example_scene.config.animated {
+        eprintln!(
+            "Scene {} is not animated, so all frames will be identical",
+            example_scene.config.name
+        );
+    }
+    let software = args.software || std::env::var_os("VELLO_SOFTWARE").map_or(false, |v| v == "1");
+    if software && args.adapter.is_some() {
+        return Err("--software and --adapter can't be used together".into());
+    }
+    let mut context = match args.backend {
+        Some(backend) => RenderContext::with_backends(backend.to_wgpu())?,
+        // Software adapters may only be on a secondary backend, such as llvmpipe on GL
+        None if software => RenderContext::with_backends(wgpu::Backends::all())?,
+        None => RenderContext::new()?,
+    };
+    if args.list_adapters {
+        adapters::list(&context);
+        return Ok(true);
+    }
+    let device_id = match &args.adapter {
+        Some(name) => adapters::device_matching(&mut context, name).await?,
+        None if software => adapters::software_device(&mut context).await?,
+        None => match context.device(None).await {
+            Ok(device_id) => device_id,
+            // The primary backends may be unavailable, for example in a container
+            // without a GPU, so fall back to any other backend which has an adapter
+            Err(vello::Error::NoCompatibleDevice) if args.backend.is_none() => {
+                eprintln!("No compatible device found, trying all backends");
+                context = RenderContext::with_backends(wgpu::Backends::all())?;
+                context.device(None).await?
+            }
+            Err(error) => return Err(error.into()),
+        },
+    };
+    let device_handle = &context.devices[device_id];
+    eprintln!(
+        "Rendering with {}",
+        adapters::describe(&device_handle.adapter().get_info())
+    );
+    let device = &device_handle.device;
+    let queue = &device_handle.queue;
+    let deterministic = args.deterministic || args.compare.is_some();
+    let mut renderer = Renderer::new(
+        device,
+        &RendererOptions {
+            surface_blit_support: false,
+            capture: args.capture.is_some(),
+            deterministic,
+            tile_size: match args.tile_size {
+                TileSize::Large => vello::TileSize::Large,
+                TileSize::Small => vello::TileSize::Small,
+                TileSize::Auto => vello::TileSize::Auto,
+            },
+            ..Default::default()
+        },
+    )?;
+    if let Some(path) = &args.capture {
+        renderer.capture_next_frame(path)?;
+    }
+    let image_params = ImageParams {
+        width: args.width,
+        height: args.height,
+        premultiplied: false,
+    };
+    let mut output = match args.compare {
+        Some(_) => None,
+        None => Some(Output::new(
+            &args.out_directory,
+            &example_scene.config.name,
+            args.format,
+            args.alpha,
+            args.frames,
+        )?),
+    };
+    let mut simple_text = SimpleText::new();
+    let mut scene = Scene::new();
+    let mut pixels = Vec::new();
+    let mut render_times = Vec::with_capacity(args.frames as usize);
+    let mut metadata = None;
+    for frame in 0..args.frames {
+        let mut params = SceneParams {
+            time: frame as f64 / args.fps,
+            text: &mut simple_text,
+        };
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        example_scene.function.render(&mut builder, &mut params);
+        builder.finish();
+        simple_text.next_frame();
+
+        let start = Instant::now();
+        let image = renderer.render_to_image_blocking(device, queue, &scene, &image_params)?;
+        render_times.push(start.elapsed());
+        if args.embed_metadata {
+            metadata = Some(renderer.metadata(
+                &device_handle.adapter().get_info(),
+                &scene,
+                args.width,
+                args.height,
+            ));
+        }
+        pixels.clear();
+        pixels.extend_from_slice(image.data.data());
+        if let (0, Some(repeats)) = (frame, args.check_determinism) {
+            let mut n_different = 0;
+            for _ in 1..repeats {
+                let repeat =
+                    renderer.render_to_image_blocking(device, queue, &scene, &image_params)?;
+                if repeat.data.data() != pixels.as_slice() {
+                    n_different += 1;
+                }
+            }
+            println!(
+                "{n_different} of {} repeated renders differed from the first",
+                repeats.saturating_sub(1)
+            );
+            if n_different > 0 && deterministic {
+                return Err("deterministic renders of the same scene differed".into());
+            }
+        }
+        if frame == 0 && args.check_seams {
+            let tile_size = match args.tile_size {
+                TileSize::Large => 16,
+                TileSize::Small | TileSize::Auto => 8,
+            };
+            let image = Image {
+                width: args.width,
+                height: args.height,
+                pixels: pixels.clone(),
+            };
+            let seams = compare::seams(&image, tile_size);
+            println!(
+                "Roughness at tile boundaries: columns {:.6} (elsewhere {:.6}), rows {:.6} (elsewhere {:.6})",
+                seams.columns.0, seams.columns.1, seams.rows.0, seams.rows.1
+            );
+            if seams.found() {
+                return Err("coverage is discontinuous at the tile boundaries".into());
+            }
+        }
+        if let (0, Some(path)) = (frame, &args.capture) {
+            vello::capture::FrameCapture::load(path)?.check()?;
+            println!("Wrote a capture of the first frame to {}", path.display());
+        }
+
+        if let Some(output) = output.as_mut() {
+            output.write_frame(
+                frame,
+                args.width,
+                args.height,
+                &mut pixels,
+                metadata.as_deref(),
+            )?;
+        }
+    }
+    if let Some(path) = &args.save_scene {
+        std::fs::write(path, scene.to_bytes())?;
+    }
+    if let Some(reference) = &args.compare {
+        let actual = Image {
+            width: args.width,
+            height: args.height,
+            pixels,
+        };
+        return compare_to_reference(reference, &actual, args.threshold, metadata.as_deref());
+    }
+    let mut output = output.unwrap();
+    output.finish()?;
+    if args.frames > 1 {
+        print_times("render", &render_times);
+    }
+    if args.format == Format::Raw {
+        println!(
+            "Play back with: ffmpeg -f rawvideo -pixel_format rgba -video_size {}x{} -framerate {} -i {}",
+            args.width,
+            args.height,
+            args.fps,
+            output.path().display()
+        );
+    }
+    Ok(true)
+}
+
+/// Creates a scene which draws the serialized scene stored at `path`.
+fn load_scene(path: &Path) -> Result<ExampleScene> {
+    let loaded = Scene::from_bytes(&std::fs::read(path)?)?;
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "loaded".into());
+    Ok(ExampleScene {
+        function: Box::new(move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+            sb.append_scene(&loaded, None);
+        }),
+        config: SceneConfig {
+            animated: false,
+            name: name.into(),
+        },
+    })
+}
+
+fn compare_to_reference(
+    reference: &Path,
+    actual: &Image,
+    threshold: f64,
+    metadata: Option<&str>,
+) -> Result<bool> {
+    let expected = Image::load_png(reference)?;
+    let comparison = compare::compare(&expected, actual)?;
+    println!("Mean absolute difference: {:.6}", comparison.score);
+    if comparison.score <= threshold {
+        return Ok(true);
+    }
+    let stem = reference
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let actual_path = reference.with_file_name(format!("{stem}.actual.png"));
+    let diff_path = reference.with_file_name(format!("{stem}.diff.png"));
+    output::write_png(
+        &actual_path,
+        actual.width,
+        actual.height,
+        &actual.pixels,
+        metadata,
+    )?;
+    let heatmap = &comparison.heatmap;
+    output::write_png(
+        &diff_path,
+        heatmap.width,
+        heatmap.height,
+        &heatmap.pixels,
+        metadata,
+    )?;
+    eprintln!(
+        "Difference exceeds threshold of {threshold}, wrote {} and {}",
+        actual_path.display(),
+        diff_path.display()
+    );
+    Ok(false)
+}
+
+fn print_times(name: &str, times: &[Duration]) {
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+    let mean = times.iter().sum::<Duration>() / times.len().max(1) as u32;
+    println!("{name}: min {min:?}, mean {mean:?}, max {max:?}");
+}
@@ -0,0 +1,299 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Writing rendered frames to disk.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::Result;
+
+/// File format for rendered frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One png file per frame
+    Png,
+    /// One binary ppm file per frame. Ppm has no alpha channel, so pixels are
+    /// composited over black
+    Ppm,
+    /// One OpenEXR file per frame, holding linear premultiplied float data
+    #[cfg(feature = "exr")]
+    Exr,
+    /// Headerless RGBA8 pixels for every frame, concatenated into a single file
+    Raw,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Ppm => "ppm",
+            #[cfg(feature = "exr")]
+            Format::Exr => "exr",
+            Format::Raw => "rgba",
+        }
+    }
+}
+
+/// Interpretation of the color channels relative to the alpha channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Alpha {
+    /// Color channels are independent of alpha, as most image viewers expect
+    Straight,
+    /// Color channels have been multiplied by alpha
+    Premultiplied,
+}
+
+/// Converts RGBA8 pixels between alpha representations in place.
+pub fn convert_alpha(pixels: &mut [u8], from: Alpha, to: Alpha) {
+    match (from, to) {
+        (Alpha::Straight, Alpha::Premultiplied) => {
+            for pixel in pixels.chunks_exact_mut(4) {
+                let a = pixel[3] as u32;
+                for c in &mut pixel[..3] {
+                    *c = ((*c as u32 * a + 127) / 255) as u8;
+                }
+            }
+        }
+        (Alpha::Premultiplied, Alpha::Straight) => {
+            for pixel in pixels.chunks_exact_mut(4) {
+                let a = pixel[3] as u32;
+                // Fully transparent pixels have no meaningful color
+                if a == 0 {
+                    pixel[..3].fill(0);
+                    continue;
+                }
+                for c in &mut pixel[..3] {
+                    *c = ((*c as u32 * 255 + a / 2) / a).min(255) as u8;
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Destination for a sequence of rendered frames.
+pub struct Output {
+    directory: PathBuf,
+    name: String,
+    format: Format,
+    alpha: Alpha,
+    frames: u32,
+    /// Open file for [`Format::Raw`], which writes all frames into one file.
+    raw: Option<BufWriter<File>>,
+}
+
+impl Output {
+    /// Creates an output for `frames` frames, where `alpha` is the representation
+    /// used for the formats that keep 8 bit alpha.
+    pub fn new(
+        directory: &Path,
+        name: &str,
+        format: Format,
+        alpha: Alpha,
+        frames: u32,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let mut output = Self {
+            directory: directory.to_owned(),
+            name: name.to_owned(),
+            format,
+            alpha,
+            frames,
+            raw: None,
+        };
+        if format == Format::Raw {
+            output.raw = Some(BufWriter::new(File::create(output.path())?));
+        }
+        Ok(output)
+    }
+
+    /// The file written for the first frame, or the only file for [`Format::Raw`].
+    pub fn path(&self) -> PathBuf {
+        self.frame_path(0)
+    }
+
+    fn frame_path(&self, frame: u32) -> PathBuf {
+        let extension = self.format.extension();
+        if self.format == Format::Raw || self.frames == 1 {
+            self.directory.join(format!("{}.{extension}", self.name))
+        } else {
+            self.directory
+                .join(format!("{}-{:04}.{extension}", self.name, frame + 1))
+        }
+    }
+
+    /// Writes tightly packed, straight alpha RGBA8 pixels for one frame. The
+    /// pixels are converted in place as needed by the format. The `metadata` from
+    /// [`Renderer::metadata`](vello::Renderer::metadata) is embedded into png files.
+    pub fn write_frame(
+        &mut self,
+        frame: u32,
+        width: u32,
+        height: u32,
+        pixels: &mut [u8],
+        metadata: Option<&str>,
+    ) -> Result<()> {
+        let path = self.frame_path(frame);
+        match self.format {
+            Format::Png => {
+                convert_alpha(pixels, Alpha::Straight, self.alpha);
+                write_png(&path, width, height, pixels, metadata)?;
+            }
+            Format::Ppm => {
+                convert_alpha(pixels, Alpha::Straight, Alpha::Premultiplied);
+                write_ppm(&path, width, height, pixels)?;
+            }
+            #[cfg(feature = "exr")]
+            Format::Exr => write_exr(&path, width, height, pixels)?,
+            Format::Raw => {
+                convert_alpha(pixels, Alpha::Straight, self.alpha);
+                self.raw.as_mut().unwrap().write_all(pixels)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(raw) = self.raw.as_mut() {
+            raw.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Keyword of the png text chunk which holds the metadata of a render.
+const METADATA_KEYWORD: &str = "vello";
+
+/// Writes tightly packed RGBA8 pixels as a png file, with `metadata` in a text chunk.
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    metadata: Option<&str>,
+) -> Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(metadata) = metadata {
+        encoder.add_text_chunk(METADATA_KEYWORD.into(), metadata.into())?;
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
+/// Reads the metadata written by [`write_png`] from a png file, if it has any.
+pub fn read_png_metadata(path: &Path) -> Result<Option<String>> {
+    let reader = png::Decoder::new(File::open(path)?).read_info()?;
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == METADATA_KEYWORD)
+        .map(|chunk| chunk.text.clone()))
+}
+
+/// Indents compact JSON, such as the metadata of a render, for printing. The JSON
+/// is otherwise unchanged, so this doesn't check that it is valid.
+pub fn pretty_json(json: &str) -> String {
+    let mut pretty = String::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let newline = |pretty: &mut String, depth: usize| {
+        pretty.push('\n');
+        pretty.extend(std::iter::repeat("  ").take(depth));
+    };
+    for c in json.chars() {
+        if in_string {
+            pretty.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                pretty.push(c);
+            }
+            '{' | '[' => {
+                depth += 1;
+                pretty.push(c);
+                newline(&mut pretty, depth);
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut pretty, depth);
+                pretty.push(c);
+            }
+            ',' => {
+                pretty.push(c);
+                newline(&mut pretty, depth);
+            }
+            ':' => pretty.push_str(": "),
+            _ => pretty.push(c),
+        }
+    }
+    pretty
+}
+
+fn write_ppm(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{width} {height}\n255\n")?;
+    for pixel in pixels.chunks_exact(4) {
+        file.write_all(&pixel[..3])?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes straight alpha sRGB pixels as linear premultiplied floats, which is
+/// the convention for OpenEXR.
+#[cfg(feature = "exr")]
+fn write_exr(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+    fn srgb_to_linear(c: u8) -> f32 {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+    exr::prelude::write_rgba_file(path, width as usize, height as usize, |x, y| {
+        let ix = (y * width as usize + x) * 4;
+        let pixel = &pixels[ix..ix + 4];
+        let a = pixel[3] as f32 / 255.0;
+        (
+            srgb_to_linear(pixel[0]) * a,
+            srgb_to_linear(pixel[1]) * a,
+            srgb_to_linear(pixel[2]) * a,
+            a,
+        )
+    })?;
+    Ok(())
+}
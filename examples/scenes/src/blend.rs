@@ -0,0 +1,172 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Conformance grid covering every combination of [`Mix`] and [`Compose`].
+//!
+//! The grid has one column per entry of [`MIXES`] and one row per entry of
+//! [`COMPOSES`], in that order, so the cell for a blend mode is found from its
+//! position in those lists. Cells are [`CELL_SIZE`] pixels square, with the
+//! first cell at the origin. Each cell draws the same backdrop circle, then a
+//! layer with the cell's blend mode containing an overlapping square.
+//!
+//! One more row after the last compose row draws the square of each mix with
+//! [`SceneBuilder::fill_blended`] instead of a layer, composited with source over.
+//! For the separable mixes, it should match the [`Compose::SrcOver`] row up to
+//! rounding, as layers store their backdrop with 8 bits per channel. Non-separable
+//! mixes can't be drawn without a layer, so their cells fall back to one.
+
+use vello::kurbo::{Affine, Circle, Point, Rect};
+use vello::peniko::{BlendMode, Color, Compose, Fill, Mix};
+use vello::SceneBuilder;
+
+use crate::SceneParams;
+
+pub const CELL_SIZE: f64 = 64.0;
+
+/// Every separable and non-separable mix mode. [`Mix::Clip`] is excluded, as it
+/// only affects how layers are clipped.
+pub const MIXES: &[Mix] = &[
+    Mix::Normal,
+    Mix::Multiply,
+    Mix::Screen,
+    Mix::Overlay,
+    Mix::Darken,
+    Mix::Lighten,
+    Mix::ColorDodge,
+    Mix::ColorBurn,
+    Mix::HardLight,
+    Mix::SoftLight,
+    Mix::Difference,
+    Mix::Exclusion,
+    Mix::Hue,
+    Mix::Saturation,
+    Mix::Color,
+    Mix::Luminosity,
+];
+
+/// Every Porter-Duff composition operator.
+pub const COMPOSES: &[Compose] = &[
+    Compose::Clear,
+    Compose::Copy,
+    Compose::Dest,
+    Compose::SrcOver,
+    Compose::DestOver,
+    Compose::SrcIn,
+    Compose::DestIn,
+    Compose::SrcOut,
+    Compose::DestOut,
+    Compose::SrcAtop,
+    Compose::DestAtop,
+    Compose::Xor,
+    Compose::Plus,
+    Compose::PlusLighter,
+];
+
+pub fn blend_grid(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    for (row, &compose) in COMPOSES.iter().enumerate() {
+        for (column, &mix) in MIXES.iter().enumerate() {
+            debug_assert_eq!(MIXES[mix_index(mix)], mix);
+            debug_assert_eq!(COMPOSES[compose_index(compose)], compose);
+            let origin = Point::new(column as f64 * CELL_SIZE, row as f64 * CELL_SIZE);
+            let transform = Affine::translate(origin.to_vec2());
+            blend_cell(sb, BlendMode::new(mix, compose), transform, false);
+        }
+    }
+    let row = COMPOSES.len();
+    for (column, &mix) in MIXES.iter().enumerate() {
+        let origin = Point::new(column as f64 * CELL_SIZE, row as f64 * CELL_SIZE);
+        let transform = Affine::translate(origin.to_vec2());
+        blend_cell(sb, BlendMode::new(mix, Compose::SrcOver), transform, true);
+    }
+}
+
+fn blend_cell(sb: &mut SceneBuilder, blend: BlendMode, transform: Affine, layerless: bool) {
+    let cell = Rect::new(2.0, 2.0, CELL_SIZE - 2.0, CELL_SIZE - 2.0);
+    let backdrop = Circle::new((CELL_SIZE * 0.375, CELL_SIZE * 0.375), CELL_SIZE * 0.28);
+    let source = Rect::new(
+        CELL_SIZE * 0.35,
+        CELL_SIZE * 0.35,
+        CELL_SIZE * 0.9,
+        CELL_SIZE * 0.9,
+    );
+    // Isolate each cell, so that destination modes only see this cell's backdrop
+    sb.push_layer(Mix::Normal, 1.0, transform, &cell);
+    sb.fill(
+        Fill::NonZero,
+        transform,
+        Color::rgba8(0xe0, 0x40, 0x80, 0xc0),
+        None,
+        &backdrop,
+    );
+    let source_color = Color::rgba8(0x30, 0xb0, 0xe0, 0xc0);
+    let blended = layerless
+        && sb
+            .fill_blended(Fill::NonZero, transform, source_color, blend, &source)
+            .is_ok();
+    if !blended {
+        sb.push_layer(blend, 1.0, transform, &cell);
+        sb.fill(Fill::NonZero, transform, source_color, None, &source);
+        sb.pop_layer();
+    }
+    sb.pop_layer();
+}
+
+/// Position of `mix` in [`MIXES`].
+///
+/// The match is deliberately exhaustive, so adding a mode to peniko fails to
+/// compile until it is added to the grid.
+fn mix_index(mix: Mix) -> usize {
+    match mix {
+        Mix::Normal => 0,
+        Mix::Multiply => 1,
+        Mix::Screen => 2,
+        Mix::Overlay => 3,
+        Mix::Darken => 4,
+        Mix::Lighten => 5,
+        Mix::ColorDodge => 6,
+        Mix::ColorBurn => 7,
+        Mix::HardLight => 8,
+        Mix::SoftLight => 9,
+        Mix::Difference => 10,
+        Mix::Exclusion => 11,
+        Mix::Hue => 12,
+        Mix::Saturation => 13,
+        Mix::Color => 14,
+        Mix::Luminosity => 15,
+        Mix::Clip => unreachable!("clip is not a blend mode"),
+    }
+}
+
+/// Position of `compose` in [`COMPOSES`], exhaustive for the same reason as
+/// [`mix_index`].
+fn compose_index(compose: Compose) -> usize {
+    match compose {
+        Compose::Clear => 0,
+        Compose::Copy => 1,
+        Compose::Dest => 2,
+        Compose::SrcOver => 3,
+        Compose::DestOver => 4,
+        Compose::SrcIn => 5,
+        Compose::DestIn => 6,
+        Compose::SrcOut => 7,
+        Compose::DestOut => 8,
+        Compose::SrcAtop => 9,
+        Compose::DestAtop => 10,
+        Compose::Xor => 11,
+        Compose::Plus => 12,
+        Compose::PlusLighter => 13,
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Degenerate inputs which are known to break rasterizers.
+//!
+//! Each case is drawn in its own cell of a grid, [`COLUMNS`] cells wide, with cells
+//! of [`CELL_SIZE`] pixels placed in the order of [`CASES`]. Every cell is clipped
+//! to its own bounds so that a case which misbehaves can't spill into its
+//! neighbours. Cases that should draw nothing are drawn over an opaque background,
+//! so any unexpected coverage is visible.
+
+use vello::kurbo::{Affine, BezPath, Circle, PathEl, Point, Rect};
+use vello::peniko::{Color, Fill, Mix, Stroke};
+use vello::SceneBuilder;
+
+use crate::SceneParams;
+
+pub const CELL_SIZE: f64 = 200.0;
+pub const COLUMNS: usize = 4;
+/// Distance between the edge of a cell and the content drawn in it.
+const MARGIN: f64 = 20.0;
+
+type Case = fn(&mut SceneBuilder);
+
+/// The name and drawing function of each case, in grid order.
+pub const CASES: &[(&str, Case)] = &[
+    ("zero_area", zero_area),
+    ("lone_move_to", lone_move_to),
+    ("coincident_points", coincident_points),
+    ("collinear_points", collinear_points),
+    ("nan_coordinates", nan_coordinates),
+    ("infinite_coordinates", infinite_coordinates),
+    ("huge_circle", huge_circle),
+    ("huge_rect", huge_rect),
+    ("offscreen_fill", offscreen_fill),
+    ("zero_length_stroke", zero_length_stroke),
+];
+
+pub fn degenerate_paths(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    for (ix, (_, case)) in CASES.iter().enumerate() {
+        let origin = Point::new(
+            (ix % COLUMNS) as f64 * CELL_SIZE,
+            (ix / COLUMNS) as f64 * CELL_SIZE,
+        );
+        // Leave a one pixel gap between cells
+        let cell = Rect::from_origin_size(origin, (CELL_SIZE, CELL_SIZE));
+        let cell = Rect::new(cell.x0 + 1.0, cell.y0 + 1.0, cell.x1 - 1.0, cell.y1 - 1.0);
+        sb.push_layer(Mix::Clip, 1.0, Affine::IDENTITY, &cell);
+        sb.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Color::rgb8(0xe0, 0xe0, 0xe0),
+            None,
+            &cell,
+        );
+        let mut fragment = vello::SceneFragment::new();
+        let mut builder = SceneBuilder::for_fragment(&mut fragment);
+        case(&mut builder);
+        builder.finish();
+        sb.append(&fragment, Some(Affine::translate(origin.to_vec2())));
+        sb.pop_layer();
+    }
+}
+
+const INNER: f64 = CELL_SIZE - 2.0 * MARGIN;
+
+fn fill(sb: &mut SceneBuilder, path: &impl vello::kurbo::Shape) {
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0x20, 0x40, 0xc0),
+        None,
+        path,
+    );
+}
+
+/// A closed path which retraces itself, enclosing no area. Should draw nothing.
+fn zero_area(sb: &mut SceneBuilder) {
+    let path = [
+        PathEl::MoveTo((MARGIN, MARGIN).into()),
+        PathEl::LineTo((MARGIN + INNER, MARGIN + INNER).into()),
+        PathEl::LineTo((MARGIN, MARGIN).into()),
+        PathEl::ClosePath,
+    ];
+    fill(sb, &path);
+}
+
+/// A path made of a single move. Should draw nothing.
+fn lone_move_to(sb: &mut SceneBuilder) {
+    fill(
+        sb,
+        &[PathEl::MoveTo((CELL_SIZE * 0.5, CELL_SIZE * 0.5).into())],
+    );
+}
+
+/// A square with every vertex repeated. Should draw the square.
+fn coincident_points(sb: &mut SceneBuilder) {
+    let corners = [
+        (MARGIN, MARGIN),
+        (MARGIN + INNER, MARGIN),
+        (MARGIN + INNER, MARGIN + INNER),
+        (MARGIN, MARGIN + INNER),
+    ];
+    let mut path = BezPath::new();
+    path.move_to(corners[0]);
+    for corner in corners.iter().chain(&corners[..1]) {
+        path.line_to(*corner);
+        path.line_to(*corner);
+        path.curve_to(*corner, *corner, *corner);
+    }
+    path.close_path();
+    fill(sb, &path);
+}
+
+/// A triangle whose long edge is split into 10000 collinear points. Should draw the
+/// triangle.
+fn collinear_points(sb: &mut SceneBuilder) {
+    const N: usize = 10_000;
+    let mut path = BezPath::new();
+    path.move_to((MARGIN, MARGIN + INNER));
+    for i in 1..=N {
+        let t = i as f64 / N as f64;
+        path.line_to((MARGIN + t * INNER, MARGIN + INNER - t * INNER));
+    }
+    path.line_to((MARGIN + INNER, MARGIN + INNER));
+    path.close_path();
+    fill(sb, &path);
+}
+
+/// A triangle with a NaN vertex. The default validation drops the elements with NaN
+/// coordinates, leaving a path with no area, so this should draw nothing.
+fn nan_coordinates(sb: &mut SceneBuilder) {
+    let path = [
+        PathEl::MoveTo((MARGIN, MARGIN).into()),
+        PathEl::LineTo((f64::NAN, MARGIN).into()),
+        PathEl::QuadTo((MARGIN, f64::NAN).into(), (MARGIN + INNER, MARGIN).into()),
+        PathEl::LineTo((MARGIN, MARGIN + INNER).into()),
+        PathEl::ClosePath,
+    ];
+    fill(sb, &path);
+}
+
+/// A triangle with infinite vertices. The default validation drops the elements with
+/// infinite coordinates, leaving a path with no area, so this should draw nothing.
+fn infinite_coordinates(sb: &mut SceneBuilder) {
+    let path = [
+        PathEl::MoveTo((MARGIN, MARGIN).into()),
+        PathEl::LineTo((f64::INFINITY, MARGIN).into()),
+        PathEl::CurveTo(
+            (f64::NEG_INFINITY, MARGIN).into(),
+            (MARGIN, f64::INFINITY).into(),
+            (MARGIN + INNER, MARGIN + INNER).into(),
+        ),
+        PathEl::LineTo((MARGIN, MARGIN + INNER).into()),
+        PathEl::ClosePath,
+    ];
+    fill(sb, &path);
+}
+
+/// A circle far larger than any viewport, whose edge passes diagonally through the
+/// cell. Should fill the lower right half of the cell.
+fn huge_circle(sb: &mut SceneBuilder) {
+    let r = 1.0e7;
+    let d = r * std::f64::consts::FRAC_1_SQRT_2;
+    let center = Point::new(CELL_SIZE * 0.5 + d, CELL_SIZE * 0.5 + d);
+    fill(sb, &Circle::new(center, r));
+}
+
+/// A rectangle far larger than any viewport. Should fill the whole cell.
+fn huge_rect(sb: &mut SceneBuilder) {
+    fill(sb, &Rect::new(-1.0e8, -1.0e8, 1.0e8, 1.0e8));
+}
+
+/// A fill whose bounding box lies entirely off screen. Should draw nothing.
+fn offscreen_fill(sb: &mut SceneBuilder) {
+    fill(
+        sb,
+        &Rect::new(-1.0e5, -1.0e5, -1.0e5 + INNER, -1.0e5 + INNER),
+    );
+}
+
+/// A stroke of a path with no length. Should draw nothing or a single dot.
+fn zero_length_stroke(sb: &mut SceneBuilder) {
+    let p = Point::new(CELL_SIZE * 0.5, CELL_SIZE * 0.5);
+    sb.stroke(
+        &Stroke::new(20.0),
+        Affine::IDENTITY,
+        Color::rgb8(0x20, 0x40, 0xc0),
+        None,
+        &[PathEl::MoveTo(p), PathEl::LineTo(p)],
+    );
+}
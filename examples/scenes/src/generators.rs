@@ -0,0 +1,317 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Scenes of controllable complexity, for performance work.
+//!
+//! Every generator is deterministic: the same parameters always produce the
+//! same encoding.
+
+use vello::kurbo::{Affine, BezPath, Circle, Ellipse, Point, Rect, RoundedRect, Vec2};
+use vello::peniko::{Color, Fill, Mix};
+use vello::{FragmentId, SceneBuilder, SceneFragment, SceneGraph};
+
+use crate::{ExampleScene, SceneConfig, SceneParams};
+
+/// Size of the square area which generated content is placed in.
+const CANVAS_SIZE: f64 = 1024.0;
+
+/// Creates a generated scene from its name and parameters, as given in a scene
+/// specification such as `many_shapes:50000:7`. Returns `None` if `name` is not
+/// a generator.
+pub fn generate(name: &str, params: &[&str]) -> Result<Option<ExampleScene>, String> {
+    fn param<T: std::str::FromStr>(params: &[&str], ix: usize, default: T) -> Result<T, String> {
+        match params.get(ix) {
+            Some(param) => param
+                .parse()
+                .map_err(|_| format!("invalid scene parameter {param:?}")),
+            None => Ok(default),
+        }
+    }
+    let scene = match name {
+        "many_shapes" => many_shapes(param(params, 0, 10_000)?, param(params, 1, 1)?),
+        "markers" => markers(param(params, 0, 100_000)?, param(params, 1, 1)?),
+        "long_path" => long_path(param(params, 0, 10_000)?),
+        "deep_overlap" => deep_overlap(param(params, 0, 64)?),
+        "sparse_shape" => sparse_shape(param(params, 0, 64.0)?),
+        "retained_cells" => retained_cells(param(params, 0, 100)?, param(params, 1, 1)? != 0),
+        _ => return Ok(None),
+    };
+    Ok(Some(scene))
+}
+
+/// `n` rounded rectangles and ellipses with random solid colors.
+///
+/// Encodes `n` draw objects, each a single filled path.
+pub fn many_shapes(n: usize, seed: u64) -> ExampleScene {
+    let function = move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+        let mut rng = Rng::new(seed);
+        for _ in 0..n {
+            let center = Point::new(rng.range(0.0, CANVAS_SIZE), rng.range(0.0, CANVAS_SIZE));
+            let width = rng.range(4.0, 64.0);
+            let height = rng.range(4.0, 64.0);
+            let color = Color::rgba8(
+                rng.next_u64() as u8,
+                rng.next_u64() as u8,
+                rng.next_u64() as u8,
+                rng.next_u64() as u8 | 0x80,
+            );
+            if rng.next_u64() & 1 == 0 {
+                let rect = Rect::from_center_size(center, (width, height));
+                let radius = rng.range(0.0, width.min(height) * 0.5);
+                let shape = RoundedRect::from_rect(rect, radius);
+                sb.fill(Fill::NonZero, Affine::IDENTITY, color, None, &shape);
+            } else {
+                let rotation = rng.range(0.0, std::f64::consts::PI);
+                let shape = Ellipse::new(center, (width * 0.5, height * 0.5), rotation);
+                sb.fill(Fill::NonZero, Affine::IDENTITY, color, None, &shape);
+            }
+        }
+    };
+    ExampleScene {
+        function: Box::new(function),
+        config: SceneConfig {
+            animated: false,
+            name: format!("many_shapes:{n}:{seed}").into(),
+        },
+    }
+}
+
+/// `n` identical small circles at random positions, like the markers of a scatter
+/// plot, encoded once with [`SceneBuilder::define_path`] and drawn as instances.
+///
+/// Encodes `n` draw objects, each with the same segments, whose points are only
+/// stored once.
+pub fn markers(n: usize, seed: u64) -> ExampleScene {
+    let function = move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+        let mut rng = Rng::new(seed);
+        let marker = sb.define_path(&Circle::new(Point::ZERO, 3.0));
+        for _ in 0..n {
+            let x = rng.range(0.0, CANVAS_SIZE);
+            // Cluster the points around a curve, as real data would be
+            let y = CANVAS_SIZE * 0.5 - (x / CANVAS_SIZE - 0.5).powi(3) * CANVAS_SIZE * 2.0
+                + rng.range(-100.0, 100.0);
+            let color = Color::rgba8(0x20, 0x60, rng.next_u64() as u8 | 0x80, 0xc0);
+            sb.fill_instance(Fill::NonZero, Affine::translate((x, y)), color, marker);
+        }
+    };
+    ExampleScene {
+        function: Box::new(function),
+        config: SceneConfig {
+            animated: false,
+            name: format!("markers:{n}:{seed}").into(),
+        },
+    }
+}
+
+/// A single filled path made of `n_segments` cubic segments, winding around
+/// the center of the canvas.
+///
+/// Encodes one draw object with `n_segments` cubics.
+pub fn long_path(n_segments: usize) -> ExampleScene {
+    let function = move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+        let center = Point::new(CANVAS_SIZE * 0.5, CANVAS_SIZE * 0.5);
+        let n = n_segments.max(1);
+        let point = |i: usize| {
+            let th = i as f64 * std::f64::consts::TAU / n as f64;
+            // Vary the radius so that the segments are not all alike
+            let r = CANVAS_SIZE * (0.3 + 0.15 * (th * 7.0).sin());
+            center + Vec2::new(r * th.cos(), r * th.sin())
+        };
+        let mut path = BezPath::new();
+        path.move_to(point(0));
+        for i in 0..n {
+            let p0 = point(i);
+            let p3 = point(i + 1);
+            let d = p3 - p0;
+            let bulge = d * (8.0 / d.hypot().max(1e-9));
+            let normal = Vec2::new(-bulge.y, bulge.x);
+            path.curve_to(p0 + bulge + normal, p3 - bulge + normal, p3);
+        }
+        path.close_path();
+        sb.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Color::rgb8(0x40, 0x80, 0xc0),
+            None,
+            &path,
+        );
+    };
+    ExampleScene {
+        function: Box::new(function),
+        config: SceneConfig {
+            animated: false,
+            name: format!("long_path:{n_segments}").into(),
+        },
+    }
+}
+
+/// A single circle of diameter `size` near the top left corner, for measuring the
+/// cost of the tiles it doesn't touch on a large target, such as one of 4096 by 4096
+/// pixels.
+///
+/// Encodes one draw object.
+pub fn sparse_shape(size: f64) -> ExampleScene {
+    let function = move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+        let circle = Circle::new((32.0 + size / 2.0, 32.0 + size / 2.0), size / 2.0);
+        sb.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Color::rgb8(0x40, 0x80, 0xff),
+            None,
+            &circle,
+        );
+    };
+    ExampleScene {
+        function: Box::new(function),
+        config: SceneConfig {
+            animated: false,
+            name: format!("sparse_shape:{size}").into(),
+        },
+    }
+}
+
+/// `layers` translucent layers stacked on top of each other over the same area.
+///
+/// Encodes `layers` layers, each containing one filled rectangle, for a total
+/// of `3 * layers` draw objects.
+pub fn deep_overlap(layers: usize) -> ExampleScene {
+    let function = move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+        for i in 0..layers {
+            let offset = (i % 32) as f64 * 4.0;
+            let rect = Rect::new(
+                64.0 + offset,
+                64.0 + offset,
+                CANVAS_SIZE - 192.0 + offset,
+                CANVAS_SIZE - 192.0 + offset,
+            );
+            let hue = (i * 37) as u8;
+            sb.push_layer(Mix::Normal, 0.5, Affine::IDENTITY, &rect);
+            sb.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                Color::rgba8(hue, 255 - hue, 0x80, 0x40),
+                None,
+                &rect,
+            );
+            sb.pop_layer();
+        }
+    };
+    ExampleScene {
+        function: Box::new(function),
+        config: SceneConfig {
+            animated: false,
+            name: format!("deep_overlap:{layers}").into(),
+        },
+    }
+}
+
+/// A grid of `n_cells` cells of small shapes, where the colors of one cell change
+/// each frame.
+///
+/// If `retained` is true, the cells are kept in a [`SceneGraph`], and only the cells
+/// which changed since the last frame are encoded again. Otherwise every cell is
+/// encoded each frame. Both draw the same image, so the two can be compared for
+/// correctness and for the time taken to build the scene. Encodes `16 * n_cells`
+/// draw objects.
+pub fn retained_cells(n_cells: usize, retained: bool) -> ExampleScene {
+    let n_cells = n_cells.max(1);
+    let columns = (n_cells as f64).sqrt().ceil() as usize;
+    let cell_size = CANVAS_SIZE / columns as f64;
+    // The number of times cell `ix` has changed by `frame`
+    let version = move |ix: usize, frame: usize| (frame + n_cells - ix) / n_cells;
+    let draw_cell = move |sb: &mut SceneBuilder, ix: usize, version: usize| {
+        let mut rng = Rng::new(ix as u64);
+        let origin = Point::new(
+            (ix % columns) as f64 * cell_size,
+            (ix / columns) as f64 * cell_size,
+        );
+        let hue = (version * 40 + ix * 7) as u8;
+        for _ in 0..16 {
+            let center = origin + Vec2::new(rng.range(0.0, cell_size), rng.range(0.0, cell_size));
+            let size = rng.range(2.0, cell_size * 0.25);
+            let rect = Rect::from_center_size(center, (size, size));
+            let color = Color::rgba8(hue, rng.next_u64() as u8, 255 - hue, 0xc0);
+            let shape = RoundedRect::from_rect(rect, size * 0.25);
+            sb.fill(Fill::NonZero, Affine::IDENTITY, color, None, &shape);
+        }
+    };
+    let mut graph: Option<(SceneGraph, Vec<FragmentId>, usize)> = None;
+    let function = move |sb: &mut SceneBuilder, params: &mut SceneParams| {
+        let frame = (params.time * 60.0) as usize;
+        if !retained {
+            for ix in 0..n_cells {
+                draw_cell(sb, ix, version(ix, frame));
+            }
+            return;
+        }
+        match &mut graph {
+            // Only the cells which changed in the frames since the last one are updated
+            Some((graph, ids, last_frame)) if *last_frame <= frame => {
+                for changed in *last_frame + 1..=frame.min(*last_frame + n_cells) {
+                    let ix = changed % n_cells;
+                    graph.update(ids[ix], |sb| draw_cell(sb, ix, version(ix, frame)));
+                }
+                *last_frame = frame;
+            }
+            _ => {
+                let mut new_graph = SceneGraph::new();
+                let ids = (0..n_cells)
+                    .map(|ix| {
+                        let mut fragment = SceneFragment::new();
+                        let mut builder = SceneBuilder::for_fragment(&mut fragment);
+                        draw_cell(&mut builder, ix, version(ix, frame));
+                        builder.finish();
+                        new_graph.push(fragment, None)
+                    })
+                    .collect();
+                graph = Some((new_graph, ids, frame));
+            }
+        }
+        if let Some((graph, _, _)) = &graph {
+            sb.append_scene(graph.scene(), None);
+        }
+    };
+    ExampleScene {
+        function: Box::new(function),
+        config: SceneConfig {
+            animated: true,
+            name: format!("retained_cells:{n_cells}:{}", retained as u8).into(),
+        },
+    }
+}
+
+/// Small deterministic random number generator (xorshift64*), so that generated
+/// scenes don't depend on an external crate's output staying stable.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32
+    }
+
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        let unit = self.next_u64() as f64 / u32::MAX as f64;
+        min + (max - min) * unit
+    }
+}
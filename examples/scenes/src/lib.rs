@@ -0,0 +1,118 @@
+// Copyright 2022 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Scenes shared between the vello examples.
+
+pub mod blend;
+pub mod degenerate;
+pub mod generators;
+pub mod pico_svg;
+pub mod simple_text;
+mod test_scenes;
+pub mod text;
+
+use std::borrow::Cow;
+
+pub use simple_text::SimpleText;
+pub use test_scenes::svg_scene;
+
+use vello::SceneBuilder;
+
+/// The GhostScript Tiger, which is rendered when no other svg is provided.
+pub const TIGER: &str = include_str!("../../assets/Ghostscript_Tiger.svg");
+
+/// Per frame state which is provided to a scene while it is being built.
+pub struct SceneParams<'a> {
+    /// Time in seconds since the start of the animation.
+    pub time: f64,
+    /// Text renderer shared between all scenes.
+    pub text: &'a mut SimpleText,
+}
+
+/// Static description of a scene.
+pub struct SceneConfig {
+    /// Whether the scene changes over time.
+    pub animated: bool,
+    /// Name used to select the scene, for example from the command line.
+    pub name: Cow<'static, str>,
+}
+
+/// A scene and its configuration.
+pub struct ExampleScene {
+    pub function: Box<dyn TestScene>,
+    pub config: SceneConfig,
+}
+
+/// Something which can be drawn into a scene builder.
+pub trait TestScene {
+    fn render(&mut self, sb: &mut SceneBuilder, params: &mut SceneParams);
+}
+
+impl<F: FnMut(&mut SceneBuilder, &mut SceneParams)> TestScene for F {
+    fn render(&mut self, sb: &mut SceneBuilder, params: &mut SceneParams) {
+        self(sb, params);
+    }
+}
+
+/// An ordered collection of scenes.
+pub struct SceneSet {
+    pub scenes: Vec<ExampleScene>,
+}
+
+impl SceneSet {
+    /// Returns the built in test scenes.
+    pub fn test_scenes() -> Self {
+        test_scenes::test_scenes()
+    }
+
+    /// Selects a scene from a specification, which is either an index, a name, or the
+    /// name of a generator followed by its parameters separated by colons (for example
+    /// `many_shapes:50000`). Generated scenes replace the registered scene of the same
+    /// name, or are appended if there is none.
+    pub fn select(&mut self, spec: &str) -> Result<usize, String> {
+        let mut parts = spec.split(':');
+        let name = parts.next().unwrap_or_default();
+        let params = parts.collect::<Vec<_>>();
+        if !params.is_empty() {
+            let scene = generators::generate(name, &params)?
+                .ok_or_else(|| format!("scene {name:?} does not take parameters"))?;
+            return Ok(match self.find(name) {
+                Some(ix) => {
+                    self.scenes[ix] = scene;
+                    ix
+                }
+                None => {
+                    self.scenes.push(scene);
+                    self.scenes.len() - 1
+                }
+            });
+        }
+        self.find(spec)
+            .ok_or_else(|| format!("no scene matching {spec:?}"))
+    }
+
+    /// Finds a scene either by its index or by its name.
+    pub fn find(&self, index_or_name: &str) -> Option<usize> {
+        if let Ok(index) = index_or_name.parse::<usize>() {
+            return (index < self.scenes.len()).then_some(index);
+        }
+        self.scenes.iter().position(|scene| {
+            // Generated scenes are named with their parameters
+            let name = &scene.config.name;
+            name == index_or_name || name.split(':').next() == Some(index_or_name)
+        })
+    }
+}
@@ -16,12 +16,11 @@
 
 use vello::{
     glyph::{
-        pinot,
         pinot::{FontRef, TableProvider},
-        GlyphContext,
+        Glyph, GlyphCache, GlyphFont,
     },
     kurbo::Affine,
-    peniko::Brush,
+    peniko::{Brush, Color},
     SceneBuilder,
 };
 
@@ -30,16 +29,21 @@ use vello::{
 const FONT_DATA: &[u8] = include_bytes!("../../assets/roboto/Roboto-Regular.ttf");
 
 pub struct SimpleText {
-    gcx: GlyphContext,
+    glyphs: GlyphCache,
 }
 
 impl SimpleText {
     pub fn new() -> Self {
         Self {
-            gcx: GlyphContext::new(),
+            glyphs: GlyphCache::new(),
         }
     }
 
+    /// Marks the end of a frame, so that glyphs which are no longer drawn are freed.
+    pub fn next_frame(&mut self) {
+        self.glyphs.next_frame();
+    }
+
     pub fn add(
         &mut self,
         builder: &mut SceneBuilder,
@@ -55,31 +59,37 @@ impl SimpleText {
         });
         if let Some(cmap) = font.cmap() {
             if let Some(hmtx) = font.hmtx() {
-                let upem = font.head().map(|head| head.units_per_em()).unwrap_or(1000) as f64;
-                let scale = size as f64 / upem;
-                let vars: [(pinot::types::Tag, f32); 0] = [];
-                let mut provider = self.gcx.new_provider(font, None, size, false, vars);
+                let upem = font.head().map(|head| head.units_per_em()).unwrap_or(1000) as f32;
+                let scale = size / upem;
                 let hmetrics = hmtx.hmetrics();
                 let default_advance = hmetrics
                     .get(hmetrics.len().saturating_sub(1))
                     .map(|h| h.advance_width)
                     .unwrap_or(0);
-                let mut pen_x = 0f64;
-                for ch in text.chars() {
+                let mut pen_x = 0f32;
+                let glyphs = text.chars().map(|ch| {
                     let gid = cmap.map(ch as u32).unwrap_or(0);
                     let advance = hmetrics
                         .get(gid as usize)
                         .map(|h| h.advance_width)
-                        .unwrap_or(default_advance) as f64
+                        .unwrap_or(default_advance) as f32
                         * scale;
-                    if let Some(glyph) = provider.get(gid, brush) {
-                        let xform = transform
-                            * Affine::translate((pen_x, 0.0))
-                            * Affine::scale_non_uniform(1.0, -1.0);
-                        builder.append(&glyph, Some(xform));
-                    }
+                    let x = pen_x;
                     pen_x += advance;
-                }
+                    Glyph { id: gid, x, y: 0.0 }
+                });
+                let font = GlyphFont {
+                    font: FontRef {
+                        data: font.data,
+                        offset: font.offset,
+                    },
+                    // Fonts are told apart by the address of their data, which is
+                    // stable for the static data used here
+                    id: font.data.as_ptr() as u64 ^ font.offset as u64,
+                    hint: false,
+                };
+                let brush = brush.cloned().unwrap_or(Brush::Solid(Color::WHITE));
+                builder.draw_glyphs(&mut self.glyphs, &font, size, glyphs, &brush, transform);
             }
         }
     }
@@ -0,0 +1,882 @@
+use std::{borrow::Cow, time::Instant};
+
+use crate::blend;
+use crate::degenerate::degenerate_paths;
+use crate::generators::{
+    deep_overlap, long_path, many_shapes, markers, retained_cells, sparse_shape,
+};
+use crate::pico_svg::PicoSvg;
+use crate::text;
+use crate::{ExampleScene, SceneConfig, SceneParams, SceneSet, TIGER};
+use vello::kurbo::{Affine, BezPath, Circle, Ellipse, PathEl, Point, Rect, RoundedRect, Vec2};
+use vello::peniko::*;
+use vello::*;
+
+macro_rules! scene {
+    ($func:expr, $name:expr, $animated:literal) => {
+        ExampleScene {
+            config: SceneConfig {
+                animated: $animated,
+                name: $name.into(),
+            },
+            function: Box::new($func),
+        }
+    };
+}
+
+pub fn test_scenes() -> SceneSet {
+    let scenes = vec![
+        scene!(animated_text, "animated_text", true),
+        scene!(blend_squares, "blend_squares", false),
+        scene!(blend::blend_grid, "blend_grid", false),
+        svg_scene("tiger", TIGER, 6.0),
+        scene!(brush_transform, "brush_transform", true),
+        scene!(sweep_gradients, "sweep_gradients", false),
+        scene!(repeating_gradients, "repeating_gradients", false),
+        scene!(gradient_interpolation, "gradient_interpolation", false),
+        scene!(dashed_strokes, "dashed_strokes", false),
+        scene!(stroke_styles, "stroke_styles", false),
+        scene!(f16_coordinates, "f16_coordinates", false),
+        scene!(scaled_shapes, "scaled_shapes", false),
+        scene!(blurred_rects, "blurred_rects", true),
+        scene!(opacity_groups, "opacity_groups", false),
+        scene!(mask_layers, "mask_layers", false),
+        scene!(nested_clips, "nested_clips", false),
+        scene!(funky_paths, "funky_paths", false),
+        scene!(cardioid_and_friends, "cardioid_and_friends", false),
+        scene!(degenerate_paths, "degenerate_paths", false),
+        scene!(tile_seams, "tile_seams", false),
+        scene!(text::paragraph, "paragraph", false),
+        scene!(text::scrolling_paragraph, "scrolling_paragraph", true),
+        many_shapes(10_000, 1),
+        markers(100_000, 1),
+        long_path(10_000),
+        deep_overlap(64),
+        sparse_shape(64.0),
+        retained_cells(100, true),
+    ];
+    SceneSet { scenes }
+}
+
+/// Creates a scene which renders the given svg, which is only parsed when the scene is first
+/// rendered.
+pub fn svg_scene(
+    name: impl Into<Cow<'static, str>>,
+    svg: impl Into<Cow<'static, str>>,
+    scale: f64,
+) -> ExampleScene {
+    let svg = svg.into();
+    let mut fragment = None;
+    let function = move |sb: &mut SceneBuilder, _: &mut SceneParams| {
+        let fragment = fragment.get_or_insert_with(|| {
+            let start = Instant::now();
+            eprintln!("Starting to parse svg");
+            let svg = PicoSvg::load(&svg, scale).unwrap();
+            eprintln!("Parsing svg took {:?}", start.elapsed());
+            let mut new_scene = SceneFragment::new();
+            let mut builder = SceneBuilder::for_fragment(&mut new_scene);
+            render_svg(&mut builder, &svg);
+            builder.finish();
+            new_scene
+        });
+        sb.append(fragment, None);
+    };
+    scene!(function, name, false)
+}
+
+fn funky_paths(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    use PathEl::*;
+    let missing_movetos = [
+        LineTo((100.0, 100.0).into()),
+        LineTo((100.0, 200.0).into()),
+        ClosePath,
+        LineTo((0.0, 400.0).into()),
+        LineTo((100.0, 400.0).into()),
+    ];
+    let only_movetos = [MoveTo((0.0, 0.0).into()), MoveTo((100.0, 100.0).into())];
+    let empty: [PathEl; 0] = [];
+    sb.fill(
+        Fill::NonZero,
+        Affine::translate((100.0, 100.0)),
+        Color::rgb8(0, 0, 255),
+        None,
+        &missing_movetos,
+    );
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0, 0, 255),
+        None,
+        &empty,
+    );
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0, 0, 255),
+        None,
+        &only_movetos,
+    );
+    sb.stroke(
+        &Stroke::new(8.0),
+        Affine::translate((100.0, 100.0)),
+        Color::rgb8(0, 255, 255),
+        None,
+        &missing_movetos,
+    );
+}
+
+fn render_svg(sb: &mut SceneBuilder, svg: &PicoSvg) {
+    use crate::pico_svg::*;
+    for item in &svg.items {
+        match item {
+            Item::Fill(fill) => {
+                sb.fill(
+                    Fill::NonZero,
+                    Affine::IDENTITY,
+                    fill.color,
+                    None,
+                    &fill.path,
+                );
+            }
+            Item::Stroke(stroke) => {
+                sb.stroke(
+                    &Stroke::new(stroke.width as f32),
+                    Affine::IDENTITY,
+                    stroke.color,
+                    None,
+                    &stroke.path,
+                );
+            }
+        }
+    }
+}
+
+fn cardioid_and_friends(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    render_cardioid(sb);
+    render_clip_test(sb);
+    render_alpha_test(sb);
+    //render_tiger(sb, false);
+}
+
+fn render_cardioid(sb: &mut SceneBuilder) {
+    let n = 601;
+    let dth = std::f64::consts::PI * 2.0 / (n as f64);
+    let center = Point::new(1024.0, 768.0);
+    let r = 750.0;
+    let mut path = BezPath::new();
+    for i in 1..n {
+        let mut p0 = center;
+        let a0 = i as f64 * dth;
+        p0.x += a0.cos() * r;
+        p0.y += a0.sin() * r;
+        let mut p1 = center;
+        let a1 = ((i * 2) % n) as f64 * dth;
+        p1.x += a1.cos() * r;
+        p1.y += a1.sin() * r;
+        path.push(PathEl::MoveTo(p0));
+        path.push(PathEl::LineTo(p1));
+    }
+    sb.stroke(
+        &Stroke::new(2.0),
+        Affine::IDENTITY,
+        Color::rgb8(0, 0, 255),
+        None,
+        &path,
+    );
+}
+
+fn render_clip_test(sb: &mut SceneBuilder) {
+    const N: usize = 16;
+    const X0: f64 = 50.0;
+    const Y0: f64 = 450.0;
+    // Note: if it gets much larger, it will exceed the 1MB scratch buffer.
+    // But this is a pretty demanding test.
+    const X1: f64 = 550.0;
+    const Y1: f64 = 950.0;
+    let step = 1.0 / ((N + 1) as f64);
+    for i in 0..N {
+        let t = ((i + 1) as f64) * step;
+        let path = [
+            PathEl::MoveTo((X0, Y0).into()),
+            PathEl::LineTo((X1, Y0).into()),
+            PathEl::LineTo((X1, Y0 + t * (Y1 - Y0)).into()),
+            PathEl::LineTo((X1 + t * (X0 - X1), Y1).into()),
+            PathEl::LineTo((X0, Y1).into()),
+            PathEl::ClosePath,
+        ];
+        sb.push_layer(Mix::Clip, 1.0, Affine::IDENTITY, &path);
+    }
+    let rect = Rect::new(X0, Y0, X1, Y1);
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        &Brush::Solid(Color::rgb8(0, 255, 0)),
+        None,
+        &rect,
+    );
+    for _ in 0..N {
+        sb.pop_layer();
+    }
+}
+
+fn render_alpha_test(sb: &mut SceneBuilder) {
+    // Alpha compositing tests.
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(255, 0, 0),
+        None,
+        &make_diamond(1024.0, 100.0),
+    );
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgba8(0, 255, 0, 0x80),
+        None,
+        &make_diamond(1024.0, 125.0),
+    );
+    sb.push_layer(
+        Mix::Clip,
+        1.0,
+        Affine::IDENTITY,
+        &make_diamond(1024.0, 150.0),
+    );
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgba8(0, 0, 255, 0x80),
+        None,
+        &make_diamond(1024.0, 175.0),
+    );
+    sb.pop_layer();
+}
+
+// Long thin wedges at angles which are close to horizontal or to the diagonal, where
+// coverage is most likely to be discontinuous at the boundaries between tiles. Each
+// wedge overlaps a copy of itself, so the fill rules differ where they overlap. The
+// wedges are spaced by a fraction of a pixel more than a whole number each, so their
+// edges cross the tile boundaries at many positions within a pixel.
+fn tile_seams(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    const ANGLES: &[f64] = &[0.1, 1.0, 45.0 - 0.01, 45.0 + 0.01];
+    const N_WEDGES: usize = 40;
+    for (column, style) in [Fill::NonZero, Fill::EvenOdd].into_iter().enumerate() {
+        for (row, angle) in ANGLES.iter().enumerate() {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let direction = Vec2::new(cos, sin);
+            let normal = Vec2::new(-sin, cos);
+            // Near horizontal wedges are stacked vertically, and diagonal ones side by side
+            let (length, step) = if *angle < 10.0 {
+                (440.0, Vec2::new(0.0, 5.37))
+            } else {
+                (200.0, Vec2::new(7.13, 0.0))
+            };
+            let origin = Point::new(20.0 + column as f64 * 500.0, 10.0 + row as f64 * 240.0);
+            let mut path = BezPath::new();
+            for ix in 0..N_WEDGES {
+                for offset in [0.0, 3.0] {
+                    let apex = origin + step * ix as f64 + normal * offset;
+                    let end = apex + direction * length;
+                    path.move_to(apex);
+                    path.line_to(end);
+                    path.line_to(end + normal * 6.0);
+                    path.close_path();
+                }
+            }
+            sb.fill(
+                style,
+                Affine::IDENTITY,
+                Color::rgb8(0xf0, 0xf0, 0xf0),
+                None,
+                &path,
+            );
+        }
+    }
+}
+
+fn blend_squares(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    const BLEND_MODES: &[Mix] = &[
+        Mix::Normal,
+        Mix::Multiply,
+        Mix::Darken,
+        Mix::Screen,
+        Mix::Lighten,
+        Mix::Overlay,
+        Mix::ColorDodge,
+        Mix::ColorBurn,
+        Mix::HardLight,
+        Mix::SoftLight,
+        Mix::Difference,
+        Mix::Exclusion,
+        Mix::Hue,
+        Mix::Saturation,
+        Mix::Color,
+        Mix::Luminosity,
+    ];
+    for (ix, &blend) in BLEND_MODES.iter().enumerate() {
+        let i = ix % 4;
+        let j = ix / 4;
+        let transform = Affine::translate((i as f64 * 225., j as f64 * 225.));
+        let square = blend_square(blend.into());
+        sb.append(&square, Some(transform));
+    }
+}
+
+fn render_blend_square(sb: &mut SceneBuilder, blend: BlendMode, transform: Affine) {
+    // Inspired by https://developer.mozilla.org/en-US/docs/Web/CSS/mix-blend-mode
+    let rect = Rect::from_origin_size(Point::new(0., 0.), (200., 200.));
+    let linear =
+        Gradient::new_linear((0.0, 0.0), (200.0, 0.0)).with_stops([Color::BLACK, Color::WHITE]);
+    sb.fill(Fill::NonZero, transform, &linear, None, &rect);
+    const GRADIENTS: &[(f64, f64, Color)] = &[
+        (150., 0., Color::rgb8(255, 240, 64)),
+        (175., 100., Color::rgb8(255, 96, 240)),
+        (125., 200., Color::rgb8(64, 192, 255)),
+    ];
+    for (x, y, c) in GRADIENTS {
+        let mut color2 = c.clone();
+        color2.a = 0;
+        let radial = Gradient::new_radial((*x, *y), 100.0).with_stops([*c, color2]);
+        sb.fill(Fill::NonZero, transform, &radial, None, &rect);
+    }
+    const COLORS: &[Color] = &[
+        Color::rgb8(255, 0, 0),
+        Color::rgb8(0, 255, 0),
+        Color::rgb8(0, 0, 255),
+    ];
+    sb.push_layer(Mix::Normal, 1.0, transform, &rect);
+    for (i, c) in COLORS.iter().enumerate() {
+        let linear = Gradient::new_linear((0.0, 0.0), (0.0, 200.0)).with_stops([Color::WHITE, *c]);
+        sb.push_layer(blend, 1.0, transform, &rect);
+        // squash the ellipse
+        let a = transform
+            * Affine::translate((100., 100.))
+            * Affine::rotate(std::f64::consts::FRAC_PI_3 * (i * 2 + 1) as f64)
+            * Affine::scale_non_uniform(1.0, 0.357)
+            * Affine::translate((-100., -100.));
+        sb.fill(
+            Fill::NonZero,
+            a,
+            &linear,
+            None,
+            &Ellipse::new((100., 100.), (90., 90.), 0.),
+        );
+        sb.pop_layer();
+    }
+    sb.pop_layer();
+}
+
+fn blend_square(blend: BlendMode) -> SceneFragment {
+    let mut fragment = SceneFragment::default();
+    let mut sb = SceneBuilder::for_fragment(&mut fragment);
+    render_blend_square(&mut sb, blend, Affine::IDENTITY);
+    sb.finish();
+    fragment
+}
+
+fn animated_text(sb: &mut SceneBuilder, params: &mut SceneParams) {
+    use PathEl::*;
+    let rect = Rect::from_origin_size(Point::new(0.0, 0.0), (1000.0, 1000.0));
+    let star = [
+        MoveTo((50.0, 0.0).into()),
+        LineTo((21.0, 90.0).into()),
+        LineTo((98.0, 35.0).into()),
+        LineTo((2.0, 35.0).into()),
+        LineTo((79.0, 90.0).into()),
+        ClosePath,
+    ];
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        &Brush::Solid(Color::rgb8(128, 128, 128)),
+        None,
+        &rect,
+    );
+    let text_size = 60.0 + 40.0 * (0.6 * params.time as f32).sin();
+    let s = "\u{1f600}hello vello text!";
+    params.text.add(
+        sb,
+        None,
+        text_size,
+        None,
+        Affine::translate((110.0, 600.0)),
+        s,
+    );
+    params.text.add(
+        sb,
+        None,
+        text_size,
+        None,
+        Affine::translate((110.0, 700.0)),
+        s,
+    );
+    let th = (std::f64::consts::PI / 3.0) * params.time;
+    let center = Point::new(500.0, 500.0);
+    let mut p1 = center;
+    p1.x += 400.0 * th.cos();
+    p1.y += 400.0 * th.sin();
+    sb.stroke(
+        &Stroke::new(5.0),
+        Affine::IDENTITY,
+        &Brush::Solid(Color::rgb8(128, 0, 0)),
+        None,
+        &[PathEl::MoveTo(center), PathEl::LineTo(p1)],
+    );
+    sb.fill(
+        Fill::NonZero,
+        Affine::translate((150.0, 150.0)) * Affine::scale(0.2),
+        Color::RED,
+        None,
+        &rect,
+    );
+    let alpha = (params.time * 1.8).sin() as f32 * 0.5 + 0.5;
+    sb.push_layer(Mix::Normal, alpha, Affine::IDENTITY, &rect);
+    sb.fill(
+        Fill::NonZero,
+        Affine::translate((100.0, 100.0)) * Affine::scale(0.2),
+        Color::BLUE,
+        None,
+        &rect,
+    );
+    sb.fill(
+        Fill::NonZero,
+        Affine::translate((200.0, 200.0)) * Affine::scale(0.2),
+        Color::GREEN,
+        None,
+        &rect,
+    );
+    sb.pop_layer();
+    sb.fill(
+        Fill::NonZero,
+        Affine::translate((400.0, 100.0)),
+        Color::PURPLE,
+        None,
+        &star,
+    );
+    sb.fill(
+        Fill::EvenOdd,
+        Affine::translate((500.0, 100.0)),
+        Color::PURPLE,
+        None,
+        &star,
+    );
+}
+
+fn brush_transform(sb: &mut SceneBuilder, params: &mut SceneParams) {
+    let th = (std::f64::consts::PI / 3.0) * params.time;
+    let linear = Gradient::new_linear((0.0, 0.0), (0.0, 200.0)).with_stops([
+        Color::RED,
+        Color::GREEN,
+        Color::BLUE,
+    ]);
+    sb.fill(
+        Fill::NonZero,
+        Affine::translate((200.0, 200.0)),
+        &linear,
+        Some(around_center(Affine::rotate(th), Point::new(200.0, 100.0))),
+        &Rect::from_origin_size(Point::default(), (400.0, 200.0)),
+    );
+    sb.stroke(
+        &Stroke::new(40.0),
+        Affine::translate((800.0, 200.0)),
+        &linear,
+        Some(around_center(Affine::rotate(th), Point::new(200.0, 100.0))),
+        &Rect::from_origin_size(Point::default(), (400.0, 200.0)),
+    );
+}
+
+/// Sweep gradients with each extend mode. The top row sweeps through a full turn,
+/// the middle row through a quarter turn so the extend mode is visible, and the
+/// bottom row repeats the middle row with a rotated and skewed brush transform.
+fn sweep_gradients(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    use std::f32::consts::{FRAC_PI_2, TAU};
+    const EXTENDS: &[Extend] = &[Extend::Pad, Extend::Repeat, Extend::Reflect];
+    let stops = [Color::RED, Color::YELLOW, Color::BLUE];
+    let rect = Rect::new(0.0, 0.0, 200.0, 200.0);
+    let center = Point::new(100.0, 100.0);
+    let brush_transform = around_center(
+        Affine::rotate(std::f64::consts::FRAC_PI_6) * Affine::skew(0.3, 0.0),
+        center,
+    );
+    for (column, extend) in EXTENDS.iter().enumerate() {
+        let rows = [
+            (0.0, TAU, None),
+            (0.0, FRAC_PI_2, None),
+            (0.0, FRAC_PI_2, Some(brush_transform)),
+        ];
+        for (row, (start_angle, end_angle, brush_transform)) in rows.into_iter().enumerate() {
+            let sweep = Gradient::new_sweep(center, start_angle, end_angle)
+                .with_stops(stops)
+                .with_extend(*extend);
+            let transform =
+                Affine::translate((50.0 + column as f64 * 225.0, 50.0 + row as f64 * 225.0));
+            sb.fill(Fill::NonZero, transform, &sweep, brush_transform, &rect);
+        }
+    }
+}
+
+/// Linear gradients with a period of a few pixels, extended across strips 4096
+/// pixels wide, which should stay sharp and in phase at both ends. The last row starts
+/// its gradient a million pixels to the left, a whole number of periods, so it should
+/// match the repeating row above it. The ends can be compared by rendering headlessly
+/// at a width of 4096.
+fn repeating_gradients(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    const WIDTH: f64 = 4096.0;
+    const PERIOD: f64 = 4.0;
+    let rows = [
+        (Extend::Pad, 0.0),
+        (Extend::Repeat, 0.0),
+        (Extend::Reflect, 0.0),
+        (Extend::Repeat, -1e6),
+    ];
+    let rect = Rect::new(0.0, 0.0, WIDTH, 50.0);
+    for (row, (extend, start)) in rows.into_iter().enumerate() {
+        let linear = Gradient::new_linear((start, 0.0), (start + PERIOD, 0.0))
+            .with_stops([Color::BLACK, Color::WHITE])
+            .with_extend(extend);
+        let transform = Affine::translate((0.0, 50.0 + row as f64 * 75.0));
+        sb.fill(Fill::NonZero, transform, &linear, None, &rect);
+    }
+}
+
+/// A blue to yellow gradient interpolated in each color space, one per row. The
+/// sRGB row has a gray midpoint, while the Oklab row stays bright and saturated.
+fn gradient_interpolation(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    use vello::encoding::InterpolationSpace;
+    const SPACES: &[InterpolationSpace] = &[
+        InterpolationSpace::Srgb,
+        InterpolationSpace::LinearSrgb,
+        InterpolationSpace::Oklab,
+        InterpolationSpace::Oklch,
+    ];
+    let linear = Gradient::new_linear((0.0, 0.0), (600.0, 0.0))
+        .with_stops([Color::rgb8(0, 0, 255), Color::rgb8(255, 255, 0)]);
+    let rect = Rect::new(0.0, 0.0, 600.0, 80.0);
+    for (row, space) in SPACES.iter().enumerate() {
+        sb.set_interpolation_space(*space);
+        let transform = Affine::translate((50.0, 50.0 + row as f64 * 100.0));
+        sb.fill(Fill::NonZero, transform, &linear, None, &rect);
+    }
+    sb.set_interpolation_space(InterpolationSpace::default());
+}
+
+/// Dashed strokes. The circle has dashes about as long as its radius, so dashes
+/// placed with an inaccurate arc length visibly drift from the equal spacing. The
+/// lines below show an eight entry pattern with zero length gaps and dots, and an
+/// offset several times the length of its pattern.
+fn dashed_strokes(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    let color = Color::rgb8(0x20, 0x60, 0xc0);
+    let circle = Circle::new((200.0, 200.0), 120.0);
+    let dash = std::f32::consts::PI * 120.0 / 6.0;
+    sb.stroke(
+        &Stroke::new(12.0).with_dashes(0.0, [dash, dash]),
+        Affine::IDENTITY,
+        color,
+        None,
+        &circle,
+    );
+    let line = [
+        PathEl::MoveTo((400.0, 80.0).into()),
+        PathEl::LineTo((900.0, 80.0).into()),
+    ];
+    sb.stroke(
+        &Stroke::new(16.0).with_dashes(0.0, [40.0, 24.0]),
+        Affine::IDENTITY,
+        color,
+        None,
+        &line,
+    );
+    sb.stroke(
+        &Stroke::new(16.0).with_dashes(0.0, [40.0, 0.0, 20.0, 24.0, 0.0, 24.0, 10.0, 30.0]),
+        Affine::translate((0.0, 80.0)),
+        color,
+        None,
+        &line,
+    );
+    sb.stroke(
+        &Stroke::new(16.0).with_dashes(3.0 * 64.0 + 20.0, [40.0, 24.0]),
+        Affine::translate((0.0, 160.0)),
+        color,
+        None,
+        &line,
+    );
+}
+
+/// The same shapes stored with f32 coordinates in the top row and f16 coordinates in
+/// the bottom row, at increasing zoom levels from left to right. The rows should look
+/// the same.
+/// A zig-zag stroked with each cap, and each join with miter limits of 1, 4 and 10.
+/// The spikes have angles of 90, 45, 20 and 10 degrees, which need miter limits of
+/// 1.4, 2.6, 5.8 and 11.5, and the zig-zag ends by reversing back on itself, which is
+/// always beveled rather than mitered.
+fn stroke_styles(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    const SPIKE_HEIGHT: f64 = 40.0;
+    let mut zig_zag = BezPath::new();
+    let mut x = 20.0;
+    zig_zag.move_to((x, SPIKE_HEIGHT));
+    for angle in [90.0f64, 45.0, 20.0, 10.0] {
+        let half_width = SPIKE_HEIGHT * (angle.to_radians() * 0.5).tan();
+        zig_zag.line_to((x + half_width, 0.0));
+        x += 2.0 * half_width;
+        zig_zag.line_to((x, SPIKE_HEIGHT));
+        x += 30.0;
+        zig_zag.line_to((x, SPIKE_HEIGHT));
+    }
+    zig_zag.line_to((x + 40.0, SPIKE_HEIGHT));
+    zig_zag.line_to((x + 10.0, SPIKE_HEIGHT));
+    let joins = [
+        (Join::Miter, 1.0),
+        (Join::Miter, 4.0),
+        (Join::Miter, 10.0),
+        (Join::Bevel, 4.0),
+        (Join::Round, 4.0),
+    ];
+    let mut y = 30.0;
+    for cap in [Cap::Butt, Cap::Square, Cap::Round] {
+        for (join, miter_limit) in joins {
+            let style = Stroke::new(8.0)
+                .with_caps(cap)
+                .with_join(join)
+                .with_miter_limit(miter_limit);
+            let transform = Affine::translate((20.0, y));
+            sb.stroke(
+                &style,
+                transform,
+                Color::rgb8(0x40, 0x80, 0xff),
+                None,
+                &zig_zag,
+            );
+            // The path itself, to show where the caps and joins are
+            sb.stroke(&Stroke::new(1.0), transform, Color::BLACK, None, &zig_zag);
+            y += SPIKE_HEIGHT + 25.0;
+        }
+    }
+}
+
+fn f16_coordinates(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    for (row, precision) in [encoding::Precision::F32, encoding::Precision::F16]
+        .into_iter()
+        .enumerate()
+    {
+        let mut fragment = SceneFragment::new();
+        let mut builder =
+            SceneBuilder::for_fragment(&mut fragment).with_coordinate_precision(precision);
+        let mut x = 20.0;
+        for zoom in [1.0, 2.0, 4.0] {
+            let transform = Affine::translate((x, 20.0)) * Affine::scale(zoom);
+            render_f16_shapes(&mut builder, transform);
+            x += 40.0 * zoom + 20.0;
+        }
+        builder.finish();
+        sb.append(
+            &fragment,
+            Some(Affine::translate((0.0, row as f64 * 500.0))),
+        );
+    }
+}
+
+fn render_f16_shapes(sb: &mut SceneBuilder, transform: Affine) {
+    let star = (0..10)
+        .map(|i| {
+            let angle = i as f64 * std::f64::consts::PI / 5.0;
+            let r = if i % 2 == 0 { 18.0 } else { 7.0 };
+            Point::new(20.0 + angle.sin() * r, 20.0 - angle.cos() * r)
+        })
+        .collect::<Vec<_>>();
+    let mut path = BezPath::new();
+    path.move_to(star[0]);
+    for p in &star[1..] {
+        path.line_to(*p);
+    }
+    path.close_path();
+    sb.fill(
+        Fill::NonZero,
+        transform,
+        Color::rgb8(0xc0, 0x40, 0x20),
+        None,
+        &path,
+    );
+    let gradient =
+        Gradient::new_linear((0.0, 0.0), (40.0, 0.0)).with_stops([Color::BLUE, Color::YELLOW]);
+    sb.fill(
+        Fill::NonZero,
+        transform,
+        &gradient,
+        None,
+        &RoundedRect::new(0.0, 50.0, 40.0, 70.0, 6.0),
+    );
+    sb.stroke(
+        &Stroke::new(1.0),
+        transform,
+        Color::rgb8(0x20, 0x60, 0xc0),
+        None,
+        &Circle::new((20.0, 100.0), 15.0),
+    );
+}
+
+/// Small curved shapes drawn under a 50x scale. These should stay smooth rather
+/// than showing the facets of an approximation made at their unscaled size.
+fn scaled_shapes(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    let scale = Affine::scale(50.0);
+    sb.fill(
+        Fill::NonZero,
+        scale,
+        Color::rgb8(0x40, 0x80, 0xc0),
+        None,
+        &Circle::new((8.0, 8.0), 6.0),
+    );
+    sb.stroke(
+        &Stroke::new(0.5),
+        scale,
+        Color::rgb8(0xc0, 0x40, 0x40),
+        None,
+        &RoundedRect::new(16.0, 2.0, 30.0, 14.0, 4.0),
+    );
+}
+
+/// Blurred rounded rectangles with a range of corner radii, down the rows, and
+/// blur standard deviations, across the columns. The last row rotates over time.
+fn blurred_rects(sb: &mut SceneBuilder, params: &mut SceneParams) {
+    const RADII: &[f64] = &[0.0, 10.0, 40.0];
+    const STD_DEVS: &[f64] = &[0.0, 2.0, 8.0, 24.0];
+    let rect = Rect::new(-60.0, -40.0, 60.0, 40.0);
+    for (row, &radius) in RADII.iter().enumerate() {
+        for (column, &std_dev) in STD_DEVS.iter().enumerate() {
+            let center = Vec2::new(120.0 + column as f64 * 200.0, 100.0 + row as f64 * 160.0);
+            sb.draw_blurred_rounded_rect(
+                Affine::translate(center),
+                rect,
+                radius,
+                std_dev,
+                Color::rgb8(0x20, 0x20, 0x60),
+            );
+        }
+    }
+    let th = (std::f64::consts::PI / 6.0) * params.time;
+    for (column, &std_dev) in STD_DEVS.iter().enumerate() {
+        let center = Vec2::new(120.0 + column as f64 * 200.0, 580.0);
+        sb.draw_blurred_rounded_rect(
+            Affine::translate(center) * Affine::rotate(th) * Affine::scale_non_uniform(1.0, 0.5),
+            rect,
+            20.0,
+            std_dev,
+            Color::rgba8(0x80, 0x20, 0x20, 0xc0),
+        );
+    }
+}
+
+/// Overlapping opaque circles faded as a group, which should show no overlap, next to
+/// the same circles each faded on their own, which should. Below, nested groups should
+/// match a single group with the product of their alphas, and a group with an alpha
+/// of 1 should match drawing without a group.
+fn opacity_groups(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0x30, 0x60, 0x30),
+        None,
+        &Rect::new(0.0, 0.0, 1000.0, 700.0),
+    );
+    let circles = |sb: &mut SceneBuilder, x: f64, y: f64, alpha: u8| {
+        sb.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Color::rgba8(0xff, 0x40, 0x40, alpha),
+            None,
+            &Circle::new((x + 120.0, y + 150.0), 100.0),
+        );
+        sb.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Color::rgba8(0x40, 0x40, 0xff, alpha),
+            None,
+            &Circle::new((x + 230.0, y + 150.0), 100.0),
+        );
+    };
+    sb.push_opacity_layer(0.5);
+    circles(sb, 0.0, 0.0, 0xff);
+    sb.pop_layer();
+    circles(sb, 500.0, 0.0, 0x80);
+    sb.push_opacity_layer(0.5);
+    sb.push_opacity_layer(0.5);
+    circles(sb, 0.0, 350.0, 0xff);
+    sb.pop_layer();
+    sb.pop_layer();
+    sb.push_opacity_layer(0.25);
+    circles(sb, 330.0, 350.0, 0xff);
+    sb.pop_layer();
+    sb.push_opacity_layer(1.0);
+    circles(sb, 660.0, 350.0, 0xff);
+    sb.pop_layer();
+}
+
+// A solid rectangle masked by a radial gradient, by its luminance on the left and by its
+// alpha on the right. Both fade from the center of the gradient out to the edge of its
+// circle, which clips the mask, so nothing is drawn outside of it.
+fn mask_layers(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    use vello::encoding::MaskKind;
+    let kinds = [
+        (MaskKind::Luminance, Color::WHITE, Color::BLACK),
+        (
+            MaskKind::Alpha,
+            Color::WHITE,
+            Color::rgba8(0xff, 0xff, 0xff, 0),
+        ),
+    ];
+    for (ix, (kind, inner, outer)) in kinds.into_iter().enumerate() {
+        let x = 50.0 + ix as f64 * 450.0;
+        let square = Rect::new(x, 50.0, x + 400.0, 450.0);
+        sb.push_layer(Mix::Clip, 1.0, Affine::IDENTITY, &square);
+        sb.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Color::rgb8(0x40, 0x80, 0xff),
+            None,
+            &square,
+        );
+        let center = square.center();
+        sb.push_mask_layer(kind, Affine::IDENTITY, Some(&Circle::new(center, 200.0)))
+            .unwrap();
+        let gradient = Gradient::new_radial(center, 200.0).with_stops([inner, outer]);
+        sb.fill(Fill::NonZero, Affine::IDENTITY, &gradient, None, &square);
+        sb.pop_layer();
+        sb.pop_layer();
+    }
+}
+
+// Clips nested more deeply than Config::MAX_CLIP_DEPTH, where the innermost ones are
+// flattened. Each level is a square 1 pixel smaller on each side than the one around it.
+fn nested_clips(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    const DEPTH: u32 = 300;
+    for level in 0..DEPTH {
+        let inset = level as f64;
+        let square = Rect::new(inset, inset, 700.0 - inset, 700.0 - inset);
+        sb.push_layer(Mix::Clip, 1.0, Affine::translate((50.0, 0.0)), &square);
+        let shade = if level % 2 == 0 { 0x40 } else { 0xc0 };
+        // Draw past the clip, so that only the clips bound each square
+        sb.fill(
+            Fill::NonZero,
+            Affine::translate((50.0, 0.0)),
+            Color::rgb8(shade, shade, 0xff),
+            None,
+            &square.inflate(10.0, 10.0),
+        );
+    }
+    for _ in 0..DEPTH {
+        sb.pop_layer();
+    }
+}
+
+fn around_center(xform: Affine, center: Point) -> Affine {
+    Affine::translate(center.to_vec2()) * xform * Affine::translate(-center.to_vec2())
+}
+
+fn make_diamond(cx: f64, cy: f64) -> [PathEl; 5] {
+    const SIZE: f64 = 50.0;
+    [
+        PathEl::MoveTo(Point::new(cx, cy - SIZE)),
+        PathEl::LineTo(Point::new(cx + SIZE, cy)),
+        PathEl::LineTo(Point::new(cx, cy + SIZE)),
+        PathEl::LineTo(Point::new(cx - SIZE, cy)),
+        PathEl::ClosePath,
+    ]
+}
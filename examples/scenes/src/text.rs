@@ -0,0 +1,79 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Scenes made of many small glyph outlines, which is the most common real world
+//! workload.
+
+use vello::kurbo::Affine;
+use vello::peniko::{Brush, Color};
+use vello::SceneBuilder;
+
+use crate::{SceneParams, SimpleText};
+
+const PARAGRAPH: &[&str] = &[
+    "Vello is a 2d graphics rendering engine, using wgpu.",
+    "It efficiently draws large 2d scenes with interactive or near-interactive",
+    "performance. It is used as the rendering backend for xilem, a UI toolkit.",
+    "The quick brown fox jumps over the lazy dog. 0123456789",
+    "Sphinx of black quartz, judge my vow! (){}[]<>@#$%^&*",
+];
+
+/// Font sizes, in pixels per em, cycled through for the lines of the paragraph.
+const SIZES: &[f32] = &[8.0, 10.0, 12.0, 14.0, 18.0, 24.0, 36.0];
+
+/// Lines of text at a variety of sizes, from 8px upwards.
+pub fn paragraph(sb: &mut SceneBuilder, params: &mut SceneParams) {
+    draw_paragraph(sb, params.text, 0.0);
+}
+
+/// The paragraph from [`paragraph`], scrolling upwards at 40 pixels per second.
+pub fn scrolling_paragraph(sb: &mut SceneBuilder, params: &mut SceneParams) {
+    let height = paragraph_height();
+    let offset = (params.time * 40.0) % height;
+    // Draw the paragraph twice so that the scroll wraps around seamlessly
+    draw_paragraph(sb, params.text, -offset);
+    draw_paragraph(sb, params.text, height - offset);
+}
+
+fn line_sizes() -> impl Iterator<Item = (&'static str, f32)> {
+    SIZES
+        .iter()
+        .flat_map(|&size| PARAGRAPH.iter().map(move |&line| (line, size)))
+}
+
+fn line_height(size: f32) -> f64 {
+    size as f64 * 1.25
+}
+
+fn paragraph_height() -> f64 {
+    line_sizes().map(|(_, size)| line_height(size)).sum::<f64>() + 40.0
+}
+
+fn draw_paragraph(sb: &mut SceneBuilder, text: &mut SimpleText, y_offset: f64) {
+    let brush = Brush::Solid(Color::rgb8(0x20, 0x20, 0x20));
+    let mut y = 20.0 + y_offset;
+    for (line, size) in line_sizes() {
+        y += line_height(size);
+        text.add(
+            sb,
+            None,
+            size,
+            Some(&brush),
+            Affine::translate((20.0, y)),
+            line,
+        );
+    }
+}
@@ -72,11 +72,19 @@ async fn run(event_loop: EventLoop<()>, window: Window, svg_files: Vec<PathBuf>)
     use winit::{event::*, event_loop::ControlFlow};
     let mut render_cx = RenderContext::new().unwrap();
     let size = window.inner_size();
-    let mut surface = render_cx
+    let mut surface = match render_cx
         .create_surface(&window, size.width, size.height)
-        .await;
+        .await
+    {
+        Ok(surface) => surface,
+        Err(error @ (vello::Error::NoCompatibleDevice | vello::Error::SurfaceCreation { .. })) => {
+            eprintln!("Can't render to the window: {error}");
+            return;
+        }
+        Err(error) => panic!("failed to create surface: {error}"),
+    };
     let device_handle = &render_cx.devices[surface.dev_id];
-    let mut renderer = Renderer::new(&device_handle.device).unwrap();
+    let mut renderer = Renderer::new(&device_handle.device, &Default::default()).unwrap();
     let mut current_frame = 0usize;
     let mut scene = Scene::new();
     let mut cached_svg_scene = vec![];
@@ -168,8 +176,7 @@ async fn run(event_loop: EventLoop<()>, window: Window, svg_files: Vec<PathBuf>)
 
             builder.finish();
             let surface_texture = surface
-                .surface
-                .get_current_texture()
+                .acquire(&device_handle.device)
                 .expect("failed to get surface texture");
             renderer
                 .render_to_surface(
@@ -181,7 +188,7 @@ async fn run(event_loop: EventLoop<()>, window: Window, svg_files: Vec<PathBuf>)
                     height,
                 )
                 .expect("failed to render to surface");
-            surface_texture.present();
+            surface.present(surface_texture);
 
             if current_frame % 60 == 0 {
                 let now = Instant::now();
@@ -1,6 +1,6 @@
 use vello::kurbo::{Affine, Point, Rect};
 use vello::peniko::{Color, Fill, Gradient, Stroke};
-use vello::{Renderer, Scene, SceneBuilder, SceneFragment};
+use vello::{Renderer, RendererOptions, Scene, SceneBuilder, SceneFragment, TargetView};
 
 use bevy::{
     prelude::*,
@@ -21,7 +21,11 @@ struct VelloRenderer(Renderer);
 impl FromWorld for VelloRenderer {
     fn from_world(world: &mut World) -> Self {
         let device = world.get_resource::<RenderDevice>().unwrap();
-        VelloRenderer(Renderer::new(device.wgpu_device()).unwrap())
+        let options = RendererOptions {
+            surface_blit_support: false,
+            ..Default::default()
+        };
+        VelloRenderer(Renderer::new(device.wgpu_device(), &options).unwrap())
     }
 }
 
@@ -52,7 +56,11 @@ fn render_scenes(
                 device.wgpu_device(),
                 &*queue,
                 &scene.0,
-                &gpu_image.texture_view,
+                TargetView::new(
+                    &gpu_image.texture_view,
+                    gpu_image.size.x as u32,
+                    gpu_image.size.y as u32,
+                ),
                 gpu_image.size.x as u32,
                 gpu_image.size.y as u32,
             )
@@ -14,17 +14,15 @@
 //
 // Also licensed under MIT license, at your choice.
 
-mod pico_svg;
-mod simple_text;
-mod test_scene;
-
-use std::{borrow::Cow, path::PathBuf, time::Instant};
+use std::{path::PathBuf, time::Instant};
 
 use clap::Parser;
+use scenes::{SceneParams, SceneSet, SimpleText};
+use session::InputEvent;
 use vello::{
-    kurbo::{Affine, Vec2},
+    kurbo::{Affine, Rect, Vec2},
     util::RenderContext,
-    Renderer, Scene, SceneBuilder,
+    DebugOverlay, Renderer, RendererOptions, Scene, SceneBuilder, SceneFragment,
 };
 use winit::{
     event_loop::{EventLoop, EventLoopBuilder},
@@ -33,6 +31,7 @@ use winit::{
 
 #[cfg(not(target_arch = "wasm32"))]
 mod hot_reload;
+mod session;
 
 #[derive(Parser, Debug)]
 #[command(about, long_about = None)]
@@ -48,57 +47,147 @@ struct Args {
     /// Switch between scenes with left and right arrow keys
     #[arg(long)]
     scene: Option<i32>,
+    /// Make the background of the window transparent, so the scene floats over the
+    /// desktop, if the platform supports it
+    #[arg(long)]
+    transparent: bool,
+    /// Limit the frame rate to at most this many frames per second
+    #[arg(long)]
+    #[cfg(not(target_arch = "wasm32"))]
+    target_fps: Option<f32>,
+    /// Record the panning, zooming and scene switches of the session to this file,
+    /// which is written on exit
+    #[arg(long)]
+    #[cfg(not(target_arch = "wasm32"))]
+    record: Option<PathBuf>,
+    /// Replay a session recorded with --record, on the recorded schedule, then print
+    /// the frame times and exit
+    #[arg(long, conflicts_with_all = ["record", "replay_fast"])]
+    #[cfg(not(target_arch = "wasm32"))]
+    replay: Option<PathBuf>,
+    /// Replay a session recorded with --record as fast as possible, applying each
+    /// event on the frame it was recorded on, so the rendered frames are the same as
+    /// while recording
+    #[arg(long, conflicts_with = "record")]
+    #[cfg(not(target_arch = "wasm32"))]
+    replay_fast: Option<PathBuf>,
 }
 
-const TIGER: &'static str = include_str!("../../assets/Ghostscript_Tiger.svg");
+/// Applies an input event to the view.
+fn apply_event(event: InputEvent, scene_ix: &mut i32, drag: &mut Vec2, scale: &mut f64) {
+    match event {
+        InputEvent::Pan(delta) => *drag += delta,
+        InputEvent::Zoom(delta) => *scale = (*scale + delta).clamp(0.1, 10.0),
+        InputEvent::Scene(step) => *scene_ix = scene_ix.saturating_add(step),
+        InputEvent::End => {}
+    }
+}
 
 async fn run(event_loop: EventLoop<UserEvent>, window: Window, args: Args) {
     use winit::{event::*, event_loop::ControlFlow};
     let mut render_cx = RenderContext::new().unwrap();
     let size = window.inner_size();
-    let mut surface = render_cx
-        .create_surface(&window, size.width, size.height)
-        .await;
-    let device_handle = &render_cx.devices[surface.dev_id];
-    let mut renderer = Renderer::new(&device_handle.device).unwrap();
-    let mut simple_text = simple_text::SimpleText::new();
+    let alpha_mode = if args.transparent {
+        wgpu::CompositeAlphaMode::PreMultiplied
+    } else {
+        wgpu::CompositeAlphaMode::Auto
+    };
+    let surface = match render_cx
+        .create_surface_with_alpha_mode(&window, size.width, size.height, alpha_mode)
+        .await
+    {
+        Ok(surface) => surface,
+        Err(error @ (vello::Error::NoCompatibleDevice | vello::Error::SurfaceCreation { .. })) => {
+            eprintln!("Can't render to the window: {error}");
+            return;
+        }
+        Err(error) => panic!("failed to create surface: {error}"),
+    };
+    if args.transparent && !surface.is_transparent() {
+        eprintln!("The surface doesn't support transparency, so the window will be opaque");
+    }
+    let dev_id = surface.dev_id;
+    let device_handle = &render_cx.devices[dev_id];
+    let start = Instant::now();
+    let mut renderer = Renderer::new(
+        &device_handle.device,
+        &RendererOptions {
+            surface_alpha_mode: surface.config.alpha_mode,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    eprintln!("Creating the renderer took {:?}", start.elapsed());
+    let mut scenes = SceneSet::test_scenes();
+    let mut simple_text = SimpleText::new();
     let mut current_frame = 0usize;
     let mut scene = Scene::new();
-    let mut cached_svg_scene = None;
+    let mut fragment = SceneFragment::new();
     let mut drag = Vec2::default();
     let mut scale = 1f64;
     let mut mouse_down = false;
+    // Input events since the last frame, which are applied before the next
+    let mut input_events = Vec::new();
     let mut prior_position = None;
-    let mut svg_static_scale = 1.0;
     // We allow looping left and right through the scenes, so use a signed index
     let mut scene_ix: i32 = 0;
     #[cfg(not(target_arch = "wasm32"))]
-    let svg_string: Cow<'static, str> = match args.svg {
-        Some(path) => {
-            // If an svg file has been specified, show that by default
-            scene_ix = 2;
-            let start = std::time::Instant::now();
-            eprintln!("Reading svg from {path:?}");
-            let svg = std::fs::read_to_string(path)
-                .expect("Provided path did not point to a file which could be read")
-                .into();
-            eprintln!("Finished reading svg, took {:?}", start.elapsed());
-            svg
-        }
-        None => {
-            svg_static_scale = 6.0;
-            TIGER.into()
-        }
-    };
+    let mut recorder = args
+        .record
+        .map(|path| session::Recorder::new(path, size.width, size.height));
+    #[cfg(target_arch = "wasm32")]
+    let mut recorder: Option<session::Recorder> = None;
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut replay = match (&args.replay, &args.replay_fast) {
+        (Some(path), _) => Some((path, false)),
+        (_, Some(path)) => Some((path, true)),
+        _ => None,
+    }
+    .map(|(path, fast)| {
+        session::Replay::load(path, fast).expect("failed to load the session to replay")
+    });
     #[cfg(target_arch = "wasm32")]
-    let svg_string: Cow<'static, str> = TIGER.into();
-    // These are set after choosing the svg, as they overwrite the defaults specified there
+    let mut replay: Option<session::Replay> = None;
+    if let Some(replay) = &replay {
+        window.set_inner_size(winit::dpi::PhysicalSize::new(replay.width, replay.height));
+    }
+    // Replaying as fast as possible is limited by the surface, not the pacer
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut pacer = vello::util::FramePacer::new(match args.replay_fast {
+        Some(_) => None,
+        None => args.target_fps,
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    let svg_path = args.svg;
+    #[cfg(target_arch = "wasm32")]
+    let svg_path: Option<PathBuf> = None;
+    if let Some(path) = svg_path {
+        // If an svg file has been specified, show that by default
+        let start = std::time::Instant::now();
+        eprintln!("Reading svg from {path:?}");
+        let svg = std::fs::read_to_string(&path)
+            .expect("Provided path did not point to a file which could be read");
+        eprintln!("Finished reading svg, took {:?}", start.elapsed());
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "svg".into());
+        let svg_scale = args.scale.unwrap_or(1.0);
+        scenes
+            .scenes
+            .insert(0, scenes::svg_scene(name, svg, svg_scale));
+    } else if let Some(set_scale) = args.scale {
+        if let Some(ix) = scenes.find("tiger") {
+            scenes.scenes[ix] = scenes::svg_scene("tiger", scenes::TIGER, set_scale);
+        }
+    }
+    // This is set after choosing the svg, as it overwrites the default specified there
     if let Some(set_scene) = args.scene {
         scene_ix = set_scene;
     }
-    if let Some(set_scale) = args.scale {
-        svg_static_scale = set_scale;
-    }
+    // The surface is dropped while the app is suspended, and kept in `suspended`
+    let mut surface = Some(surface);
+    let mut suspended = None;
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
@@ -107,18 +196,33 @@ async fn run(event_loop: EventLoop<UserEvent>, window: Window, args: Args) {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
             WindowEvent::KeyboardInput { input, .. } => {
                 if input.state == ElementState::Pressed {
-                    match input.virtual_keycode {
-                        Some(VirtualKeyCode::Left) => scene_ix = scene_ix.saturating_sub(1),
-                        Some(VirtualKeyCode::Right) => scene_ix = scene_ix.saturating_add(1),
+                    let step = match input.virtual_keycode {
+                        Some(VirtualKeyCode::Left) => -1,
+                        Some(VirtualKeyCode::Right) => 1,
                         Some(VirtualKeyCode::Escape) => {
                             *control_flow = ControlFlow::Exit;
+                            return;
                         }
-                        _ => {}
-                    }
+                        Some(VirtualKeyCode::D) => {
+                            let overlay = match renderer.debug_overlay() {
+                                DebugOverlay::None => DebugOverlay::DrawBboxes,
+                                DebugOverlay::DrawBboxes => DebugOverlay::TileOccupancy,
+                                DebugOverlay::TileOccupancy => DebugOverlay::SegmentDensity,
+                                DebugOverlay::SegmentDensity => DebugOverlay::None,
+                            };
+                            eprintln!("Debug overlay: {overlay:?}");
+                            renderer.set_debug_overlay(overlay);
+                            return;
+                        }
+                        _ => return,
+                    };
+                    input_events.push(InputEvent::Scene(step));
                 }
             }
             WindowEvent::Resized(size) => {
-                render_cx.resize_surface(&mut surface, size.width, size.height);
+                if let Some(surface) = &mut surface {
+                    render_cx.resize_surface(surface, size.width, size.height);
+                }
                 window.request_redraw();
             }
             WindowEvent::MouseInput { state, button, .. } => {
@@ -127,14 +231,11 @@ async fn run(event_loop: EventLoop<UserEvent>, window: Window, args: Args) {
                 }
             }
             WindowEvent::MouseWheel { delta, .. } => {
-                if let MouseScrollDelta::PixelDelta(delta) = delta {
-                    scale += delta.y * 0.1;
-                    scale = scale.clamp(0.1, 10.0);
-                }
-                if let MouseScrollDelta::LineDelta(_, y) = delta {
-                    scale += *y as f64 * 0.1;
-                    scale = scale.clamp(0.1, 10.0);
-                }
+                let delta = match delta {
+                    MouseScrollDelta::PixelDelta(delta) => delta.y * 0.1,
+                    MouseScrollDelta::LineDelta(_, y) => *y as f64 * 0.1,
+                };
+                input_events.push(InputEvent::Zoom(delta));
             }
             WindowEvent::CursorLeft { .. } => {
                 prior_position = None;
@@ -143,7 +244,7 @@ async fn run(event_loop: EventLoop<UserEvent>, window: Window, args: Args) {
                 let position = Vec2::new(position.x, position.y);
                 if mouse_down {
                     if let Some(prior) = prior_position {
-                        drag += (position - prior) * (1.0 / scale);
+                        input_events.push(InputEvent::Pan((position - prior) * (1.0 / scale)));
                     }
                 }
                 prior_position = Some(position);
@@ -151,41 +252,73 @@ async fn run(event_loop: EventLoop<UserEvent>, window: Window, args: Args) {
             _ => {}
         },
         Event::MainEventsCleared => {
+            // Live input is ignored while replaying
+            if replay.is_some() {
+                input_events.clear();
+            }
+            for event in input_events.drain(..) {
+                apply_event(event, &mut scene_ix, &mut drag, &mut scale);
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(current_frame + 1, event);
+                }
+            }
             window.request_redraw();
         }
+        Event::LoopDestroyed => {
+            if let Some(recorder) = &mut recorder {
+                match recorder.finish(current_frame + 1) {
+                    Ok(()) => eprintln!("Recorded the session"),
+                    Err(e) => eprintln!("Failed to write the recorded session because of {e}"),
+                }
+            }
+        }
         Event::RedrawRequested(_) => {
+            // The surface can't be drawn to while the app is suspended
+            let surface = match &mut surface {
+                Some(surface) => surface,
+                None => return,
+            };
             current_frame += 1;
+            if let Some(replay) = &mut replay {
+                for event in replay.events_for_frame(current_frame) {
+                    if event == InputEvent::End {
+                        replay.print_times();
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    apply_event(event, &mut scene_ix, &mut drag, &mut scale);
+                }
+            }
             let width = surface.config.width;
             let height = surface.config.height;
             let device_handle = &render_cx.devices[surface.dev_id];
-            let mut builder = SceneBuilder::for_scene(&mut scene);
 
-            const N_SCENES: i32 = 6;
             // Allow looping forever
-            scene_ix = scene_ix.rem_euclid(N_SCENES);
-            // Remainder operation allows negative results, which isn't the right semantics
-            match scene_ix {
-                0 => test_scene::render_anim_frame(&mut builder, &mut simple_text, current_frame),
-                1 => test_scene::render_blend_grid(&mut builder),
-                2 => {
-                    let transform = Affine::scale(scale) * Affine::translate(drag);
-                    test_scene::render_svg_scene(
-                        &mut builder,
-                        &mut cached_svg_scene,
-                        transform,
-                        &svg_string,
-                        svg_static_scale,
-                    )
-                }
-                3 => test_scene::render_brush_transform(&mut builder, current_frame),
-                4 => test_scene::render_funky_paths(&mut builder),
-                5 => test_scene::render_scene(&mut builder),
-                _ => unreachable!("N_SCENES is too large"),
-            }
+            scene_ix = scene_ix.rem_euclid(scenes.scenes.len() as i32);
+            let example_scene = &mut scenes.scenes[scene_ix as usize];
+            let mut params = SceneParams {
+                // Animations are driven by the frame count, assuming 60 frames per second
+                time: current_frame as f64 / 60.0,
+                text: &mut simple_text,
+            };
+            let transform = Affine::scale(scale) * Affine::translate(drag);
+            let mut builder = SceneBuilder::for_fragment(&mut fragment);
+            // Skip content which is panned or zoomed out of the window
+            let window_rect = Rect::new(0.0, 0.0, width as f64, height as f64);
+            builder.set_viewport_hint(Some(transform.inverse().transform_rect_bbox(window_rect)));
+            example_scene.function.render(&mut builder, &mut params);
+            builder.finish();
+            simple_text.next_frame();
+            let mut builder = SceneBuilder::for_scene(&mut scene);
+            builder.append(&fragment, Some(transform));
             builder.finish();
+            #[cfg(not(target_arch = "wasm32"))]
+            let surface_texture = surface
+                .acquire_with_pacer(&device_handle.device, &mut pacer)
+                .expect("failed to get surface texture");
+            #[cfg(target_arch = "wasm32")]
             let surface_texture = surface
-                .surface
-                .get_current_texture()
+                .acquire(&device_handle.device)
                 .expect("failed to get surface texture");
             renderer
                 .render_to_surface(
@@ -197,13 +330,44 @@ async fn run(event_loop: EventLoop<UserEvent>, window: Window, args: Args) {
                     height,
                 )
                 .expect("failed to render to surface");
-            surface_texture.present();
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                pacer.present(surface, surface_texture);
+                if current_frame % 60 == 0 {
+                    let stats = pacer.stats();
+                    window.set_title(&format!(
+                        "Vello demo - {:.2?} per frame (max {:.2?}), {:.2?} acquire, {} missed",
+                        stats.mean_frame_time,
+                        stats.max_frame_time,
+                        stats.mean_acquire_time,
+                        stats.missed_frames
+                    ));
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            surface.present(surface_texture);
             device_handle.device.poll(wgpu::Maintain::Wait);
         }
+        Event::Suspended => {
+            if let Some(active) = surface.take() {
+                suspended = Some(render_cx.suspend_surface(active));
+            }
+        }
+        Event::Resumed => {
+            if let Some(state) = suspended.take() {
+                let mut resumed = render_cx
+                    .resume_surface(state, &window)
+                    .expect("failed to recreate the surface");
+                let size = window.inner_size();
+                render_cx.resize_surface(&mut resumed, size.width, size.height);
+                surface = Some(resumed);
+                window.request_redraw();
+            }
+        }
         Event::UserEvent(event) => match event {
             #[cfg(not(target_arch = "wasm32"))]
             UserEvent::HotReload => {
-                let device_handle = &render_cx.devices[surface.dev_id];
+                let device_handle = &render_cx.devices[dev_id];
                 eprintln!("==============\nReloading shaders");
                 let start = Instant::now();
                 let result = renderer.reload_shaders(&device_handle.device);
@@ -239,6 +403,7 @@ fn main() {
             .with_inner_size(LogicalSize::new(1044, 800))
             .with_resizable(true)
             .with_title("Vello demo")
+            .with_transparent(args.transparent)
             .build(&event_loop)
             .unwrap();
         pollster::block_on(run(event_loop, window, args));
@@ -261,6 +426,6 @@ fn main() {
             .and_then(|doc| doc.body())
             .and_then(|body| body.append_child(&web_sys::Element::from(canvas)).ok())
             .expect("couldn't append canvas to document body");
-        wasm_bindgen_futures::spawn_local(run(event_loop, window));
+        wasm_bindgen_futures::spawn_local(run(event_loop, window, args));
     }
 }
@@ -0,0 +1,228 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Recording and replaying of input sessions, for reproducing performance problems.
+//!
+//! A session is a JSON file with the size of the window and the input events which
+//! changed the view, each with the frame it was applied before and the time since the
+//! start of the session. The file is written with one event per line, and read back
+//! by matching that layout, so only files written by [`Recorder`] can be replayed.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use vello::kurbo::Vec2;
+
+/// A change to the view, simplified from the window events which caused it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    /// Moves the view by a distance in scene units.
+    Pan(Vec2),
+    /// Changes the scale of the view.
+    Zoom(f64),
+    /// Moves through the list of scenes by a number of steps.
+    Scene(i32),
+    /// Ends the session.
+    End,
+}
+
+#[derive(Copy, Clone)]
+struct TimedEvent {
+    frame: usize,
+    time: f64,
+    event: InputEvent,
+}
+
+/// Records the input events of a session, which are written when it is finished.
+pub struct Recorder {
+    path: PathBuf,
+    start: Instant,
+    width: u32,
+    height: u32,
+    events: Vec<TimedEvent>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, width: u32, height: u32) -> Self {
+        Self {
+            path,
+            start: Instant::now(),
+            width,
+            height,
+            events: Vec::new(),
+        }
+    }
+
+    /// Records an event which was applied before rendering `frame`.
+    pub fn record(&mut self, frame: usize, event: InputEvent) {
+        self.events.push(TimedEvent {
+            frame,
+            time: self.start.elapsed().as_secs_f64(),
+            event,
+        });
+    }
+
+    /// Ends the session at `frame` and writes it to the file.
+    pub fn finish(&mut self, frame: usize) -> io::Result<()> {
+        self.record(frame, InputEvent::End);
+        let mut out = format!(
+            "{{\"width\": {}, \"height\": {}, \"events\": [\n",
+            self.width, self.height
+        );
+        for (ix, event) in self.events.iter().enumerate() {
+            let _ = write!(
+                out,
+                "{{\"frame\": {}, \"time\": {:.6}, ",
+                event.frame, event.time
+            );
+            let _ = match event.event {
+                InputEvent::Pan(delta) => write!(out, "\"pan\": [{}, {}]}}", delta.x, delta.y),
+                InputEvent::Zoom(delta) => write!(out, "\"zoom\": {delta}}}"),
+                InputEvent::Scene(step) => write!(out, "\"scene\": {step}}}"),
+                InputEvent::End => write!(out, "\"end\": true}}"),
+            };
+            out.push_str(if ix + 1 < self.events.len() {
+                ",\n"
+            } else {
+                "\n"
+            });
+        }
+        out.push_str("]}\n");
+        fs::write(&self.path, out)
+    }
+}
+
+/// Feeds the events of a recorded session back, either on the recorded schedule or
+/// as fast as frames are rendered.
+pub struct Replay {
+    pub width: u32,
+    pub height: u32,
+    events: Vec<TimedEvent>,
+    next: usize,
+    /// Whether events are applied on the frame they were recorded on, ignoring time.
+    fast: bool,
+    start: Option<Instant>,
+    last_frame: Option<Instant>,
+    frame_times: Vec<Duration>,
+}
+
+impl Replay {
+    pub fn load(path: &Path, fast: bool) -> io::Result<Self> {
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {what}", path.display()),
+            )
+        };
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let header = lines.next().ok_or_else(|| invalid("empty session"))?;
+        let width = field(header, "width").ok_or_else(|| invalid("missing width"))?;
+        let height = field(header, "height").ok_or_else(|| invalid("missing height"))?;
+        let mut events = Vec::new();
+        for line in lines.filter(|line| line.starts_with('{')) {
+            let (frame, time) = match (field(line, "frame"), field(line, "time")) {
+                (Some(frame), Some(time)) => (frame, time),
+                _ => return Err(invalid("event without a frame and time")),
+            };
+            let event = if let Some(pan) = field(line, "pan") {
+                let mut coords = pan.split(',').map(|x| x.trim().parse::<f64>());
+                match (coords.next(), coords.next()) {
+                    (Some(Ok(x)), Some(Ok(y))) => InputEvent::Pan(Vec2::new(x, y)),
+                    _ => return Err(invalid("bad pan")),
+                }
+            } else if let Some(zoom) = field(line, "zoom") {
+                InputEvent::Zoom(zoom.parse().map_err(|_| invalid("bad zoom"))?)
+            } else if let Some(scene) = field(line, "scene") {
+                InputEvent::Scene(scene.parse().map_err(|_| invalid("bad scene"))?)
+            } else if field(line, "end").is_some() {
+                InputEvent::End
+            } else {
+                return Err(invalid("unknown event"));
+            };
+            events.push(TimedEvent {
+                frame: frame.parse().map_err(|_| invalid("bad frame"))?,
+                time: time.parse().map_err(|_| invalid("bad time"))?,
+                event,
+            });
+        }
+        Ok(Self {
+            width: width.parse().map_err(|_| invalid("bad width"))?,
+            height: height.parse().map_err(|_| invalid("bad height"))?,
+            events,
+            next: 0,
+            fast,
+            start: None,
+            last_frame: None,
+            frame_times: Vec::new(),
+        })
+    }
+
+    /// Returns the events to apply before rendering `frame`, and records the time
+    /// since the previous frame.
+    pub fn events_for_frame(&mut self, frame: usize) -> Vec<InputEvent> {
+        let now = Instant::now();
+        let start = *self.start.get_or_insert(now);
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.frame_times.push(now - last_frame);
+        }
+        let elapsed = (now - start).as_secs_f64();
+        let first = self.next;
+        while let Some(event) = self.events.get(self.next) {
+            let due = if self.fast {
+                event.frame <= frame
+            } else {
+                event.time <= elapsed
+            };
+            if !due {
+                break;
+            }
+            self.next += 1;
+        }
+        self.events[first..self.next]
+            .iter()
+            .map(|event| event.event)
+            .collect()
+    }
+
+    /// Prints the frame times of the replay.
+    pub fn print_times(&self) {
+        let times = &self.frame_times;
+        let total = times.iter().sum::<Duration>();
+        let min = times.iter().min().copied().unwrap_or_default();
+        let max = times.iter().max().copied().unwrap_or_default();
+        let mean = total / times.len().max(1) as u32;
+        println!(
+            "Replayed {} frames in {total:?}: min {min:?}, mean {mean:?}, max {max:?} per frame",
+            times.len()
+        );
+    }
+}
+
+/// Returns the text of the value of `key` in a line written by [`Recorder`], without
+/// the brackets of arrays.
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let key = format!("\"{key}\": ");
+    let start = line.find(&key)? + key.len();
+    let rest = &line[start..];
+    if let Some(array) = rest.strip_prefix('[') {
+        return Some(&array[..array.find(']')?]);
+    }
+    let end = rest.find(|c| c == ',' || c == '}').unwrap_or(rest.len());
+    Some(rest[..end].trim())
+}
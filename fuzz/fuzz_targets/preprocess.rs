@@ -0,0 +1,46 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Fuzzes the shader preprocessor, which must return an error rather than panic or
+//! loop forever on any input.
+//!
+//! The input is split at NUL bytes into the source to preprocess and the sources of
+//! the imports `a`, `b` and so on, so that imports can refer to each other.
+
+#![no_main]
+
+use std::collections::{HashMap, HashSet};
+
+use libfuzzer_sys::fuzz_target;
+
+const IMPORT_NAMES: [&str; 4] = ["a", "b", "c", "d"];
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(data) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut sources = data.split('\0');
+    let input = sources.next().unwrap_or_default();
+    let imports = IMPORT_NAMES
+        .into_iter()
+        .zip(sources)
+        .collect::<HashMap<_, _>>();
+    let defines = ["full", "small"]
+        .into_iter()
+        .map(String::from)
+        .collect::<HashSet<_>>();
+    let _ = vello::wgsl_preprocess(input, &defines, &imports);
+});
@@ -19,11 +19,45 @@ let WG_SIZE = 256u;
 var<workgroup> sh_row_width: array<u32, WG_SIZE>;
 var<workgroup> sh_row_count: array<u32, WG_SIZE>;
 var<workgroup> sh_offset: array<u32, WG_SIZE>;
+#ifdef parallel_backdrop
+var<workgroup> sh_tile_count: array<u32, WG_SIZE>;
+var<workgroup> sh_value: array<i32, WG_SIZE>;
+var<workgroup> sh_head: array<u32, WG_SIZE>;
+
+// Returns the index of the path which holds `tile`, counting the tiles of the paths
+// of this workgroup in order
+fn find_path(tile: u32) -> u32 {
+    var el_ix = 0u;
+    for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
+        let probe = el_ix + ((WG_SIZE / 2u) >> i);
+        if tile >= sh_tile_count[probe - 1u] {
+            el_ix = probe;
+        }
+    }
+    return el_ix;
+}
+
+// Returns the first tile at or after `tile` which starts a row
+fn row_start(tile: u32) -> u32 {
+    let total_tiles = sh_tile_count[WG_SIZE - 1u];
+    if tile >= total_tiles {
+        return total_tiles;
+    }
+    let el_ix = find_path(tile);
+    let path_start = select(0u, sh_tile_count[el_ix - 1u], el_ix > 0u);
+    let width = sh_row_width[el_ix];
+    return path_start + (tile - path_start + width - 1u) / width * width;
+}
+#endif
 
 @compute @workgroup_size(256)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
+#ifdef parallel_backdrop
+    @builtin(workgroup_id) wg_id: vec3<u32>,
+    @builtin(num_workgroups) n_workgroups: vec3<u32>,
+#endif
 ) {
     let drawobj_ix = global_id.x;
     var row_count = 0u;
@@ -35,17 +69,77 @@ fn main(
         sh_offset[local_id.x] = path.tiles;
     }
     sh_row_count[local_id.x] = row_count;
+#ifdef parallel_backdrop
+    var tile_count = row_count * sh_row_width[local_id.x];
+    sh_tile_count[local_id.x] = tile_count;
+#endif
 
     // Prefix sum of row counts
     for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
         workgroupBarrier();
         if local_id.x >= (1u << i) {
             row_count += sh_row_count[local_id.x - (1u << i)];
+#ifdef parallel_backdrop
+            tile_count += sh_tile_count[local_id.x - (1u << i)];
+#endif
         }
         workgroupBarrier();
         sh_row_count[local_id.x] = row_count;
+#ifdef parallel_backdrop
+        sh_tile_count[local_id.x] = tile_count;
+#endif
     }
     workgroupBarrier();
+#ifdef parallel_backdrop
+    // The tiles of the paths are shared between the workgroups with the same x, which
+    // each take the rows starting in their share, so that no row is split between them
+    let total_tiles = sh_tile_count[WG_SIZE - 1u];
+    let start = row_start(total_tiles * wg_id.y / n_workgroups.y);
+    let end = row_start(total_tiles * (wg_id.y + 1u) / n_workgroups.y);
+    // Sum of the row which continues from the previous chunk
+    var carry = 0;
+    for (var chunk = start; chunk < end; chunk += WG_SIZE) {
+        let tile = chunk + local_id.x;
+        var tile_ix = 0u;
+        var value = 0;
+        // Tiles past the end are the heads of their own rows, so that no sum is carried
+        // into them
+        var head = 1u;
+        if tile < end {
+            let el_ix = find_path(tile);
+            let seq_ix = tile - select(0u, sh_tile_count[el_ix - 1u], el_ix > 0u);
+            tile_ix = sh_offset[el_ix] + seq_ix;
+            value = tiles[tile_ix].backdrop;
+            head = select(0u, 1u, seq_ix % sh_row_width[el_ix] == 0u);
+        }
+        // Segmented prefix sum, which starts again at the first tile of each row
+        sh_value[local_id.x] = value;
+        sh_head[local_id.x] = head;
+        for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
+            workgroupBarrier();
+            if local_id.x >= (1u << i) {
+                let other = local_id.x - (1u << i);
+                if head == 0u {
+                    value += sh_value[other];
+                }
+                head = head | sh_head[other];
+            }
+            workgroupBarrier();
+            sh_value[local_id.x] = value;
+            sh_head[local_id.x] = head;
+        }
+        if head == 0u {
+            value += carry;
+        }
+        if tile < end {
+            tiles[tile_ix].backdrop = value;
+        }
+        workgroupBarrier();
+        let last_value = sh_value[WG_SIZE - 1u];
+        carry = select(last_value + carry, last_value, sh_head[WG_SIZE - 1u] != 0u);
+        workgroupBarrier();
+    }
+#else
     let total_rows = sh_row_count[WG_SIZE - 1u];
     for (var row = local_id.x; row < total_rows; row += WG_SIZE) {
         var el_ix = 0u;
@@ -67,4 +161,5 @@ fn main(
             }
         }
     }
+#endif
 }
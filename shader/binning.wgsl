@@ -38,8 +38,13 @@ struct BinHeader {
 var<storage, read_write> bin_header: array<BinHeader>;
 
 // conversion factors from coordinates to bin
+#ifdef small_tiles
+let SX = 0.0078125;
+let SY = 0.0078125;
+#else
 let SX = 0.00390625;
 let SY = 0.00390625;
+#endif
 //let SX = 1.0 / f32(N_TILE_X * TILE_WIDTH);
 //let SY = 1.0 / f32(N_TILE_Y * TILE_HEIGHT);
 
@@ -99,7 +104,9 @@ fn main(
     y0 = clamp(y0, 0, height_in_bins);
     x1 = clamp(x1, 0, width_in_bins);
     y1 = clamp(y1, 0, height_in_bins);
-    if x0 == x1 {
+    // Also covers inverted bounding boxes, which come from non-finite coordinates and
+    // would otherwise never terminate the loops below.
+    if x0 >= x1 {
         y1 = y0;
     }
     var x = x0;
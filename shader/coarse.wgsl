@@ -41,6 +41,12 @@ var<storage, read_write> bump: BumpAllocators;
 @group(0) @binding(8)
 var<storage, read_write> ptcl: array<u32>;
 
+@group(0) @binding(9)
+var<storage, read_write> tile_queue: array<atomic<u32>>;
+
+// The arguments of the indirect dispatches of fine and of the clear stage
+@group(0) @binding(10)
+var<storage, read_write> fine_args: array<atomic<u32>>;
 
 
 // Much of this code assumes WG_SIZE == N_TILE. If these diverge, then
@@ -126,6 +132,25 @@ fn write_grad(ty: u32, index: u32, info_offset: u32) {
     cmd_offset += 3u;
 }
 
+fn write_blur_rect(rgba_color: u32, info_offset: u32) {
+    alloc_cmd(3u);
+    ptcl[cmd_offset] = CMD_BLUR_RECT;
+    ptcl[cmd_offset + 1u] = rgba_color;
+    ptcl[cmd_offset + 2u] = info_offset;
+    cmd_offset += 3u;
+}
+
+// Writes the blend mode of a blended brush, which applies to the brush
+// command that follows it.
+fn write_blend(tag_and_flags: u32, dd: u32) {
+    if (tag_and_flags & DRAWTAG_BLEND) != 0u {
+        alloc_cmd(2u);
+        ptcl[cmd_offset] = CMD_BLEND;
+        ptcl[cmd_offset + 1u] = scene[dd + ((tag_and_flags >> 2u) & 0x07u)];
+        cmd_offset += 2u;
+    }
+}
+
 fn write_begin_clip() {
     alloc_cmd(1u);
     ptcl[cmd_offset] = CMD_BEGIN_CLIP;
@@ -140,17 +165,24 @@ fn write_end_clip(end_clip: CmdEndClip) {
     cmd_offset += 3u;
 }
 
+// Appends a tile to the queue of tiles fine draws, or to those the clear stage clears,
+// and adds a row to the dispatch over them when it starts a new one.
+fn enqueue_tile(tile_ix: u32, cleared: bool) {
+    let list = select(0u, 1u, cleared);
+    let slot = atomicAdd(&tile_queue[list], 1u);
+    if slot % TILE_QUEUE_WIDTH == 0u {
+        atomicAdd(&fine_args[list * 3u + 1u], 1u);
+    }
+    let n_tiles = config.width_in_tiles * config.height_in_tiles;
+    let queue_ix = select(slot, n_tiles - 1u - slot, cleared);
+    atomicStore(&tile_queue[TILE_QUEUE_HEADER + queue_ix], tile_ix);
+}
+
 @compute @workgroup_size(256)
 fn main(
     @builtin(local_invocation_id) local_id: vec3<u32>,
     @builtin(workgroup_id) wg_id: vec3<u32>,
 ) {
-    // Exit early if prior stages failed, as we can't run this stage.
-    // We need to check only prior stages, as if this stage has failed in another workgroup, 
-    // we still want to know this workgroup's memory requirement.   
-    if (atomicLoad(&bump.failed) & (STAGE_BINNING | STAGE_TILE_ALLOC | STAGE_PATH_COARSE)) != 0u {
-        return;
-    }
     let width_in_bins = (config.width_in_tiles + N_TILE_X - 1u) / N_TILE_X;
     let bin_ix = width_in_bins * wg_id.y + wg_id.x;
     let n_partitions = (config.n_drawobj + N_TILE - 1u) / N_TILE;
@@ -162,6 +194,18 @@ fn main(
     let tile_x = local_id.x % N_TILE_X;
     let tile_y = local_id.x / N_TILE_X;
     let this_tile_ix = (bin_tile_y + tile_y) * config.width_in_tiles + bin_tile_x + tile_x;
+    let in_target = bin_tile_x + tile_x < config.width_in_tiles && bin_tile_y + tile_y < config.height_in_tiles;
+
+    // Exit early if prior stages failed, as we can't run this stage.
+    // We need to check only prior stages, as if this stage has failed in another workgroup, 
+    // we still want to know this workgroup's memory requirement.   
+    if (atomicLoad(&bump.failed) & (STAGE_BINNING | STAGE_TILE_ALLOC | STAGE_PATH_COARSE)) != 0u {
+        // The tiles have no command lists, so they are cleared rather than drawn
+        if in_target {
+            enqueue_tile(this_tile_ix, true);
+        }
+        return;
+    }
     cmd_offset = this_tile_ix * PTCL_INITIAL_ALLOC;
     cmd_limit = cmd_offset + (PTCL_INITIAL_ALLOC - PTCL_HEADROOM);
 
@@ -330,7 +374,8 @@ fn main(
             drawobj_ix = sh_drawobj_ix[el_ix];
             // clear LSB of bitmap, using bit magic
             bitmap &= bitmap - 1u;
-            let drawtag = scene[config.drawtag_base + drawobj_ix];
+            let tag_and_flags = scene[config.drawtag_base + drawobj_ix];
+            let drawtag = tag_and_flags & ~DRAWTAG_BLEND;
             let dm = draw_monoids[drawobj_ix];
             let dd = config.drawdata_base + dm.scene_offset;
             let di = dm.info_offset;
@@ -343,15 +388,17 @@ fn main(
                         let linewidth = bitcast<f32>(info_bin_data[di]);
                         if write_path(tile, linewidth) {
                             let rgba_color = scene[dd];
+                            write_blend(tag_and_flags, dd);
                             write_color(CmdColor(rgba_color));
                         }
                     }
                     // DRAWTAG_FILL_LIN_GRADIENT
-                    case 0x114u: {
+                    case 0x158u: {
                         let linewidth = bitcast<f32>(info_bin_data[di]);
                         if write_path(tile, linewidth) {
                             let index = scene[dd];
                             let info_offset = di + 1u;
+                            write_blend(tag_and_flags, dd);
                             write_grad(CMD_LIN_GRAD, index, info_offset);
                         }
                     }
@@ -361,9 +408,29 @@ fn main(
                         if write_path(tile, linewidth) {
                             let index = scene[dd];
                             let info_offset = di + 1u;
+                            write_blend(tag_and_flags, dd);
                             write_grad(CMD_RAD_GRAD, index, info_offset);
                         }
                     }
+                    // DRAWTAG_FILL_SWEEP_GRADIENT
+                    case 0x298u: {
+                        let linewidth = bitcast<f32>(info_bin_data[di]);
+                        if write_path(tile, linewidth) {
+                            let index = scene[dd];
+                            let info_offset = di + 1u;
+                            write_blend(tag_and_flags, dd);
+                            write_grad(CMD_SWEEP_GRAD, index, info_offset);
+                        }
+                    }
+                    // DRAWTAG_BLUR_RECT
+                    case 0x394u: {
+                        let linewidth = bitcast<f32>(info_bin_data[di]);
+                        if write_path(tile, linewidth) {
+                            let rgba_color = scene[dd];
+                            write_blend(tag_and_flags, dd);
+                            write_blur_rect(rgba_color, di + 1u);
+                        }
+                    }
                     // DRAWTAG_BEGIN_CLIP
                     case 0x9u: {
                         if tile.segments == 0u && tile.backdrop == 0 {
@@ -411,7 +478,9 @@ fn main(
         }
         workgroupBarrier();
     }
-    if bin_tile_x + tile_x < config.width_in_tiles && bin_tile_y + tile_y < config.height_in_tiles {
+    if in_target {
+        // Fine is only dispatched over the tiles which have commands to draw
+        enqueue_tile(this_tile_ix, cmd_offset == blend_offset + 1u);
         ptcl[cmd_offset] = CMD_END;
         if max_blend_depth > BLEND_STACK_SPLIT {
             let scratch_size = max_blend_depth * TILE_WIDTH * TILE_HEIGHT;
@@ -6,6 +6,7 @@
 #import clip
 #import drawtag
 #import bbox
+#import blur
 
 @group(0) @binding(0)
 var<uniform> config: Config;
@@ -78,8 +79,8 @@ fn main(
     workgroupBarrier();
     var m = sh_scratch[0];
     workgroupBarrier();
-    let tag_word = scene[config.drawtag_base + ix];
-    agg = map_draw_tag(tag_word);
+    let tag_and_flags = scene[config.drawtag_base + ix];
+    agg = map_draw_tag(tag_and_flags);
     sh_scratch[local_id.x] = agg;
     for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
         workgroupBarrier();
@@ -96,10 +97,13 @@ fn main(
     }
     // m now contains exclusive prefix sum of draw monoid
     draw_monoid[ix] = m;
+    // Blending doesn't change how the brush is decoded
+    let tag_word = tag_and_flags & ~DRAWTAG_BLEND;
     let dd = config.drawdata_base + m.scene_offset;
     let di = m.info_offset;
     if tag_word == DRAWTAG_FILL_COLOR || tag_word == DRAWTAG_FILL_LIN_GRADIENT ||
-        tag_word == DRAWTAG_FILL_RAD_GRADIENT || tag_word == DRAWTAG_FILL_IMAGE ||
+        tag_word == DRAWTAG_FILL_RAD_GRADIENT || tag_word == DRAWTAG_FILL_SWEEP_GRADIENT ||
+        tag_word == DRAWTAG_FILL_IMAGE || tag_word == DRAWTAG_BLUR_RECT ||
         tag_word == DRAWTAG_BEGIN_CLIP
     {
         let bbox = path_bbox[m.path_ix];
@@ -113,13 +117,17 @@ fn main(
         var matrx: vec4<f32>;
         var translate: vec2<f32>;
         var linewidth = bbox.linewidth;
-        if linewidth >= 0.0 || tag_word == DRAWTAG_FILL_LIN_GRADIENT || tag_word == DRAWTAG_FILL_RAD_GRADIENT {
+        if linewidth >= 0.0 || tag_word == DRAWTAG_FILL_LIN_GRADIENT || tag_word == DRAWTAG_FILL_RAD_GRADIENT ||
+            tag_word == DRAWTAG_FILL_SWEEP_GRADIENT || tag_word == DRAWTAG_BLUR_RECT
+        {
             let transform = read_transform(config.transform_base, bbox.trans_ix);
             matrx = transform.matrx;
             translate = transform.translate;
         }
         if linewidth >= 0.0 {
-            // Note: doesn't deal with anisotropic case
+            // The scene builder expands strokes with anisotropic transforms into
+            // fills, so this is only approximate for strokes in fragments appended
+            // with such a transform
             linewidth *= sqrt(abs(matrx.x * matrx.w - matrx.y * matrx.z));
         }
         switch tag_word {
@@ -128,7 +136,7 @@ fn main(
                 info[di] = bitcast<u32>(linewidth);
             }
             // DRAWTAG_FILL_LIN_GRADIENT
-            case 0x114u: {
+            case 0x158u: {
                 info[di] = bitcast<u32>(linewidth);
                 var p0 = bitcast<vec2<f32>>(vec2(scene[dd + 1u], scene[dd + 2u]));
                 var p1 = bitcast<vec2<f32>>(vec2(scene[dd + 3u], scene[dd + 4u]));
@@ -141,6 +149,7 @@ fn main(
                 info[di + 1u] = bitcast<u32>(line_xy.x);
                 info[di + 2u] = bitcast<u32>(line_xy.y);
                 info[di + 3u] = bitcast<u32>(line_c);
+                info[di + 4u] = scene[dd + 5u];
             }
             // DRAWTAG_FILL_RAD_GRADIENT
             case 0x2dcu: {
@@ -170,6 +179,60 @@ fn main(
                 info[di + 9u] = bitcast<u32>(ra);
                 info[di + 10u] = bitcast<u32>(roff);
             }
+            // DRAWTAG_FILL_SWEEP_GRADIENT
+            case 0x298u: {
+                info[di] = bitcast<u32>(linewidth);
+                let p0 = bitcast<vec2<f32>>(vec2(scene[dd + 1u], scene[dd + 2u]));
+                let t0 = bitcast<f32>(scene[dd + 3u]);
+                let t1 = bitcast<f32>(scene[dd + 4u]);
+                // Map pixels back into the space of the gradient, relative to its center
+                let inv_det = 1.0 / (matrx.x * matrx.w - matrx.y * matrx.z);
+                let inv_mat = inv_det * vec4(matrx.w, -matrx.y, -matrx.z, matrx.x);
+                let inv_tr = inv_mat.xy * translate.x + inv_mat.zw * translate.y + p0;
+                info[di + 1u] = bitcast<u32>(inv_mat.x);
+                info[di + 2u] = bitcast<u32>(inv_mat.y);
+                info[di + 3u] = bitcast<u32>(inv_mat.z);
+                info[di + 4u] = bitcast<u32>(inv_mat.w);
+                info[di + 5u] = bitcast<u32>(inv_tr.x);
+                info[di + 6u] = bitcast<u32>(inv_tr.y);
+                info[di + 7u] = bitcast<u32>(t0);
+                info[di + 8u] = bitcast<u32>(1.0 / (t1 - t0));
+                info[di + 9u] = scene[dd + 5u];
+            }
+            // DRAWTAG_BLUR_RECT
+            case 0x394u: {
+                info[di] = bitcast<u32>(linewidth);
+                // The path transform maps from coordinates relative to the center of
+                // the rectangle, so its inverse maps pixels back into that space.
+                let inv_det = 1.0 / (matrx.x * matrx.w - matrx.y * matrx.z);
+                let inv_mat = inv_det * vec4(matrx.w, -matrx.y, -matrx.z, matrx.x);
+                let inv_tr = inv_mat.xy * translate.x + inv_mat.zw * translate.y;
+                let width = bitcast<f32>(scene[dd + 1u]);
+                let height = bitcast<f32>(scene[dd + 2u]);
+                let radius = bitcast<f32>(scene[dd + 3u]);
+                let std_dev = max(bitcast<f32>(scene[dd + 4u]), 1e-6);
+                // See https://raphlinus.github.io/graphics/2020/04/21/blurred-rounded-rects.html
+                let min_edge = min(width, height);
+                let r_max = 0.5 * min_edge;
+                let r0 = min(length(vec2(radius, 1.15 * std_dev)), r_max);
+                let r1 = min(length(vec2(radius, 2.0 * std_dev)), r_max);
+                let exponent = 2.0 * r1 / max(r0, 1e-6);
+                let std_dev_inv = 0.7071067812 / std_dev;
+                let scale = 0.5 * erf7(std_dev_inv * 0.5 * (max(width, height) - 0.5 * radius));
+                info[di + 1u] = bitcast<u32>(inv_mat.x);
+                info[di + 2u] = bitcast<u32>(inv_mat.y);
+                info[di + 3u] = bitcast<u32>(inv_mat.z);
+                info[di + 4u] = bitcast<u32>(inv_mat.w);
+                info[di + 5u] = bitcast<u32>(inv_tr.x);
+                info[di + 6u] = bitcast<u32>(inv_tr.y);
+                info[di + 7u] = bitcast<u32>(0.5 * width - r1);
+                info[di + 8u] = bitcast<u32>(0.5 * height - r1);
+                info[di + 9u] = bitcast<u32>(r1);
+                info[di + 10u] = bitcast<u32>(exponent);
+                info[di + 11u] = bitcast<u32>(std_dev_inv);
+                info[di + 12u] = bitcast<u32>(min_edge);
+                info[di + 13u] = bitcast<u32>(scale);
+            }
             default: {}
         }
     }
@@ -24,10 +24,16 @@ var<storage> segments: array<Segment>;
 #ifdef full
 
 #import blend
+#import blur
 #import ptcl
 
 let GRADIENT_WIDTH = 512;
 
+let INV_TAU = 0.15915494309;
+
+// Normal mixing with source over composition, packed as (mix << 8) | compose
+let BLEND_SRC_OVER = 3u;
+
 @group(0) @binding(3)
 var output: texture_storage_2d<rgba8unorm, write>;
 
@@ -40,6 +46,114 @@ var gradients: texture_2d<f32>;
 @group(0) @binding(6)
 var<storage> info: array<u32>;
 
+#ifdef debug_overlay
+// Written by binning, in pixels, intersected with the enclosing clips
+@group(0) @binding(7)
+var<storage> draw_bboxes: array<vec4<f32>>;
+#endif
+
+#ifndef dense
+// Written by coarse, see TILE_QUEUE_HEADER
+@group(0) @binding(7)
+var<storage> tile_queue: array<u32>;
+#endif
+
+#ifdef debug_overlay
+
+// Maps 0 to blue, 0.5 to green and 1 or more to red.
+fn heat(t: f32) -> vec3<f32> {
+    let u = clamp(t, 0.0, 1.0) * 2.0 - 1.0;
+    return clamp(vec3(u, 1.0 - abs(u), -u), vec3(0.0), vec3(1.0));
+}
+
+fn count_segments(first: u32) -> u32 {
+    var n = 0u;
+    var segment_ix = first;
+    while segment_ix != 0u {
+        n += 1u;
+        segment_ix = segments[segment_ix].next;
+    }
+    return n;
+}
+
+// Draws the overlay over the premultiplied color of the pixel at `coords`, given the
+// number of commands in its tile and of segments they read.
+fn debug_overlay(rgba: vec4<f32>, coords: vec2<u32>, n_cmds: u32, n_segments: u32) -> vec4<f32> {
+#ifdef debug_draw_bboxes
+    let p = vec2<f32>(coords);
+    for (var ix = 0u; ix < config.n_drawobj; ix += 1u) {
+        let bbox = draw_bboxes[ix];
+        if bbox.x < bbox.z && bbox.y < bbox.w {
+            // The first and last rows and columns of pixels the bounding box touches
+            let lo = floor(bbox.xy);
+            let hi = ceil(bbox.zw) - 1.0;
+            if all(p >= lo) && all(p <= hi) && (any(p == lo) || any(p == hi)) {
+                return vec4(1.0, 0.0, 1.0, 1.0);
+            }
+        }
+    }
+    return rgba;
+#else
+#ifdef debug_tile_occupancy
+    let t = f32(n_cmds) / 32.0;
+#else
+    let t = f32(n_segments) / 64.0;
+#endif
+    // Drawn at half opacity, so that the scene can still be recognized
+    let overlay = vec4(heat(t) * 0.5, 0.5);
+    return overlay + rgba * (1.0 - overlay.a);
+#endif
+}
+#endif
+
+// Maps a gradient parameter into the range of the ramp, following the extend mode.
+fn extend_mode(t: f32, mode: u32) -> f32 {
+    var result: f32;
+    switch mode {
+        // Extend::Repeat
+        case 1u: {
+            result = fract(t);
+        }
+        // Extend::Reflect
+        case 2u: {
+            result = 1.0 - abs(fract(t * 0.5) * 2.0 - 1.0);
+        }
+        // Extend::Pad
+        default: {
+            result = clamp(t, 0.0, 1.0);
+        }
+    }
+    return result;
+}
+
+// Returns the fractional part of `a * n` for a whole number `n` below 2^16. This
+// stays accurate when the product is too large for its fractional part to be
+// represented: `a` is split into three parts of at most 8 significant bits, whose
+// products with `n` are exact.
+fn fract_product(a: f32, n: f32) -> f32 {
+    let a_hi = bitcast<f32>(bitcast<u32>(a) & 0xffff0000u);
+    let a_rest = a - a_hi;
+    let a_mid = bitcast<f32>(bitcast<u32>(a_rest) & 0xffff0000u);
+    let a_lo = a_rest - a_mid;
+    return fract(fract(a_hi * n) + fract(a_mid * n) + fract(a_lo * n));
+}
+
+// Evaluates a linear gradient at the pixel `xy`, mapped into the range of the ramp
+// by its extend mode. Repeat and reflect only depend on the parameter modulo their
+// period, which is computed without forming the parameter, so that the pattern stays
+// sharp and in phase far from the start of the gradient.
+fn linear_gradient_t(lin: CmdLinGrad, xy: vec2<f32>) -> f32 {
+    // Extend::Pad
+    if lin.extend == 0u {
+        return clamp(lin.line_x * xy.x + lin.line_y * xy.y + lin.line_c, 0.0, 1.0);
+    }
+    // Extend::Reflect has a period of 2
+    let reflect = lin.extend == 2u;
+    let s = select(1.0, 0.5, reflect);
+    let u = fract(fract_product(lin.line_x * s, xy.x) + fract_product(lin.line_y * s, xy.y) + fract(lin.line_c * s));
+    return select(u, 1.0 - abs(u * 2.0 - 1.0), reflect);
+}
+
 fn read_fill(cmd_ix: u32) -> CmdFill {
     let tile = ptcl[cmd_ix + 1u];
     let backdrop = i32(ptcl[cmd_ix + 2u]);
@@ -63,7 +177,8 @@ fn read_lin_grad(cmd_ix: u32) -> CmdLinGrad {
     let line_x = bitcast<f32>(info[info_offset]);
     let line_y = bitcast<f32>(info[info_offset + 1u]);
     let line_c = bitcast<f32>(info[info_offset + 2u]);
-    return CmdLinGrad(index, line_x, line_y, line_c);
+    let extend = info[info_offset + 3u];
+    return CmdLinGrad(index, line_x, line_y, line_c, extend);
 }
 
 fn read_rad_grad(cmd_ix: u32) -> CmdRadGrad {
@@ -81,6 +196,39 @@ fn read_rad_grad(cmd_ix: u32) -> CmdRadGrad {
     return CmdRadGrad(index, matrx, xlat, c1, ra, roff);
 }
 
+fn read_sweep_grad(cmd_ix: u32) -> CmdSweepGrad {
+    let index = ptcl[cmd_ix + 1u];
+    let info_offset = ptcl[cmd_ix + 2u];
+    let m0 = bitcast<f32>(info[info_offset]);
+    let m1 = bitcast<f32>(info[info_offset + 1u]);
+    let m2 = bitcast<f32>(info[info_offset + 2u]);
+    let m3 = bitcast<f32>(info[info_offset + 3u]);
+    let matrx = vec4(m0, m1, m2, m3);
+    let xlat = vec2(bitcast<f32>(info[info_offset + 4u]), bitcast<f32>(info[info_offset + 5u]));
+    let t0 = bitcast<f32>(info[info_offset + 6u]);
+    let t_scale = bitcast<f32>(info[info_offset + 7u]);
+    let extend = info[info_offset + 8u];
+    return CmdSweepGrad(index, matrx, xlat, t0, t_scale, extend);
+}
+
+fn read_blur_rect(cmd_ix: u32) -> CmdBlurRect {
+    let rgba_color = ptcl[cmd_ix + 1u];
+    let info_offset = ptcl[cmd_ix + 2u];
+    let m0 = bitcast<f32>(info[info_offset]);
+    let m1 = bitcast<f32>(info[info_offset + 1u]);
+    let m2 = bitcast<f32>(info[info_offset + 2u]);
+    let m3 = bitcast<f32>(info[info_offset + 3u]);
+    let matrx = vec4(m0, m1, m2, m3);
+    let xlat = vec2(bitcast<f32>(info[info_offset + 4u]), bitcast<f32>(info[info_offset + 5u]));
+    let inner = vec2(bitcast<f32>(info[info_offset + 6u]), bitcast<f32>(info[info_offset + 7u]));
+    let r1 = bitcast<f32>(info[info_offset + 8u]);
+    let exponent = bitcast<f32>(info[info_offset + 9u]);
+    let std_dev_inv = bitcast<f32>(info[info_offset + 10u]);
+    let min_edge = bitcast<f32>(info[info_offset + 11u]);
+    let scale = bitcast<f32>(info[info_offset + 12u]);
+    return CmdBlurRect(rgba_color, matrx, xlat, inner, r1, exponent, std_dev_inv, min_edge, scale);
+}
+
 fn read_end_clip(cmd_ix: u32) -> CmdEndClip {
     let blend = ptcl[cmd_ix + 1u];
     let alpha = bitcast<f32>(ptcl[cmd_ix + 2u]);
@@ -96,12 +244,21 @@ var output: texture_storage_2d<r8, write>;
 
 let PIXELS_PER_THREAD = 4u;
 
+#ifdef deterministic
+// The order of the segments in a tile varies between runs, so in deterministic mode
+// coverage is summed in fixed point, where the order doesn't change the result
+let AREA_SCALE = 65536.0;
+#endif
+
 fn fill_path(tile: Tile, xy: vec2<f32>, even_odd: bool) -> array<f32, PIXELS_PER_THREAD> {
     var area: array<f32, PIXELS_PER_THREAD>;
     let backdrop_f = f32(tile.backdrop);
     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
         area[i] = backdrop_f;
     }
+#ifdef deterministic
+    var area_fixed: array<i32, PIXELS_PER_THREAD>;
+#endif
     var segment_ix = tile.segments;
     while segment_ix != 0u {
         let segment = segments[segment_ix];
@@ -126,15 +283,28 @@ fn fill_path(tile: Tile, xy: vec2<f32>, even_odd: bool) -> array<f32, PIXELS_PER
                 let c = max(b, 0.0);
                 let d = max(xmin, 0.0);
                 let a = (b + 0.5 * (d * d - c * c) - xmin) / (xmax - xmin);
+#ifdef deterministic
+                area_fixed[i] += i32(round(a * dy * AREA_SCALE));
+#else
                 area[i] += a * dy;
+#endif
             }
         }
         let y_edge = sign(segment.delta.x) * clamp(xy.y - segment.y_edge + 1.0, 0.0, 1.0);
         for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+#ifdef deterministic
+            area_fixed[i] += i32(round(y_edge * AREA_SCALE));
+#else
             area[i] += y_edge;
+#endif
         }
         segment_ix = segment.next;
     }
+#ifdef deterministic
+    for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+        area[i] += f32(area_fixed[i]) / AREA_SCALE;
+    }
+#endif
     if even_odd {
         // even-odd winding rule
         for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
@@ -176,29 +346,58 @@ fn stroke_path(seg: u32, half_width: f32, xy: vec2<f32>) -> array<f32, PIXELS_PE
     return df;
 }
 
-// The X size should be 16 / PIXELS_PER_THREAD
+// The X size should be TILE_WIDTH / PIXELS_PER_THREAD
+#ifdef small_tiles
+@compute @workgroup_size(2, 8)
+#else
 @compute @workgroup_size(4, 16)
+#endif
 fn main(
-    @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     @builtin(workgroup_id) wg_id: vec3<u32>,
 ) {
-    let tile_ix = wg_id.y * config.width_in_tiles + wg_id.x;
-    let xy = vec2(f32(global_id.x * PIXELS_PER_THREAD), f32(global_id.y));
+#ifdef full
+#ifdef dense
+    // Every tile is dispatched, such as for an overlay which is also drawn over empty tiles
+    let tile_xy = wg_id.xy;
+#else
+    // Only the tiles in the queue are dispatched, the others are cleared by fine_clear
+    let queue_ix = wg_id.y * TILE_QUEUE_WIDTH + wg_id.x;
+    if queue_ix >= tile_queue[0] {
+        return;
+    }
+    let queued_ix = tile_queue[TILE_QUEUE_HEADER + queue_ix];
+    let tile_xy = vec2(queued_ix % config.width_in_tiles, queued_ix / config.width_in_tiles);
+#endif
+#else
+    let tile_xy = wg_id.xy;
+#endif
+    let tile_ix = tile_xy.y * config.width_in_tiles + tile_xy.x;
+    let tile_origin = tile_xy * vec2(TILE_WIDTH, TILE_HEIGHT);
+    let xy = vec2<f32>(tile_origin + vec2(local_id.x * PIXELS_PER_THREAD, local_id.y));
 #ifdef full
     var rgba: array<vec4<f32>, PIXELS_PER_THREAD>;
     var blend_stack: array<array<u32, PIXELS_PER_THREAD>, BLEND_STACK_SPLIT>;
     var clip_depth = 0u;
+    // Blend mode of the next brush, set by CMD_BLEND
+    var draw_blend = BLEND_SRC_OVER;
     var area: array<f32, PIXELS_PER_THREAD>;
     var cmd_ix = tile_ix * PTCL_INITIAL_ALLOC;
     let blend_offset = ptcl[cmd_ix];
     cmd_ix += 1u;
+#ifdef debug_overlay
+    var n_cmds = 0u;
+    var n_segments = 0u;
+#endif
     // main interpretation loop
     while true {
         let tag = ptcl[cmd_ix];
         if tag == CMD_END {
             break;
         }
+#ifdef debug_overlay
+        n_cmds += 1u;
+#endif
         switch tag {
             // CMD_FILL
             case 1u: {
@@ -207,12 +406,18 @@ fn main(
                 let even_odd = (fill.tile & 1u) != 0u;
                 let tile = Tile(fill.backdrop, segments);
                 area = fill_path(tile, xy, even_odd);
+#ifdef debug_overlay
+                n_segments += count_segments(segments);
+#endif
                 cmd_ix += 3u;
             }
             // CMD_STROKE
             case 2u: {
                 let stroke = read_stroke(cmd_ix);
                 area = stroke_path(stroke.tile, stroke.half_width, xy);
+#ifdef debug_overlay
+                n_segments += count_segments(stroke.tile);
+#endif
                 cmd_ix += 3u;
             }
             // CMD_SOLID
@@ -228,21 +433,22 @@ fn main(
                 let fg = unpack4x8unorm(color.rgba_color).wzyx;
                 for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
                     let fg_i = fg * area[i];
-                    rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                    rgba[i] = blend_mix_compose(rgba[i], fg_i, draw_blend);
                 }
+                draw_blend = BLEND_SRC_OVER;
                 cmd_ix += 2u;
             }
             // CMD_LIN_GRAD
             case 6u: {
                 let lin = read_lin_grad(cmd_ix);
-                let d = lin.line_x * xy.x + lin.line_y * xy.y + lin.line_c;
                 for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
-                    let my_d = d + lin.line_x * f32(i);
-                    let x = i32(round(clamp(my_d, 0.0, 1.0) * f32(GRADIENT_WIDTH - 1)));
+                    let t = linear_gradient_t(lin, vec2(xy.x + f32(i), xy.y));
+                    let x = i32(round(t * f32(GRADIENT_WIDTH - 1)));
                     let fg_rgba = textureLoad(gradients, vec2(x, i32(lin.index)), 0);
                     let fg_i = fg_rgba * area[i];
-                    rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                    rgba[i] = blend_mix_compose(rgba[i], fg_i, draw_blend);
                 }
+                draw_blend = BLEND_SRC_OVER;
                 cmd_ix += 3u;
             }
             // CMD_RAD_GRAD
@@ -258,8 +464,48 @@ fn main(
                     let x = i32(round(clamp(t, 0.0, 1.0) * f32(GRADIENT_WIDTH - 1)));
                     let fg_rgba = textureLoad(gradients, vec2(x, i32(rad.index)), 0);
                     let fg_i = fg_rgba * area[i];
-                    rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                    rgba[i] = blend_mix_compose(rgba[i], fg_i, draw_blend);
+                }
+                draw_blend = BLEND_SRC_OVER;
+                cmd_ix += 3u;
+            }
+            // CMD_SWEEP_GRAD
+            case 8u: {
+                let sweep = read_sweep_grad(cmd_ix);
+                for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+                    let my_xy = vec2(xy.x + f32(i) + 0.5, xy.y + 0.5);
+                    let local_xy = sweep.matrx.xy * my_xy.x + sweep.matrx.zw * my_xy.y - sweep.xlat;
+                    // atan2 is discontinuous on the negative x axis, so wrap the angle
+                    // into a single turn starting at the positive x axis
+                    let angle = fract(atan2(local_xy.y, local_xy.x) * INV_TAU);
+                    let t = extend_mode((angle - sweep.t0) * sweep.t_scale, sweep.extend);
+                    let x = i32(round(t * f32(GRADIENT_WIDTH - 1)));
+                    let fg_rgba = textureLoad(gradients, vec2(x, i32(sweep.index)), 0);
+                    let fg_i = fg_rgba * area[i];
+                    rgba[i] = blend_mix_compose(rgba[i], fg_i, draw_blend);
+                }
+                draw_blend = BLEND_SRC_OVER;
+                cmd_ix += 3u;
+            }
+            // CMD_BLUR_RECT
+            case 12u: {
+                let blur = read_blur_rect(cmd_ix);
+                let fg = unpack4x8unorm(blur.rgba_color).wzyx;
+                for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+                    let my_xy = vec2(xy.x + f32(i) + 0.5, xy.y + 0.5);
+                    let local_xy = blur.matrx.xy * my_xy.x + blur.matrx.zw * my_xy.y - blur.xlat;
+                    // Signed distance to a superellipse approximating the blurred
+                    // rounded corners, negative inside
+                    let d0 = abs(local_xy) - blur.inner;
+                    let d1 = max(d0, vec2(0.0));
+                    let d_pos = pow(pow(d1.x, blur.exponent) + pow(d1.y, blur.exponent), 1.0 / blur.exponent);
+                    let d_neg = min(max(d0.x, d0.y), 0.0);
+                    let d = d_pos + d_neg - blur.r1;
+                    let alpha = blur.scale * (erf7(blur.std_dev_inv * (blur.min_edge + d)) - erf7(blur.std_dev_inv * d));
+                    let fg_i = fg * alpha * area[i];
+                    rgba[i] = blend_mix_compose(rgba[i], fg_i, draw_blend);
                 }
+                draw_blend = BLEND_SRC_OVER;
                 cmd_ix += 3u;
             }
             // CMD_BEGIN_CLIP
@@ -288,10 +534,19 @@ fn main(
                     }
                     let bg = unpack4x8unorm(bg_rgba);
                     let fg = rgba[i] * area[i] * end_clip.alpha;
-                    rgba[i] = blend_mix_compose(bg, fg, end_clip.blend);
+                    if is_mask(end_clip.blend) {
+                        rgba[i] = blend_mask(bg, fg, end_clip.blend);
+                    } else {
+                        rgba[i] = blend_mix_compose(bg, fg, end_clip.blend);
+                    }
                 }
                 cmd_ix += 3u;
             }
+            // CMD_BLEND
+            case 13u: {
+                draw_blend = ptcl[cmd_ix + 1u];
+                cmd_ix += 2u;
+            }
             // CMD_JUMP
             case 11u: {
                 cmd_ix = ptcl[cmd_ix + 1u];
@@ -302,12 +557,19 @@ fn main(
     let xy_uint = vec2<u32>(xy);
     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
         let coords = xy_uint + vec2(i, 0u);
+        // Edge tiles straddle the boundary of the target region, so this check also
+        // keeps the store inside the region when rendering into part of a texture
         if coords.x < config.target_width && coords.y < config.target_height {
+#ifdef debug_overlay
+            let fg = debug_overlay(rgba[i], coords, n_cmds, n_segments);
+#else
             let fg = rgba[i];
+#endif
             // Max with a small epsilon to avoid NaNs
             let a_inv = 1.0 / max(fg.a, 1e-6);
             let rgba_sep = vec4(fg.rgb * a_inv, fg.a);            
-            textureStore(output, vec2<i32>(coords), rgba_sep);
+            let target_coords = coords + vec2(config.target_x, config.target_y);
+            textureStore(output, vec2<i32>(target_coords), rgba_sep);
         }
     } 
 #else
@@ -318,7 +580,8 @@ fn main(
     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
         let coords = xy_uint + vec2(i, 0u);
         if coords.x < config.target_width && coords.y < config.target_height {
-            textureStore(output, vec2<i32>(coords), vec4(area[i]));
+            let target_coords = coords + vec2(config.target_x, config.target_y);
+            textureStore(output, vec2<i32>(target_coords), vec4(area[i]));
         }
     }
 #endif
@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
+
+// Clears the tiles whose command lists draw nothing, which fine is not dispatched
+// over, so that every tile of the target region is written once.
+
+#import config
+#import ptcl
+
+@group(0) @binding(0)
+var<uniform> config: Config;
+
+@group(0) @binding(1)
+var<storage> tile_queue: array<u32>;
+
+@group(0) @binding(2)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+// This must match fine, which the workgroup size is the same as
+let PIXELS_PER_THREAD = 4u;
+
+#ifdef small_tiles
+@compute @workgroup_size(2, 8)
+#else
+@compute @workgroup_size(4, 16)
+#endif
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) wg_id: vec3<u32>,
+) {
+    let queue_ix = wg_id.y * TILE_QUEUE_WIDTH + wg_id.x;
+    if queue_ix >= tile_queue[1] {
+        return;
+    }
+    let n_tiles = config.width_in_tiles * config.height_in_tiles;
+    let tile_ix = tile_queue[TILE_QUEUE_HEADER + n_tiles - 1u - queue_ix];
+    let tile_xy = vec2(tile_ix % config.width_in_tiles, tile_ix / config.width_in_tiles);
+    let xy = tile_xy * vec2(TILE_WIDTH, TILE_HEIGHT) + vec2(local_id.x * PIXELS_PER_THREAD, local_id.y);
+    for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+        let coords = xy + vec2(i, 0u);
+        if coords.x < config.target_width && coords.y < config.target_height {
+            let target_coords = coords + vec2(config.target_x, config.target_y);
+            textureStore(output, vec2<i32>(target_coords), vec4(0.0));
+        }
+    }
+}
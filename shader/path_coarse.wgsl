@@ -39,11 +39,8 @@ fn read_f32_point(ix: u32) -> vec2<f32> {
     return vec2(x, y);
 }
 
-fn read_i16_point(ix: u32) -> vec2<f32> {
-    let raw = scene[pathdata_base + ix];
-    let x = f32(i32(raw << 16u) >> 16u);
-    let y = f32(i32(raw) >> 16u);
-    return vec2(x, y);
+fn read_f16_point(ix: u32) -> vec2<f32> {
+    return unpack2x16float(scene[pathdata_base + ix]);
 }
 
 #ifndef cubics_out
@@ -141,12 +138,12 @@ fn main(
                 }
             }
         } else {
-            p0 = read_i16_point(tm.pathseg_offset);
-            p1 = read_i16_point(tm.pathseg_offset + 1u);
+            p0 = read_f16_point(tm.pathseg_offset);
+            p1 = read_f16_point(tm.pathseg_offset + 1u);
             if seg_type >= PATH_TAG_QUADTO {
-                p2 = read_i16_point(tm.pathseg_offset + 2u);
+                p2 = read_f16_point(tm.pathseg_offset + 2u);
                 if seg_type == PATH_TAG_CUBICTO {
-                    p3 = read_i16_point(tm.pathseg_offset + 3u);
+                    p3 = read_f16_point(tm.pathseg_offset + 3u);
                 }
             }
         }
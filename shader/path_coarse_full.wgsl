@@ -102,10 +102,15 @@ fn alloc_segment() -> u32 {
 }
 
 let MAX_QUADS = 16u;
+// Upper bound on the number of lines a single cubic is flattened into. Without it,
+// huge or non-finite coordinates can make a single invocation loop for long enough
+// to hit a GPU timeout.
+let MAX_LINES = 4096u;
 
 @compute @workgroup_size(256)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) n_workgroups: vec3<u32>,
 ) {
     // Exit early if prior stages failed, as we can't run this stage.
     // We need to check only prior stages, as if this stage has failed in another workgroup, 
@@ -113,7 +118,11 @@ fn main(
     if (atomicLoad(&bump.failed) & (STAGE_BINNING | STAGE_TILE_ALLOC)) != 0u {
         return;
     }
-    let ix = global_id.x;
+    // Large scenes are dispatched over two dimensions
+    let ix = global_id.y * n_workgroups.x * 256u + global_id.x;
+    if ix >= (config.pathdata_base - config.pathtag_base) * 4u {
+        return;
+    }
     let tag_word = scene[config.pathtag_base + (ix >> 2u)];
     let shift = (ix & 3u) * 8u;
     var tag_byte = (tag_word >> shift) & 0xffu;
@@ -122,7 +131,7 @@ fn main(
         // Discussion question: it might actually be cheaper to do the path segment
         // decoding & transform again rather than store the result in a buffer;
         // classic memory vs ALU tradeoff.
-        let cubic = cubics[global_id.x];
+        let cubic = cubics[ix];
         let path = paths[cubic.path_ix];
         let is_stroke = (cubic.flags & CUBIC_IS_STROKE) != 0u;
         let bbox = vec4<i32>(path.bbox);
@@ -152,7 +161,9 @@ fn main(
             val += params.val;
             qp0 = qp2;
         }
-        let n = max(u32(ceil(val * (0.5 / sqrt(REM_ACCURACY)))), 1u);
+        // The conversion of a non-finite value to u32 is undefined, but the clamp still
+        // bounds the result.
+        let n = clamp(u32(ceil(val * (0.5 / sqrt(REM_ACCURACY)))), 1u, MAX_LINES);
         var lp0 = p0;
         qp0 = p0;
         let v_step = val / f32(n);
@@ -78,11 +78,8 @@ fn read_f32_point(ix: u32) -> vec2<f32> {
     return vec2(x, y);
 }
 
-fn read_i16_point(ix: u32) -> vec2<f32> {
-    let raw = scene[pathdata_base + ix];
-    let x = f32(i32(raw << 16u) >> 16u);
-    let y = f32(i32(raw) >> 16u);
-    return vec2(x, y);
+fn read_f16_point(ix: u32) -> vec2<f32> {
+    return unpack2x16float(scene[pathdata_base + ix]);
 }
 
 struct Transform {
@@ -119,8 +116,13 @@ fn round_up(x: f32) -> i32 {
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(num_workgroups) n_workgroups: vec3<u32>,
 ) {
-    let ix = global_id.x;
+    // Large scenes are dispatched over two dimensions
+    let ix = global_id.y * n_workgroups.x * 256u + global_id.x;
+    if ix >= (config.pathdata_base - config.pathtag_base) * 4u {
+        return;
+    }
     let tag_word = scene[config.pathtag_base + (ix >> 2u)];
     pathdata_base = config.pathdata_base;
     let shift = (ix & 3u) * 8u;
@@ -137,6 +139,10 @@ fn main(
     // Decode path data
     let seg_type = tag_byte & PATH_TAG_SEG_TYPE;
     if seg_type != 0u {
+        if config.path_offset_base != 0u && tm.path_ix < config.n_path {
+            // Shared paths read their points from where an earlier path stored them
+            pathdata_base += scene[config.path_offset_base + tm.path_ix];
+        }
         var p0: vec2<f32>;
         var p1: vec2<f32>;
         var p2: vec2<f32>;
@@ -151,12 +157,12 @@ fn main(
                 }
             }
         } else {
-            p0 = read_i16_point(tm.pathseg_offset);
-            p1 = read_i16_point(tm.pathseg_offset + 1u);
+            p0 = read_f16_point(tm.pathseg_offset);
+            p1 = read_f16_point(tm.pathseg_offset + 1u);
             if seg_type >= PATH_TAG_QUADTO {
-                p2 = read_i16_point(tm.pathseg_offset + 2u);
+                p2 = read_f16_point(tm.pathseg_offset + 2u);
                 if seg_type == PATH_TAG_CUBICTO {
-                    p3 = read_i16_point(tm.pathseg_offset + 3u);
+                    p3 = read_f16_point(tm.pathseg_offset + 3u);
                 }
             }
         }
@@ -190,7 +196,7 @@ fn main(
             bbox += vec4(-stroke, stroke);
         }
         let flags = u32(linewidth >= 0.0);
-        cubics[global_id.x] = Cubic(p0, p1, p2, p3, stroke, tm.path_ix, flags);
+        cubics[ix] = Cubic(p0, p1, p2, p3, stroke, tm.path_ix, flags);
         // Update bounding box using atomics only. Computing a monoid is a
         // potential future optimization.
         if bbox.z > bbox.x || bbox.w > bbox.y {
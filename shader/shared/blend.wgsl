@@ -19,6 +19,10 @@ let MIX_SATURATION = 13u;
 let MIX_COLOR = 14u;
 let MIX_LUMINOSITY = 15u;
 let MIX_CLIP = 128u;
+// Mask layers, which multiply their backdrop by the alpha or luminance of their
+// content
+let MIX_MASK_ALPHA = 129u;
+let MIX_MASK_LUMINANCE = 130u;
 
 fn screen(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
     return cb + cs - (cb * cs);
@@ -309,6 +313,24 @@ fn blend_compose(
     return vec4(co, as_fa + ab_fb);
 }
 
+// Returns true if a layer with blend `mode` is a mask layer.
+fn is_mask(mode: u32) -> bool {
+    let mix_mode = (mode & 0x7fffu) >> 8u;
+    return mix_mode == MIX_MASK_ALPHA || mix_mode == MIX_MASK_LUMINANCE;
+}
+
+// Returns the backdrop of a mask layer with blend `mode`, masked by the premultiplied
+// content of the layer.
+fn blend_mask(backdrop: vec4<f32>, mask: vec4<f32>, mode: u32) -> vec4<f32> {
+    let mix_mode = (mode & 0x7fffu) >> 8u;
+    var coverage = mask.a;
+    if mix_mode == MIX_MASK_LUMINANCE {
+        // The color is premultiplied, so this is the luminance times alpha
+        coverage = dot(mask.rgb, vec3(0.2125, 0.7154, 0.0721));
+    }
+    return backdrop * clamp(coverage, 0.0, 1.0);
+}
+
 // Apply color mixing and composition. Both input and output colors are
 // premultiplied RGB.
 fn blend_mix_compose(backdrop: vec4<f32>, src: vec4<f32>, mode: u32) -> vec4<f32> {
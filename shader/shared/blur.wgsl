@@ -0,0 +1,12 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
+
+// Helpers for the analytic evaluation of blurred shapes
+
+// Approximation of the error function, with a maximum error of about 1e-4.
+fn erf7(x: f32) -> f32 {
+    // Clamp to avoid overflow of the polynomial for large inputs
+    var y = clamp(x, -6.0, 6.0) * 1.1283791671;
+    let yy = y * y;
+    y += (0.24295 + (0.03395 + 0.0104 * yy) * yy) * (y * yy);
+    return y / sqrt(1.0 + y * y);
+}
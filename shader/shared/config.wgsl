@@ -24,18 +24,30 @@ struct Config {
 
     transform_base: u32,
     linewidth_base: u32,
+    // Offset of the points of each path from where the path tag monoid places them,
+    // or 0 if no paths share their points
+    path_offset_base: u32,
 
     // Sizes of bump allocated buffers (in element size units)
     binning_size: u32,
     tiles_size: u32,
     segments_size: u32,    
     ptcl_size: u32,
+
+    // Position of the target region within the output texture (in pixels)
+    target_x: u32,
+    target_y: u32,
 }
 
 // Geometry of tiles and bins
 
+#ifdef small_tiles
+let TILE_WIDTH = 8u;
+let TILE_HEIGHT = 8u;
+#else
 let TILE_WIDTH = 16u;
 let TILE_HEIGHT = 16u;
+#endif
 // Number of tiles per bin
 let N_TILE_X = 16u;
 let N_TILE_Y = 16u;
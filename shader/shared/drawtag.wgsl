@@ -17,12 +17,18 @@ struct DrawMonoid {
 // version of the draw monoid.
 let DRAWTAG_NOP = 0u;
 let DRAWTAG_FILL_COLOR = 0x44u;
-let DRAWTAG_FILL_LIN_GRADIENT = 0x114u;
+let DRAWTAG_FILL_LIN_GRADIENT = 0x158u;
 let DRAWTAG_FILL_RAD_GRADIENT = 0x2dcu;
+let DRAWTAG_FILL_SWEEP_GRADIENT = 0x298u;
 let DRAWTAG_FILL_IMAGE = 0x48u;
+let DRAWTAG_BLUR_RECT = 0x394u;
 let DRAWTAG_BEGIN_CLIP = 0x9u;
 let DRAWTAG_END_CLIP = 0x21u;
 
+// Flag for brushes which are composited with a blend mode, which is stored
+// in an extra word after the draw data of the brush.
+let DRAWTAG_BLEND = 0x400u;
+
 fn draw_monoid_identity() -> DrawMonoid {
     return DrawMonoid();
 }
@@ -40,7 +46,7 @@ fn map_draw_tag(tag_word: u32) -> DrawMonoid {
     var c: DrawMonoid;
     c.path_ix = u32(tag_word != DRAWTAG_NOP);
     c.clip_ix = tag_word & 1u;
-    c.scene_offset = (tag_word >> 2u) & 0x07u;
+    c.scene_offset = ((tag_word >> 2u) & 0x07u) + ((tag_word >> 10u) & 1u);
     c.info_offset = (tag_word >> 6u) & 0x0fu;
     return c;
 }
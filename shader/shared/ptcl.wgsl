@@ -8,6 +8,14 @@ let PTCL_INCREMENT = 256u;
 // Amount of space taken by jump
 let PTCL_HEADROOM = 2u;
 
+// The tile queue written by coarse starts with the number of tiles whose command
+// lists draw something, and the number which draw nothing. The indices of the former
+// follow from the start, and those of the latter are stored backwards from the end.
+let TILE_QUEUE_HEADER = 2u;
+// Width in workgroups of the indirect dispatches over the queue, which grow by a row
+// for every this many tiles. This must be kept in sync with the constant in render.rs
+let TILE_QUEUE_WIDTH = 256u;
+
 // Tags for PTCL commands
 let CMD_END = 0u;
 let CMD_FILL = 1u;
@@ -16,9 +24,12 @@ let CMD_SOLID = 3u;
 let CMD_COLOR = 5u;
 let CMD_LIN_GRAD = 6u;
 let CMD_RAD_GRAD = 7u;
+let CMD_SWEEP_GRAD = 8u;
 let CMD_BEGIN_CLIP = 9u;
 let CMD_END_CLIP = 10u;
 let CMD_JUMP = 11u;
+let CMD_BLUR_RECT = 12u;
+let CMD_BLEND = 13u;
 
 // The individual PTCL structs are written here, but read/write is by
 // hand in the relevant shaders
@@ -46,6 +57,7 @@ struct CmdLinGrad {
     line_x: f32,
     line_y: f32,
     line_c: f32,
+    extend: u32,
 }
 
 struct CmdRadGrad {
@@ -57,6 +69,28 @@ struct CmdRadGrad {
     roff: f32,
 }
 
+struct CmdSweepGrad {
+    index: u32,
+    matrx: vec4<f32>,
+    xlat: vec2<f32>,
+    t0: f32,
+    t_scale: f32,
+    extend: u32,
+}
+
+struct CmdBlurRect {
+    rgba_color: u32,
+    matrx: vec4<f32>,
+    xlat: vec2<f32>,
+    // Half the size of the rectangle, less the corner radius.
+    inner: vec2<f32>,
+    r1: f32,
+    exponent: f32,
+    std_dev_inv: f32,
+    min_edge: f32,
+    scale: f32,
+}
+
 struct CmdEndClip {
     blend: u32,
     alpha: f32,
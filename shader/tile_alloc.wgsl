@@ -64,8 +64,9 @@ fn main(
     }
     let ux0 = u32(clamp(x0, 0, i32(config.width_in_tiles)));
     let uy0 = u32(clamp(y0, 0, i32(config.height_in_tiles)));
-    let ux1 = u32(clamp(x1, 0, i32(config.width_in_tiles)));
-    let uy1 = u32(clamp(y1, 0, i32(config.height_in_tiles)));
+    // Inverted bounding boxes (from non-finite coordinates) are treated as empty
+    let ux1 = max(u32(clamp(x1, 0, i32(config.width_in_tiles))), ux0);
+    let uy1 = max(u32(clamp(y1, 0, i32(config.height_in_tiles))), uy0);
     let tile_count = (ux1 - ux0) * (uy1 - uy0);
     var total_tile_count = tile_count;
     sh_tile_count[local_id.x] = tile_count;
@@ -0,0 +1,222 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Captures of the intermediate buffers of a frame, for debugging the GPU stages.
+//!
+//! A capture, as written by [`Renderer::capture_next_frame`](crate::Renderer::capture_next_frame),
+//! is a directory holding a `<name>.bin` file with the contents of each buffer at the
+//! end of the frame, `config.bin` with the [`Config`] the frame was rendered with, and
+//! `manifest.json`, which lists the buffers with their sizes and the fields of the
+//! config.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::mem::size_of;
+use std::path::Path;
+use std::{fs, io};
+
+use crate::encoding::{Config, PathMonoid, PathSegment};
+use crate::shaders::types;
+
+/// Size of a path in the path buffer, in words.
+const PATH_WORDS: usize = size_of::<types::Path>() / 4;
+/// Size of a segment in the segment buffer, in words.
+const SEGMENT_WORDS: usize = size_of::<PathSegment>() / 4;
+/// Size of a tag monoid in the tag monoid buffer, in words.
+const TAG_MONOID_WORDS: usize = size_of::<PathMonoid>() / 4;
+
+/// Writes a capture of `buffers`, given as names and contents, to `dir`.
+pub(crate) fn write(dir: &Path, config: &Config, buffers: &[(&str, Vec<u8>)]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("config.bin"), bytemuck::bytes_of(config))?;
+    let mut manifest = String::from("{\n  \"config\": {\n");
+    let layout = &config.layout;
+    let fields = [
+        ("width_in_tiles", config.width_in_tiles),
+        ("height_in_tiles", config.height_in_tiles),
+        ("target_width", config.target_width),
+        ("target_height", config.target_height),
+        ("binning_size", config.binning_size),
+        ("tiles_size", config.tiles_size),
+        ("segments_size", config.segments_size),
+        ("ptcl_size", config.ptcl_size),
+        ("target_x", config.target_x),
+        ("target_y", config.target_y),
+        ("n_draw_objects", layout.n_draw_objects),
+        ("n_paths", layout.n_paths),
+        ("n_clips", layout.n_clips),
+        ("bin_data_start", layout.bin_data_start),
+        ("path_tag_base", layout.path_tag_base),
+        ("path_data_base", layout.path_data_base),
+        ("draw_tag_base", layout.draw_tag_base),
+        ("draw_data_base", layout.draw_data_base),
+        ("transform_base", layout.transform_base),
+        ("linewidth_base", layout.linewidth_base),
+        ("path_offset_base", layout.path_offset_base),
+    ];
+    for (ix, (name, value)) in fields.iter().enumerate() {
+        let separator = if ix + 1 < fields.len() { "," } else { "" };
+        let _ = writeln!(manifest, "    \"{name}\": {value}{separator}");
+    }
+    manifest.push_str("  },\n  \"buffers\": [\n");
+    for (ix, (name, data)) in buffers.iter().enumerate() {
+        fs::write(dir.join(format!("{name}.bin")), data)?;
+        let separator = if ix + 1 < buffers.len() { "," } else { "" };
+        let _ = writeln!(
+            manifest,
+            "    {{ \"name\": \"{name}\", \"file\": \"{name}.bin\", \"size\": {} }}{separator}",
+            data.len()
+        );
+    }
+    manifest.push_str("  ]\n}\n");
+    fs::write(dir.join("manifest.json"), manifest)
+}
+
+/// A frame capture read back from disk, for checking the invariants of the GPU stages.
+pub struct FrameCapture {
+    /// Configuration the frame was rendered with.
+    pub config: Config,
+    buffers: HashMap<String, Vec<u8>>,
+}
+
+impl FrameCapture {
+    /// Reads the capture in `dir`.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let config = bytemuck::try_pod_read_unaligned(&fs::read(dir.join("config.bin"))?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "config has the wrong size"))?;
+        let mut buffers = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() != Some("bin".as_ref()) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                if name != "config" {
+                    buffers.insert(name.to_owned(), fs::read(&path)?);
+                }
+            }
+        }
+        Ok(Self { config, buffers })
+    }
+
+    /// Returns the contents of the buffer named `name`, such as `"tile_buf"`.
+    pub fn buffer(&self, name: &str) -> Option<&[u8]> {
+        self.buffers.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns the names of the captured buffers, in no particular order.
+    pub fn buffer_names(&self) -> impl Iterator<Item = &str> {
+        self.buffers.keys().map(String::as_str)
+    }
+
+    /// Checks the structure of the data written by the GPU stages, returning a
+    /// description of the first inconsistency found.
+    ///
+    /// This checks that no field of the tag monoids decreases, as they are prefix sums
+    /// of counts, that the tiles of each path are within the allocated tiles, that
+    /// the segment list of each tile only links allocated segments and ends, and that
+    /// the backdrop of a tile only differs from the backdrop of the tile to its left
+    /// if that tile has segments, since only segments change the winding number.
+    pub fn check(&self) -> Result<(), String> {
+        let bump = self.words("bump_buf")?;
+        if bump.len() < 5 {
+            return Err("the bump buffer is too small".into());
+        }
+        let (failed, n_tiles, n_segments) = (bump[0], bump[3], bump[4]);
+        if failed != 0 {
+            return Err(format!(
+                "the frame overflowed a buffer (failed stages {failed:#x}), so the capture is incomplete"
+            ));
+        }
+        let monoids = self.words("tagmonoid_buf")?;
+        for (ix, pair) in monoids
+            .chunks_exact(TAG_MONOID_WORDS)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .enumerate()
+        {
+            if pair[0].iter().zip(pair[1]).any(|(a, b)| a > b) {
+                return Err(format!(
+                    "tag monoid {} decreases from the one before it",
+                    ix + 1
+                ));
+            }
+        }
+        let paths = self.words("path_buf")?;
+        let tiles = self.words("tile_buf")?;
+        let segments = self.words("segments_buf")?;
+        let config = &self.config;
+        for path_ix in 0..config.layout.n_draw_objects as usize {
+            let path = paths
+                .get(path_ix * PATH_WORDS..path_ix * PATH_WORDS + 5)
+                .ok_or("the path buffer is too small")?;
+            let (x0, y0, x1, y1, offset) = (path[0], path[1], path[2], path[3], path[4]);
+            if x0 > x1 || y0 > y1 || x1 > config.width_in_tiles || y1 > config.height_in_tiles {
+                return Err(format!("path {path_ix} has an invalid bounding box"));
+            }
+            let width = x1 - x0;
+            if offset as u64 + (width * (y1 - y0)) as u64 > n_tiles as u64 {
+                return Err(format!("path {path_ix} has tiles past those allocated"));
+            }
+            for row in 0..y1 - y0 {
+                let mut left = None;
+                for column in 0..width {
+                    let tile_ix = (offset + row * width + column) as usize;
+                    let tile = tiles
+                        .get(tile_ix * 2..tile_ix * 2 + 2)
+                        .ok_or("the tile buffer is too small")?;
+                    let (backdrop, first_segment) = (tile[0] as i32, tile[1]);
+                    let describe = || format!("tile {column}, {row} of path {path_ix}");
+                    let mut segment = first_segment;
+                    let mut n_linked = 0;
+                    while segment != 0 {
+                        if segment > n_segments {
+                            return Err(format!("{} links an unallocated segment", describe()));
+                        }
+                        n_linked += 1;
+                        if n_linked > n_segments {
+                            return Err(format!("{} has a cycle of segments", describe()));
+                        }
+                        segment = *segments
+                            .get(segment as usize * SEGMENT_WORDS + 5)
+                            .ok_or("the segment buffer is too small")?;
+                    }
+                    if let Some((left_backdrop, left_has_segments)) = left {
+                        if backdrop != left_backdrop && !left_has_segments {
+                            return Err(format!(
+                                "{} has backdrop {backdrop}, but the tile to its left has backdrop {left_backdrop} and no segments",
+                                describe()
+                            ));
+                        }
+                    }
+                    left = Some((backdrop, first_segment != 0));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn words(&self, name: &str) -> Result<Vec<u32>, String> {
+        let data = self
+            .buffer(name)
+            .ok_or_else(|| format!("the capture has no {name}"))?;
+        Ok(data
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect())
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Splitting of stroked paths into dashes.
+
+use peniko::kurbo::{BezPath, ParamCurve, ParamCurveArclen, PathEl, PathSeg, Point};
+
+/// Accuracy of the arc length computations which place dash boundaries, in the units
+/// of the path.
+const ARCLEN_ACCURACY: f64 = 1e-3;
+
+/// Splits a path into the dashes of `pattern`, which alternates between the lengths of
+/// dashes and gaps, starting `offset` units into the pattern. Patterns with an odd
+/// number of entries are repeated to make their length even, as in SVG, and offsets
+/// outside of the pattern wrap around it.
+///
+/// The pattern restarts at the beginning of every subpath. Each dash is a separate
+/// subpath, so it gets its own caps when it is stroked, and zero length dashes become
+/// dots.
+///
+/// Returns `None` if the pattern can't be used for dashing, because it is empty, has
+/// a negative or non-finite entry, or sums to zero. The path should then be stroked
+/// without dashes.
+pub fn dash(
+    path: impl IntoIterator<Item = PathEl>,
+    offset: f64,
+    pattern: &[f32],
+) -> Option<BezPath> {
+    let mut pattern = pattern.iter().map(|d| *d as f64).collect::<Vec<_>>();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_within(..);
+    }
+    let total = pattern.iter().sum::<f64>();
+    if pattern.iter().any(|d| !d.is_finite() || *d < 0.0) || total <= 0.0 || !offset.is_finite() {
+        return None;
+    }
+    // Find where in the pattern the dashes start
+    let mut offset = offset.rem_euclid(total);
+    let mut start = DashState {
+        ix: 0,
+        remaining: 0.0,
+    };
+    // Zero length entries at the start are kept, so a leading zero length dash is a dot
+    while offset > 0.0 && offset >= pattern[start.ix] {
+        offset -= pattern[start.ix];
+        start.ix = (start.ix + 1) % pattern.len();
+    }
+    start.remaining = pattern[start.ix] - offset;
+    let mut dasher = Dasher {
+        pattern: &pattern,
+        start,
+        state: start,
+        out: BezPath::new(),
+        needs_move: true,
+    };
+    let mut start_point = Point::ZERO;
+    let mut last_point = Point::ZERO;
+    for el in path {
+        let seg = match el {
+            PathEl::MoveTo(p) => {
+                dasher.restart();
+                start_point = p;
+                last_point = p;
+                continue;
+            }
+            PathEl::LineTo(p) => PathSeg::Line((last_point, p).into()),
+            PathEl::QuadTo(p1, p2) => PathSeg::Quad((last_point, p1, p2).into()),
+            PathEl::CurveTo(p1, p2, p3) => PathSeg::Cubic((last_point, p1, p2, p3).into()),
+            PathEl::ClosePath => {
+                let seg = PathSeg::Line((last_point, start_point).into());
+                dasher.segment(seg);
+                last_point = start_point;
+                continue;
+            }
+        };
+        dasher.segment(seg);
+        last_point = seg.end();
+    }
+    Some(dasher.out)
+}
+
+#[derive(Copy, Clone)]
+struct DashState {
+    /// Index of the current entry of the pattern. Even entries are dashes.
+    ix: usize,
+    /// Length left in the current entry.
+    remaining: f64,
+}
+
+struct Dasher<'a> {
+    pattern: &'a [f64],
+    start: DashState,
+    state: DashState,
+    out: BezPath,
+    /// Whether the next piece of a dash starts a new subpath.
+    needs_move: bool,
+}
+
+impl<'a> Dasher<'a> {
+    fn restart(&mut self) {
+        self.state = self.start;
+        self.needs_move = true;
+    }
+
+    fn segment(&mut self, seg: PathSeg) {
+        let len = seg.arclen(ARCLEN_ACCURACY);
+        if !len.is_finite() {
+            // Invalid coordinates are left for path validation to deal with
+            if self.state.ix % 2 == 0 {
+                self.emit(seg);
+            }
+            return;
+        }
+        let mut pos = 0.0;
+        let mut t0 = 0.0;
+        loop {
+            if pos + self.state.remaining > len {
+                // A dash that starts exactly at the end of the segment continues in the
+                // next one, unless the segment is degenerate
+                if self.state.ix % 2 == 0 && (pos < len || len == 0.0) {
+                    self.emit(seg.subsegment(t0..1.0));
+                }
+                self.state.remaining -= len - pos;
+                return;
+            }
+            pos += self.state.remaining;
+            let t1 = seg.inv_arclen(pos, ARCLEN_ACCURACY).min(1.0);
+            if self.state.ix % 2 == 0 {
+                self.emit(seg.subsegment(t0..t1));
+                // The dash ends here
+                self.needs_move = true;
+            }
+            t0 = t1;
+            self.state.ix = (self.state.ix + 1) % self.pattern.len();
+            self.state.remaining = self.pattern[self.state.ix];
+        }
+    }
+
+    fn emit(&mut self, seg: PathSeg) {
+        if self.needs_move {
+            self.out.move_to(seg.start());
+            self.needs_move = false;
+        }
+        match seg {
+            PathSeg::Line(line) => self.out.line_to(line.p1),
+            PathSeg::Quad(quad) => self.out.quad_to(quad.p1, quad.p2),
+            PathSeg::Cubic(cubic) => self.out.curve_to(cubic.p1, cubic.p2, cubic.p3),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the start, end and length of each dash, which are polylines for the
+    /// paths below.
+    fn dashes(path: &BezPath) -> Vec<(Point, Point, f64)> {
+        let mut dashes: Vec<(Point, Point, f64)> = Vec::new();
+        for el in path.elements() {
+            match el {
+                PathEl::MoveTo(p) => dashes.push((*p, *p, 0.0)),
+                PathEl::LineTo(p) => {
+                    let dash = dashes.last_mut().unwrap();
+                    dash.2 += dash.1.distance(*p);
+                    dash.1 = *p;
+                }
+                el => panic!("unexpected element {el:?}"),
+            }
+        }
+        dashes
+    }
+
+    fn line(length: f64) -> BezPath {
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.line_to((length, 0.0));
+        path
+    }
+
+    /// Checks that the dashes of a horizontal line span `expected`.
+    fn assert_dashes(path: &BezPath, expected: &[(f64, f64)]) {
+        let dashes = dashes(path);
+        assert_eq!(dashes.len(), expected.len(), "{dashes:?}");
+        for ((start, end, length), (x0, x1)) in dashes.into_iter().zip(expected) {
+            assert!(start.distance((*x0, 0.0).into()) < 2.0 * ARCLEN_ACCURACY);
+            assert!(end.distance((*x1, 0.0).into()) < 2.0 * ARCLEN_ACCURACY);
+            assert!((length - (x1 - x0)).abs() < 2.0 * ARCLEN_ACCURACY);
+        }
+    }
+
+    #[test]
+    fn dashes_follow_pattern() {
+        let path = dash(line(11.0).iter(), 0.0, &[2.0, 3.0]).unwrap();
+        assert_dashes(&path, &[(0.0, 2.0), (5.0, 7.0), (10.0, 11.0)]);
+    }
+
+    #[test]
+    fn dash_starting_at_end_is_dropped() {
+        let path = dash(line(10.0).iter(), 0.0, &[2.0, 3.0]).unwrap();
+        assert_dashes(&path, &[(0.0, 2.0), (5.0, 7.0)]);
+    }
+
+    #[test]
+    fn dashes_continue_around_corners() {
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.line_to((4.0, 0.0));
+        path.line_to((4.0, 4.0));
+        path.close_path();
+        let dashed = dash(path.iter(), 0.0, &[5.0, 1.0]).unwrap();
+        let dashes = dashes(&dashed);
+        let lengths = dashes.iter().map(|d| d.2).collect::<Vec<_>>();
+        assert_eq!(lengths.len(), 3, "{lengths:?}");
+        // The last dash is cut short by the end of the closing segment
+        let expected = [5.0, 5.0, 8.0 + 32f64.sqrt() - 12.0];
+        for (length, expected) in lengths.iter().zip(expected) {
+            assert!((length - expected).abs() < 2.0 * ARCLEN_ACCURACY);
+        }
+        assert_eq!(dashed.elements().len(), 3 + 3 + 2);
+        assert!(dashes[0].1.distance((4.0, 1.0).into()) < 2.0 * ARCLEN_ACCURACY);
+    }
+
+    #[test]
+    fn zero_length_dashes_are_dots() {
+        let path = dash(line(10.0).iter(), 0.0, &[0.0, 4.0]).unwrap();
+        assert_dashes(&path, &[(0.0, 0.0), (4.0, 4.0), (8.0, 8.0)]);
+        // The offset doesn't lose the dot at the start
+        let path = dash(line(10.0).iter(), 4.0, &[0.0, 4.0]).unwrap();
+        assert_dashes(&path, &[(0.0, 0.0), (4.0, 4.0), (8.0, 8.0)]);
+    }
+
+    #[test]
+    fn odd_patterns_are_repeated() {
+        let path = dash(line(12.0).iter(), 0.0, &[1.0, 2.0, 3.0]).unwrap();
+        assert_dashes(&path, &[(0.0, 1.0), (3.0, 6.0), (7.0, 9.0)]);
+    }
+
+    #[test]
+    fn offsets_wrap_around_pattern() {
+        let expected = [(1.0, 3.0), (6.0, 8.0)];
+        for offset in [4.0, -1.0, 9.0, -6.0] {
+            let path = dash(line(10.0).iter(), offset, &[2.0, 3.0]).unwrap();
+            assert_dashes(&path, &expected);
+        }
+        // An offset ending a dash doesn't leave a dot behind
+        let path = dash(line(10.0).iter(), 2.0, &[2.0, 3.0]).unwrap();
+        assert_dashes(&path, &[(3.0, 5.0), (8.0, 10.0)]);
+    }
+
+    #[test]
+    fn pattern_restarts_at_subpaths() {
+        let mut path = line(3.0);
+        path.move_to((0.0, 0.0));
+        path.line_to((3.0, 0.0));
+        let path = dash(path.iter(), 0.0, &[2.0, 2.0]).unwrap();
+        assert_dashes(&path, &[(0.0, 2.0), (0.0, 2.0)]);
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let path = line(10.0);
+        assert!(dash(path.iter(), 0.0, &[]).is_none());
+        assert!(dash(path.iter(), 0.0, &[0.0, 0.0]).is_none());
+        assert!(dash(path.iter(), 0.0, &[-1.0, 2.0]).is_none());
+        assert!(dash(path.iter(), 0.0, &[f32::NAN, 2.0]).is_none());
+        assert!(dash(path.iter(), f64::INFINITY, &[1.0, 2.0]).is_none());
+    }
+}
@@ -22,15 +22,21 @@ mod math;
 mod monoid;
 mod packed;
 mod path;
+mod serialize;
 
 pub mod resource;
 
 pub use draw::{
-    DrawBeginClip, DrawColor, DrawImage, DrawLinearGradient, DrawMonoid, DrawRadialGradient,
-    DrawTag,
+    DrawBeginClip, DrawBlurRect, DrawColor, DrawImage, DrawLinearGradient, DrawMonoid,
+    DrawRadialGradient, DrawSweepGradient, DrawTag, MaskKind,
 };
-pub use encoding::Encoding;
+pub use encoding::{AreaHistogram, Encoding, HitRegion, PathDataRange, SharedPath, StreamLengths};
 pub use math::Transform;
 pub use monoid::Monoid;
 pub use packed::{Config, Layout, PackedEncoding};
-pub use path::{PathBbox, PathEncoder, PathMonoid, PathSegment, PathSegmentType, PathTag};
+pub use path::{
+    PathBbox, PathEncoder, PathMonoid, PathSegment, PathSegmentType, PathTag, PathValidation,
+    Precision, MAX_COORDINATE,
+};
+pub use resource::InterpolationSpace;
+pub use serialize::DecodeError;
@@ -32,14 +32,20 @@ impl DrawTag {
     pub const COLOR: Self = Self(0x44);
 
     /// Linear gradient fill.
-    pub const LINEAR_GRADIENT: Self = Self(0x114);
+    pub const LINEAR_GRADIENT: Self = Self(0x158);
 
     /// Radial gradient fill.
     pub const RADIAL_GRADIENT: Self = Self(0x2dc);
 
+    /// Sweep gradient fill.
+    pub const SWEEP_GRADIENT: Self = Self(0x298);
+
     /// Image fill.
     pub const IMAGE: Self = Self(0x48);
 
+    /// Blurred rounded rectangle.
+    pub const BLUR_RECT: Self = Self(0x394);
+
     /// Begin layer/clip.
     pub const BEGIN_CLIP: Self = Self(0x9);
 
@@ -48,10 +54,34 @@ impl DrawTag {
 }
 
 impl DrawTag {
+    /// Flag for brushes which are composited with a blend mode instead of source
+    /// over. The blend mode is encoded in the word after the draw data of the brush.
+    const BLEND_FLAG: u32 = 0x400;
+
     /// Returns the size of the info buffer (in u32s) used by this tag.
     pub const fn info_size(self) -> u32 {
         (self.0 >> 6) & 0xf
     }
+
+    /// Returns the size of the draw data (in u32s) used by this tag.
+    pub const fn data_size(self) -> u32 {
+        ((self.0 >> 2) & 0x7) + (self.0 & Self::BLEND_FLAG) / Self::BLEND_FLAG
+    }
+
+    /// Returns the tag of the same brush, composited with a blend mode.
+    pub const fn blended(self) -> Self {
+        Self(self.0 | Self::BLEND_FLAG)
+    }
+
+    /// Returns whether the brush is composited with a blend mode.
+    pub const fn is_blended(self) -> bool {
+        self.0 & Self::BLEND_FLAG != 0
+    }
+
+    /// Returns the tag of the same brush, composited with source over.
+    pub const fn without_blend(self) -> Self {
+        Self(self.0 & !Self::BLEND_FLAG)
+    }
 }
 
 /// Draw data for a solid color.
@@ -82,6 +112,8 @@ pub struct DrawLinearGradient {
     pub p0: [f32; 2],
     /// End point.
     pub p1: [f32; 2],
+    /// Extend mode, as the discriminant of [`Extend`](peniko::Extend).
+    pub extend: u32,
 }
 
 /// Draw data for a radial gradient.
@@ -100,6 +132,22 @@ pub struct DrawRadialGradient {
     pub r1: f32,
 }
 
+/// Draw data for a sweep gradient.
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct DrawSweepGradient {
+    /// Ramp index.
+    pub index: u32,
+    /// Center point.
+    pub p0: [f32; 2],
+    /// Normalized start angle, in turns.
+    pub t0: f32,
+    /// Normalized end angle, in turns.
+    pub t1: f32,
+    /// Extend mode, as the discriminant of [`Extend`](peniko::Extend).
+    pub extend: u32,
+}
+
 /// Draw data for an image.
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 #[repr(C)]
@@ -110,6 +158,23 @@ pub struct DrawImage {
     pub offset: u32,
 }
 
+/// Draw data for a blurred rounded rectangle, centered on the origin.
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct DrawBlurRect {
+    /// Packed little endian RGBA premultiplied color with the alpha component
+    /// in the low byte.
+    pub rgba: u32,
+    /// Width of the rectangle.
+    pub width: f32,
+    /// Height of the rectangle.
+    pub height: f32,
+    /// Corner radius.
+    pub radius: f32,
+    /// Standard deviation of the gaussian blur.
+    pub std_dev: f32,
+}
+
 /// Draw data for a clip or layer.
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 #[repr(C)]
@@ -128,6 +193,30 @@ impl DrawBeginClip {
             alpha,
         }
     }
+
+    /// Creates draw data for a mask layer. Mask layers are encoded as clips with a
+    /// mix mode after those of [`peniko::Mix`].
+    pub fn mask(kind: MaskKind) -> Self {
+        let mix = match kind {
+            MaskKind::Alpha => 129,
+            MaskKind::Luminance => 130,
+        };
+        Self {
+            blend_mode: mix << 8 | peniko::Compose::SrcOver as u32,
+            alpha: 1.0,
+        }
+    }
+}
+
+/// Which part of the content of a mask layer is used as the mask.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MaskKind {
+    /// The alpha of the content.
+    #[default]
+    Alpha,
+    /// The luminance of the content, multiplied by its alpha, as in CSS and SVG
+    /// luminance masks.
+    Luminance,
 }
 
 /// Monoid for the draw tag stream.
@@ -151,7 +240,7 @@ impl Monoid for DrawMonoid {
         Self {
             path_ix: (tag != DrawTag::NOP) as u32,
             clip_ix: tag.0 & 1,
-            scene_offset: (tag.0 >> 2) & 0x7,
+            scene_offset: tag.data_size(),
             info_offset: (tag.0 >> 6) & 0xf,
         }
     }
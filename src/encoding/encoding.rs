@@ -14,12 +14,134 @@
 //
 // Also licensed under MIT license, at your choice.
 
-use super::resource::Patch;
+use super::resource::{InterpolationSpace, Patch};
 use super::{
-    DrawColor, DrawLinearGradient, DrawRadialGradient, DrawTag, PathEncoder, PathTag, Transform,
+    DrawBlurRect, DrawColor, DrawLinearGradient, DrawRadialGradient, DrawSweepGradient, DrawTag,
+    MaskKind, PathEncoder, PathTag, Transform,
 };
 
-use peniko::{kurbo::Shape, BlendMode, BrushRef, Color, ColorStop, Extend, GradientKind};
+use bytemuck::{Pod, Zeroable};
+use peniko::{
+    kurbo::{Point, Rect, Shape},
+    BlendMode, BrushRef, Color, ColorStop, Extend, GradientKind,
+};
+
+/// A path whose points are read from elsewhere in the path data stream, usually
+/// those of an earlier path with the same segments, rather than following the points
+/// of the path before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Zeroable, Pod)]
+#[repr(C)]
+pub struct SharedPath {
+    /// Index of the path.
+    pub path_ix: u32,
+    /// Offset of the points of the path in the path data stream, in bytes.
+    pub data_offset: u32,
+}
+
+/// Placement of the points of a path in the path data stream, in words.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PathDataRange {
+    /// Offset of the points of the path.
+    pub start: u32,
+    /// Offset where the path tag monoid places the points of the path, which is
+    /// the number of words counted for the paths before it.
+    pub counted_start: u32,
+    /// Number of words the path tag monoid counts for the path.
+    pub words: u32,
+}
+
+/// Number of buckets of an [`AreaHistogram`].
+const AREA_BUCKETS: usize = 64;
+
+/// Counts of the areas of the bounding boxes of the paths drawn in an encoding, by
+/// the power of two above each area, which bounds their median without storing
+/// every area.
+///
+/// Areas below 1 are counted in the first bucket, and bucket `k` above it counts
+/// areas of at least `2^(k - 1)` and below `2^k`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AreaHistogram {
+    /// Number of areas in each bucket.
+    pub counts: [u32; AREA_BUCKETS],
+}
+
+impl Default for AreaHistogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; AREA_BUCKETS],
+        }
+    }
+}
+
+impl AreaHistogram {
+    /// Counts an area. Invalid areas are counted as empty.
+    pub fn add(&mut self, area: f64) {
+        self.counts[Self::bucket(area)] += 1;
+    }
+
+    /// Adds the counts of `other`, with its areas scaled by `scale`, which is
+    /// rounded to a power of two.
+    pub fn merge(&mut self, other: &Self, scale: f64) {
+        let shift = if scale.is_finite() && scale > 0.0 {
+            scale.log2().round() as i64
+        } else {
+            0
+        };
+        for (ix, count) in other.counts.iter().enumerate() {
+            let ix = (ix as i64 + shift).clamp(0, AREA_BUCKETS as i64 - 1);
+            self.counts[ix as usize] += count;
+        }
+    }
+
+    /// Returns the power of two which the median area is below, or `None` if
+    /// nothing was counted. The median of an even number of areas is the lower of
+    /// the two middle ones.
+    pub fn median_bound(&self) -> Option<f64> {
+        let total = self.counts.iter().map(|count| *count as u64).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let mut below = 0;
+        for (ix, count) in self.counts.iter().enumerate() {
+            below += *count as u64;
+            if below > (total - 1) / 2 {
+                return Some((ix as f64).exp2());
+            }
+        }
+        unreachable!()
+    }
+
+    fn bucket(area: f64) -> usize {
+        if area >= 1.0 {
+            // Also true for infinite areas, which go in the last bucket
+            (area.log2().floor() as usize + 1).min(AREA_BUCKETS - 1)
+        } else {
+            0
+        }
+    }
+}
+
+/// The bounding box of a draw object which was drawn with a tag, for hit testing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitRegion {
+    /// Tag the draw object was drawn with.
+    pub tag: u32,
+    /// Bounding box of the draw object after its transform, clipped to the bounding
+    /// boxes of the layers it was drawn in.
+    pub bbox: Rect,
+}
+
+impl HitRegion {
+    fn transformed(&self, transform: &Option<Transform>) -> Self {
+        match transform {
+            Some(transform) => Self {
+                tag: self.tag,
+                bbox: transform.to_kurbo().transform_rect_bbox(self.bbox),
+            },
+            None => *self,
+        }
+    }
+}
 
 /// Encoded data streams for a scene.
 #[derive(Default)]
@@ -28,6 +150,9 @@ pub struct Encoding {
     pub path_tags: Vec<PathTag>,
     /// The path data stream.
     pub path_data: Vec<u8>,
+    /// Paths which read their points from earlier in the path data stream, in order
+    /// of their index.
+    pub shared_paths: Vec<SharedPath>,
     /// The draw tag stream.
     pub draw_tags: Vec<DrawTag>,
     /// The draw data stream.
@@ -46,6 +171,13 @@ pub struct Encoding {
     pub n_path_segments: u32,
     /// Number of encoded clips/layers.
     pub n_clips: u32,
+    /// Union of the transformed bounding boxes of all filled and stroked paths.
+    pub bbox: Option<Rect>,
+    /// Areas of the transformed bounding boxes of the filled and stroked paths.
+    pub path_areas: AreaHistogram,
+    /// Bounding boxes of the draw objects which were drawn with a tag, in drawing
+    /// order. These are not uploaded.
+    pub hit_regions: Vec<HitRegion>,
 }
 
 impl Encoding {
@@ -59,17 +191,78 @@ impl Encoding {
         self.path_tags.is_empty()
     }
 
+    /// Returns the total size in bytes of the encoded streams.
+    pub fn byte_size(&self) -> usize {
+        use std::mem::size_of_val;
+        size_of_val(&self.path_tags[..])
+            + size_of_val(&self.path_data[..])
+            + size_of_val(&self.shared_paths[..])
+            + size_of_val(&self.draw_tags[..])
+            + size_of_val(&self.draw_data[..])
+            + size_of_val(&self.patches[..])
+            + size_of_val(&self.color_stops[..])
+            + size_of_val(&self.transforms[..])
+            + size_of_val(&self.linewidths[..])
+            + size_of_val(&self.hit_regions[..])
+    }
+
+    /// Returns the size in bytes of the memory allocated for the encoded streams,
+    /// which includes the spare capacity left by building and clearing them.
+    pub fn allocated_byte_size(&self) -> usize {
+        fn allocated<T>(v: &Vec<T>) -> usize {
+            v.capacity() * std::mem::size_of::<T>()
+        }
+        allocated(&self.path_tags)
+            + allocated(&self.path_data)
+            + allocated(&self.shared_paths)
+            + allocated(&self.draw_tags)
+            + allocated(&self.draw_data)
+            + allocated(&self.patches)
+            + allocated(&self.color_stops)
+            + allocated(&self.transforms)
+            + allocated(&self.linewidths)
+            + allocated(&self.hit_regions)
+    }
+
+    /// Returns the number of bytes saved in the path data stream by segments whose
+    /// points are stored in f16 rather than f32.
+    pub fn path_data_saved_bytes(&self) -> usize {
+        self.path_tags
+            .iter()
+            .filter(|tag| tag.is_path_segment() && !tag.is_f32())
+            .map(|tag| tag.point_words() as usize * 4)
+            .sum()
+    }
+
+    /// Frees the spare capacity of the encoded streams.
+    pub fn shrink_to_fit(&mut self) {
+        self.path_tags.shrink_to_fit();
+        self.path_data.shrink_to_fit();
+        self.shared_paths.shrink_to_fit();
+        self.draw_tags.shrink_to_fit();
+        self.draw_data.shrink_to_fit();
+        self.patches.shrink_to_fit();
+        self.color_stops.shrink_to_fit();
+        self.transforms.shrink_to_fit();
+        self.linewidths.shrink_to_fit();
+        self.hit_regions.shrink_to_fit();
+    }
+
     /// Clears the encoding.
     pub fn reset(&mut self, is_fragment: bool) {
         self.transforms.clear();
         self.path_tags.clear();
         self.path_data.clear();
+        self.shared_paths.clear();
         self.linewidths.clear();
         self.draw_data.clear();
         self.draw_tags.clear();
         self.n_paths = 0;
         self.n_path_segments = 0;
         self.n_clips = 0;
+        self.bbox = None;
+        self.path_areas = AreaHistogram::default();
+        self.hit_regions.clear();
         self.patches.clear();
         self.color_stops.clear();
         if !is_fragment {
@@ -82,6 +275,13 @@ impl Encoding {
     pub fn append(&mut self, other: &Self, transform: &Option<Transform>) {
         let stops_base = self.color_stops.len();
         let draw_data_base = self.draw_data.len();
+        let path_data_base = self.path_data.len() as u32;
+        let path_base = self.n_paths;
+        self.shared_paths
+            .extend(other.shared_paths.iter().map(|shared| SharedPath {
+                path_ix: shared.path_ix + path_base,
+                data_offset: shared.data_offset + path_data_base,
+            }));
         self.path_tags.extend_from_slice(&other.path_tags);
         self.path_data.extend_from_slice(&other.path_data);
         self.draw_tags.extend_from_slice(&other.draw_tags);
@@ -91,11 +291,16 @@ impl Encoding {
         self.n_clips += other.n_clips;
         self.patches
             .extend(other.patches.iter().map(|patch| match patch {
-                Patch::Ramp { offset, stops } => {
+                Patch::Ramp {
+                    offset,
+                    stops,
+                    space,
+                } => {
                     let stops = stops.start + stops_base..stops.end + stops_base;
                     Patch::Ramp {
                         offset: draw_data_base + offset,
                         stops,
+                        space: *space,
                     }
                 }
             }));
@@ -107,6 +312,247 @@ impl Encoding {
             self.transforms.extend_from_slice(&other.transforms);
         }
         self.linewidths.extend_from_slice(&other.linewidths);
+        self.hit_regions.extend(
+            other
+                .hit_regions
+                .iter()
+                .map(|region| region.transformed(transform)),
+        );
+        if let Some(bbox) = other.bbox {
+            let bbox = match transform {
+                Some(transform) => transform.to_kurbo().transform_rect_bbox(bbox),
+                None => bbox,
+            };
+            self.add_bbox(bbox);
+        }
+        let scale = transform.map_or(1.0, |transform| transform.to_kurbo().determinant().abs());
+        self.path_areas.merge(&other.path_areas, scale);
+    }
+
+    /// Returns the deepest nesting of clips in the encoding.
+    pub fn max_clip_depth(&self) -> u32 {
+        let mut depth = 0u32;
+        let mut max_depth = 0;
+        for tag in &self.draw_tags {
+            match *tag {
+                DrawTag::BEGIN_CLIP => {
+                    depth += 1;
+                    max_depth = max_depth.max(depth);
+                }
+                DrawTag::END_CLIP => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        max_depth
+    }
+
+    /// Replaces the part of the streams which starts at `start` and has the lengths
+    /// `removed`, such as an encoding which was appended there, with `other`, with an
+    /// optional transform. The patches and shared paths after the replaced part are
+    /// moved along with the draw data, color stops, paths and path data they refer to.
+    ///
+    /// The bounding box is not changed, as the bounding box of the rest of the
+    /// encoding can't be recovered from it.
+    pub fn splice(
+        &mut self,
+        start: &StreamLengths,
+        removed: &StreamLengths,
+        other: &Self,
+        transform: &Option<Transform>,
+    ) {
+        fn replace<T: Copy>(stream: &mut Vec<T>, start: usize, len: usize, with: &[T]) {
+            stream.splice(start..start + len, with.iter().copied());
+        }
+        fn moved(patch: &Patch, draw_data: isize, stops: isize) -> Patch {
+            match patch {
+                Patch::Ramp {
+                    offset,
+                    stops: range,
+                    space,
+                } => Patch::Ramp {
+                    offset: (*offset as isize + draw_data) as usize,
+                    stops: (range.start as isize + stops) as usize
+                        ..(range.end as isize + stops) as usize,
+                    space: *space,
+                },
+            }
+        }
+        replace(
+            &mut self.path_tags,
+            start.path_tags,
+            removed.path_tags,
+            &other.path_tags,
+        );
+        replace(
+            &mut self.path_data,
+            start.path_data,
+            removed.path_data,
+            &other.path_data,
+        );
+        replace(
+            &mut self.draw_tags,
+            start.draw_tags,
+            removed.draw_tags,
+            &other.draw_tags,
+        );
+        replace(
+            &mut self.draw_data,
+            start.draw_data,
+            removed.draw_data,
+            &other.draw_data,
+        );
+        replace(
+            &mut self.color_stops,
+            start.color_stops,
+            removed.color_stops,
+            &other.color_stops,
+        );
+        replace(
+            &mut self.linewidths,
+            start.linewidths,
+            removed.linewidths,
+            &other.linewidths,
+        );
+        let transforms = start.transforms..start.transforms + removed.transforms;
+        if let Some(transform) = *transform {
+            self.transforms
+                .splice(transforms, other.transforms.iter().map(|x| transform * *x));
+        } else {
+            self.transforms
+                .splice(transforms, other.transforms.iter().copied());
+        }
+        let draw_data_delta = other.draw_data.len() as isize - removed.draw_data as isize;
+        let stops_delta = other.color_stops.len() as isize - removed.color_stops as isize;
+        let following = self.patches[start.patches + removed.patches..]
+            .iter()
+            .map(|patch| moved(patch, draw_data_delta, stops_delta))
+            .collect::<Vec<_>>();
+        self.patches.truncate(start.patches);
+        self.patches.extend(
+            other
+                .patches
+                .iter()
+                .map(|patch| moved(patch, start.draw_data as isize, start.color_stops as isize)),
+        );
+        self.patches.extend(following);
+        let path_delta = other.n_paths as i64 - removed.n_paths as i64;
+        let path_data_delta = other.path_data.len() as i64 - removed.path_data as i64;
+        let shift = |shared: &SharedPath, paths: i64, path_data: i64| SharedPath {
+            path_ix: (shared.path_ix as i64 + paths) as u32,
+            data_offset: (shared.data_offset as i64 + path_data) as u32,
+        };
+        let following = self.shared_paths[start.shared_paths + removed.shared_paths..]
+            .iter()
+            .map(|shared| shift(shared, path_delta, path_data_delta))
+            .collect::<Vec<_>>();
+        self.shared_paths.truncate(start.shared_paths);
+        self.shared_paths.extend(
+            other
+                .shared_paths
+                .iter()
+                .map(|shared| shift(shared, start.n_paths as i64, start.path_data as i64)),
+        );
+        self.shared_paths.extend(following);
+        let hit_regions = start.hit_regions..start.hit_regions + removed.hit_regions;
+        self.hit_regions.splice(
+            hit_regions,
+            other
+                .hit_regions
+                .iter()
+                .map(|region| region.transformed(transform)),
+        );
+        self.n_paths = self.n_paths - removed.n_paths + other.n_paths;
+        self.n_path_segments =
+            self.n_path_segments - removed.n_path_segments + other.n_path_segments;
+        self.n_clips = self.n_clips - removed.n_clips + other.n_clips;
+    }
+
+    /// Appends another encoding which was reset as a scene rather than as a
+    /// fragment, with an optional transform.
+    ///
+    /// The streams of a scene begin with a transform and line width that have no
+    /// corresponding path tags. Tags are pushed for them here so that they apply to
+    /// the appended paths, rather than offsetting every transform and line width
+    /// that follows.
+    pub fn append_scene(&mut self, other: &Self, transform: &Option<Transform>) {
+        if other.is_empty() {
+            return;
+        }
+        self.path_tags.push(PathTag::TRANSFORM);
+        self.path_tags.push(PathTag::LINEWIDTH);
+        self.append(other, transform);
+    }
+}
+
+/// Lengths of the streams of an encoding, and its counts of paths, segments and
+/// clips, for finding encodings which were appended to another with
+/// [`Encoding::splice`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamLengths {
+    /// Number of path tags.
+    pub path_tags: usize,
+    /// Size of the path data stream in bytes.
+    pub path_data: usize,
+    /// Number of shared paths.
+    pub shared_paths: usize,
+    /// Number of draw tags.
+    pub draw_tags: usize,
+    /// Size of the draw data stream in bytes.
+    pub draw_data: usize,
+    /// Number of patches.
+    pub patches: usize,
+    /// Number of color stops.
+    pub color_stops: usize,
+    /// Number of transforms.
+    pub transforms: usize,
+    /// Number of line widths.
+    pub linewidths: usize,
+    /// Number of hit regions.
+    pub hit_regions: usize,
+    /// Number of encoded paths.
+    pub n_paths: u32,
+    /// Number of encoded path segments.
+    pub n_path_segments: u32,
+    /// Number of encoded clips/layers.
+    pub n_clips: u32,
+}
+
+impl StreamLengths {
+    /// Returns the lengths of the streams of `encoding`.
+    pub fn of(encoding: &Encoding) -> Self {
+        Self {
+            path_tags: encoding.path_tags.len(),
+            path_data: encoding.path_data.len(),
+            shared_paths: encoding.shared_paths.len(),
+            draw_tags: encoding.draw_tags.len(),
+            draw_data: encoding.draw_data.len(),
+            patches: encoding.patches.len(),
+            color_stops: encoding.color_stops.len(),
+            transforms: encoding.transforms.len(),
+            linewidths: encoding.linewidths.len(),
+            hit_regions: encoding.hit_regions.len(),
+            n_paths: encoding.n_paths,
+            n_path_segments: encoding.n_path_segments,
+            n_clips: encoding.n_clips,
+        }
+    }
+
+    /// Adds the lengths of `other`, giving the lengths of the two encodings appended
+    /// to each other.
+    pub fn add(&mut self, other: &Self) {
+        self.path_tags += other.path_tags;
+        self.path_data += other.path_data;
+        self.shared_paths += other.shared_paths;
+        self.draw_tags += other.draw_tags;
+        self.draw_data += other.draw_data;
+        self.patches += other.patches;
+        self.color_stops += other.color_stops;
+        self.transforms += other.transforms;
+        self.linewidths += other.linewidths;
+        self.hit_regions += other.hit_regions;
+        self.n_paths += other.n_paths;
+        self.n_path_segments += other.n_path_segments;
+        self.n_clips += other.n_clips;
     }
 }
 
@@ -127,6 +573,33 @@ impl Encoding {
         }
     }
 
+    /// Returns the tag of the topmost hit region which contains `point`.
+    pub fn hit_test(&self, point: Point) -> Option<u32> {
+        self.hit_regions
+            .iter()
+            .rev()
+            .find(|region| {
+                let bbox = region.bbox;
+                point.x >= bbox.x0 && point.x <= bbox.x1 && point.y >= bbox.y0 && point.y <= bbox.y1
+            })
+            .map(|region| region.tag)
+    }
+
+    /// Extends the bounding box of the encoding to include `bbox`.
+    pub fn add_bbox(&mut self, bbox: Rect) {
+        self.bbox = Some(match self.bbox {
+            Some(current) => current.union(bbox),
+            None => bbox,
+        });
+    }
+
+    /// Extends the bounding box of the encoding to include `bbox`, the transformed
+    /// bounding box of a drawn path, and counts its area.
+    pub fn add_path_bbox(&mut self, bbox: Rect) {
+        self.add_bbox(bbox);
+        self.path_areas.add(bbox.area());
+    }
+
     /// Returns an encoder for encoding a path. If `is_fill` is true, all subpaths will
     /// be automatically closed.
     pub fn encode_path(&mut self, is_fill: bool) -> PathEncoder {
@@ -147,8 +620,118 @@ impl Encoding {
         encoder.finish(true) != 0
     }
 
-    /// Encodes a brush with an optional alpha modifier.
+    /// Encodes a shape like [`encode_shape`](Self::encode_shape), approximating it to
+    /// within `tolerance` units if it is not made of Bézier segments.
+    pub fn encode_shape_with_tolerance(
+        &mut self,
+        shape: &impl Shape,
+        is_fill: bool,
+        tolerance: f64,
+    ) -> bool {
+        let mut encoder = self.encode_path(is_fill);
+        encoder.shape_with_tolerance(shape, tolerance);
+        encoder.finish(true) != 0
+    }
+
+    /// Encodes a path from segments which were previously encoded with a
+    /// [`PathEncoder`] whose path marker was omitted. Returns true if a non-zero
+    /// number of segments were encoded.
+    pub fn encode_path_segments(&mut self, tags: &[PathTag], data: &[u8], n_segments: u32) -> bool {
+        if n_segments == 0 {
+            return false;
+        }
+        self.path_tags.extend_from_slice(tags);
+        self.path_tags.push(PathTag::PATH);
+        self.path_data.extend_from_slice(data);
+        self.n_path_segments += n_segments;
+        self.n_paths += 1;
+        true
+    }
+
+    /// Encodes a path from segments like [`encode_path_segments`](Self::encode_path_segments),
+    /// but reads their points from `data_offset` in the path data stream, where an
+    /// earlier path with the same segments stored them, instead of copying them.
+    /// Returns true if a non-zero number of segments were encoded.
+    pub fn encode_shared_path_segments(
+        &mut self,
+        tags: &[PathTag],
+        data_offset: usize,
+        n_segments: u32,
+    ) -> bool {
+        if n_segments == 0 {
+            return false;
+        }
+        self.shared_paths.push(SharedPath {
+            path_ix: self.n_paths,
+            data_offset: data_offset as u32,
+        });
+        self.path_tags.extend_from_slice(tags);
+        self.path_tags.push(PathTag::PATH);
+        self.n_path_segments += n_segments;
+        self.n_paths += 1;
+        true
+    }
+
+    /// Returns the size in bytes of the stream of path data offsets uploaded with the
+    /// encoding, which has an offset for each path if any of them are shared.
+    pub(crate) fn path_offsets_size(&self) -> usize {
+        if self.shared_paths.is_empty() {
+            0
+        } else {
+            self.n_paths as usize * 4
+        }
+    }
+
+    /// Returns where the points of each path are in the path data stream, and the
+    /// number of words of points of the paths which are not shared, which the path
+    /// data stream holds in order.
+    ///
+    /// Segments after the last path marker are counted as part of the points which
+    /// are not shared, but not as a path.
+    pub fn path_data_ranges(&self) -> (Vec<PathDataRange>, u32) {
+        let mut ranges = Vec::with_capacity(self.n_paths as usize);
+        let mut shared = self.shared_paths.iter().peekable();
+        let mut counted = 0u32;
+        let mut unshared = 0u32;
+        let mut words = 0u32;
+        for tag in &self.path_tags {
+            words = words.wrapping_add(tag.point_words());
+            if *tag == PathTag::PATH {
+                let path_ix = ranges.len() as u32;
+                let start = match shared.next_if(|shared| shared.path_ix == path_ix) {
+                    Some(shared) => shared.data_offset / 4,
+                    None => {
+                        let start = unshared;
+                        unshared = unshared.wrapping_add(words);
+                        start
+                    }
+                };
+                ranges.push(PathDataRange {
+                    start,
+                    counted_start: counted,
+                    words,
+                });
+                counted = counted.wrapping_add(words);
+                words = 0;
+            }
+        }
+        (ranges, unshared.wrapping_add(words))
+    }
+
+    /// Encodes a brush with an optional alpha modifier. Gradients are interpolated in
+    /// sRGB.
     pub fn encode_brush<'b>(&mut self, brush: impl Into<BrushRef<'b>>, alpha: f32) {
+        self.encode_brush_in_space(brush, alpha, InterpolationSpace::default());
+    }
+
+    /// Encodes a brush with an optional alpha modifier, interpolating gradients in
+    /// the given color space.
+    pub fn encode_brush_in_space<'b>(
+        &mut self,
+        brush: impl Into<BrushRef<'b>>,
+        alpha: f32,
+        space: InterpolationSpace,
+    ) {
         use super::math::point_to_f32;
         match brush.into() {
             BrushRef::Solid(color) => {
@@ -166,10 +749,12 @@ impl Encoding {
                             index: 0,
                             p0: point_to_f32(start),
                             p1: point_to_f32(end),
+                            extend: 0,
                         },
                         gradient.stops.iter().copied(),
                         alpha,
                         gradient.extend,
+                        space,
                     );
                 }
                 GradientKind::Radial {
@@ -189,10 +774,28 @@ impl Encoding {
                         gradient.stops.iter().copied(),
                         alpha,
                         gradient.extend,
+                        space,
                     );
                 }
-                GradientKind::Sweep { .. } => {
-                    todo!("sweep gradients aren't supported yet!")
+                GradientKind::Sweep {
+                    center,
+                    start_angle,
+                    end_angle,
+                } => {
+                    use std::f32::consts::TAU;
+                    self.encode_sweep_gradient(
+                        DrawSweepGradient {
+                            index: 0,
+                            p0: point_to_f32(center),
+                            t0: start_angle / TAU,
+                            t1: end_angle / TAU,
+                            extend: 0,
+                        },
+                        gradient.stops.iter().copied(),
+                        alpha,
+                        gradient.extend,
+                        space,
+                    );
                 }
             },
             BrushRef::Image(_) => {
@@ -210,13 +813,15 @@ impl Encoding {
     /// Encodes a linear gradient brush.
     pub fn encode_linear_gradient(
         &mut self,
-        gradient: DrawLinearGradient,
+        mut gradient: DrawLinearGradient,
         color_stops: impl Iterator<Item = ColorStop>,
         alpha: f32,
-        _extend: Extend,
+        extend: Extend,
+        space: InterpolationSpace,
     ) {
-        self.add_ramp(color_stops, alpha);
+        self.add_ramp(color_stops, alpha, space);
         self.draw_tags.push(DrawTag::LINEAR_GRADIENT);
+        gradient.extend = extend as u32;
         self.draw_data
             .extend_from_slice(bytemuck::bytes_of(&gradient));
     }
@@ -228,13 +833,52 @@ impl Encoding {
         color_stops: impl Iterator<Item = ColorStop>,
         alpha: f32,
         _extend: Extend,
+        space: InterpolationSpace,
     ) {
-        self.add_ramp(color_stops, alpha);
+        self.add_ramp(color_stops, alpha, space);
         self.draw_tags.push(DrawTag::RADIAL_GRADIENT);
         self.draw_data
             .extend_from_slice(bytemuck::bytes_of(&gradient));
     }
 
+    /// Encodes a sweep gradient brush. The angles are measured clockwise from the
+    /// positive x axis (in a y down coordinate system), in turns.
+    pub fn encode_sweep_gradient(
+        &mut self,
+        mut gradient: DrawSweepGradient,
+        color_stops: impl Iterator<Item = ColorStop>,
+        alpha: f32,
+        extend: Extend,
+        space: InterpolationSpace,
+    ) {
+        self.add_ramp(color_stops, alpha, space);
+        self.draw_tags.push(DrawTag::SWEEP_GRADIENT);
+        gradient.extend = extend as u32;
+        self.draw_data
+            .extend_from_slice(bytemuck::bytes_of(&gradient));
+    }
+
+    /// Encodes a blurred rounded rectangle brush. The path it fills should cover
+    /// the rectangle and the extent of the blur, with a transform that places the
+    /// center of the rectangle at the origin.
+    pub fn encode_blurred_rounded_rect(&mut self, blur: DrawBlurRect) {
+        self.draw_tags.push(DrawTag::BLUR_RECT);
+        self.draw_data.extend_from_slice(bytemuck::bytes_of(&blur));
+    }
+
+    /// Composites the brush encoded last with `blend_mode` instead of source over.
+    ///
+    /// The blend mode is applied to the brush against what is already drawn, without
+    /// a layer, so it must have a separable mix mode and source over composition.
+    pub fn encode_blend(&mut self, blend_mode: BlendMode) {
+        use super::DrawBeginClip;
+        if let Some(tag) = self.draw_tags.last_mut() {
+            *tag = tag.blended();
+            let blend = DrawBeginClip::new(blend_mode, 1.0).blend_mode;
+            self.draw_data.extend_from_slice(bytemuck::bytes_of(&blend));
+        }
+    }
+
     /// Encodes a begin clip command.
     pub fn encode_begin_clip(&mut self, blend_mode: BlendMode, alpha: f32) {
         use super::DrawBeginClip;
@@ -244,6 +888,16 @@ impl Encoding {
         self.n_clips += 1;
     }
 
+    /// Encodes a begin clip command for a mask layer, which multiplies what is under
+    /// it by the alpha or luminance of its content when it ends.
+    pub fn encode_begin_mask(&mut self, kind: MaskKind) {
+        use super::DrawBeginClip;
+        self.draw_tags.push(DrawTag::BEGIN_CLIP);
+        self.draw_data
+            .extend_from_slice(bytemuck::bytes_of(&DrawBeginClip::mask(kind)));
+        self.n_clips += 1;
+    }
+
     /// Encodes an end clip command.
     pub fn encode_end_clip(&mut self) {
         self.draw_tags.push(DrawTag::END_CLIP);
@@ -260,7 +914,12 @@ impl Encoding {
         self.path_tags.swap(len - 1, len - 2);
     }
 
-    fn add_ramp(&mut self, color_stops: impl Iterator<Item = ColorStop>, alpha: f32) {
+    fn add_ramp(
+        &mut self,
+        color_stops: impl Iterator<Item = ColorStop>,
+        alpha: f32,
+        space: InterpolationSpace,
+    ) {
         let offset = self.draw_data.len();
         let stops_start = self.color_stops.len();
         if alpha != 1.0 {
@@ -274,6 +933,7 @@ impl Encoding {
         self.patches.push(Patch::Ramp {
             offset,
             stops: stops_start..self.color_stops.len(),
+            space,
         });
     }
 }
@@ -46,6 +46,8 @@ pub struct Layout {
     pub transform_base: u32,
     /// Start of linewidth stream.
     pub linewidth_base: u32,
+    /// Start of the stream of path data offsets, or 0 if no paths are shared.
+    pub path_offset_base: u32,
 }
 
 /// Scene configuration.
@@ -70,6 +72,19 @@ pub struct Config {
     pub segments_size: u32,
     /// Size of per-tile command list buffer allocation (in u32s).
     pub ptcl_size: u32,
+    /// Horizontal position of the target region within the output texture in pixels.
+    pub target_x: u32,
+    /// Vertical position of the target region within the output texture in pixels.
+    pub target_y: u32,
+}
+
+impl Config {
+    /// Deepest nesting of clips and layers that can be rendered.
+    ///
+    /// The clip stages find the enclosing clip of each draw object within a stack
+    /// held by a single workgroup, so clips nested more deeply than that workgroup's
+    /// size would be matched with the wrong parents.
+    pub const MAX_CLIP_DEPTH: u32 = 256;
 }
 
 /// Packed encoding of scene data.
@@ -134,7 +149,20 @@ impl PackedEncoding {
     /// Returns the linewidth stream.
     pub fn linewidths(&self) -> &[f32] {
         let start = self.layout.linewidth_base as usize * 4;
-        bytemuck::cast_slice(&self.data[start..])
+        let end = match self.layout.path_offset_base {
+            0 => self.data.len(),
+            base => base as usize * 4,
+        };
+        bytemuck::cast_slice(&self.data[start..end])
+    }
+
+    /// Returns the stream of path data offsets, which is empty if no paths are shared.
+    /// See [`Layout::path_offset_base`].
+    pub fn path_offsets(&self) -> &[u32] {
+        match self.layout.path_offset_base {
+            0 => &[],
+            base => bytemuck::cast_slice(&self.data[base as usize * 4..]),
+        }
     }
 }
 
@@ -160,7 +188,8 @@ impl PackedEncoding {
             + slice_size_in_bytes(&encoding.draw_tags)
             + slice_size_in_bytes(&encoding.draw_data)
             + slice_size_in_bytes(&encoding.transforms)
-            + slice_size_in_bytes(&encoding.linewidths);
+            + slice_size_in_bytes(&encoding.linewidths)
+            + encoding.path_offsets_size();
         data.reserve(capacity);
         layout.path_tag_base = size_to_words(data.len());
         data.extend_from_slice(bytemuck::cast_slice(&encoding.path_tags));
@@ -181,8 +210,12 @@ impl PackedEncoding {
             let mut pos = 0;
             for patch in &encoding.patches {
                 let (offset, value) = match patch {
-                    Patch::Ramp { offset, stops } => {
-                        let ramp_id = resource_cache.add_ramp(&stop_data[stops.clone()]);
+                    Patch::Ramp {
+                        offset,
+                        stops,
+                        space,
+                    } => {
+                        let ramp_id = resource_cache.add_ramp(&stop_data[stops.clone()], *space);
                         (*offset, ramp_id)
                     }
                 };
@@ -204,6 +237,17 @@ impl PackedEncoding {
         // Linewidth stream
         layout.linewidth_base = size_to_words(data.len());
         data.extend_from_slice(bytemuck::cast_slice(&encoding.linewidths));
+        // Path data offset stream
+        if !encoding.shared_paths.is_empty() {
+            layout.path_offset_base = size_to_words(data.len());
+            // The offset of each path, added to where the path tag monoid places its
+            // points, gives where they actually are
+            let (ranges, _) = encoding.path_data_ranges();
+            for range in ranges {
+                let offset = range.start.wrapping_sub(range.counted_start);
+                data.extend_from_slice(bytemuck::bytes_of(&offset));
+            }
+        }
     }
 }
 
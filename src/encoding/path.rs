@@ -69,14 +69,14 @@ impl PathTag {
     /// This is equivalent to (PathSegmentType::CUBIC_TO | PathTag::F32_BIT).
     pub const CUBIC_TO_F32: Self = Self(0xb);
 
-    /// 16-bit integral line segment.
-    pub const LINE_TO_I16: Self = Self(0x1);
+    /// 16-bit floating point line segment.
+    pub const LINE_TO_F16: Self = Self(0x1);
 
-    /// 16-bit integral quadratic segment.
-    pub const QUAD_TO_I16: Self = Self(0x2);
+    /// 16-bit floating point quadratic segment.
+    pub const QUAD_TO_F16: Self = Self(0x2);
 
-    /// 16-bit integral cubic segment.
-    pub const CUBIC_TO_I16: Self = Self(0x3);
+    /// 16-bit floating point cubic segment.
+    pub const CUBIC_TO_F16: Self = Self(0x3);
 
     /// Transform marker.
     pub const TRANSFORM: Self = Self(0x20);
@@ -88,7 +88,7 @@ impl PathTag {
     pub const LINEWIDTH: Self = Self(0x40);
 
     /// Bit for path segments that are represented as f32 values. If unset
-    /// they are represented as i16.
+    /// they are represented as f16.
     const F32_BIT: u8 = 0x8;
 
     /// Bit that marks a segment that is the end of a subpath.
@@ -121,6 +121,21 @@ impl PathTag {
     pub fn path_segment_type(self) -> PathSegmentType {
         PathSegmentType(self.0 & Self::SEGMENT_MASK)
     }
+
+    /// Returns the number of words of path data the path tag monoid counts for the
+    /// tag. Each point of a segment takes 2 words in f32 or 1 word in f16, and a
+    /// segment which ends a subpath also counts the first point of the next one.
+    pub fn point_words(self) -> u32 {
+        if !self.is_path_segment() {
+            return 0;
+        }
+        let n_points = (self.path_segment_type().0 + self.is_subpath_end() as u8) as u32;
+        if self.is_f32() {
+            n_points * 2
+        } else {
+            n_points
+        }
+    }
 }
 
 /// Monoid for the path tag stream.
@@ -188,6 +203,40 @@ pub struct PathBbox {
     pub trans_ix: u32,
 }
 
+/// Largest magnitude of a coordinate after [`PathValidation::Sanitize`].
+pub const MAX_COORDINATE: f64 = 1.0e9;
+
+/// How a [`PathEncoder`] treats shapes with coordinates which can't be rendered.
+///
+/// Non-finite coordinates can make the GPU stages produce garbage tiles or even hang,
+/// so they should never reach the encoding.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PathValidation {
+    /// Panics on any element which [`Sanitize`](Self::Sanitize) would change.
+    Strict,
+    /// Drops elements with non-finite coordinates, and clamps the coordinates of the
+    /// others to ±[`MAX_COORDINATE`].
+    #[default]
+    Sanitize,
+    /// Encodes coordinates as they are, for shapes which are known to be valid.
+    Off,
+}
+
+/// How a [`PathEncoder`] stores the points of a path.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// 32-bit floating point coordinates.
+    #[default]
+    F32,
+    /// 16-bit floating point coordinates relative to an origin, which halves the size
+    /// of the path data.
+    ///
+    /// Half precision floats have 11 significant bits, so each coordinate is rounded
+    /// by up to 1/2048 of its distance from the origin. This is only suitable for
+    /// paths which are small on screen, such as the features of a map tile.
+    F16,
+}
+
 /// Encoder for path segments.
 pub struct PathEncoder<'a> {
     tags: &'a mut Vec<PathTag>,
@@ -198,6 +247,9 @@ pub struct PathEncoder<'a> {
     state: PathState,
     n_encoded_segments: u32,
     is_fill: bool,
+    validation: PathValidation,
+    precision: Precision,
+    origin: [f32; 2],
 }
 
 #[derive(PartialEq)]
@@ -226,26 +278,43 @@ impl<'a> PathEncoder<'a> {
             state: PathState::Start,
             n_encoded_segments: 0,
             is_fill,
+            validation: PathValidation::default(),
+            precision: Precision::default(),
+            origin: [0.0, 0.0],
         }
     }
 
+    /// Sets how shapes encoded with [`shape`](Self::shape) are validated. The other
+    /// methods always encode coordinates as they are.
+    pub fn set_validation(&mut self, validation: PathValidation) {
+        self.validation = validation;
+    }
+
+    /// Sets how the points of the path are stored. With [`Precision::F16`] they are
+    /// stored relative to `origin`, so the path must be drawn with a transform which
+    /// translates by `origin`.
+    ///
+    /// This must be called before any segments are encoded.
+    pub fn set_precision(&mut self, precision: Precision, origin: [f32; 2]) {
+        self.precision = precision;
+        self.origin = origin;
+    }
+
     /// Encodes a move, starting a new subpath.
     pub fn move_to(&mut self, x: f32, y: f32) {
         if self.is_fill {
             self.close();
         }
-        let buf = [x, y];
-        let bytes = bytemuck::bytes_of(&buf);
-        self.first_point = buf;
+        self.first_point = [x, y];
         if self.state == PathState::MoveTo {
-            let new_len = self.data.len() - 8;
+            let new_len = self.data.len() - self.point_size();
             self.data.truncate(new_len);
         } else if self.state == PathState::NonemptySubpath {
             if let Some(tag) = self.tags.last_mut() {
                 tag.set_subpath_end();
             }
         }
-        self.data.extend_from_slice(bytes);
+        self.push_point(x, y);
         self.state = PathState::MoveTo;
     }
 
@@ -260,10 +329,8 @@ impl<'a> PathEncoder<'a> {
             }
             self.move_to(self.first_point[0], self.first_point[1]);
         }
-        let buf = [x, y];
-        let bytes = bytemuck::bytes_of(&buf);
-        self.data.extend_from_slice(bytes);
-        self.tags.push(PathTag::LINE_TO_F32);
+        self.push_point(x, y);
+        self.push_tag(PathTag::LINE_TO_F32);
         self.state = PathState::NonemptySubpath;
         self.n_encoded_segments += 1;
     }
@@ -277,10 +344,9 @@ impl<'a> PathEncoder<'a> {
             }
             self.move_to(self.first_point[0], self.first_point[1]);
         }
-        let buf = [x1, y1, x2, y2];
-        let bytes = bytemuck::bytes_of(&buf);
-        self.data.extend_from_slice(bytes);
-        self.tags.push(PathTag::QUAD_TO_F32);
+        self.push_point(x1, y1);
+        self.push_point(x2, y2);
+        self.push_tag(PathTag::QUAD_TO_F32);
         self.state = PathState::NonemptySubpath;
         self.n_encoded_segments += 1;
     }
@@ -294,10 +360,10 @@ impl<'a> PathEncoder<'a> {
             }
             self.move_to(self.first_point[0], self.first_point[1]);
         }
-        let buf = [x1, y1, x2, y2, x3, y3];
-        let bytes = bytemuck::bytes_of(&buf);
-        self.data.extend_from_slice(bytes);
-        self.tags.push(PathTag::CUBIC_TO_F32);
+        self.push_point(x1, y1);
+        self.push_point(x2, y2);
+        self.push_point(x3, y3);
+        self.push_tag(PathTag::CUBIC_TO_F32);
         self.state = PathState::NonemptySubpath;
         self.n_encoded_segments += 1;
     }
@@ -307,7 +373,7 @@ impl<'a> PathEncoder<'a> {
         match self.state {
             PathState::Start => return,
             PathState::MoveTo => {
-                let new_len = self.data.len() - 8;
+                let new_len = self.data.len() - self.point_size();
                 self.data.truncate(new_len);
                 self.state = PathState::Start;
                 return;
@@ -315,27 +381,51 @@ impl<'a> PathEncoder<'a> {
             PathState::NonemptySubpath => (),
         }
         let len = self.data.len();
-        if len < 8 {
+        let size = self.point_size();
+        if len < size {
             // can't happen
             return;
         }
-        let first_bytes = bytemuck::bytes_of(&self.first_point);
-        if &self.data[len - 8..len] != first_bytes {
-            self.data.extend_from_slice(first_bytes);
+        // Compare the encoded points, as they may be rounded
+        self.push_point(self.first_point[0], self.first_point[1]);
+        if self.data[len - size..len] != self.data[len..] {
             let mut tag = PathTag::LINE_TO_F32;
             tag.set_subpath_end();
-            self.tags.push(tag);
+            self.push_tag(tag);
             self.n_encoded_segments += 1;
-        } else if let Some(tag) = self.tags.last_mut() {
-            tag.set_subpath_end();
+        } else {
+            self.data.truncate(len);
+            if let Some(tag) = self.tags.last_mut() {
+                tag.set_subpath_end();
+            }
         }
         self.state = PathState::Start;
     }
 
-    /// Encodes a shape.
+    /// Encodes a shape, approximating it to within 0.1 units if it is not made of
+    /// Bézier segments.
     pub fn shape(&mut self, shape: &impl Shape) {
+        self.shape_with_tolerance(shape, 0.1);
+    }
+
+    /// Encodes a shape, approximating it to within `tolerance` units if it is not
+    /// made of Bézier segments.
+    pub fn shape_with_tolerance(&mut self, shape: &impl Shape, tolerance: f64) {
         use peniko::kurbo::PathEl;
-        for el in shape.path_elements(0.1) {
+        for (ix, el) in shape.path_elements(tolerance).enumerate() {
+            let el = match self.validation {
+                PathValidation::Off => el,
+                PathValidation::Sanitize => match sanitize(el) {
+                    Some(el) => el,
+                    None => continue,
+                },
+                PathValidation::Strict => {
+                    if sanitize(el) != Some(el) {
+                        panic!("path element {ix} ({el:?}) has an invalid coordinate");
+                    }
+                    el
+                }
+            };
             match el {
                 PathEl::MoveTo(p0) => self.move_to(p0.x as f32, p0.y as f32),
                 PathEl::LineTo(p0) => self.line_to(p0.x as f32, p0.y as f32),
@@ -365,7 +455,7 @@ impl<'a> PathEncoder<'a> {
             self.close();
         }
         if self.state == PathState::MoveTo {
-            let new_len = self.data.len() - 8;
+            let new_len = self.data.len() - self.point_size();
             self.data.truncate(new_len);
         }
         if self.n_encoded_segments != 0 {
@@ -380,4 +470,175 @@ impl<'a> PathEncoder<'a> {
         }
         self.n_encoded_segments
     }
+
+    /// Returns the size of an encoded point in bytes.
+    fn point_size(&self) -> usize {
+        match self.precision {
+            Precision::F32 => 8,
+            Precision::F16 => 4,
+        }
+    }
+
+    fn push_point(&mut self, x: f32, y: f32) {
+        match self.precision {
+            Precision::F32 => self.data.extend_from_slice(bytemuck::bytes_of(&[x, y])),
+            Precision::F16 => {
+                let point = [
+                    f32_to_f16(x - self.origin[0]),
+                    f32_to_f16(y - self.origin[1]),
+                ];
+                self.data.extend_from_slice(bytemuck::bytes_of(&point));
+            }
+        }
+    }
+
+    /// Pushes a segment tag, given as its f32 variant, in the encoder's precision.
+    fn push_tag(&mut self, tag: PathTag) {
+        self.tags.push(match self.precision {
+            Precision::F32 => tag,
+            Precision::F16 => PathTag(tag.0 & !PathTag::F32_BIT),
+        });
+    }
+}
+
+/// Converts a value to the bits of the nearest half precision float, rounding ties to
+/// even. Values too large for half precision become infinite.
+fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity, or a quiet NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal, including the implicit leading bit
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let rounded = (mantissa + (1 << (shift - 1)) - 1 + ((mantissa >> shift) & 1)) >> shift;
+        return sign | rounded as u16;
+    }
+    // A carry out of the mantissa while rounding correctly increments the exponent
+    let rounded = (mantissa + 0xfff + ((mantissa >> 13) & 1)) >> 13;
+    let half = ((exponent as u32) << 10) + rounded;
+    if half >= 0x7c00 {
+        sign | 0x7c00
+    } else {
+        sign | half as u16
+    }
+}
+
+/// Returns the element with its coordinates clamped to ±[`MAX_COORDINATE`], or `None`
+/// if any of them is not finite.
+fn sanitize(mut el: peniko::kurbo::PathEl) -> Option<peniko::kurbo::PathEl> {
+    use peniko::kurbo::PathEl;
+    match &mut el {
+        PathEl::MoveTo(p0) | PathEl::LineTo(p0) => clamp_point(p0)?,
+        PathEl::QuadTo(p0, p1) => {
+            clamp_point(p0)?;
+            clamp_point(p1)?;
+        }
+        PathEl::CurveTo(p0, p1, p2) => {
+            clamp_point(p0)?;
+            clamp_point(p1)?;
+            clamp_point(p2)?;
+        }
+        PathEl::ClosePath => {}
+    }
+    Some(el)
+}
+
+fn clamp_point(p: &mut peniko::kurbo::Point) -> Option<()> {
+    if !(p.x.is_finite() && p.y.is_finite()) {
+        return None;
+    }
+    p.x = p.x.clamp(-MAX_COORDINATE, MAX_COORDINATE);
+    p.y = p.y.clamp(-MAX_COORDINATE, MAX_COORDINATE);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_conversion_matches_ieee_bits() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(2.5), 0x4100);
+        // The largest finite half, and the smallest value that overflows it
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(65519.0), 0x7bff);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(-1e10), 0xfc00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        // The smallest normal and subnormal halves
+        assert_eq!(f32_to_f16(2f32.powi(-14)), 0x0400);
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(3.0 * 2f32.powi(-24)), 0x0003);
+        assert_eq!(f32_to_f16(2f32.powi(-30)), 0x0000);
+    }
+
+    #[test]
+    fn f16_conversion_rounds_to_nearest_even() {
+        let ulp = 2f32.powi(-10);
+        // Halfway between 1 and the next half rounds down to the even mantissa, and
+        // halfway above that rounds up
+        assert_eq!(f32_to_f16(1.0 + 0.5 * ulp), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 1.5 * ulp), 0x3c02);
+        assert_eq!(f32_to_f16(1.0 + 0.51 * ulp), 0x3c01);
+        assert_eq!(f32_to_f16(1.0 + 0.49 * ulp), 0x3c00);
+        // A carry out of the mantissa increments the exponent
+        assert_eq!(f32_to_f16(2.0 - 0.5 * ulp), 0x4000);
+        // Subnormals round the same way
+        let min = 2f32.powi(-24);
+        assert_eq!(f32_to_f16(0.5 * min), 0x0000);
+        assert_eq!(f32_to_f16(1.5 * min), 0x0002);
+        assert_eq!(f32_to_f16(2.5 * min), 0x0002);
+        // Rounding up from the largest subnormal gives the smallest normal
+        assert_eq!(f32_to_f16(2f32.powi(-14) - 0.5 * min), 0x0400);
+    }
+
+    #[test]
+    fn f16_conversion_keeps_nan() {
+        for nan in [f32::NAN, -f32::NAN, f32::from_bits(0x7f80_0001)] {
+            let half = f32_to_f16(nan);
+            assert_eq!(half & 0x7c00, 0x7c00);
+            assert_ne!(half & 0x03ff, 0);
+        }
+    }
+
+    #[test]
+    fn f16_points_are_relative_to_origin() {
+        let mut tags = Vec::new();
+        let mut data = Vec::new();
+        let (mut n_segments, mut n_paths) = (0, 0);
+        let mut encoder =
+            PathEncoder::new(&mut tags, &mut data, &mut n_segments, &mut n_paths, false);
+        encoder.set_precision(Precision::F16, [100.0, 200.0]);
+        encoder.move_to(100.0, 200.0);
+        encoder.line_to(101.0, 202.5);
+        encoder.line_to(99.0, 200.0);
+        assert_eq!(encoder.finish(true), 2);
+        let tags = tags.iter().map(|tag| tag.0).collect::<Vec<_>>();
+        let end = PathTag::LINE_TO_F16.0 | PathTag::SUBPATH_END_BIT;
+        assert_eq!(tags, [PathTag::LINE_TO_F16.0, end, PathTag::PATH.0]);
+        let words = data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(words, [0x0000, 0x0000, 0x3c00, 0x4100, 0xbc00, 0x0000]);
+        assert_eq!((n_segments, n_paths), (2, 1));
+    }
 }
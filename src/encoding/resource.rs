@@ -21,8 +21,15 @@ use std::ops::Range;
 
 use peniko::{Color, ColorStop, ColorStops};
 
-const N_SAMPLES: usize = 512;
-const RETAINED_COUNT: usize = 64;
+/// Number of samples in each gradient ramp.
+///
+/// This is enough for interpolation in RGB spaces, but spaces which interpolate hue
+/// change color faster along the ramp, and gradients which are wide on screen may
+/// need more samples to avoid banding. This must be kept in sync with `GRADIENT_WIDTH`
+/// in shader/fine.wgsl.
+pub const RAMP_WIDTH: usize = 512;
+/// Number of consecutive packs a ramp can go unused before its row is freed.
+const MAX_UNUSED_EPOCHS: u64 = 64;
 
 /// Token for ensuring that an encoded scene matches the current state
 /// of a resource cache.
@@ -56,9 +63,33 @@ impl ResourceCache {
         Token(self.ramps.epoch)
     }
 
-    pub(crate) fn add_ramp(&mut self, stops: &[ColorStop]) -> u32 {
-        self.ramps.add(stops)
+    pub(crate) fn add_ramp(&mut self, stops: &[ColorStop], space: InterpolationSpace) -> u32 {
+        self.ramps.add(stops, space)
     }
+
+    /// Returns counters describing how well gradient ramps are being reused.
+    pub fn ramp_stats(&self) -> RampCacheStats {
+        RampCacheStats {
+            rows: self.ramps.height(),
+            live_ramps: self.ramps.map.len() as u32,
+            ..self.ramps.stats
+        }
+    }
+}
+
+/// Counters for the gradient ramp cache, from [`ResourceCache::ramp_stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RampCacheStats {
+    /// Rows in the ramp texture, including freed rows which are waiting for reuse.
+    pub rows: u32,
+    /// Ramps currently held by the cache.
+    pub live_ramps: u32,
+    /// Ramps which were found in the cache.
+    pub hits: u64,
+    /// Ramps which had to be generated.
+    pub misses: u64,
+    /// Ramps which were freed after going unused.
+    pub evictions: u64,
 }
 
 #[derive(Clone)]
@@ -70,61 +101,101 @@ pub enum Patch {
         offset: usize,
         /// Range of the gradient stops in the resource set.
         stops: Range<usize>,
+        /// Color space the ramp is interpolated in.
+        space: InterpolationSpace,
     },
 }
 
+/// Color space in which the colors of a gradient are interpolated between its stops.
+///
+/// [`peniko::Gradient`] has no interpolation space, so it is chosen when the gradient
+/// is encoded, for example with
+/// [`SceneBuilder::set_interpolation_space`](crate::SceneBuilder::set_interpolation_space).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum InterpolationSpace {
+    /// Interpolates the gamma encoded sRGB components, as CSS does by default.
+    #[default]
+    Srgb = 0,
+    /// Interpolates linear light sRGB components.
+    LinearSrgb = 1,
+    /// Interpolates in the perceptual Oklab space, which avoids the dark or muddy
+    /// midpoints of sRGB interpolation.
+    Oklab = 2,
+    /// Interpolates the lightness, chroma and hue of Oklab, taking the shorter way
+    /// around the hue circle.
+    Oklch = 3,
+}
+
+impl InterpolationSpace {
+    /// Returns the space with the given discriminant.
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::Srgb,
+            1 => Self::LinearSrgb,
+            2 => Self::Oklab,
+            3 => Self::Oklch,
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Default)]
 struct RampCache {
     epoch: u64,
-    map: HashMap<ColorStops, (u32, u64)>,
+    map: HashMap<(ColorStops, InterpolationSpace), (u32, u64)>,
     data: Vec<u32>,
+    /// Rows below the end of `data` which are no longer used by any ramp.
+    free: Vec<u32>,
+    stats: RampCacheStats,
 }
 
 impl RampCache {
     pub fn advance(&mut self) {
         self.epoch += 1;
-        if self.map.len() > RETAINED_COUNT {
-            self.map
-                .retain(|_key, value| value.0 < RETAINED_COUNT as u32);
-            self.data.truncate(RETAINED_COUNT * N_SAMPLES);
-        }
+        let epoch = self.epoch;
+        let free = &mut self.free;
+        let len = self.map.len();
+        self.map.retain(|_key, (id, last_used)| {
+            let keep = *last_used + MAX_UNUSED_EPOCHS >= epoch;
+            if !keep {
+                free.push(*id);
+            }
+            keep
+        });
+        self.stats.evictions += (len - self.map.len()) as u64;
+        // Shrink the ramp texture past any free rows at its end
+        let height = self.map.values().map(|(id, _)| id + 1).max().unwrap_or(0);
+        self.data.truncate(height as usize * RAMP_WIDTH);
+        self.free.retain(|id| *id < height);
+        // Reuse the lowest free rows first, to keep the texture compact
+        self.free.sort_unstable_by(|a, b| b.cmp(a));
     }
 
-    pub fn add(&mut self, stops: &[ColorStop]) -> u32 {
-        if let Some(entry) = self.map.get_mut(stops) {
+    pub fn add(&mut self, stops: &[ColorStop], space: InterpolationSpace) -> u32 {
+        let key = (stops.into(), space);
+        if let Some(entry) = self.map.get_mut(&key) {
+            self.stats.hits += 1;
             entry.1 = self.epoch;
-            entry.0
-        } else if self.map.len() < RETAINED_COUNT {
-            let id = (self.data.len() / N_SAMPLES) as u32;
-            self.data.extend(make_ramp(stops));
-            self.map.insert(stops.into(), (id, self.epoch));
+            return entry.0;
+        }
+        self.stats.misses += 1;
+        let id = if let Some(id) = self.free.pop() {
+            let start = id as usize * RAMP_WIDTH;
+            for (dst, src) in self.data[start..start + RAMP_WIDTH]
+                .iter_mut()
+                .zip(make_ramp(stops, space))
+            {
+                *dst = src;
+            }
             id
         } else {
-            let mut reuse = None;
-            for (stops, (id, epoch)) in &self.map {
-                if *epoch + 2 < self.epoch {
-                    reuse = Some((stops.to_owned(), *id));
-                    break;
-                }
-            }
-            if let Some((old_stops, id)) = reuse {
-                self.map.remove(&old_stops);
-                let start = id as usize * N_SAMPLES;
-                for (dst, src) in self.data[start..start + N_SAMPLES]
-                    .iter_mut()
-                    .zip(make_ramp(stops))
-                {
-                    *dst = src;
-                }
-                self.map.insert(stops.into(), (id, self.epoch));
-                id
-            } else {
-                let id = (self.data.len() / N_SAMPLES) as u32;
-                self.data.extend(make_ramp(stops));
-                self.map.insert(stops.into(), (id, self.epoch));
-                id
-            }
-        }
+            let id = (self.data.len() / RAMP_WIDTH) as u32;
+            self.data.extend(make_ramp(stops, space));
+            id
+        };
+        self.map.insert(key, (id, self.epoch));
+        id
     }
 
     pub fn data(&self) -> &[u32] {
@@ -132,28 +203,28 @@ impl RampCache {
     }
 
     pub fn width(&self) -> u32 {
-        N_SAMPLES as u32
+        RAMP_WIDTH as u32
     }
 
     pub fn height(&self) -> u32 {
-        (self.data.len() / N_SAMPLES) as u32
+        (self.data.len() / RAMP_WIDTH) as u32
     }
 }
 
-fn make_ramp(stops: &[ColorStop]) -> impl Iterator<Item = u32> + '_ {
+fn make_ramp(stops: &[ColorStop], space: InterpolationSpace) -> impl Iterator<Item = u32> + '_ {
     let mut last_u = 0.0;
-    let mut last_c = ColorF64::from_color(stops[0].color);
+    let mut last_c = ColorF64::from_color(stops[0].color, space);
     let mut this_u = last_u;
     let mut this_c = last_c;
     let mut j = 0;
-    (0..N_SAMPLES).map(move |i| {
-        let u = (i as f64) / (N_SAMPLES - 1) as f64;
+    (0..RAMP_WIDTH).map(move |i| {
+        let u = (i as f64) / (RAMP_WIDTH - 1) as f64;
         while u > this_u {
             last_u = this_u;
             last_c = this_c;
             if let Some(s) = stops.get(j + 1) {
                 this_u = s.offset as f64;
-                this_c = ColorF64::from_color(s.color);
+                this_c = ColorF64::from_color(s.color, space);
                 j += 1;
             } else {
                 break;
@@ -163,43 +234,124 @@ fn make_ramp(stops: &[ColorStop]) -> impl Iterator<Item = u32> + '_ {
         let c = if du < 1e-9 {
             this_c
         } else {
-            last_c.lerp(&this_c, (u - last_u) / du)
+            last_c.lerp(&this_c, (u - last_u) / du, space)
         };
-        c.as_premul_u32()
+        c.as_premul_u32(space)
     })
 }
 
+/// A color with straight alpha, with its first three components in an interpolation
+/// space.
 #[derive(Copy, Clone, Debug)]
 struct ColorF64([f64; 4]);
 
 impl ColorF64 {
-    fn from_color(color: Color) -> Self {
-        Self([
+    fn from_color(color: Color, space: InterpolationSpace) -> Self {
+        let srgb = [
             color.r as f64 / 255.0,
             color.g as f64 / 255.0,
             color.b as f64 / 255.0,
-            color.a as f64 / 255.0,
-        ])
+        ];
+        let [c0, c1, c2] = match space {
+            InterpolationSpace::Srgb => srgb,
+            InterpolationSpace::LinearSrgb => srgb.map(srgb_to_linear),
+            InterpolationSpace::Oklab => linear_to_oklab(srgb.map(srgb_to_linear)),
+            InterpolationSpace::Oklch => {
+                let [l, a, b] = linear_to_oklab(srgb.map(srgb_to_linear));
+                [l, a.hypot(b), b.atan2(a)]
+            }
+        };
+        Self([c0, c1, c2, color.a as f64 / 255.0])
     }
 
-    fn lerp(&self, other: &Self, a: f64) -> Self {
+    fn lerp(&self, other: &Self, a: f64, space: InterpolationSpace) -> Self {
         fn l(x: f64, y: f64, a: f64) -> f64 {
             x * (1.0 - a) + y * a
         }
+        let hue = if space == InterpolationSpace::Oklch {
+            use std::f64::consts::{PI, TAU};
+            // Gray has no hue, so keep the hue of the other color rather than
+            // sweeping through unrelated hues
+            const ACHROMATIC: f64 = 1e-4;
+            let (mut h0, mut h1) = (self.0[2], other.0[2]);
+            if self.0[1] < ACHROMATIC {
+                h0 = h1;
+            } else if other.0[1] < ACHROMATIC {
+                h1 = h0;
+            }
+            // Take the shorter way around
+            let mut dh = (h1 - h0) % TAU;
+            if dh > PI {
+                dh -= TAU;
+            } else if dh < -PI {
+                dh += TAU;
+            }
+            h0 + dh * a
+        } else {
+            l(self.0[2], other.0[2], a)
+        };
         Self([
             l(self.0[0], other.0[0], a),
             l(self.0[1], other.0[1], a),
-            l(self.0[2], other.0[2], a),
+            hue,
             l(self.0[3], other.0[3], a),
         ])
     }
 
-    fn as_premul_u32(&self) -> u32 {
-        let a = self.0[3].clamp(0.0, 1.0);
-        let r = ((self.0[0] * a).clamp(0.0, 1.0) * 255.0) as u32;
-        let g = ((self.0[1] * a).clamp(0.0, 1.0) * 255.0) as u32;
-        let b = ((self.0[2] * a).clamp(0.0, 1.0) * 255.0) as u32;
+    fn as_premul_u32(&self, space: InterpolationSpace) -> u32 {
+        let [c0, c1, c2, a] = self.0;
+        let srgb = match space {
+            InterpolationSpace::Srgb => [c0, c1, c2],
+            InterpolationSpace::LinearSrgb => [c0, c1, c2].map(linear_to_srgb),
+            InterpolationSpace::Oklab => oklab_to_linear([c0, c1, c2]).map(linear_to_srgb),
+            InterpolationSpace::Oklch => {
+                oklab_to_linear([c0, c1 * c2.cos(), c1 * c2.sin()]).map(linear_to_srgb)
+            }
+        };
+        let a = a.clamp(0.0, 1.0);
+        let r = ((srgb[0] * a).clamp(0.0, 1.0) * 255.0) as u32;
+        let g = ((srgb[1] * a).clamp(0.0, 1.0) * 255.0) as u32;
+        let b = ((srgb[2] * a).clamp(0.0, 1.0) * 255.0) as u32;
         let a = (a * 255.0) as u32;
         r | (g << 8) | (b << 16) | (a << 24)
     }
 }
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// See https://bottosson.github.io/posts/oklab/
+fn linear_to_oklab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+fn oklab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ]
+}
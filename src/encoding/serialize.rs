@@ -0,0 +1,450 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Serialization of encodings to and from bytes.
+//!
+//! The format is a header of [`MAGIC`] followed by a little endian `u32`
+//! [`VERSION`], then the counts and bounding box of the encoding, then each stream
+//! as a `u32` element count followed by its elements. Streams which are uploaded
+//! to the GPU as they are (path tags and data, draw tags and data, transforms and
+//! line widths) are stored in their in-memory representation, as are the shared
+//! paths, so the format is only portable between little endian hosts. The
+//! hit regions follow the color stops, and the counts of the histogram of path
+//! areas follow them.
+
+use std::fmt;
+
+use bytemuck::Pod;
+use peniko::kurbo::Rect;
+use peniko::{Color, ColorStop};
+
+use super::resource::{InterpolationSpace, Patch};
+use super::{DrawTag, Encoding, HitRegion, PathTag};
+
+/// Bytes at the start of every serialized encoding.
+pub const MAGIC: [u8; 4] = *b"VLLO";
+
+/// Current version of the serialized format.
+pub const VERSION: u32 = 6;
+
+/// Error produced when serialized encoding data is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data does not start with [`MAGIC`].
+    BadMagic,
+    /// The data was written with a version of the format which is not supported.
+    UnsupportedVersion(u32),
+    /// The data ended before the encoding was complete.
+    UnexpectedEnd,
+    /// The data was read completely, but doesn't describe a valid encoding.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a serialized vello encoding"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported encoding version {version}")
+            }
+            Self::UnexpectedEnd => write!(f, "unexpected end of encoding data"),
+            Self::Invalid(reason) => write!(f, "invalid encoding: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Encoding {
+    /// Serializes the encoding, including the color stops of its gradients.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        write_u32(&mut out, VERSION);
+        write_u32(&mut out, self.n_paths);
+        write_u32(&mut out, self.n_path_segments);
+        write_u32(&mut out, self.n_clips);
+        match self.bbox {
+            Some(bbox) => {
+                out.push(1);
+                for x in [bbox.x0, bbox.y0, bbox.x1, bbox.y1] {
+                    out.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            None => out.push(0),
+        }
+        write_pod_slice(&mut out, &self.path_tags);
+        write_pod_slice(&mut out, &self.path_data);
+        write_pod_slice(&mut out, &self.shared_paths);
+        write_pod_slice(&mut out, &self.draw_tags);
+        write_pod_slice(&mut out, &self.draw_data);
+        write_pod_slice(&mut out, &self.transforms);
+        write_pod_slice(&mut out, &self.linewidths);
+        write_u32(&mut out, self.patches.len() as u32);
+        for patch in &self.patches {
+            match patch {
+                Patch::Ramp {
+                    offset,
+                    stops,
+                    space,
+                } => {
+                    out.push(0);
+                    write_u32(&mut out, *offset as u32);
+                    write_u32(&mut out, stops.start as u32);
+                    write_u32(&mut out, stops.end as u32);
+                    out.push(*space as u8);
+                }
+            }
+        }
+        write_u32(&mut out, self.color_stops.len() as u32);
+        for stop in &self.color_stops {
+            out.extend_from_slice(&stop.offset.to_le_bytes());
+            let Color { r, g, b, a } = stop.color;
+            out.extend_from_slice(&[r, g, b, a]);
+        }
+        write_u32(&mut out, self.hit_regions.len() as u32);
+        for region in &self.hit_regions {
+            write_u32(&mut out, region.tag);
+            let bbox = region.bbox;
+            for x in [bbox.x0, bbox.y0, bbox.x1, bbox.y1] {
+                out.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        for count in self.path_areas.counts {
+            write_u32(&mut out, count);
+        }
+        out
+    }
+
+    /// Deserializes an encoding which was serialized with [`to_bytes`](Self::to_bytes).
+    /// `is_fragment` must match the value passed to [`reset`](Self::reset) when the
+    /// encoding was built.
+    ///
+    /// The streams are checked for consistency with each other, so that a decoded
+    /// encoding can't cause the renderer to read out of bounds.
+    pub fn from_bytes(bytes: &[u8], is_fragment: bool) -> Result<Self, DecodeError> {
+        let mut reader = Reader(bytes);
+        if reader.take(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let mut encoding = Encoding {
+            n_paths: reader.u32()?,
+            n_path_segments: reader.u32()?,
+            n_clips: reader.u32()?,
+            ..Default::default()
+        };
+        encoding.bbox = match reader.take(1)?[0] {
+            0 => None,
+            1 => {
+                let x0 = reader.f64()?;
+                let y0 = reader.f64()?;
+                let x1 = reader.f64()?;
+                let y1 = reader.f64()?;
+                Some(Rect::new(x0, y0, x1, y1))
+            }
+            _ => return Err(DecodeError::Invalid("bad bounding box flag")),
+        };
+        encoding.path_tags = reader.pod_vec()?;
+        encoding.path_data = reader.pod_vec()?;
+        encoding.shared_paths = reader.pod_vec()?;
+        encoding.draw_tags = reader.pod_vec()?;
+        encoding.draw_data = reader.pod_vec()?;
+        encoding.transforms = reader.pod_vec()?;
+        encoding.linewidths = reader.pod_vec()?;
+        for _ in 0..reader.u32()? {
+            if reader.take(1)?[0] != 0 {
+                return Err(DecodeError::Invalid("unknown patch kind"));
+            }
+            let offset = reader.u32()? as usize;
+            let start = reader.u32()? as usize;
+            let end = reader.u32()? as usize;
+            let space = InterpolationSpace::from_u8(reader.take(1)?[0])
+                .ok_or(DecodeError::Invalid("unknown interpolation space"))?;
+            encoding.patches.push(Patch::Ramp {
+                offset,
+                stops: start..end,
+                space,
+            });
+        }
+        for _ in 0..reader.u32()? {
+            let offset = reader.f32()?;
+            let rgba = reader.take(4)?;
+            encoding.color_stops.push(ColorStop {
+                offset,
+                color: Color::rgba8(rgba[0], rgba[1], rgba[2], rgba[3]),
+            });
+        }
+        for _ in 0..reader.u32()? {
+            let tag = reader.u32()?;
+            let x0 = reader.f64()?;
+            let y0 = reader.f64()?;
+            let x1 = reader.f64()?;
+            let y1 = reader.f64()?;
+            encoding.hit_regions.push(HitRegion {
+                tag,
+                bbox: Rect::new(x0, y0, x1, y1),
+            });
+        }
+        for count in &mut encoding.path_areas.counts {
+            *count = reader.u32()?;
+        }
+        if !reader.0.is_empty() {
+            return Err(DecodeError::Invalid("trailing data"));
+        }
+        encoding.validate(is_fragment)?;
+        Ok(encoding)
+    }
+
+    fn validate(&self, is_fragment: bool) -> Result<(), DecodeError> {
+        let count_tags = |tag: PathTag| self.path_tags.iter().filter(|t| **t == tag).count();
+        let n_segments = self
+            .path_tags
+            .iter()
+            .filter(|tag| tag.is_path_segment())
+            .count();
+        if n_segments != self.n_path_segments as usize {
+            return Err(DecodeError::Invalid("path segment count mismatch"));
+        }
+        let (ranges, unshared_words) = self.path_data_ranges();
+        if unshared_words as usize * 4 != self.path_data.len() {
+            return Err(DecodeError::Invalid("path data size mismatch"));
+        }
+        if count_tags(PathTag::PATH) != self.n_paths as usize
+            || self.draw_tags.len() != self.n_paths as usize
+        {
+            return Err(DecodeError::Invalid("path count mismatch"));
+        }
+        // Shared paths are resolved in order while packing
+        if self
+            .shared_paths
+            .windows(2)
+            .any(|pair| pair[0].path_ix >= pair[1].path_ix)
+            || self
+                .shared_paths
+                .iter()
+                .any(|shared| shared.path_ix >= self.n_paths || shared.data_offset % 4 != 0)
+        {
+            return Err(DecodeError::Invalid("invalid shared path"));
+        }
+        if ranges
+            .iter()
+            .any(|range| range.start as u64 + range.words as u64 > unshared_words as u64)
+        {
+            return Err(DecodeError::Invalid("shared path data out of bounds"));
+        }
+        // Scenes start with a transform and line width which have no tags
+        let n_initial = if is_fragment { 0 } else { 1 };
+        if self.transforms.len() != count_tags(PathTag::TRANSFORM) + n_initial {
+            return Err(DecodeError::Invalid("transform count mismatch"));
+        }
+        if self.linewidths.len() != count_tags(PathTag::LINEWIDTH) + n_initial {
+            return Err(DecodeError::Invalid("line width count mismatch"));
+        }
+        let mut n_clips = 0;
+        let mut draw_data_size = 0;
+        for tag in &self.draw_tags {
+            match tag.without_blend() {
+                DrawTag::COLOR
+                | DrawTag::LINEAR_GRADIENT
+                | DrawTag::RADIAL_GRADIENT
+                | DrawTag::SWEEP_GRADIENT
+                | DrawTag::BLUR_RECT => {}
+                DrawTag::BEGIN_CLIP | DrawTag::END_CLIP if !tag.is_blended() => n_clips += 1,
+                _ => return Err(DecodeError::Invalid("unsupported draw tag")),
+            }
+            draw_data_size += tag.data_size() as usize * 4;
+        }
+        if n_clips != self.n_clips as usize {
+            return Err(DecodeError::Invalid("clip count mismatch"));
+        }
+        if draw_data_size != self.draw_data.len() {
+            return Err(DecodeError::Invalid("draw data size mismatch"));
+        }
+        // Patches are applied in order while packing
+        let mut patch_end = 0;
+        for patch in &self.patches {
+            match patch {
+                Patch::Ramp { offset, stops, .. } => {
+                    if *offset < patch_end
+                        || offset + 4 > self.draw_data.len()
+                        || stops.start > stops.end
+                        || stops.end > self.color_stops.len()
+                    {
+                        return Err(DecodeError::Invalid("patch out of bounds"));
+                    }
+                    patch_end = offset + 4;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_pod_slice<T: Pod>(out: &mut Vec<u8>, slice: &[T]) {
+    write_u32(out, slice.len() as u32);
+    out.extend_from_slice(bytemuck::cast_slice(slice));
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64, DecodeError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn pod_vec<T: Pod>(&mut self) -> Result<Vec<T>, DecodeError> {
+        let len = self.u32()? as usize;
+        let size = len
+            .checked_mul(std::mem::size_of::<T>())
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        // The data may not be aligned for `T`, so copy it element by element
+        Ok(self
+            .take(size)?
+            .chunks_exact(std::mem::size_of::<T>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use peniko::kurbo::{Affine, Circle, Rect};
+    use peniko::{Color, Fill, Gradient, Mix, Stroke};
+
+    use super::*;
+    use crate::{Scene, SceneBuilder};
+
+    fn scene() -> Scene {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        let gradient =
+            Gradient::new_linear((0.0, 0.0), (100.0, 0.0)).with_stops([Color::RED, Color::BLUE]);
+        sb.push_layer(
+            Mix::Multiply,
+            0.5,
+            Affine::IDENTITY,
+            &Rect::new(0.0, 0.0, 200.0, 200.0),
+        );
+        sb.fill(
+            Fill::NonZero,
+            Affine::translate((50.0, 50.0)),
+            &gradient,
+            None,
+            &Circle::new((0.0, 0.0), 40.0),
+        );
+        sb.stroke(
+            &Stroke::new(4.0),
+            Affine::IDENTITY,
+            Color::GREEN,
+            None,
+            &Rect::new(10.0, 10.0, 90.0, 60.0),
+        );
+        sb.pop_layer();
+        sb.finish();
+        scene
+    }
+
+    #[test]
+    fn round_trip() {
+        let scene = scene();
+        let bytes = scene.to_bytes();
+        let decoded = Scene::from_bytes(&bytes).unwrap();
+        let (original, decoded) = (scene.data(), decoded.data());
+        assert_eq!(decoded.n_paths, original.n_paths);
+        assert_eq!(decoded.n_path_segments, original.n_path_segments);
+        assert_eq!(decoded.n_clips, original.n_clips);
+        assert_eq!(decoded.bbox, original.bbox);
+        assert_eq!(decoded.path_data, original.path_data);
+        assert_eq!(decoded.draw_data, original.draw_data);
+        assert_eq!(decoded.color_stops.len(), original.color_stops.len());
+        assert_eq!(decoded.patches.len(), original.patches.len());
+        assert_eq!(decoded.hit_regions, original.hit_regions);
+        assert_eq!(decoded.path_areas, original.path_areas);
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let bytes = Scene::new().to_bytes();
+        assert!(Scene::from_bytes(&bytes).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut bytes = scene().to_bytes();
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Scene::from_bytes(&bytes).err(),
+            Some(DecodeError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = scene().to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(Scene::from_bytes(&bytes).err(), Some(DecodeError::BadMagic));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = scene().to_bytes();
+        for len in [0, 7, bytes.len() / 2, bytes.len() - 1] {
+            assert!(Scene::from_bytes(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn path_data_size_mismatch() {
+        let mut encoding = Encoding::from_bytes(&scene().to_bytes(), false).unwrap();
+        let truncated = encoding.path_data.len() - 4;
+        encoding.path_data.truncate(truncated);
+        assert_eq!(
+            Encoding::from_bytes(&encoding.to_bytes(), false).err(),
+            Some(DecodeError::Invalid("path data size mismatch"))
+        );
+        encoding.path_data.extend_from_slice(&[0; 12]);
+        assert_eq!(
+            Encoding::from_bytes(&encoding.to_bytes(), false).err(),
+            Some(DecodeError::Invalid("path data size mismatch"))
+        );
+    }
+}
@@ -16,20 +16,25 @@
 
 use std::{
     borrow::Cow,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    future::Future,
     num::{NonZeroU32, NonZeroU64},
-    sync::atomic::{AtomicU64, Ordering},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
 };
 
 use futures_intrusive::channel::shared::GenericOneshotReceiver;
-use parking_lot::RawMutex;
+use parking_lot::{Mutex, RawMutex};
 use wgpu::{
     util::DeviceExt, BindGroup, BindGroupLayout, Buffer, BufferAsyncError, BufferSlice,
-    BufferUsages, BufferView, ComputePipeline, Device, Queue, Texture, TextureAspect,
-    TextureFormat, TextureUsages, TextureView, TextureViewDimension,
+    BufferUsages, BufferView, CommandEncoder, ComputePipeline, Device, Queue, Texture,
+    TextureAspect, TextureUsages, TextureView, TextureViewDimension,
 };
 
-pub type Error = Box<dyn std::error::Error>;
+use crate::Error;
 
 #[derive(Clone, Copy)]
 pub struct ShaderId(usize);
@@ -42,11 +47,30 @@ static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
 pub struct Engine {
     shaders: Vec<Shader>,
     pool: ResourcePool,
+    bind_groups: BindGroupCache,
+    submissions: u64,
+    staging: StagingArena,
+    /// Validation error scopes around the shaders added since
+    /// [`capture_shader_errors`](Engine::capture_shader_errors), with their labels.
+    /// The futures aren't `Sync`, so they are behind a mutex, which is only accessed
+    /// mutably.
+    shader_error_scopes: Mutex<Option<Vec<(&'static str, ShaderErrorScope)>>>,
 }
 
+type ShaderErrorScope = Pin<Box<dyn Future<Output = Option<wgpu::Error>> + Send>>;
+
 struct Shader {
     pipeline: ComputePipeline,
     bind_group_layout: BindGroupLayout,
+    info: ShaderInfo,
+}
+
+/// The bindings of a shader, which dispatches of it are validated against.
+struct ShaderInfo {
+    label: &'static str,
+    bind_types: Vec<BindType>,
+    /// Smallest size in bytes of the buffer bound to each slot, where it is known.
+    min_sizes: Vec<u64>,
 }
 
 #[derive(Default)]
@@ -61,7 +85,7 @@ pub struct BufProxy {
     name: &'static str,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ImageFormat {
     Rgba8,
     Bgra8,
@@ -94,8 +118,23 @@ pub enum Command {
     // Maybe use tricks to make more ergonomic?
     // Alternative: provide bufs & images as separate sequences
     Dispatch(ShaderId, (u32, u32, u32), Vec<ResourceProxy>),
+    /// A dispatch whose workgroup counts are read from the buffer at the offset, for
+    /// stages whose size is only known on the GPU.
+    DispatchIndirect(ShaderId, BufProxy, u64, Vec<ResourceProxy>),
     Download(BufProxy),
     Clear(BufProxy, u64, Option<NonZeroU64>),
+    /// A point where the recording can be paused with [`Engine::run_until`].
+    Signpost(&'static str),
+}
+
+/// Progress through a recording which is run in parts, separated by signposts.
+///
+/// The resources used by the parts which have run stay alive until the run is
+/// finished, so later parts see their contents.
+#[derive(Default)]
+pub struct PartialRun {
+    bind_map: BindMap,
+    next_command: usize,
 }
 
 #[derive(Default)]
@@ -121,6 +160,10 @@ pub enum BindType {
 
 struct BindMapBuffer {
     buffer: Buffer,
+    /// Identifies the pooled allocation, which outlives the proxy it is bound to.
+    alloc_id: Id,
+    /// Order in which the buffer was first used in the recording.
+    seq: usize,
     #[cfg_attr(not(feature = "buffer_labels"), allow(unused))]
     label: &'static str,
 }
@@ -128,7 +171,7 @@ struct BindMapBuffer {
 #[derive(Default)]
 struct BindMap {
     buf_map: HashMap<Id, BindMapBuffer>,
-    image_map: HashMap<Id, (Texture, TextureView)>,
+    image_map: HashMap<Id, PooledImage>,
 }
 
 #[derive(Hash, PartialEq, Eq)]
@@ -139,9 +182,126 @@ struct BufferProperties {
     name: &'static str,
 }
 
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+struct ImageProperties {
+    width: u32,
+    height: u32,
+    format: ImageFormat,
+    usages: TextureUsages,
+}
+
+struct PooledImage {
+    props: ImageProperties,
+    texture: Texture,
+    view: TextureView,
+    alloc_id: Id,
+    /// Value of the pool's generation when the image was last returned to it.
+    last_used: u64,
+}
+
+/// Usage of pooled storage buffers, which can also hold indirect dispatch arguments.
+const STORAGE_USAGE: BufferUsages = BufferUsages::COPY_SRC
+    .union(BufferUsages::COPY_DST)
+    .union(BufferUsages::STORAGE)
+    .union(BufferUsages::INDIRECT);
+
+/// Size in bytes of the workgroup counts read by an indirect dispatch.
+const DISPATCH_ARGS_SIZE: u64 = 12;
+
 #[derive(Default)]
 struct ResourcePool {
-    bufs: HashMap<BufferProperties, Vec<Buffer>>,
+    bufs: HashMap<BufferProperties, Vec<(Id, Buffer)>>,
+    images: HashMap<ImageProperties, Vec<PooledImage>>,
+    /// Number of bind maps reaped so far.
+    generation: u64,
+}
+
+/// Number of recordings a pooled image can go unused before it is freed.
+const IMAGE_MAX_AGE: u64 = 4;
+
+/// Bind groups which only refer to pooled resources, reused for as long as the same
+/// allocations are bound to the same shader.
+///
+/// The key is made of allocation ids rather than proxy ids, so an entry stays valid
+/// when its buffers are recycled to different proxies in a later recording.
+#[derive(Default)]
+struct BindGroupCache {
+    groups: HashMap<BindGroupKey, CachedBindGroup>,
+    /// Number of recordings run so far.
+    generation: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct BindGroupKey {
+    shader: usize,
+    allocs: Vec<Id>,
+}
+
+struct CachedBindGroup {
+    bind_group: BindGroup,
+    last_used: u64,
+}
+
+/// Number of recordings a cached bind group can go unused before it is evicted.
+const BIND_GROUP_MAX_AGE: u64 = 4;
+
+/// Error returned in debug builds when a recording is inconsistent with the shaders
+/// it dispatches.
+#[derive(Debug)]
+pub struct InvalidRecording {
+    /// Index of the offending command in the recording.
+    pub command: usize,
+    /// Label of the shader, if the command is a dispatch.
+    pub shader: Option<&'static str>,
+    /// Description of the problem.
+    pub reason: String,
+}
+
+impl std::fmt::Display for InvalidRecording {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.shader {
+            Some(shader) => write!(
+                f,
+                "invalid recording: command {} (dispatch of {shader}): {}",
+                self.command, self.reason
+            ),
+            None => write!(
+                f,
+                "invalid recording: command {}: {}",
+                self.command, self.reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidRecording {}
+
+/// Counters describing how well the engine is reusing resources.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    /// Number of dispatches which reused a cached bind group.
+    pub bind_group_hits: u64,
+    /// Number of dispatches which created a new bind group.
+    pub bind_group_misses: u64,
+    /// Number of bind groups currently cached.
+    pub cached_bind_groups: usize,
+    /// Total size in bytes of the buffers which are held by the pool for reuse.
+    pub retained_buffer_bytes: u64,
+    /// Total size in bytes of the images which are held by the pool for reuse.
+    pub retained_image_bytes: u64,
+    /// Number of command buffers submitted to the queue.
+    pub submissions: u64,
+    /// Number of staging buffers created for uploads. Each recording, or part of a
+    /// partial run, uses one, which is reused once the GPU is done with it, so this
+    /// only grows while more uploads are in flight than ever before.
+    pub staging_buffers: u64,
+    /// Total size in bytes of the staging buffers created for uploads.
+    pub staging_bytes: u64,
+    /// Total size in bytes of the staging buffers which are held for reuse, including
+    /// those in use by submitted work.
+    pub retained_staging_bytes: u64,
 }
 
 impl Engine {
@@ -149,9 +309,78 @@ impl Engine {
         Engine {
             shaders: vec![],
             pool: Default::default(),
+            bind_groups: Default::default(),
+            submissions: 0,
+            staging: Default::default(),
+            shader_error_scopes: Mutex::new(None),
+        }
+    }
+
+    /// Captures validation errors of each shader added after this call separately,
+    /// so that [`shader_error`](Self::shader_error) can tell which shader failed.
+    #[cfg(feature = "hot_reload")]
+    pub fn capture_shader_errors(&mut self) {
+        *self.shader_error_scopes.get_mut() = Some(Vec::new());
+    }
+
+    /// Returns the label and error of the first shader added since
+    /// [`capture_shader_errors`](Self::capture_shader_errors) which failed to compile
+    /// or validate, and stops capturing errors.
+    #[cfg(feature = "hot_reload")]
+    pub async fn shader_error(&mut self) -> Option<(&'static str, wgpu::Error)> {
+        let scopes = self.shader_error_scopes.get_mut().take()?;
+        for (label, scope) in scopes {
+            if let Some(error) = scope.await {
+                return Some((label, error));
+            }
+        }
+        None
+    }
+
+    /// Returns counters for resource reuse since the engine was created.
+    pub fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            bind_group_hits: self.bind_groups.hits,
+            bind_group_misses: self.bind_groups.misses,
+            cached_bind_groups: self.bind_groups.groups.len(),
+            retained_buffer_bytes: self.pool.retained_buffer_bytes(),
+            retained_image_bytes: self.pool.retained_image_bytes(),
+            submissions: self.submissions,
+            staging_buffers: self.staging.created,
+            staging_bytes: self.staging.created_bytes,
+            retained_staging_bytes: self.staging.retained_bytes(),
         }
     }
 
+    /// Frees the images held for reuse, for example after a large resize has left
+    /// images at the old size which will not be used again.
+    pub fn clear_image_pool(&mut self) {
+        self.pool.images.clear();
+        // Cached bind groups would otherwise keep the images alive
+        self.bind_groups.groups.clear();
+    }
+
+    /// Returns the size of the buffer which the pool allocates to hold `size` bytes.
+    pub fn pooled_buffer_size(size: u64) -> u64 {
+        ResourcePool::size_class(size, SIZE_CLASS_BITS)
+    }
+
+    /// Frees every buffer and image held for reuse, and the cached bind groups which
+    /// refer to them. Shaders are kept.
+    pub fn clear_pools(&mut self) {
+        self.pool.bufs.clear();
+        self.staging.clear();
+        self.clear_image_pool();
+    }
+
+    /// Reuses the staging buffers of recordings encoded with
+    /// [`encode_into`](Self::encode_into), which must be called once their encoder has
+    /// been submitted.
+    pub fn after_submit(&mut self) {
+        let buffers = self.staging.external.drain(..).collect::<Vec<_>>();
+        self.staging.map(buffers);
+    }
+
     /// Add a shader.
     ///
     /// This function is somewhat limited, it doesn't apply a label, only allows one bind group,
@@ -166,6 +395,9 @@ impl Engine {
         wgsl: Cow<'static, str>,
         layout: &[BindType],
     ) -> Result<ShaderId, Error> {
+        if self.shader_error_scopes.get_mut().is_some() {
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+        }
         let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some(label),
             source: wgpu::ShaderSource::Wgsl(wgsl),
@@ -234,15 +466,35 @@ impl Engine {
             module: &shader_module,
             entry_point: "main",
         });
+        if let Some(scopes) = self.shader_error_scopes.get_mut() {
+            scopes.push((label, Box::pin(device.pop_error_scope())));
+        }
         let shader = Shader {
             pipeline,
             bind_group_layout,
+            info: ShaderInfo {
+                label,
+                bind_types: layout.to_vec(),
+                min_sizes: vec![0; layout.len()],
+            },
         };
         let id = self.shaders.len();
         self.shaders.push(shader);
         Ok(ShaderId(id))
     }
 
+    /// Returns the types of the slots of a shader.
+    pub fn bind_types(&self, shader: ShaderId) -> &[BindType] {
+        &self.shaders[shader.0].info.bind_types
+    }
+
+    /// Declares the smallest buffer which can be bound to `slot` of a shader, such as
+    /// the size of a uniform struct, so that debug builds reject recordings which bind
+    /// a smaller one.
+    pub fn set_min_binding_size(&mut self, shader: ShaderId, slot: usize, size: u64) {
+        self.shaders[shader.0].info.min_sizes[slot] = size;
+    }
+
     pub fn run_recording(
         &mut self,
         device: &Device,
@@ -250,27 +502,176 @@ impl Engine {
         recording: &Recording,
         external_resources: &[ExternalResource],
     ) -> Result<Downloads, Error> {
+        let mut downloads =
+            self.run_recordings(device, queue, &[(recording, external_resources)])?;
+        Ok(downloads.pop().unwrap())
+    }
+
+    /// Runs several recordings, in order, in a single submission.
+    ///
+    /// The pooled resources used by each recording are returned to the pool before the
+    /// next is encoded, so recordings with similar sizes share their intermediate buffers.
+    pub fn run_recordings(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        recordings: &[(&Recording, &[ExternalResource])],
+    ) -> Result<Vec<Downloads>, Error> {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut downloads = Vec::with_capacity(recordings.len());
+        for (recording, external_resources) in recordings {
+            downloads.push(self.encode_recording(
+                device,
+                &mut encoder,
+                recording,
+                external_resources,
+            )?);
+        }
+        queue.submit(Some(encoder.finish()));
+        self.submissions += 1;
+        self.staging.submitted();
+        Ok(downloads)
+    }
+
+    /// Encodes the commands of a recording into `encoder`, which the caller submits.
+    ///
+    /// The encoder may already hold other work, including earlier recordings, as
+    /// uploads are copied in order with the other commands. Signposts are ignored, and
+    /// the downloads can only be read once the encoder has been submitted.
+    pub fn encode_into(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        recording: &Recording,
+        external_resources: &[ExternalResource],
+    ) -> Result<Downloads, Error> {
+        let downloads = self.encode_recording(device, encoder, recording, external_resources);
+        self.staging.defer_unsubmitted();
+        downloads
+    }
+
+    /// Runs the commands of a recording from where `run` stopped up to the signpost
+    /// `name`, in a single submission, so that the CPU can inspect downloads before
+    /// the rest of the recording is run.
+    ///
+    /// Every part of a run must be given the same recording and external resources.
+    pub fn run_until(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        run: &mut PartialRun,
+        recording: &Recording,
+        external_resources: &[ExternalResource],
+        name: &str,
+    ) -> Result<Downloads, Error> {
+        let end = recording.commands[run.next_command..]
+            .iter()
+            .position(|command| matches!(command, Command::Signpost(signpost) if *signpost == name))
+            .ok_or(Error::Internal("signpost not in the rest of the recording"))?;
+        self.run_part(
+            device,
+            queue,
+            run,
+            recording,
+            external_resources,
+            run.next_command + end + 1,
+        )
+    }
+
+    /// Runs the remaining commands of a recording started with
+    /// [`run_until`](Self::run_until), and returns its resources to the pool.
+    pub fn finish_run(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        mut run: PartialRun,
+        recording: &Recording,
+        external_resources: &[ExternalResource],
+    ) -> Result<Downloads, Error> {
+        let end = recording.commands.len();
+        let downloads =
+            self.run_part(device, queue, &mut run, recording, external_resources, end)?;
+        self.pool.reap_bindmap(run.bind_map);
+        self.bind_groups.finish_recording();
+        Ok(downloads)
+    }
+
+    fn run_part(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        run: &mut PartialRun,
+        recording: &Recording,
+        external_resources: &[ExternalResource],
+        end: usize,
+    ) -> Result<Downloads, Error> {
+        if run.next_command == 0 && cfg!(debug_assertions) {
+            self.validate_recording(recording, external_resources)?;
+        }
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut downloads = Downloads::default();
+        self.encode_commands(
+            device,
+            &mut encoder,
+            &recording.commands[run.next_command..end],
+            external_resources,
+            &mut run.bind_map,
+            &mut downloads,
+        )?;
+        queue.submit(Some(encoder.finish()));
+        self.submissions += 1;
+        self.staging.submitted();
+        run.next_command = end;
+        Ok(downloads)
+    }
+
+    fn encode_recording(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        recording: &Recording,
+        external_resources: &[ExternalResource],
+    ) -> Result<Downloads, Error> {
+        if cfg!(debug_assertions) {
+            self.validate_recording(recording, external_resources)?;
+        }
         let mut bind_map = BindMap::default();
         let mut downloads = Downloads::default();
+        self.encode_commands(
+            device,
+            encoder,
+            &recording.commands,
+            external_resources,
+            &mut bind_map,
+            &mut downloads,
+        )?;
+        self.pool.reap_bindmap(bind_map);
+        self.bind_groups.finish_recording();
+        Ok(downloads)
+    }
 
-        let mut encoder = device.create_command_encoder(&Default::default());
-        for command in &recording.commands {
+    fn encode_commands(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        commands: &[Command],
+        external_resources: &[ExternalResource],
+        bind_map: &mut BindMap,
+        downloads: &mut Downloads,
+    ) -> Result<(), Error> {
+        let mut staging = self.stage_uploads(device, commands);
+        for command in commands {
             match command {
                 Command::Upload(buf_proxy, bytes) => {
-                    let usage =
-                        BufferUsages::COPY_SRC | BufferUsages::COPY_DST | BufferUsages::STORAGE;
-                    let buf = self.pool.get_buf(buf_proxy, usage, device);
-                    // TODO: if buffer is newly created, might be better to make it mapped at creation
-                    // and copy. However, we expect reuse will be most common.
-                    queue.write_buffer(&buf, 0, bytes);
-                    bind_map.insert_buf(buf_proxy, buf);
+                    let (alloc_id, buf) = self.pool.get_buf(buf_proxy, STORAGE_USAGE, device);
+                    staging.copy_to(encoder, &buf, bytes.len());
+                    bind_map.insert_buf(buf_proxy, alloc_id, buf);
                 }
                 Command::UploadUniform(buf_proxy, bytes) => {
                     let usage = BufferUsages::UNIFORM | BufferUsages::COPY_DST;
-                    // Same consideration as above
-                    let buf = self.pool.get_buf(buf_proxy, usage, device);
-                    queue.write_buffer(&buf, 0, bytes);
-                    bind_map.insert_buf(buf_proxy, buf);
+                    let (alloc_id, buf) = self.pool.get_buf(buf_proxy, usage, device);
+                    staging.copy_to(encoder, &buf, bytes.len());
+                    bind_map.insert_buf(buf_proxy, alloc_id, buf);
                 }
                 Command::UploadImage(image_proxy, bytes) => {
                     let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -278,29 +679,11 @@ impl Engine {
                         contents: bytes,
                         usage: wgpu::BufferUsages::COPY_SRC,
                     });
-                    let texture = device.create_texture(&wgpu::TextureDescriptor {
-                        label: None,
-                        size: wgpu::Extent3d {
-                            width: image_proxy.width,
-                            height: image_proxy.height,
-                            depth_or_array_layers: 1,
-                        },
-                        mip_level_count: 1,
-                        sample_count: 1,
-                        dimension: wgpu::TextureDimension::D2,
-                        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                        format: image_proxy.format.to_wgpu(),
-                    });
-                    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
-                        label: None,
-                        dimension: Some(TextureViewDimension::D2),
-                        aspect: TextureAspect::All,
-                        mip_level_count: None,
-                        base_mip_level: 0,
-                        base_array_layer: 0,
-                        array_layer_count: None,
-                        format: Some(TextureFormat::Rgba8Unorm),
-                    });
+                    let image = self.pool.get_image(
+                        image_proxy,
+                        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                        device,
+                    );
                     encoder.copy_buffer_to_texture(
                         wgpu::ImageCopyBuffer {
                             buffer: &buf,
@@ -311,7 +694,7 @@ impl Engine {
                             },
                         },
                         wgpu::ImageCopyTexture {
-                            texture: &texture,
+                            texture: &image.texture,
                             mip_level: 0,
                             origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
                             aspect: TextureAspect::All,
@@ -322,25 +705,53 @@ impl Engine {
                             depth_or_array_layers: 1,
                         },
                     );
-                    bind_map.insert_image(image_proxy.id, texture, texture_view)
+                    bind_map.image_map.insert(image_proxy.id, image);
                 }
-                Command::Dispatch(shader_id, wg_size, bindings) => {
-                    // println!("dispatching {:?} with {} bindings", wg_size, bindings.len());
+                Command::Dispatch(shader_id, _, bindings)
+                | Command::DispatchIndirect(shader_id, _, _, bindings) => {
                     let shader = &self.shaders[shader_id.0];
-                    let bind_group = bind_map.create_bind_group(
-                        device,
-                        &shader.bind_group_layout,
-                        bindings,
-                        external_resources,
-                        &mut self.pool,
-                    )?;
+                    bind_map.allocate(device, bindings, external_resources, &mut self.pool);
+                    let uncached;
+                    let bind_group = match bind_map.cache_key(*shader_id, bindings) {
+                        Some(key) => self.bind_groups.get_or_create(key, || {
+                            bind_map.create_bind_group(
+                                device,
+                                &shader.bind_group_layout,
+                                bindings,
+                                external_resources,
+                            )
+                        })?,
+                        None => {
+                            uncached = bind_map.create_bind_group(
+                                device,
+                                &shader.bind_group_layout,
+                                bindings,
+                                external_resources,
+                            )?;
+                            &uncached
+                        }
+                    };
                     let mut cpass = encoder.begin_compute_pass(&Default::default());
                     cpass.set_pipeline(&shader.pipeline);
-                    cpass.set_bind_group(0, &bind_group, &[]);
-                    cpass.dispatch_workgroups(wg_size.0, wg_size.1, wg_size.2);
+                    cpass.set_bind_group(0, bind_group, &[]);
+                    match command {
+                        Command::DispatchIndirect(_, args, offset, _) => {
+                            let buffer = find_buf(external_resources, args)
+                                .or_else(|| bind_map.buf_map.get(&args.id).map(|buf| &buf.buffer))
+                                .ok_or(Error::Internal("buffer not in map"))?;
+                            cpass.dispatch_workgroups_indirect(buffer, *offset);
+                        }
+                        Command::Dispatch(_, wg_size, _) => {
+                            cpass.dispatch_workgroups(wg_size.0, wg_size.1, wg_size.2);
+                        }
+                        _ => unreachable!(),
+                    }
                 }
                 Command::Download(proxy) => {
-                    let src_buf = bind_map.buf_map.get(&proxy.id).ok_or("buffer not in map")?;
+                    let src_buf = bind_map
+                        .buf_map
+                        .get(&proxy.id)
+                        .ok_or(Error::Internal("buffer not in map"))?;
                     let buf = device.create_buffer(&wgpu::BufferDescriptor {
                         label: Some(proxy.name),
                         size: proxy.size,
@@ -354,14 +765,492 @@ impl Engine {
                     let buffer = bind_map.get_or_create(*proxy, device, &mut self.pool)?;
                     encoder.clear_buffer(buffer, *offset, *size);
                 }
+                Command::Signpost(_) => {}
             }
         }
-        queue.submit(Some(encoder.finish()));
-        self.pool.reap_bindmap(bind_map);
-        Ok(downloads)
+        self.staging.unsubmitted.extend(staging.buffer);
+        Ok(())
     }
 }
 
+impl Engine {
+    /// Packs the data of the buffer uploads in `commands` into a single staging buffer.
+    ///
+    /// Uploads are copied from it in order with the other commands, so they can go to
+    /// pooled buffers which earlier work in the same submission is still using. Writes
+    /// through the queue would happen before the whole submission, and allocate a
+    /// staging buffer each.
+    ///
+    /// Each upload starts at an offset aligned for copies, and uniform uploads at one
+    /// aligned for uniform bindings.
+    fn stage_uploads(&mut self, device: &Device, commands: &[Command]) -> StagingBuffer {
+        let uniform_align = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let (offsets, size) = staging_layout(commands, uniform_align);
+        if size == 0 {
+            return StagingBuffer {
+                buffer: None,
+                offsets,
+                next: 0,
+            };
+        }
+        let buffer = self.staging.take(device, size);
+        {
+            let mut mapped = buffer.slice(..size).get_mapped_range_mut();
+            let uploads = commands.iter().filter_map(|command| match command {
+                Command::Upload(_, bytes) | Command::UploadUniform(_, bytes) => Some(bytes),
+                _ => None,
+            });
+            for (bytes, &offset) in uploads.zip(&offsets) {
+                let start = offset as usize;
+                let end = start + staging_size(bytes.len()) as usize;
+                mapped[start..start + bytes.len()].copy_from_slice(bytes);
+                // The copy includes the padding, which the buffer may hold old data in
+                mapped[start + bytes.len()..end].fill(0);
+            }
+        }
+        buffer.unmap();
+        StagingBuffer {
+            buffer: Some(buffer),
+            offsets,
+            next: 0,
+        }
+    }
+}
+
+/// Returns the offset of each upload of `commands` in their staging buffer, and the
+/// size of the buffer.
+fn staging_layout(commands: &[Command], uniform_align: u64) -> (Vec<u64>, u64) {
+    let mut offsets = vec![];
+    let mut size = 0;
+    for command in commands {
+        let (bytes, align) = match command {
+            Command::Upload(_, bytes) => (bytes, wgpu::COPY_BUFFER_ALIGNMENT),
+            Command::UploadUniform(_, bytes) => (bytes, uniform_align),
+            _ => continue,
+        };
+        size = align_up(size, align);
+        offsets.push(size);
+        size += staging_size(bytes.len());
+    }
+    (offsets, size)
+}
+
+/// Returns the space taken by an upload of `len` bytes in a staging buffer, where
+/// copies must start at aligned offsets and have aligned sizes.
+fn staging_size(len: usize) -> u64 {
+    align_up(len as u64, wgpu::COPY_BUFFER_ALIGNMENT)
+}
+
+fn align_up(x: u64, align: u64) -> u64 {
+    (x + align - 1) / align * align
+}
+
+/// The data of the uploads of a sequence of commands, in the order of the commands.
+struct StagingBuffer {
+    /// The staging buffer, if there is data to upload.
+    buffer: Option<Buffer>,
+    /// Offset of the data of each upload.
+    offsets: Vec<u64>,
+    /// Index of the next upload.
+    next: usize,
+}
+
+impl StagingBuffer {
+    /// Copies the data of the next upload, which is `len` bytes long, to the start of
+    /// `buf`. The copy is rounded up to a multiple of the copy alignment, which `buf`
+    /// must have room for.
+    fn copy_to(&mut self, encoder: &mut CommandEncoder, buf: &Buffer, len: usize) {
+        if let Some(buffer) = &self.buffer {
+            let size = staging_size(len);
+            if size > 0 {
+                debug_assert!(buf.size() >= size, "upload larger than its buffer");
+                encoder.copy_buffer_to_buffer(buffer, self.offsets[self.next], buf, 0, size);
+            }
+            self.next += 1;
+        }
+    }
+}
+
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// The smallest staging buffer which is created, so that recordings with a few
+/// small uploads share a size.
+const STAGING_MIN_SIZE: u64 = 1 << 16;
+/// Maximum number of mapped staging buffers kept for reuse.
+const STAGING_MAX_FREE: usize = 4;
+/// Maximum number of staging buffers kept for recordings encoded into encoders of
+/// the caller, in case [`Engine::after_submit`] is never called.
+const STAGING_MAX_EXTERNAL: usize = 8;
+
+/// Staging buffers for uploads, which are mapped again and reused once the work
+/// which copies from them is done, so that uploads don't allocate every frame.
+#[derive(Default)]
+struct StagingArena {
+    /// Buffers which are mapped, and can be written.
+    free: Vec<Buffer>,
+    /// Buffers used by work which the engine hasn't submitted yet.
+    unsubmitted: Vec<Buffer>,
+    /// Buffers used by work encoded into encoders of the caller, which can only be
+    /// mapped again once the caller has submitted them.
+    external: VecDeque<Buffer>,
+    /// Buffers which are being mapped, with the state of their mapping.
+    mapping: Vec<(Buffer, Arc<AtomicU8>)>,
+    created: u64,
+    created_bytes: u64,
+}
+
+impl StagingArena {
+    /// Returns a mapped buffer of at least `size` bytes, reusing one if possible.
+    fn take(&mut self, device: &Device, size: u64) -> Buffer {
+        self.reclaim();
+        if !self.free.iter().any(|buffer| buffer.size() >= size) && !self.mapping.is_empty() {
+            // Runs the callbacks of the mappings which are done, without waiting
+            device.poll(wgpu::Maintain::Poll);
+            self.reclaim();
+        }
+        let smallest = (self.free.iter().enumerate())
+            .filter(|(_, buffer)| buffer.size() >= size)
+            .min_by_key(|(_, buffer)| buffer.size())
+            .map(|(ix, _)| ix);
+        if let Some(ix) = smallest {
+            return self.free.swap_remove(ix);
+        }
+        let size = size.next_power_of_two().max(STAGING_MIN_SIZE);
+        self.created += 1;
+        self.created_bytes += size;
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: BufferUsages::MAP_WRITE | BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        })
+    }
+
+    /// Moves the buffers which have been mapped again to the free list.
+    fn reclaim(&mut self) {
+        let mut ix = 0;
+        while ix < self.mapping.len() {
+            match self.mapping[ix].1.load(Ordering::Acquire) {
+                MAP_PENDING => ix += 1,
+                state => {
+                    let (buffer, _) = self.mapping.swap_remove(ix);
+                    if state == MAP_DONE {
+                        self.free.push(buffer);
+                    }
+                }
+            }
+        }
+        if self.free.len() > STAGING_MAX_FREE {
+            self.free
+                .sort_by_key(|buffer| std::cmp::Reverse(buffer.size()));
+            self.free.truncate(STAGING_MAX_FREE);
+        }
+    }
+
+    /// Maps the buffers used by work which has just been submitted, so that they can
+    /// be reused once it is done.
+    fn submitted(&mut self) {
+        let buffers = std::mem::take(&mut self.unsubmitted);
+        self.map(buffers);
+    }
+
+    /// Keeps the buffers used by work encoded into an encoder of the caller until
+    /// [`Engine::after_submit`].
+    fn defer_unsubmitted(&mut self) {
+        for buffer in std::mem::take(&mut self.unsubmitted) {
+            if self.external.len() == STAGING_MAX_EXTERNAL {
+                // Freed by wgpu once the work using it is done
+                self.external.pop_front();
+            }
+            self.external.push_back(buffer);
+        }
+    }
+
+    fn map(&mut self, buffers: impl IntoIterator<Item = Buffer>) {
+        for buffer in buffers {
+            let state = Arc::new(AtomicU8::new(MAP_PENDING));
+            let callback_state = state.clone();
+            buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Write, move |result| {
+                    let mapped = if result.is_ok() { MAP_DONE } else { MAP_FAILED };
+                    callback_state.store(mapped, Ordering::Release);
+                });
+            self.mapping.push((buffer, state));
+        }
+    }
+
+    fn retained_bytes(&self) -> u64 {
+        let mapping = self.mapping.iter().map(|(buffer, _)| buffer);
+        (self.free.iter())
+            .chain(&self.unsubmitted)
+            .chain(&self.external)
+            .chain(mapping)
+            .map(|buffer| buffer.size())
+            .sum()
+    }
+
+    /// Frees the buffers which aren't used by work which may not have been submitted.
+    fn clear(&mut self) {
+        self.free.clear();
+        self.mapping.clear();
+    }
+}
+
+impl Engine {
+    fn validate_recording(
+        &self,
+        recording: &Recording,
+        external_resources: &[ExternalResource],
+    ) -> Result<(), InvalidRecording> {
+        let external = external_resources
+            .iter()
+            .map(|resource| match resource {
+                ExternalResource::Buf(proxy, buffer) => External::Buf(*proxy, buffer.size()),
+                ExternalResource::Image(proxy, _) => External::Image(*proxy),
+            })
+            .collect::<Vec<_>>();
+        validate_commands(&self.shaders, &recording.commands, &external)
+    }
+}
+
+/// An external resource, as far as [`validate_commands`] is concerned.
+#[derive(Clone, Copy)]
+enum External {
+    /// A buffer, with the size of the buffer which is bound to the proxy.
+    Buf(BufProxy, u64),
+    Image(ImageProxy),
+}
+
+impl AsRef<ShaderInfo> for Shader {
+    fn as_ref(&self) -> &ShaderInfo {
+        &self.info
+    }
+}
+
+impl AsRef<ShaderInfo> for ShaderInfo {
+    fn as_ref(&self) -> &ShaderInfo {
+        self
+    }
+}
+
+/// Checks that every resource is initialized before it is read, that the resources
+/// bound by each dispatch match the layout of its shader, and that buffers are large
+/// enough for what is copied to and from them, and for the sizes declared with
+/// [`Engine::set_min_binding_size`].
+///
+/// These mistakes are otherwise reported by wgpu, if at all, without saying which
+/// command caused them. The sizes of other bindings aren't checked, as shaders don't
+/// declare the sizes they need.
+fn validate_commands(
+    shaders: &[impl AsRef<ShaderInfo>],
+    commands: &[Command],
+    external: &[External],
+) -> Result<(), InvalidRecording> {
+    let mut bufs = HashSet::new();
+    let mut uniforms = HashSet::new();
+    let mut images = HashSet::new();
+    for resource in external {
+        match resource {
+            External::Buf(proxy, _) => bufs.insert(proxy.id),
+            External::Image(proxy) => images.insert(proxy.id),
+        };
+    }
+    let is_external = |id: Id| {
+        external.iter().any(|resource| match resource {
+            External::Buf(proxy, _) => proxy.id == id,
+            External::Image(proxy) => proxy.id == id,
+        })
+    };
+    // External buffers may be smaller than their proxies, while pooled buffers
+    // are at least as large
+    let buf_size = |proxy: &BufProxy| {
+        external
+            .iter()
+            .find_map(|resource| match resource {
+                External::Buf(external, size) if external.id == proxy.id => Some(*size),
+                _ => None,
+            })
+            .unwrap_or(proxy.size)
+    };
+    for (command_ix, command) in commands.iter().enumerate() {
+        let error = |shader: Option<&'static str>, reason: String| InvalidRecording {
+            command: command_ix,
+            shader,
+            reason,
+        };
+        match command {
+            Command::Upload(proxy, bytes) | Command::UploadUniform(proxy, bytes) => {
+                if staging_size(bytes.len()) > proxy.size {
+                    return Err(error(
+                        None,
+                        format!(
+                            "upload of {} bytes to {}, which is {} bytes",
+                            bytes.len(),
+                            proxy.name,
+                            proxy.size
+                        ),
+                    ));
+                }
+                if matches!(command, Command::Upload(..)) {
+                    bufs.insert(proxy.id);
+                } else {
+                    uniforms.insert(proxy.id);
+                }
+            }
+            Command::UploadImage(proxy, _) => {
+                images.insert(proxy.id);
+            }
+            Command::Clear(proxy, offset, size) => {
+                if uniforms.contains(&proxy.id) {
+                    return Err(error(None, format!("uniform {} is cleared", proxy.name)));
+                }
+                let end = offset + size.map_or(0, |size| size.get());
+                if end > buf_size(proxy) {
+                    return Err(error(
+                        None,
+                        format!(
+                            "clear up to byte {end} of {}, which is {} bytes",
+                            proxy.name,
+                            buf_size(proxy)
+                        ),
+                    ));
+                }
+                bufs.insert(proxy.id);
+            }
+            Command::Signpost(_) => {}
+            Command::Download(proxy) => {
+                if !bufs.contains(&proxy.id) {
+                    return Err(error(
+                        None,
+                        format!("{} is downloaded before it is written", proxy.name),
+                    ));
+                }
+                if proxy.size > buf_size(proxy) {
+                    return Err(error(
+                        None,
+                        format!(
+                            "download of {} bytes from {}, which is {} bytes",
+                            proxy.size,
+                            proxy.name,
+                            buf_size(proxy)
+                        ),
+                    ));
+                }
+            }
+            Command::Dispatch(shader_id, _, bindings)
+            | Command::DispatchIndirect(shader_id, _, _, bindings) => {
+                let shader = shaders[shader_id.0].as_ref();
+                let error = |reason| error(Some(shader.label), reason);
+                if let Command::DispatchIndirect(_, args, offset, _) = command {
+                    if !bufs.contains(&args.id) {
+                        return Err(error(format!(
+                            "dispatch arguments {} are read before they are written",
+                            args.name
+                        )));
+                    }
+                    if offset % 4 != 0 || offset + DISPATCH_ARGS_SIZE > buf_size(args) {
+                        return Err(error(format!(
+                            "dispatch arguments at byte {offset} of {}, which is {} bytes",
+                            args.name,
+                            buf_size(args)
+                        )));
+                    }
+                }
+                if bindings.len() != shader.bind_types.len() {
+                    return Err(error(format!(
+                        "{} resources are bound, but the shader declares {}",
+                        bindings.len(),
+                        shader.bind_types.len()
+                    )));
+                }
+                for (slot, (bind_type, proxy)) in shader.bind_types.iter().zip(bindings).enumerate()
+                {
+                    if let ResourceProxy::Buf(proxy) = proxy {
+                        let min_size = shader.min_sizes[slot];
+                        if buf_size(proxy) < min_size {
+                            return Err(error(format!(
+                                "{} is {} bytes, but slot {slot} needs at least {min_size}",
+                                proxy.name,
+                                buf_size(proxy)
+                            )));
+                        }
+                    }
+                    match (bind_type, proxy) {
+                        (BindType::Buffer, ResourceProxy::Buf(proxy)) => {
+                            if uniforms.contains(&proxy.id) {
+                                return Err(error(format!(
+                                    "uniform {} is bound to storage slot {slot}",
+                                    proxy.name
+                                )));
+                            }
+                        }
+                        (BindType::BufReadOnly, ResourceProxy::Buf(proxy)) => {
+                            if !bufs.contains(&proxy.id) {
+                                return Err(error(format!(
+                                    "{} is bound to read only slot {slot} before it is written",
+                                    proxy.name
+                                )));
+                            }
+                        }
+                        (BindType::Uniform, ResourceProxy::Buf(proxy)) => {
+                            if !uniforms.contains(&proxy.id) && !is_external(proxy.id) {
+                                return Err(error(format!(
+                                        "{} is bound to uniform slot {slot}, but was not uploaded with upload_uniform",
+                                        proxy.name
+                                    )));
+                            }
+                        }
+                        (BindType::Image(format), ResourceProxy::Image(proxy)) => {
+                            // The engine only creates images for sampling
+                            if !is_external(proxy.id) {
+                                return Err(error(format!(
+                                    "storage image in slot {slot} is not an external resource"
+                                )));
+                            }
+                            if proxy.format != *format {
+                                return Err(error(format!(
+                                    "image in slot {slot} has the wrong format"
+                                )));
+                            }
+                        }
+                        (BindType::ImageRead(format), ResourceProxy::Image(proxy)) => {
+                            if !images.contains(&proxy.id) {
+                                return Err(error(format!(
+                                    "image in slot {slot} is read before it is uploaded"
+                                )));
+                            }
+                            if proxy.format != *format {
+                                return Err(error(format!(
+                                    "image in slot {slot} has the wrong format"
+                                )));
+                            }
+                        }
+                        (_, ResourceProxy::Buf(proxy)) => {
+                            return Err(error(format!(
+                                "buffer {} is bound to image slot {slot}",
+                                proxy.name
+                            )));
+                        }
+                        (_, ResourceProxy::Image(_)) => {
+                            return Err(error(format!("image is bound to buffer slot {slot}")));
+                        }
+                    }
+                }
+                // Read/write buffers are assumed to be written by the dispatch
+                for (bind_type, proxy) in shader.bind_types.iter().zip(bindings) {
+                    if let (BindType::Buffer, ResourceProxy::Buf(proxy)) = (bind_type, proxy) {
+                        bufs.insert(proxy.id);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Recording {
     pub fn push(&mut self, cmd: Command) {
         self.commands.push(cmd);
@@ -369,14 +1258,14 @@ impl Recording {
 
     pub fn upload(&mut self, name: &'static str, data: impl Into<Vec<u8>>) -> BufProxy {
         let data = data.into();
-        let buf_proxy = BufProxy::new(data.len() as u64, name);
+        let buf_proxy = BufProxy::new(staging_size(data.len()), name);
         self.push(Command::Upload(buf_proxy, data));
         buf_proxy
     }
 
     pub fn upload_uniform(&mut self, name: &'static str, data: impl Into<Vec<u8>>) -> BufProxy {
         let data = data.into();
-        let buf_proxy = BufProxy::new(data.len() as u64, name);
+        let buf_proxy = BufProxy::new(staging_size(data.len()), name);
         self.push(Command::UploadUniform(buf_proxy, data));
         buf_proxy
     }
@@ -406,10 +1295,35 @@ impl Recording {
         ));
     }
 
+    /// Dispatches `shader` with the workgroup counts stored as three `u32`s at `offset`
+    /// in `args`, which are written by earlier commands.
+    pub fn dispatch_indirect<R>(
+        &mut self,
+        shader: ShaderId,
+        args: BufProxy,
+        offset: u64,
+        resources: R,
+    ) where
+        R: IntoIterator,
+        R::Item: Into<ResourceProxy>,
+    {
+        self.push(Command::DispatchIndirect(
+            shader,
+            args,
+            offset,
+            resources.into_iter().map(|r| r.into()).collect(),
+        ));
+    }
+
     pub fn download(&mut self, buf: BufProxy) {
         self.push(Command::Download(buf));
     }
 
+    /// Marks a point where the recording can be paused, see [`Engine::run_until`].
+    pub fn signpost(&mut self, name: &'static str) {
+        self.push(Command::Signpost(name));
+    }
+
     pub fn clear_all(&mut self, buf: BufProxy) {
         self.push(Command::Clear(buf, 0, None));
     }
@@ -424,6 +1338,11 @@ impl BufProxy {
             name,
         }
     }
+
+    /// Returns the name the buffer was created with.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
 }
 
 impl ImageFormat {
@@ -492,70 +1411,40 @@ impl Id {
 }
 
 impl BindMap {
-    fn insert_buf(&mut self, proxy: &BufProxy, buffer: Buffer) {
+    fn insert_buf(&mut self, proxy: &BufProxy, alloc_id: Id, buffer: Buffer) {
+        let seq = self.buf_map.len();
         self.buf_map.insert(
             proxy.id,
             BindMapBuffer {
                 buffer,
+                alloc_id,
+                seq,
                 label: proxy.name,
             },
         );
     }
 
-    fn insert_image(&mut self, id: Id, image: Texture, image_view: TextureView) {
-        self.image_map.insert(id, (image, image_view));
-    }
-
-    fn create_bind_group(
+    /// Creates the resources for any bindings which don't have one yet.
+    fn allocate(
         &mut self,
         device: &Device,
-        layout: &BindGroupLayout,
         bindings: &[ResourceProxy],
         external_resources: &[ExternalResource],
         pool: &mut ResourcePool,
-    ) -> Result<BindGroup, Error> {
-        // These functions are ugly and linear, but the remap array should generally be
-        // small. Should find a better solution for this.
-        fn find_buf<'a>(
-            resources: &[ExternalResource<'a>],
-            proxy: &BufProxy,
-        ) -> Option<&'a Buffer> {
-            for resource in resources {
-                match resource {
-                    ExternalResource::Buf(p, buf) if p.id == proxy.id => {
-                        return Some(buf);
-                    }
-                    _ => {}
-                }
-            }
-            None
-        }
-        fn find_image<'a>(
-            resources: &[ExternalResource<'a>],
-            proxy: &ImageProxy,
-        ) -> Option<&'a TextureView> {
-            for resource in resources {
-                match resource {
-                    ExternalResource::Image(p, view) if p.id == proxy.id => {
-                        return Some(view);
-                    }
-                    _ => {}
-                }
-            }
-            None
-        }
+    ) {
         for proxy in bindings {
             match proxy {
                 ResourceProxy::Buf(proxy) => {
                     if find_buf(external_resources, proxy).is_some() {
                         continue;
                     }
+                    let seq = self.buf_map.len();
                     if let Entry::Vacant(v) = self.buf_map.entry(proxy.id) {
-                        let usage =
-                            BufferUsages::COPY_SRC | BufferUsages::COPY_DST | BufferUsages::STORAGE;
-                        let buf = pool.get_buf(&proxy, usage, device);
+                        let (alloc_id, buf) = pool.get_buf(proxy, STORAGE_USAGE, device);
                         v.insert(BindMapBuffer {
                             buffer: buf,
+                            alloc_id,
+                            seq,
                             label: proxy.name,
                         });
                     }
@@ -565,34 +1454,39 @@ impl BindMap {
                         continue;
                     }
                     if let Entry::Vacant(v) = self.image_map.entry(proxy.id) {
-                        let texture = device.create_texture(&wgpu::TextureDescriptor {
-                            label: None,
-                            size: wgpu::Extent3d {
-                                width: proxy.width,
-                                height: proxy.height,
-                                depth_or_array_layers: 1,
-                            },
-                            mip_level_count: 1,
-                            sample_count: 1,
-                            dimension: wgpu::TextureDimension::D2,
-                            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                            format: proxy.format.to_wgpu(),
-                        });
-                        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
-                            label: None,
-                            dimension: Some(TextureViewDimension::D2),
-                            aspect: TextureAspect::All,
-                            mip_level_count: None,
-                            base_mip_level: 0,
-                            base_array_layer: 0,
-                            array_layer_count: None,
-                            format: Some(proxy.format.to_wgpu()),
-                        });
-                        v.insert((texture, texture_view));
+                        let usage = TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST;
+                        v.insert(pool.get_image(proxy, usage, device));
                     }
                 }
             }
         }
+    }
+
+    /// Returns the key for caching the bind group of a dispatch, if every binding is a
+    /// pooled resource. This must be called after the bindings have been allocated.
+    fn cache_key(&self, shader_id: ShaderId, bindings: &[ResourceProxy]) -> Option<BindGroupKey> {
+        let allocs = bindings
+            .iter()
+            .map(|proxy| match proxy {
+                ResourceProxy::Buf(proxy) => self.buf_map.get(&proxy.id).map(|buf| buf.alloc_id),
+                ResourceProxy::Image(proxy) => {
+                    self.image_map.get(&proxy.id).map(|image| image.alloc_id)
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(BindGroupKey {
+            shader: shader_id.0,
+            allocs,
+        })
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        bindings: &[ResourceProxy],
+        external_resources: &[ExternalResource],
+    ) -> Result<BindGroup, Error> {
         let entries = bindings
             .iter()
             .enumerate()
@@ -608,7 +1502,7 @@ impl BindMap {
                 }
                 ResourceProxy::Image(proxy) => {
                     let view = find_image(external_resources, proxy)
-                        .or_else(|| self.image_map.get(&proxy.id).map(|v| &v.1))
+                        .or_else(|| self.image_map.get(&proxy.id).map(|image| &image.view))
                         .unwrap();
                     Ok(wgpu::BindGroupEntry {
                         binding: i as u32,
@@ -631,14 +1525,16 @@ impl BindMap {
         device: &Device,
         pool: &mut ResourcePool,
     ) -> Result<&Buffer, Error> {
+        let seq = self.buf_map.len();
         match self.buf_map.entry(proxy.id) {
             Entry::Occupied(occupied) => Ok(&occupied.into_mut().buffer),
             Entry::Vacant(vacant) => {
-                let usage = BufferUsages::COPY_SRC | BufferUsages::COPY_DST | BufferUsages::STORAGE;
-                let buf = pool.get_buf(&proxy, usage, device);
+                let (alloc_id, buf) = pool.get_buf(&proxy, STORAGE_USAGE, device);
                 Ok(&vacant
                     .insert(BindMapBuffer {
                         buffer: buf,
+                        alloc_id,
+                        seq,
                         label: proxy.name,
                     })
                     .buffer)
@@ -647,6 +1543,72 @@ impl BindMap {
     }
 }
 
+// These functions are ugly and linear, but the remap array should generally be
+// small. Should find a better solution for this.
+fn find_buf<'a>(resources: &[ExternalResource<'a>], proxy: &BufProxy) -> Option<&'a Buffer> {
+    for resource in resources {
+        match resource {
+            ExternalResource::Buf(p, buf) if p.id == proxy.id => {
+                return Some(buf);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn find_image<'a>(
+    resources: &[ExternalResource<'a>],
+    proxy: &ImageProxy,
+) -> Option<&'a TextureView> {
+    for resource in resources {
+        match resource {
+            ExternalResource::Image(p, view) if p.id == proxy.id => {
+                return Some(view);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+impl BindGroupCache {
+    fn get_or_create(
+        &mut self,
+        key: BindGroupKey,
+        create: impl FnOnce() -> Result<BindGroup, Error>,
+    ) -> Result<&BindGroup, Error> {
+        let generation = self.generation;
+        match self.groups.entry(key) {
+            Entry::Occupied(occupied) => {
+                self.hits += 1;
+                let cached = occupied.into_mut();
+                cached.last_used = generation;
+                Ok(&cached.bind_group)
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                let bind_group = create()?;
+                Ok(&vacant
+                    .insert(CachedBindGroup {
+                        bind_group,
+                        last_used: generation,
+                    })
+                    .bind_group)
+            }
+        }
+    }
+
+    /// Evicts bind groups which haven't been used recently, as they keep their
+    /// buffers alive.
+    fn finish_recording(&mut self) {
+        let generation = self.generation;
+        self.groups
+            .retain(|_, cached| generation - cached.last_used < BIND_GROUP_MAX_AGE);
+        self.generation += 1;
+    }
+}
+
 pub struct DownloadsMapped<'a>(
     HashMap<
         Id,
@@ -669,15 +1631,90 @@ impl Downloads {
         }
         DownloadsMapped(map)
     }
+
+    /// Reads a downloaded buffer, waiting for the device to finish the work which
+    /// writes it.
+    ///
+    /// Returns `None` if the buffer can't be read without yielding to the event loop,
+    /// which is always the case on the web.
+    pub fn read_blocking(
+        &self,
+        device: &Device,
+        proxy: BufProxy,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let buf = self
+            .buf_map
+            .get(&proxy.id)
+            .ok_or(Error::Internal("buffer not in map"))?;
+        let buf_slice = buf.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buf_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        match receiver.try_recv() {
+            Ok(result) => result?,
+            Err(_) => return Ok(None),
+        }
+        let data = buf_slice.get_mapped_range().to_vec();
+        buf.unmap();
+        Ok(Some(data))
+    }
+
+    /// Reads back the contents of a buffer once the work which writes it is done.
+    ///
+    /// Unlike [`read_blocking`](Self::read_blocking), this doesn't block the thread, so
+    /// it also works on the web.
+    pub async fn read(&self, device: &Device, proxy: BufProxy) -> Result<Vec<u8>, Error> {
+        let buf = self
+            .buf_map
+            .get(&proxy.id)
+            .ok_or(Error::Internal("buffer not in map"))?;
+        let buf_slice = buf.slice(..);
+        map_buffer(device, buf_slice, wgpu::MapMode::Read).await?;
+        let data = buf_slice.get_mapped_range().to_vec();
+        buf.unmap();
+        Ok(data)
+    }
+}
+
+/// Maps a buffer once the work using it is done, without blocking the thread.
+///
+/// On native, wgpu only runs mapping callbacks when the device is polled, so the
+/// device is polled without waiting each time the future is, and the future wakes
+/// itself until the buffer is mapped. On the web, polling does nothing, and the
+/// callback is run by the browser.
+pub async fn map_buffer(
+    device: &Device,
+    slice: BufferSlice<'_>,
+    mode: wgpu::MapMode,
+) -> Result<(), Error> {
+    let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+    slice.map_async(mode, move |v| {
+        let _ = sender.send(v);
+    });
+    let mut receive = Box::pin(receiver.receive());
+    let result = std::future::poll_fn(|context| {
+        device.poll(wgpu::Maintain::Poll);
+        let poll = receive.as_mut().poll(context);
+        if poll.is_pending() && cfg!(not(target_arch = "wasm32")) {
+            context.waker().wake_by_ref();
+        }
+        poll
+    })
+    .await;
+    result.ok_or(Error::Internal("mapping callback was dropped"))??;
+    Ok(())
 }
 
 impl<'a> DownloadsMapped<'a> {
     pub async fn get_mapped(&self, proxy: BufProxy) -> Result<BufferView, Error> {
-        let (slice, recv) = self.0.get(&proxy.id).ok_or("buffer not in map")?;
+        let (slice, recv) = self
+            .0
+            .get(&proxy.id)
+            .ok_or(Error::Internal("buffer not in map"))?;
         if let Some(recv_result) = recv.receive().await {
             recv_result?;
         } else {
-            return Err("channel was closed".into());
+            return Err(Error::Internal("channel was closed"));
         }
         Ok(slice.get_mapped_range())
     }
@@ -687,7 +1724,9 @@ const SIZE_CLASS_BITS: u32 = 1;
 
 impl ResourcePool {
     /// Get a buffer from the pool or create one.
-    fn get_buf(&mut self, proxy: &BufProxy, usage: BufferUsages, device: &Device) -> Buffer {
+    ///
+    /// The returned id identifies the allocation for as long as it is in use.
+    fn get_buf(&mut self, proxy: &BufProxy, usage: BufferUsages, device: &Device) -> (Id, Buffer) {
         let rounded_size = Self::size_class(proxy.size, SIZE_CLASS_BITS);
         let props = BufferProperties {
             size: rounded_size,
@@ -700,7 +1739,7 @@ impl ResourcePool {
                 return buf;
             }
         }
-        device.create_buffer(&wgpu::BufferDescriptor {
+        let buf = device.create_buffer(&wgpu::BufferDescriptor {
             #[cfg(feature = "buffer_labels")]
             label: Some(proxy.name),
             #[cfg(not(feature = "buffer_labels"))]
@@ -708,11 +1747,17 @@ impl ResourcePool {
             size: rounded_size,
             usage,
             mapped_at_creation: false,
-        })
+        });
+        (Id::next(), buf)
     }
 
     fn reap_bindmap(&mut self, bind_map: BindMap) {
-        for (_id, buf) in bind_map.buf_map {
+        // Return buffers in reverse order of first use, so that a recording which uses
+        // buffers in the same order gets the same allocations back, and can reuse its
+        // cached bind groups.
+        let mut bufs = bind_map.buf_map.into_values().collect::<Vec<_>>();
+        bufs.sort_by_key(|buf| std::cmp::Reverse(buf.seq));
+        for buf in bufs {
             let size = buf.buffer.size();
             let props = BufferProperties {
                 size,
@@ -720,10 +1765,88 @@ impl ResourcePool {
                 #[cfg(feature = "buffer_labels")]
                 name: buf.label,
             };
-            self.bufs.entry(props).or_default().push(buf.buffer);
+            self.bufs
+                .entry(props)
+                .or_default()
+                .push((buf.alloc_id, buf.buffer));
+        }
+        self.generation += 1;
+        for (_id, mut image) in bind_map.image_map {
+            image.last_used = self.generation;
+            self.images.entry(image.props).or_default().push(image);
+        }
+        let generation = self.generation;
+        self.images.retain(|_, images| {
+            images.retain(|image| generation - image.last_used < IMAGE_MAX_AGE);
+            !images.is_empty()
+        });
+    }
+
+    /// Get an image from the pool or create one.
+    fn get_image(
+        &mut self,
+        proxy: &ImageProxy,
+        usage: TextureUsages,
+        device: &Device,
+    ) -> PooledImage {
+        let props = ImageProperties {
+            width: proxy.width,
+            height: proxy.height,
+            format: proxy.format,
+            usages: usage,
+        };
+        if let Some(image) = self.images.get_mut(&props).and_then(|images| images.pop()) {
+            return image;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: proxy.width,
+                height: proxy.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            format: proxy.format.to_wgpu(),
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            dimension: Some(TextureViewDimension::D2),
+            aspect: TextureAspect::All,
+            mip_level_count: None,
+            base_mip_level: 0,
+            base_array_layer: 0,
+            array_layer_count: None,
+            format: Some(proxy.format.to_wgpu()),
+        });
+        PooledImage {
+            props,
+            texture,
+            view,
+            alloc_id: Id::next(),
+            last_used: self.generation,
         }
     }
 
+    fn retained_buffer_bytes(&self) -> u64 {
+        self.bufs
+            .iter()
+            .map(|(props, bufs)| props.size * bufs.len() as u64)
+            .sum()
+    }
+
+    fn retained_image_bytes(&self) -> u64 {
+        self.images
+            .iter()
+            .map(|(props, images)| {
+                // Both supported formats have 4 bytes per pixel
+                props.width as u64 * props.height as u64 * 4 * images.len() as u64
+            })
+            .sum()
+    }
+
     /// Quantize a size up to the nearest size class.
     fn size_class(x: u64, bits: u32) -> u64 {
         if x > 1 << bits {
@@ -735,3 +1858,108 @@ impl ResourcePool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staging_layout_aligns_uploads() {
+        let mut recording = Recording::default();
+        recording.upload("a", vec![1; 5]);
+        recording.upload_uniform("config", vec![2; 12]);
+        recording.clear_all(BufProxy::new(16, "cleared"));
+        recording.upload("b", vec![3; 3]);
+        recording.upload_uniform("empty", vec![]);
+        let (offsets, size) = staging_layout(&recording.commands, 256);
+        assert_eq!(offsets, [0, 256, 268, 512]);
+        assert_eq!(size, 512);
+    }
+
+    #[test]
+    fn upload_buffers_hold_rounded_copies() {
+        let mut recording = Recording::default();
+        for len in [0, 1, 17, 30, 1000] {
+            let proxy = recording.upload("data", vec![0; len]);
+            assert!(proxy.size >= staging_size(len));
+            assert!(Engine::pooled_buffer_size(proxy.size) >= staging_size(len));
+        }
+        assert_eq!(staging_size(17), 20);
+        assert_eq!(staging_size(20), 20);
+    }
+
+    #[test]
+    fn bindings_smaller_than_declared_are_rejected() {
+        let shaders = [ShaderInfo {
+            label: "test",
+            bind_types: vec![BindType::Uniform, BindType::Buffer],
+            min_sizes: vec![64, 0],
+        }];
+        let mut recording = Recording::default();
+        let config = recording.upload_uniform("config", vec![0; 32]);
+        recording.dispatch(ShaderId(0), (1, 1, 1), [config, BufProxy::new(16, "buf")]);
+        let error = validate_commands(&shaders, &recording.commands, &[]).unwrap_err();
+        assert_eq!(error.command, 1);
+        assert_eq!(error.shader, Some("test"));
+        assert!(error.reason.contains("slot 0"), "{}", error.reason);
+
+        let mut recording = Recording::default();
+        let config = recording.upload_uniform("config", vec![0; 64]);
+        recording.dispatch(ShaderId(0), (1, 1, 1), [config, BufProxy::new(16, "buf")]);
+        assert!(validate_commands(&shaders, &recording.commands, &[]).is_ok());
+    }
+
+    #[test]
+    fn downloads_larger_than_their_source_are_rejected() {
+        let no_shaders: [ShaderInfo; 0] = [];
+        let buf = BufProxy::new(256, "external");
+        let mut recording = Recording::default();
+        recording.download(buf);
+        let external = [External::Buf(buf, 128)];
+        let error = validate_commands(&no_shaders, &recording.commands, &external).unwrap_err();
+        assert_eq!(error.command, 0);
+        assert!(error.reason.contains("256 bytes"), "{}", error.reason);
+        let external = [External::Buf(buf, 256)];
+        assert!(validate_commands(&no_shaders, &recording.commands, &external).is_ok());
+    }
+
+    #[test]
+    fn copies_past_the_end_of_buffers_are_rejected() {
+        let no_shaders: [ShaderInfo; 0] = [];
+        let mut recording = Recording::default();
+        recording.push(Command::Upload(BufProxy::new(16, "small"), vec![0; 20]));
+        let error = validate_commands(&no_shaders, &recording.commands, &[]).unwrap_err();
+        assert_eq!(error.command, 0);
+
+        let mut recording = Recording::default();
+        let buf = BufProxy::new(64, "cleared");
+        recording.push(Command::Clear(buf, 32, NonZeroU64::new(32)));
+        assert!(validate_commands(&no_shaders, &recording.commands, &[]).is_ok());
+        recording.push(Command::Clear(buf, 32, NonZeroU64::new(64)));
+        let error = validate_commands(&no_shaders, &recording.commands, &[]).unwrap_err();
+        assert_eq!(error.command, 1);
+    }
+
+    #[test]
+    fn indirect_dispatch_arguments_are_checked() {
+        let shaders = [ShaderInfo {
+            label: "test",
+            bind_types: vec![BindType::Buffer],
+            min_sizes: vec![0],
+        }];
+        let buf = BufProxy::new(16, "buf");
+        let mut recording = Recording::default();
+        recording.dispatch_indirect(ShaderId(0), BufProxy::new(12, "args"), 0, [buf]);
+        let error = validate_commands(&shaders, &recording.commands, &[]).unwrap_err();
+        assert_eq!(error.command, 0);
+        assert!(error.reason.contains("before"), "{}", error.reason);
+
+        let mut recording = Recording::default();
+        let args = recording.upload("args", vec![0; 24]);
+        recording.dispatch_indirect(ShaderId(0), args, 12, [buf]);
+        assert!(validate_commands(&shaders, &recording.commands, &[]).is_ok());
+        recording.dispatch_indirect(ShaderId(0), args, 16, [buf]);
+        let error = validate_commands(&shaders, &recording.commands, &[]).unwrap_err();
+        assert_eq!(error.command, 2);
+    }
+}
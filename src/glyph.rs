@@ -18,7 +18,9 @@
 
 pub use moscato::pinot;
 
-use crate::scene::{SceneBuilder, SceneFragment};
+use std::collections::hash_map::{Entry, HashMap};
+
+use crate::scene::{EncodedPath, SceneBuilder, SceneFragment};
 use peniko::kurbo::{Affine, Rect};
 use peniko::{Brush, Color, Fill, Mix};
 
@@ -27,6 +29,13 @@ use pinot::{types::Tag, FontRef};
 
 use smallvec::SmallVec;
 
+/// Number of frames a cached glyph can go unused before it is evicted.
+const MAX_UNUSED_FRAMES: u64 = 64;
+
+/// Number of outline sizes cached per pixel per em. Glyphs are drawn by scaling the
+/// outline of the nearest size, which is at most 1/8 pixel per em away.
+const SIZE_BUCKETS_PER_PIXEL: f32 = 4.0;
+
 /// General context for creating scene fragments for glyph outlines.
 pub struct GlyphContext {
     ctx: Context,
@@ -73,6 +82,199 @@ impl GlyphContext {
     }
 }
 
+/// A font to draw glyphs from with [`SceneBuilder::draw_glyphs`].
+pub struct GlyphFont<'a> {
+    /// The font data.
+    pub font: FontRef<'a>,
+    /// Identifier of the font, which must be the same every time the font is drawn
+    /// with a [`GlyphCache`], and differ from the identifiers of other fonts.
+    pub id: u64,
+    /// Whether the outlines are hinted.
+    pub hint: bool,
+}
+
+/// A glyph to draw with [`SceneBuilder::draw_glyphs`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Glyph {
+    /// Identifier of the glyph in its font.
+    pub id: u16,
+    /// Horizontal position of the origin of the glyph.
+    pub x: f32,
+    /// Vertical position of the baseline of the glyph.
+    pub y: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_id: u64,
+    glyph_id: u16,
+    size_bucket: u32,
+    hint: bool,
+}
+
+struct CachedGlyph {
+    /// Encoded outline, or `None` if the glyph has no outline.
+    path: Option<EncodedPath>,
+    last_used: u64,
+}
+
+/// Cache of encoded glyph outlines, which is kept between frames so that each glyph
+/// is only scaled and encoded once.
+///
+/// Only the outlines of glyphs are drawn, so bitmap and color glyphs are drawn with
+/// their fallback outlines, and variations are not supported.
+pub struct GlyphCache {
+    ctx: Context,
+    glyphs: HashMap<GlyphKey, CachedGlyph>,
+    frame: u64,
+    stats: GlyphCacheStats,
+}
+
+/// Counters for a [`GlyphCache`], from [`GlyphCache::stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GlyphCacheStats {
+    /// Glyphs currently held by the cache.
+    pub glyphs: usize,
+    /// Size of the encoded outlines held by the cache, in bytes.
+    pub encoded_bytes: usize,
+    /// Glyphs which were found in the cache.
+    pub hits: u64,
+    /// Glyphs which had to be scaled and encoded.
+    pub misses: u64,
+    /// Glyphs which were freed after going unused.
+    pub evictions: u64,
+}
+
+impl GlyphCache {
+    /// Creates a new, empty cache.
+    pub fn new() -> Self {
+        Self {
+            ctx: Context::new(),
+            glyphs: HashMap::new(),
+            frame: 0,
+            stats: GlyphCacheStats::default(),
+        }
+    }
+
+    /// Marks the end of a frame. Glyphs which have not been drawn for a number of
+    /// frames are evicted.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        let len = self.glyphs.len();
+        self.glyphs
+            .retain(|_, glyph| glyph.last_used + MAX_UNUSED_FRAMES >= frame);
+        self.stats.evictions += (len - self.glyphs.len()) as u64;
+    }
+
+    /// Frees all of the cached glyphs.
+    pub fn clear(&mut self) {
+        self.stats.evictions += self.glyphs.len() as u64;
+        self.glyphs.clear();
+    }
+
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> GlyphCacheStats {
+        GlyphCacheStats {
+            glyphs: self.glyphs.len(),
+            encoded_bytes: self
+                .glyphs
+                .values()
+                .filter_map(|glyph| glyph.path.as_ref())
+                .map(EncodedPath::byte_size)
+                .sum(),
+            ..self.stats
+        }
+    }
+
+    /// Returns the source of the outlines of `font` at `size` pixels per em.
+    pub(crate) fn outlines<'a>(&'a mut self, font: &'a GlyphFont<'a>, size: f32) -> Outlines<'a> {
+        let size_bucket = if size.is_finite() && size > 0.0 {
+            (size * SIZE_BUCKETS_PER_PIXEL).round() as u32
+        } else {
+            0
+        };
+        let bucket_size = size_bucket as f32 / SIZE_BUCKETS_PER_PIXEL;
+        Outlines {
+            ctx: Some(&mut self.ctx),
+            scaler: None,
+            glyphs: &mut self.glyphs,
+            stats: &mut self.stats,
+            frame: self.frame,
+            font,
+            size_bucket,
+            bucket_size,
+            scale: (size / bucket_size) as f64,
+        }
+    }
+}
+
+impl Default for GlyphCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Outlines of one font at one size, which are scaled on first use.
+pub(crate) struct Outlines<'a> {
+    ctx: Option<&'a mut Context>,
+    scaler: Option<Scaler<'a>>,
+    glyphs: &'a mut HashMap<GlyphKey, CachedGlyph>,
+    stats: &'a mut GlyphCacheStats,
+    frame: u64,
+    font: &'a GlyphFont<'a>,
+    size_bucket: u32,
+    bucket_size: f32,
+    /// Scale from the cached outline to the requested size.
+    scale: f64,
+}
+
+impl<'a> Outlines<'a> {
+    /// Returns the outline of a glyph and the scale it should be drawn with, or `None`
+    /// if the glyph has no outline.
+    pub fn get(&mut self, glyph_id: u16) -> Option<(&EncodedPath, f64)> {
+        if self.size_bucket == 0 {
+            return None;
+        }
+        let key = GlyphKey {
+            font_id: self.font.id,
+            glyph_id,
+            size_bucket: self.size_bucket,
+            hint: self.font.hint,
+        };
+        let glyph = match self.glyphs.entry(key) {
+            Entry::Occupied(entry) => {
+                self.stats.hits += 1;
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                self.stats.misses += 1;
+                if self.scaler.is_none() {
+                    let ctx = self.ctx.take()?;
+                    let scaler = ctx
+                        .new_scaler_with_id(&self.font.font, self.font.id)
+                        .size(self.bucket_size)
+                        .hint(self.font.hint)
+                        .build();
+                    self.scaler = Some(scaler);
+                }
+                let path = self
+                    .scaler
+                    .as_mut()?
+                    .glyph(glyph_id)
+                    .and_then(|glyph| glyph.path(0).map(|path| convert_path(path.elements())))
+                    .map(|path| EncodedPath::new(&path));
+                entry.insert(CachedGlyph {
+                    path,
+                    last_used: self.frame,
+                })
+            }
+        };
+        glyph.last_used = self.frame;
+        Some((glyph.path.as_ref()?, self.scale))
+    }
+}
+
 /// Generator for scene fragments containing glyph outlines for a specific
 /// font.
 pub struct GlyphProvider<'a> {
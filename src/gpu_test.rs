@@ -0,0 +1,109 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Helpers for tests which render on the GPU. Such tests return early, and pass,
+//! where no adapter is available.
+
+use std::num::NonZeroU32;
+
+use wgpu::{Texture, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::util::{DeviceHandle, RenderContext};
+use crate::{ImageParams, Renderer, RendererOptions, Scene};
+
+/// Returns a device to run tests on, or `None` if there is no adapter.
+pub fn device() -> Option<DeviceHandle> {
+    let mut context = RenderContext::new().ok()?;
+    let dev_id = pollster::block_on(context.device(None)).ok()?;
+    let handle = context.devices.swap_remove(dev_id);
+    Some(handle)
+}
+
+/// Returns a renderer without support for surfaces, which tests don't have.
+pub fn renderer(handle: &DeviceHandle) -> Renderer {
+    let options = RendererOptions {
+        surface_blit_support: false,
+        ..Default::default()
+    };
+    Renderer::new(&handle.device, &options).unwrap()
+}
+
+/// Renders `scene` and returns its pixels, as stored by
+/// [`Renderer::render_to_texture`], with straight alpha.
+pub fn render(
+    handle: &DeviceHandle,
+    renderer: &mut Renderer,
+    scene: &Scene,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    let params = ImageParams {
+        width,
+        height,
+        premultiplied: false,
+    };
+    let render = renderer.render_to_image(&handle.device, &handle.queue, scene, &params);
+    let image = pollster::block_on(render).unwrap();
+    image.data.data().to_vec()
+}
+
+/// Returns the pixel at `x`, `y` of tightly packed RGBA rows which are `width`
+/// pixels wide.
+pub fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+    let at = (y * width + x) as usize * 4;
+    pixels[at..at + 4].try_into().unwrap()
+}
+
+/// Returns the pixels of a `width` by `height` Rgba8 texture with `COPY_SRC` usage, as
+/// tightly packed rows.
+pub fn read_texture(handle: &DeviceHandle, texture: &Texture, width: u32, height: u32) -> Vec<u8> {
+    let padded = (width * 4 + COPY_BYTES_PER_ROW_ALIGNMENT - 1) / COPY_BYTES_PER_ROW_ALIGNMENT
+        * COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = handle.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("test readback"),
+        size: (padded * height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = handle
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded),
+                rows_per_image: None,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    handle.queue.submit(Some(encoder.finish()));
+    let slice = buffer.slice(..);
+    let map = crate::engine::map_buffer(&handle.device, slice, wgpu::MapMode::Read);
+    pollster::block_on(map).unwrap();
+    let data = slice.get_mapped_range();
+    data.chunks(padded as usize)
+        .flat_map(|row| &row[..width as usize * 4])
+        .copied()
+        .collect()
+}
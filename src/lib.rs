@@ -14,78 +14,1064 @@
 //
 // Also licensed under MIT license, at your choice.
 
+mod dash;
 mod engine;
+#[cfg(test)]
+mod gpu_test;
+mod metadata;
+mod readback;
 mod render;
 mod scene;
 mod shaders;
+mod stroke;
 
 /// Styling and composition primitives.
 pub use peniko;
 /// 2D geometry, with a focus on curves.
 pub use peniko::kurbo;
 
+pub mod capture;
 pub mod encoding;
 
 pub mod glyph;
 pub mod util;
 
-pub use scene::{Scene, SceneBuilder, SceneFragment};
+pub use scene::{
+    EncodedPath, FragmentId, PathId, Scene, SceneBuilder, SceneFragment, SceneGraph, SceneStats,
+    UnsupportedBlend,
+};
 
-use engine::{Engine, ExternalResource};
+pub use engine::{InvalidRecording, PoolStats};
+pub use render::{BufferSizes, BufferUsage, OverflowInfo};
+pub use shaders::{preprocess as wgsl_preprocess, PreprocessError};
+
+use encoding::resource::{RampCacheStats, ResourceCache};
+use engine::{Engine, ExternalResource, PartialRun};
+use readback::ImageReadback;
 use shaders::FullShaders;
+use util::TextureBlitter;
+
+use wgpu::{CommandEncoder, Device, Queue, SurfaceTexture, Texture, TextureFormat, TextureView};
 
-use wgpu::{Device, Queue, SurfaceTexture, TextureFormat, TextureView};
+/// Errors returned by vello.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// No adapter compatible with the requested surface was found.
+    NoCompatibleDevice,
+    /// The adapter failed to create a device.
+    DeviceRequest(wgpu::RequestDeviceError),
+    /// A surface could not be created for a window.
+    SurfaceCreation { detail: String },
+    /// A shader failed to compile or validate.
+    ShaderCompilation {
+        shader: &'static str,
+        detail: String,
+    },
+    /// The preprocessor directives of a shader were malformed.
+    Preprocess(PreprocessError),
+    /// The scene can't be rendered with this renderer and device.
+    Render(RenderError),
+    /// The scene has more path tags or draw objects than can be processed in a single
+    /// dispatch on the device. `stage` is the name of the first shader which would
+    /// exceed the limit, such as `"pathtag_reduce"`, and `workgroups` is the size of
+    /// its dispatch.
+    SceneTooComplex {
+        stage: &'static str,
+        workgroups: u32,
+        limit: u32,
+    },
+    /// A job of [`Renderer::render_batch`] failed.
+    Job { job: usize, error: Box<Error> },
+    /// A recording was inconsistent with the shaders it dispatches. This indicates a
+    /// bug in vello.
+    InvalidRecording(InvalidRecording),
+    /// A buffer could not be mapped to read results back.
+    BufferMapping(wgpu::BufferAsyncError),
+    /// A frame capture could not be written.
+    Capture(std::io::Error),
+    /// An internal invariant was broken. This indicates a bug in vello.
+    Internal(&'static str),
+}
 
-/// Catch-all error type.
-pub type Error = Box<dyn std::error::Error>;
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NoCompatibleDevice => write!(f, "no compatible device found"),
+            Self::DeviceRequest(error) => write!(f, "failed to create device: {error}"),
+            Self::SurfaceCreation { detail } => write!(f, "failed to create surface: {detail}"),
+            Self::ShaderCompilation { shader, detail } => {
+                write!(f, "failed to compile shader {shader}: {detail}")
+            }
+            Self::Preprocess(error) => write!(f, "failed to preprocess shader: {error}"),
+            Self::Render(error) => error.fmt(f),
+            Self::SceneTooComplex {
+                stage,
+                workgroups,
+                limit,
+            } => write!(
+                f,
+                "scene needs a dispatch of {workgroups} workgroups in {stage}, but the limit is {limit}"
+            ),
+            Self::Job { job, error } => write!(f, "job {job} of the batch failed: {error}"),
+            Self::InvalidRecording(error) => error.fmt(f),
+            Self::BufferMapping(error) => write!(f, "failed to map buffer: {error}"),
+            Self::Capture(error) => write!(f, "failed to write frame capture: {error}"),
+            Self::Internal(message) => write!(f, "internal error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DeviceRequest(error) => Some(error),
+            Self::Preprocess(error) => Some(error),
+            Self::Render(error) => Some(error),
+            Self::Job { error, .. } => Some(&**error),
+            Self::InvalidRecording(error) => Some(error),
+            Self::BufferMapping(error) => Some(error),
+            Self::Capture(error) => Some(error),
+            _ => None,
+        }
+    }
+}
 
-/// Specialization of `Result` for our catch-all error type.
+impl From<PreprocessError> for Error {
+    fn from(value: PreprocessError) -> Self {
+        Self::Preprocess(value)
+    }
+}
+
+impl From<RenderError> for Error {
+    fn from(value: RenderError) -> Self {
+        Self::Render(value)
+    }
+}
+
+impl From<InvalidRecording> for Error {
+    fn from(value: InvalidRecording) -> Self {
+        Self::InvalidRecording(value)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(value: wgpu::RequestDeviceError) -> Self {
+        Self::DeviceRequest(value)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for Error {
+    fn from(value: wgpu::BufferAsyncError) -> Self {
+        Self::BufferMapping(value)
+    }
+}
+
+/// Errors returned by [`Renderer`] for scenes which it can't render.
+#[derive(Clone, Debug)]
+pub enum RenderError {
+    /// Rendering the scene needs a buffer larger than the device supports. Both
+    /// sizes are in bytes.
+    SceneTooLarge { needed: u64, limit: u64 },
+    /// A texture which the renderer would allocate to render into is larger than
+    /// the device supports. `limit` is the largest dimension of a 2D texture.
+    TargetTooLarge { width: u32, height: u32, limit: u32 },
+    /// Rendering to a surface was requested, but the renderer was created without
+    /// [`surface_blit_support`](RendererOptions::surface_blit_support).
+    SurfaceBlitUnsupported,
+    /// A frame capture was requested, but the renderer was created without
+    /// [`capture`](RendererOptions::capture).
+    CaptureUnsupported,
+    /// The scene nests clips more deeply than [`Config::MAX_CLIP_DEPTH`], which is
+    /// only possible with [`SceneBuilder::set_strict_clip_depth`] or by appending
+    /// fragments inside layers.
+    ///
+    /// [`Config::MAX_CLIP_DEPTH`]: encoding::Config::MAX_CLIP_DEPTH
+    ClipDepthExceeded { depth: u32, limit: u32 },
+    /// The region to render into extends past the edge of the target. All of the
+    /// sizes are in pixels.
+    RegionOutsideTarget {
+        origin: (u32, u32),
+        size: (u32, u32),
+        target: (u32, u32),
+    },
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::SceneTooLarge { needed, limit } => write!(
+                f,
+                "scene needs a buffer of {needed} bytes, but the device limit is {limit} bytes"
+            ),
+            Self::TargetTooLarge {
+                width,
+                height,
+                limit,
+            } => write!(
+                f,
+                "target of {width}x{height} pixels is larger than the device limit of {limit} pixels"
+            ),
+            Self::SurfaceBlitUnsupported => write!(
+                f,
+                "renderer was created without support for rendering to surfaces"
+            ),
+            Self::CaptureUnsupported => write!(
+                f,
+                "renderer was created without support for capturing frames"
+            ),
+            Self::ClipDepthExceeded { depth, limit } => write!(
+                f,
+                "scene nests clips {depth} deep, but the limit is {limit}"
+            ),
+            Self::RegionOutsideTarget {
+                origin,
+                size,
+                target,
+            } => write!(
+                f,
+                "region of {}x{} pixels at {}, {} extends outside of the {}x{} pixel target",
+                size.0, size.1, origin.0, origin.1, target.0, target.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Specialization of `Result` for vello's [`Error`].
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Renders a scene into a texture or surface.
 pub struct Renderer {
     engine: Engine,
     shaders: FullShaders,
-    blit: BlitPipeline,
+    /// Shaders for small tiles with [`TileSize::Auto`], once a scene has needed them.
+    small_tile_shaders: Option<FullShaders>,
+    tile_size: TileSize,
+    blit: Option<TextureBlitter>,
     target: Option<TargetTexture>,
+    overflow_readback: bool,
+    last_overflow: Option<OverflowInfo>,
+    readback: Option<ImageReadback>,
+    resources: ResourceCache,
+    capture_support: bool,
+    pending_capture: Option<std::path::PathBuf>,
+    deterministic: bool,
+    /// Overflow downloads of renders recorded into external encoders, which are read
+    /// by [`Renderer::after_submit`].
+    deferred_overflow: Vec<(engine::Downloads, render::BumpBuffer)>,
+    debug_overlay: DebugOverlay,
+}
+
+/// A render submitted by [`Renderer::submit_region`], with the readbacks which are
+/// still to be read.
+struct RegionRender {
+    recording: engine::Recording,
+    target: engine::ResourceProxy,
+    bump: render::BumpBuffer,
+    /// Path to write the capture of this render to.
+    capture: Option<std::path::PathBuf>,
+    /// The rest of the render, which is submitted once the bump allocators have been
+    /// read back.
+    run: Option<PartialRun>,
+    /// Downloads of the last submitted part of the render.
+    downloads: engine::Downloads,
+}
+
+/// A scene to render with [`Renderer::render_batch`], and the texture to render it to.
+///
+/// The texture has the same requirements as for [`Renderer::render_to_texture`].
+pub struct RenderJob<'a> {
+    pub scene: &'a Scene,
+    pub texture: TargetView<'a>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Sizes in bytes of the GPU memory held by a [`Renderer`] between frames, as returned
+/// by [`Renderer::memory_usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Buffers held by the resource pool for reuse.
+    pub pool_buffers: u64,
+    /// Images held by the resource pool for reuse, including gradient ramps.
+    pub pool_images: u64,
+    /// Intermediate textures and readback buffers kept for
+    /// [`render_to_surface`](Renderer::render_to_surface) and
+    /// [`render_to_image`](Renderer::render_to_image).
+    pub target_cache: u64,
+}
+
+impl MemoryReport {
+    /// Returns the sum of all categories.
+    pub fn total(&self) -> u64 {
+        self.pool_buffers + self.pool_images + self.target_cache
+    }
+}
+
+/// Conservative estimate of the GPU memory needed to render a scene, in bytes, as
+/// returned by [`Renderer::estimate_memory`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Buffers holding the scene itself.
+    pub scene: u64,
+    /// Buffers written and read by the stages of the pipeline, which grow with the
+    /// number of paths and segments in the scene.
+    pub intermediate: u64,
+    /// Buffers whose size doesn't depend on the scene, such as the tile and per tile
+    /// command list buffers.
+    pub fixed: u64,
+    /// The gradient ramp texture.
+    pub ramps: u64,
+    /// The target texture, which is allocated by
+    /// [`render_to_surface`](Renderer::render_to_surface) and
+    /// [`render_to_image`](Renderer::render_to_image), and otherwise by the caller.
+    pub target: u64,
+}
+
+impl MemoryEstimate {
+    /// Returns the sum of all categories.
+    pub fn total(&self) -> u64 {
+        self.scene + self.intermediate + self.fixed + self.ramps + self.target
+    }
+}
+
+/// Parameters for [`Renderer::render_to_image`].
+#[derive(Clone, Copy, Debug)]
+pub struct ImageParams {
+    pub width: u32,
+    pub height: u32,
+    /// Whether the color channels of the returned pixels are premultiplied by alpha.
+    /// Otherwise they have straight alpha, as stored by
+    /// [`render_to_texture`](Renderer::render_to_texture).
+    pub premultiplied: bool,
+}
+
+/// A texture which a scene can be rendered into, with its size, which the rendered
+/// region is checked against so that nothing is written outside of the texture.
+#[derive(Clone, Copy)]
+pub enum TargetView<'a> {
+    /// A view of a two dimensional texture of the given size. wgpu can't report the
+    /// size of a view, so it has to be given.
+    View {
+        view: &'a TextureView,
+        width: u32,
+        height: u32,
+    },
+    /// A single array layer of a texture of the given size, for example one eye of a
+    /// stereo target or a page of an atlas.
+    Layer {
+        texture: &'a Texture,
+        layer: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl<'a> TargetView<'a> {
+    /// Creates a target for a view of a texture of `width` by `height` pixels.
+    pub fn new(view: &'a TextureView, width: u32, height: u32) -> Self {
+        Self::View {
+            view,
+            width,
+            height,
+        }
+    }
+
+    /// Creates the view for a layer target, which must live as long as it is bound.
+    fn layer_view(&self) -> Option<TextureView> {
+        match *self {
+            Self::View { .. } => None,
+            Self::Layer { texture, layer, .. } => {
+                Some(texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                }))
+            }
+        }
+    }
+
+    /// Returns the view to bind, given the result of [`layer_view`](Self::layer_view).
+    fn view<'b>(&self, layer_view: &'b Option<TextureView>) -> &'b TextureView
+    where
+        'a: 'b,
+    {
+        match *self {
+            Self::View { view, .. } => view,
+            Self::Layer { .. } => layer_view.as_ref().unwrap(),
+        }
+    }
+
+    /// Returns the size of the target in pixels.
+    fn size(&self) -> (u32, u32) {
+        match *self {
+            Self::View { width, height, .. } | Self::Layer { width, height, .. } => (width, height),
+        }
+    }
+
+    /// Checks that the region of `size` at `origin` is inside the target.
+    fn check_region(&self, origin: (u32, u32), size: (u32, u32)) -> Result<(), RenderError> {
+        let target = self.size();
+        let right = origin.0 as u64 + size.0 as u64;
+        let bottom = origin.1 as u64 + size.1 as u64;
+        if right > target.0 as u64 || bottom > target.1 as u64 {
+            return Err(RenderError::RegionOutsideTarget {
+                origin,
+                size,
+                target,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Options which control the features supported by a [`Renderer`].
+///
+/// Pipelines for unsupported features are not created, which reduces startup time.
+#[derive(Clone, Copy, Debug)]
+pub struct RendererOptions {
+    /// Whether [`Renderer::render_to_surface`] can be used.
+    pub surface_blit_support: bool,
+    /// Alpha mode of the surfaces passed to [`Renderer::render_to_surface`], such as
+    /// the mode chosen by [`RenderContext::create_surface_with_alpha_mode`], which
+    /// decides how alpha is written to them.
+    ///
+    /// [`RenderContext::create_surface_with_alpha_mode`]: util::RenderContext::create_surface_with_alpha_mode
+    pub surface_alpha_mode: wgpu::CompositeAlphaMode,
+    /// Whether to read back the usage of the dynamically sized buffers after each
+    /// render, so that overflows are reported by [`Renderer::last_frame_overflow`].
+    ///
+    /// The readback waits for the GPU to finish the frame, so this is intended for
+    /// diagnostics rather than for every frame of a release build.
+    pub overflow_readback: bool,
+    /// Whether frames can be captured with [`Renderer::capture_next_frame`].
+    pub capture: bool,
+    /// Whether rendering the same scene twice on the same adapter produces
+    /// identical pixels.
+    ///
+    /// The segments of each tile are linked in the order they were allocated, which
+    /// varies between runs, so the coverage of a pixel is normally summed in a
+    /// different order each time and can differ in the last bit. With this set, fine
+    /// sums coverage in fixed point, which is slightly slower. This is intended for
+    /// comparisons against reference images.
+    pub deterministic: bool,
+    /// Size of the tiles the target is divided into.
+    pub tile_size: TileSize,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            surface_blit_support: true,
+            surface_alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            overflow_readback: false,
+            capture: false,
+            deterministic: false,
+            tile_size: TileSize::Large,
+        }
+    }
+}
+
+/// Size of the tiles which a [`Renderer`] divides its targets into, set with
+/// [`RendererOptions::tile_size`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileSize {
+    /// Tiles of 16 by 16 pixels, which suit scenes of large paths.
+    #[default]
+    Large,
+    /// Tiles of 8 by 8 pixels, which waste less work on small paths such as glyphs.
+    Small,
+    /// Chooses the size for each scene from the average area of its paths. The
+    /// shaders for small tiles are built when they are first needed.
+    Auto,
+}
+
+impl TileSize {
+    /// Returns the width and height of a tile in pixels. For [`Auto`](Self::Auto),
+    /// this is the size of large tiles, which are used unless a scene suits small ones.
+    pub fn pixels(self) -> u32 {
+        match self {
+            Self::Small => 8,
+            Self::Large | Self::Auto => 16,
+        }
+    }
+}
+
+/// A visualization of the intermediate results of the pipeline, drawn over the scene
+/// by [`Renderer::set_debug_overlay`] to diagnose missing or slow geometry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugOverlay {
+    /// The scene is drawn as it is.
+    #[default]
+    None,
+    /// Outlines the bounding box of each draw object in pixels, as binning computed
+    /// it. Anything drawn outside of its box is missing from the rendering.
+    DrawBboxes,
+    /// Colors each tile by the number of commands coarse rasterization wrote for it,
+    /// from blue for none to red for 32 or more.
+    TileOccupancy,
+    /// Colors each tile by the number of path segments its commands read, from blue
+    /// for none to red for 64 or more.
+    SegmentDensity,
 }
 
 impl Renderer {
     /// Creates a new renderer for the specified device.
-    pub fn new(device: &Device) -> Result<Self> {
+    pub fn new(device: &Device, options: &RendererOptions) -> Result<Self> {
         let mut engine = Engine::new();
-        let shaders = shaders::full_shaders(device, &mut engine)?;
-        let blit = BlitPipeline::new(device, TextureFormat::Bgra8Unorm);
+        let shaders = shaders::full_shaders(
+            device,
+            &mut engine,
+            options.deterministic,
+            options.tile_size,
+        )?;
+        let blit = options.surface_blit_support.then(|| {
+            TextureBlitter::with_alpha_mode(
+                device,
+                TextureFormat::Bgra8Unorm,
+                options.surface_alpha_mode,
+            )
+        });
         Ok(Self {
             engine,
             shaders,
+            small_tile_shaders: None,
+            tile_size: options.tile_size,
             blit,
             target: None,
+            overflow_readback: options.overflow_readback,
+            last_overflow: None,
+            readback: None,
+            resources: ResourceCache::new(),
+            capture_support: options.capture,
+            pending_capture: None,
+            deterministic: options.deterministic,
+            deferred_overflow: Vec::new(),
+            debug_overlay: DebugOverlay::None,
         })
     }
 
+    /// Returns the usage of the dynamically sized buffers in the last frame rendered
+    /// with [`render_to_texture`](Self::render_to_texture) or a method which calls it,
+    /// if any of them overflowed.
+    ///
+    /// Parts of a frame which overflowed are not drawn. This always returns `None`
+    /// unless the renderer was created with
+    /// [`overflow_readback`](RendererOptions::overflow_readback), and on the web, where
+    /// the readback can't complete without yielding, unless the frame was rendered with
+    /// [`render_to_texture_async`](Self::render_to_texture_async) or
+    /// [`render_to_image`](Self::render_to_image).
+    pub fn last_frame_overflow(&self) -> Option<OverflowInfo> {
+        self.last_overflow
+    }
+
+    /// Captures the next frame rendered with [`render_to_texture`](Self::render_to_texture)
+    /// or a method which calls it, writing every intermediate buffer into the directory
+    /// `path` as described in [`capture`].
+    ///
+    /// The capture waits for the GPU to finish the frame and downloads all of its
+    /// buffers, so the frame is slow. Captured frames are not batched, so
+    /// [`render_batch`](Self::render_batch) doesn't capture.
+    ///
+    /// Returns [`RenderError::CaptureUnsupported`] if the renderer was created without
+    /// [`capture`](RendererOptions::capture).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn capture_next_frame(&mut self, path: &std::path::Path) -> Result<()> {
+        if !self.capture_support {
+            return Err(RenderError::CaptureUnsupported.into());
+        }
+        self.pending_capture = Some(path.to_owned());
+        Ok(())
+    }
+
+    /// Returns counters describing how well resources are being reused between frames.
+    pub fn pool_stats(&self) -> PoolStats {
+        self.engine.pool_stats()
+    }
+
+    /// Returns counters for the gradient ramps which are shared between frames.
+    ///
+    /// Ramps are generated once for each distinct set of color stops, and their rows
+    /// in the ramp texture are reused until they go unused for a number of frames.
+    pub fn ramp_cache_stats(&self) -> RampCacheStats {
+        self.resources.ramp_stats()
+    }
+
+    /// Returns the GPU memory held between frames. This only sums sizes which are
+    /// already known, so it is cheap enough to call every frame.
+    pub fn memory_usage(&self) -> MemoryReport {
+        let stats = self.engine.pool_stats();
+        let target = self
+            .target
+            .as_ref()
+            .map_or(0, |target| target.width as u64 * target.height as u64 * 4);
+        let readback = self.readback.as_ref().map_or(0, ImageReadback::byte_size);
+        MemoryReport {
+            pool_buffers: stats.retained_buffer_bytes,
+            pool_images: stats.retained_image_bytes,
+            target_cache: target + readback,
+        }
+    }
+
+    /// Estimates the GPU memory needed to render `scene` at the given size, without
+    /// touching the GPU.
+    ///
+    /// Buffers are sized by the same [`BufferSizes`] that rendering uses, rounded up
+    /// to the sizes they are allocated with, so the estimate is an upper bound on the
+    /// memory allocated for a frame. Memory already held by the renderer, which
+    /// [`memory_usage`](Self::memory_usage) reports, is reused where possible and so
+    /// may reduce what a render actually allocates.
+    pub fn estimate_memory(&self, scene: &Scene, width: u32, height: u32) -> MemoryEstimate {
+        let encoding = scene.data();
+        let sizes = BufferSizes::new(encoding);
+        let pooled = |buffers: &[u64]| {
+            buffers
+                .iter()
+                .filter(|size| **size != 0)
+                .map(|size| Engine::pooled_buffer_size(*size))
+                .sum::<u64>()
+        };
+        let scene_bytes = pooled(&[sizes.scene, sizes.config]);
+        let fixed = pooled(&sizes.fixed());
+        let intermediate = pooled(&sizes.all()) - scene_bytes - fixed;
+        // Each distinct set of color stops needs a row of the ramp texture
+        let ramp_rows = encoding
+            .patches
+            .iter()
+            .map(|patch| match patch {
+                crate::encoding::resource::Patch::Ramp { stops, space, .. } => {
+                    (&encoding.color_stops[stops.clone()], *space)
+                }
+            })
+            .collect::<std::collections::HashSet<_>>();
+        let ramps = (ramp_rows.len() * crate::encoding::resource::RAMP_WIDTH * 4) as u64;
+        MemoryEstimate {
+            scene: scene_bytes,
+            intermediate,
+            fixed,
+            ramps,
+            target: width as u64 * height as u64 * 4,
+        }
+    }
+
+    /// Frees all of the GPU memory held between frames, for example when the document
+    /// being rendered is closed. Pipelines are kept, so the renderer can still be used,
+    /// and the next render allocates what it needs again.
+    pub fn release_transient_resources(&mut self) {
+        self.target = None;
+        self.readback = None;
+        self.engine.clear_pools();
+    }
+
+    /// Frees the intermediate textures kept for reuse between frames.
+    ///
+    /// Textures are freed automatically once they have gone unused for a few frames, but
+    /// applications may want to call this after a large resize to release the memory
+    /// immediately.
+    pub fn clear_target_cache(&mut self) {
+        self.target = None;
+        self.readback = None;
+        self.engine.clear_image_pool();
+    }
+
     /// Renders a scene to the target texture.
     ///
-    /// The texture is assumed to be of the specified dimensions and have been created with
-    /// the [wgpu::TextureFormat::Rgba8Unorm] format and the [wgpu::TextureUsages::STORAGE_BINDING]
-    /// flag set.
+    /// The texture must have been created with the [wgpu::TextureFormat::Rgba8Unorm] format
+    /// and the [wgpu::TextureUsages::STORAGE_BINDING] flag set. The rendered pixels are
+    /// stored with straight (not premultiplied) alpha.
+    ///
+    /// The scene is rendered into the top left `width` by `height` pixels of the texture,
+    /// which may be larger. The rest of the texture is left untouched, as nothing is cleared,
+    /// so a target can be reused for letterboxed renders of any smaller size.
+    ///
+    /// The target is either a view of a given size, or a layer of a texture with several
+    /// array layers, in which case the other layers are left untouched. A target which is
+    /// smaller than `width` by `height` returns [`RenderError::RegionOutsideTarget`].
+    ///
+    /// Returns a [`RenderError`] without rendering anything if the scene exceeds the
+    /// limits of the device.
     pub fn render_to_texture(
         &mut self,
         device: &Device,
         queue: &Queue,
         scene: &Scene,
-        texture: &TextureView,
+        texture: TargetView<'_>,
         width: u32,
         height: u32,
     ) -> Result<()> {
-        let (recording, target) = render::render_full(scene, &self.shaders, width, height);
+        self.render_to_texture_region(device, queue, scene, texture, (0, 0), (width, height))
+    }
+
+    /// Renders a scene into a region of the target texture, such as a cell of an atlas.
+    ///
+    /// The scene is rendered as if to a texture of `size`, and the result is stored with its
+    /// top left corner at `origin`. Pixels outside of the region are never written, so
+    /// scenes can be rendered into disjoint regions of the same texture one after another.
+    ///
+    /// The requirements on the texture are the same as for
+    /// [`render_to_texture`](Self::render_to_texture), and a region which is not inside
+    /// the target returns [`RenderError::RegionOutsideTarget`].
+    pub fn render_to_texture_region(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: TargetView<'_>,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> Result<()> {
+        texture.check_region(origin, size)?;
+        let layer_view = texture.layer_view();
+        let view = texture.view(&layer_view);
+        let mut render = self.submit_region(device, queue, scene, view, origin, size)?;
+        if render.run.is_some() {
+            let overflow = render.downloads.read_blocking(device, render.bump.buf)?;
+            self.finish_region(device, queue, view, &mut render, overflow)?;
+        }
+        if let Some(path) = render.capture.take() {
+            let mut buffers = Vec::with_capacity(render.bump.captured.len());
+            for buf in &render.bump.captured {
+                let data = render
+                    .downloads
+                    .read_blocking(device, *buf)?
+                    .ok_or(Error::Internal("capture could not be read back"))?;
+                buffers.push((buf.name(), data));
+            }
+            capture::write(&path, &render.bump.config, &buffers).map_err(Error::Capture)?;
+        }
+        Ok(())
+    }
+
+    /// Equivalent to [`render_to_texture`](Self::render_to_texture), but waits for the
+    /// readbacks of [`overflow_readback`](RendererOptions::overflow_readback) and
+    /// [`capture_next_frame`](Self::capture_next_frame) by returning a future rather
+    /// than blocking, so that they also work on the web.
+    ///
+    /// Without those readbacks, the future is ready once the render is submitted.
+    pub async fn render_to_texture_async(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: TargetView<'_>,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        self.render_to_texture_region_async(device, queue, scene, texture, (0, 0), (width, height))
+            .await
+    }
+
+    /// Equivalent to [`render_to_texture_region`](Self::render_to_texture_region), but
+    /// waits for readbacks like [`render_to_texture_async`](Self::render_to_texture_async).
+    pub async fn render_to_texture_region_async(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: TargetView<'_>,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> Result<()> {
+        texture.check_region(origin, size)?;
+        let layer_view = texture.layer_view();
+        let view = texture.view(&layer_view);
+        let mut render = self.submit_region(device, queue, scene, view, origin, size)?;
+        if render.run.is_some() {
+            let overflow = render.downloads.read(device, render.bump.buf).await?;
+            self.finish_region(device, queue, view, &mut render, Some(overflow))?;
+        }
+        if let Some(path) = render.capture.take() {
+            let mut buffers = Vec::with_capacity(render.bump.captured.len());
+            for buf in &render.bump.captured {
+                let data = render.downloads.read(device, *buf).await?;
+                buffers.push((buf.name(), data));
+            }
+            capture::write(&path, &render.bump.config, &buffers).map_err(Error::Capture)?;
+        }
+        Ok(())
+    }
+
+    /// Records the rendering of a scene into a region of `view` and submits it, for
+    /// [`render_to_texture_region`](Self::render_to_texture_region) and its async
+    /// equivalent, which wait for its readbacks.
+    ///
+    /// With [`overflow_readback`](RendererOptions::overflow_readback), only the stages
+    /// up to fine are submitted, and the render is completed by
+    /// [`finish_region`](Self::finish_region) once the bump allocators have been read.
+    fn submit_region(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        view: &TextureView,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> Result<RegionRender> {
+        let (recording, target, bump, capture) = self.record_region(device, scene, origin, size)?;
+        let external_resources = [ExternalResource::Image(*target.as_image().unwrap(), view)];
+        let (run, downloads) = if self.overflow_readback {
+            // Read the bump allocators back between coarse and fine
+            let mut run = PartialRun::default();
+            let downloads = self.engine.run_until(
+                device,
+                queue,
+                &mut run,
+                &recording,
+                &external_resources,
+                render::OVERFLOW_SIGNPOST,
+            )?;
+            (Some(run), downloads)
+        } else {
+            let downloads =
+                self.engine
+                    .run_recording(device, queue, &recording, &external_resources)?;
+            (None, downloads)
+        };
+        Ok(RegionRender {
+            recording,
+            target,
+            bump,
+            capture,
+            run,
+            downloads,
+        })
+    }
+
+    /// Records the overflow read back from the bump allocators of a render started by
+    /// [`submit_region`](Self::submit_region), and submits the rest of it.
+    fn finish_region(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        view: &TextureView,
+        render: &mut RegionRender,
+        overflow: Option<Vec<u8>>,
+    ) -> Result<()> {
+        self.last_overflow = overflow.and_then(|data| render.bump.overflow(&data));
+        if let Some(run) = render.run.take() {
+            let external_resources = [ExternalResource::Image(
+                *render.target.as_image().unwrap(),
+                view,
+            )];
+            render.downloads = self.engine.finish_run(
+                device,
+                queue,
+                run,
+                &render.recording,
+                &external_resources,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records the rendering of a scene into a region of a target, for
+    /// [`render_to_texture_region`](Self::render_to_texture_region) and its async
+    /// equivalent. Also returns the path of the capture requested for this frame.
+    fn record_region(
+        &mut self,
+        device: &Device,
+        scene: &Scene,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> Result<(
+        engine::Recording,
+        engine::ResourceProxy,
+        render::BumpBuffer,
+        Option<std::path::PathBuf>,
+    )> {
+        render::check_limits(scene.data(), &device.limits())?;
+        let capture = self.pending_capture.take();
+        let readback = render::Readback {
+            overflow: self.overflow_readback,
+            capture: capture.is_some(),
+        };
+        self.prepare_shaders(device, scene, size.0, size.1)?;
+        let shaders = select_shaders(
+            &self.shaders,
+            &self.small_tile_shaders,
+            self.tile_size,
+            scene,
+            size.0,
+            size.1,
+        );
+        let (recording, target, bump) = render::render_full(
+            scene,
+            &mut self.resources,
+            shaders,
+            size.0,
+            size.1,
+            origin,
+            readback,
+        );
+        Ok((recording, target, bump, capture))
+    }
+
+    /// Records the rendering of a scene into `encoder` instead of submitting it, so that
+    /// it can be ordered with other work in the caller's submission. The target has the
+    /// same requirements as for [`render_to_texture`](Self::render_to_texture).
+    ///
+    /// Uploads of the scene are copied within the encoder, so several scenes can be
+    /// recorded into the same encoder. Buffer usage can't be read back between the
+    /// stages, so with [`overflow_readback`](RendererOptions::overflow_readback) it is
+    /// downloaded at the end of the encoder, and only reported by
+    /// [`last_frame_overflow`](Self::last_frame_overflow) once
+    /// [`after_submit`](Self::after_submit) has been called. Frames are not captured in
+    /// this mode, so a capture requested with `capture_next_frame` is left for the next
+    /// call to `render_to_texture`.
+    pub fn render_to_texture_in(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        scene: &Scene,
+        texture: TargetView<'_>,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        texture.check_region((0, 0), (width, height))?;
+        render::check_limits(scene.data(), &device.limits())?;
+        let readback = render::Readback {
+            overflow: self.overflow_readback,
+            capture: false,
+        };
+        self.prepare_shaders(device, scene, width, height)?;
+        let shaders = select_shaders(
+            &self.shaders,
+            &self.small_tile_shaders,
+            self.tile_size,
+            scene,
+            width,
+            height,
+        );
+        let (recording, target, bump) = render::render_full(
+            scene,
+            &mut self.resources,
+            shaders,
+            width,
+            height,
+            (0, 0),
+            readback,
+        );
+        let layer_view = texture.layer_view();
         let external_resources = [ExternalResource::Image(
             *target.as_image().unwrap(),
-            texture,
+            texture.view(&layer_view),
         )];
-        let _ = self
-            .engine
-            .run_recording(device, queue, &recording, &external_resources)?;
+        let downloads =
+            self.engine
+                .encode_into(device, encoder, &recording, &external_resources)?;
+        if self.overflow_readback {
+            self.deferred_overflow.push((downloads, bump));
+        }
+        Ok(())
+    }
+
+    /// Finishes the renders recorded with [`render_to_texture_in`](Self::render_to_texture_in)
+    /// since the last call, which must be called after their encoder has been submitted.
+    ///
+    /// This lets the renders' staging buffers be reused. With
+    /// [`overflow_readback`](RendererOptions::overflow_readback), it also waits for the
+    /// GPU to finish the renders, and [`last_frame_overflow`](Self::last_frame_overflow)
+    /// then reports the first of them which overflowed.
+    pub fn after_submit(&mut self, device: &Device) -> Result<()> {
+        self.engine.after_submit();
+        if self.deferred_overflow.is_empty() {
+            return Ok(());
+        }
+        self.last_overflow = None;
+        for (downloads, bump) in std::mem::take(&mut self.deferred_overflow) {
+            let overflow = downloads
+                .read_blocking(device, bump.buf)?
+                .and_then(|data| bump.overflow(&data));
+            self.last_overflow = self.last_overflow.or(overflow);
+        }
+        Ok(())
+    }
+
+    /// Equivalent to [`after_submit`](Self::after_submit), but waits for the GPU by
+    /// returning a future rather than blocking, so that overflows of renders recorded
+    /// into encoders are also reported on the web.
+    pub async fn after_submit_async(&mut self, device: &Device) -> Result<()> {
+        self.engine.after_submit();
+        if self.deferred_overflow.is_empty() {
+            return Ok(());
+        }
+        self.last_overflow = None;
+        for (downloads, bump) in std::mem::take(&mut self.deferred_overflow) {
+            let data = downloads.read(device, bump.buf).await?;
+            self.last_overflow = self.last_overflow.or_else(|| bump.overflow(&data));
+        }
+        Ok(())
+    }
+
+    /// Renders several scenes, each into its own texture, in a single submission.
+    ///
+    /// This is equivalent to calling [`render_to_texture`](Self::render_to_texture) for
+    /// each job in order, but jobs share intermediate buffers. If any job can't be
+    /// rendered, an [`Error::Job`] identifying it is returned, and nothing is rendered.
+    ///
+    /// Overflows in batched jobs are not reported by
+    /// [`last_frame_overflow`](Self::last_frame_overflow).
+    pub fn render_batch(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        jobs: &[RenderJob],
+    ) -> Result<()> {
+        let limits = device.limits();
+        for (job, render_job) in jobs.iter().enumerate() {
+            render_job
+                .texture
+                .check_region((0, 0), (render_job.width, render_job.height))
+                .map_err(Error::from)
+                .and_then(|_| render::check_limits(render_job.scene.data(), &limits))
+                .map_err(|error| Error::Job {
+                    job,
+                    error: Box::new(error),
+                })?;
+        }
+        for job in jobs {
+            self.prepare_shaders(device, job.scene, job.width, job.height)?;
+        }
+        let layer_views = jobs
+            .iter()
+            .map(|job| job.texture.layer_view())
+            .collect::<Vec<_>>();
+        let recordings = jobs
+            .iter()
+            .zip(&layer_views)
+            .map(|(job, layer_view)| {
+                let shaders = select_shaders(
+                    &self.shaders,
+                    &self.small_tile_shaders,
+                    self.tile_size,
+                    job.scene,
+                    job.width,
+                    job.height,
+                );
+                let (recording, target, _) = render::render_full(
+                    job.scene,
+                    &mut self.resources,
+                    shaders,
+                    job.width,
+                    job.height,
+                    (0, 0),
+                    render::Readback::default(),
+                );
+                let external_resources = [ExternalResource::Image(
+                    *target.as_image().unwrap(),
+                    job.texture.view(layer_view),
+                )];
+                (recording, external_resources)
+            })
+            .collect::<Vec<_>>();
+        let recordings = recordings
+            .iter()
+            .map(|(recording, external_resources)| (recording, &external_resources[..]))
+            .collect::<Vec<_>>();
+        let _ = self.engine.run_recordings(device, queue, &recordings)?;
         Ok(())
     }
 
@@ -95,7 +1081,15 @@ impl Renderer {
     /// specified surface texture.
     ///
     /// The surface is assumed to be of the specified dimensions and have been created with the
-    /// [wgpu::TextureFormat::Bgra8Unorm] format.
+    /// [wgpu::TextureFormat::Bgra8Unorm] format and the
+    /// [`surface_alpha_mode`](RendererOptions::surface_alpha_mode) of the renderer.
+    /// Transparent parts of the scene stay transparent on surfaces which are
+    /// composited with alpha.
+    ///
+    /// Returns [`RenderError::SurfaceBlitUnsupported`] if the renderer was created without
+    /// [`surface_blit_support`](RendererOptions::surface_blit_support), and
+    /// [`RenderError::TargetTooLarge`] if the surface is larger than the largest texture
+    /// the device supports.
     pub fn render_to_surface(
         &mut self,
         device: &Device,
@@ -105,6 +1099,10 @@ impl Renderer {
         width: u32,
         height: u32,
     ) -> Result<()> {
+        if self.blit.is_none() {
+            return Err(RenderError::SurfaceBlitUnsupported.into());
+        }
+        check_target_size(device, width, height)?;
         let mut target = self
             .target
             .take()
@@ -114,58 +1112,270 @@ impl Renderer {
         if target.width != width || target.height != height {
             target = TargetTexture::new(device, width, height);
         }
-        self.render_to_texture(device, queue, scene, &target.view, width, height)?;
+        let view = TargetView::new(&target.view, width, height);
+        self.render_to_texture(device, queue, scene, view, width, height)?;
+        let blit = self.blit.as_ref().unwrap();
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let surface_view = surface
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor::default());
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &self.blit.bind_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&target.view),
-                }],
-            });
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &surface_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::default()),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-            render_pass.set_pipeline(&self.blit.pipeline);
-            render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
-        }
+        let surface_view = surface
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        blit.blit(device, &mut encoder, &target.view, &surface_view);
         queue.submit(Some(encoder.finish()));
         self.target = Some(target);
         Ok(())
     }
 
+    /// Renders a scene and copies the result back to the CPU, as an RGBA image of the
+    /// requested size.
+    ///
+    /// The target texture and readback buffer are kept for the next call with the
+    /// same size, so rendering many images of one size doesn't allocate for each.
+    ///
+    /// Returns [`RenderError::TargetTooLarge`] if the image is larger than the largest
+    /// texture the device supports.
+    pub async fn render_to_image(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        params: &ImageParams,
+    ) -> Result<peniko::Image> {
+        let readback = self.take_readback(device, params)?;
+        self.render_to_texture_async(
+            device,
+            queue,
+            scene,
+            TargetView::new(&readback.view, params.width, params.height),
+            params.width,
+            params.height,
+        )
+        .await?;
+        readback.copy(device, queue);
+        engine::map_buffer(device, readback.buffer.slice(..), wgpu::MapMode::Read).await?;
+        Ok(self.finish_readback(readback, params))
+    }
+
+    /// Equivalent to [`render_to_image`](Self::render_to_image), but blocks until the
+    /// image has been read back instead of returning a future.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_image_blocking(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        params: &ImageParams,
+    ) -> Result<peniko::Image> {
+        let readback = self.render_for_readback(device, queue, scene, params)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |v| {
+                let _ = sender.send(v);
+            });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|_| Error::Internal("channel was closed"))??;
+        Ok(self.finish_readback(readback, params))
+    }
+
+    /// Renders a scene into a readback target of the requested size, and submits the
+    /// copy into its buffer.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn render_for_readback(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        params: &ImageParams,
+    ) -> Result<ImageReadback> {
+        let readback = self.take_readback(device, params)?;
+        self.render_to_texture(
+            device,
+            queue,
+            scene,
+            TargetView::new(&readback.view, params.width, params.height),
+            params.width,
+            params.height,
+        )?;
+        readback.copy(device, queue);
+        Ok(readback)
+    }
+
+    /// Takes the readback kept from the last image, or creates one if it has a
+    /// different size.
+    fn take_readback(&mut self, device: &Device, params: &ImageParams) -> Result<ImageReadback> {
+        check_target_size(device, params.width, params.height)?;
+        Ok(match self.readback.take() {
+            Some(readback)
+                if readback.width == params.width && readback.height == params.height =>
+            {
+                readback
+            }
+            _ => ImageReadback::new(device, params.width, params.height),
+        })
+    }
+
+    fn finish_readback(&mut self, readback: ImageReadback, params: &ImageParams) -> peniko::Image {
+        let pixels = readback.take_pixels(params.premultiplied);
+        self.readback = Some(readback);
+        peniko::Image::new(
+            peniko::Blob::new(std::sync::Arc::new(pixels)),
+            peniko::Format::Rgba8,
+            params.width,
+            params.height,
+        )
+    }
+
+    /// Builds the shaders for small tiles, if [`TileSize::Auto`] chooses them for
+    /// rendering `scene` at the given size and they haven't been built yet.
+    fn prepare_shaders(
+        &mut self,
+        device: &Device,
+        scene: &Scene,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        if self.tile_size == TileSize::Auto
+            && self.small_tile_shaders.is_none()
+            && render::choose_tile_size(scene.data(), width, height) == TileSize::Small
+        {
+            self.small_tile_shaders = Some(shaders::full_shaders(
+                device,
+                &mut self.engine,
+                self.deterministic,
+                TileSize::Small,
+            )?);
+        }
+        if self.debug_overlay != DebugOverlay::None {
+            for shaders in std::iter::once(&mut self.shaders).chain(&mut self.small_tile_shaders) {
+                if shaders.debug_fine.is_none() {
+                    shaders.debug_fine = Some(shaders::debug_fine_shader(
+                        device,
+                        &mut self.engine,
+                        self.deterministic,
+                        shaders.tile_size,
+                        self.debug_overlay,
+                    )?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws `overlay` over the scenes rendered from now on, or stops drawing an
+    /// overlay if it is [`DebugOverlay::None`].
+    ///
+    /// The overlay is drawn by a variant of the fine shader which is built when it is
+    /// first needed, so that rendering without an overlay has no cost.
+    pub fn set_debug_overlay(&mut self, overlay: DebugOverlay) {
+        if overlay == self.debug_overlay {
+            return;
+        }
+        self.debug_overlay = overlay;
+        self.shaders.debug_fine = None;
+        if let Some(small_tile_shaders) = &mut self.small_tile_shaders {
+            small_tile_shaders.debug_fine = None;
+        }
+    }
+
+    /// Returns the overlay set by [`set_debug_overlay`](Self::set_debug_overlay).
+    pub fn debug_overlay(&self) -> DebugOverlay {
+        self.debug_overlay
+    }
+
+    /// Describes how `scene` is rendered at the given size on the adapter described
+    /// by `adapter`, as a compact JSON object.
+    ///
+    /// The object holds the crate version, the adapter's name, driver and backend, the
+    /// options and shader variants used for the scene, and its [`SceneStats`]. It can
+    /// be embedded into rendered images, so that unexpected output can be traced back
+    /// to what produced it.
+    pub fn metadata(
+        &self,
+        adapter: &wgpu::AdapterInfo,
+        scene: &Scene,
+        width: u32,
+        height: u32,
+    ) -> String {
+        let selected_tile_size = match self.tile_size {
+            TileSize::Auto => render::choose_tile_size(scene.data(), width, height),
+            tile_size => tile_size,
+        };
+        metadata::Metadata {
+            adapter,
+            width,
+            height,
+            tile_size: self.tile_size,
+            selected_tile_size,
+            deterministic: self.deterministic,
+            overflow_readback: self.overflow_readback,
+            debug_overlay: self.debug_overlay,
+            scene: scene.stats(),
+        }
+        .to_json()
+    }
+
     /// Reload the shaders. This should only be used during `vello` development
     #[cfg(feature = "hot_reload")]
     pub async fn reload_shaders(&mut self, device: &Device) -> Result<()> {
-        device.push_error_scope(wgpu::ErrorFilter::Validation);
         let mut engine = Engine::new();
-        let shaders = shaders::full_shaders(device, &mut engine)?;
-        let error = device.pop_error_scope().await;
-        if let Some(error) = error {
-            return Err(error.into());
+        engine.capture_shader_errors();
+        let shaders =
+            shaders::full_shaders(device, &mut engine, self.deterministic, self.tile_size)?;
+        if let Some((shader, error)) = engine.shader_error().await {
+            return Err(Error::ShaderCompilation {
+                shader,
+                detail: error.to_string(),
+            });
         }
         self.engine = engine;
         self.shaders = shaders;
+        // The small tile shaders belonged to the old engine, so they are built again
+        // when they are next needed
+        self.small_tile_shaders = None;
         Ok(())
     }
 }
 
+/// Returns the shaders of a [`Renderer`] to render `scene` at the given size with,
+/// after [`Renderer::prepare_shaders`].
+fn select_shaders<'a>(
+    shaders: &'a FullShaders,
+    small_tile_shaders: &'a Option<FullShaders>,
+    tile_size: TileSize,
+    scene: &Scene,
+    width: u32,
+    height: u32,
+) -> &'a FullShaders {
+    match small_tile_shaders {
+        Some(small_tile_shaders)
+            if tile_size == TileSize::Auto
+                && render::choose_tile_size(scene.data(), width, height) == TileSize::Small =>
+        {
+            small_tile_shaders
+        }
+        _ => shaders,
+    }
+}
+
+/// Checks that a texture of the given size can be allocated on `device`, since
+/// creating one which is too large is a validation error rather than a failure which
+/// can be handled.
+fn check_target_size(device: &Device, width: u32, height: u32) -> Result<(), RenderError> {
+    let limit = device.limits().max_texture_dimension_2d;
+    if width > limit || height > limit {
+        return Err(RenderError::TargetTooLarge {
+            width,
+            height,
+            limit,
+        });
+    }
+    Ok(())
+}
+
 struct TargetTexture {
     view: TextureView,
     width: u32,
@@ -196,102 +1406,150 @@ impl TargetTexture {
     }
 }
 
-struct BlitPipeline {
-    bind_layout: wgpu::BindGroupLayout,
-    pipeline: wgpu::RenderPipeline,
-}
+#[cfg(test)]
+mod tests {
+    use peniko::kurbo::{Affine, Rect};
+    use peniko::{Color, Fill};
 
-impl BlitPipeline {
-    fn new(device: &Device, format: TextureFormat) -> Self {
-        const SHADERS: &str = r#"
-            @vertex
-            fn vs_main(@builtin(vertex_index) ix: u32) -> @builtin(position) vec4<f32> {
-                // Generate a full screen quad in NDCs
-                var vertex = vec2(-1.0, 1.0);
-                switch ix {
-                    case 1u: {
-                        vertex = vec2(-1.0, -1.0);
-                    }
-                    case 2u, 4u: {
-                        vertex = vec2(1.0, -1.0);
-                    }
-                    case 5u: {
-                        vertex = vec2(1.0, 1.0);
-                    }
-                    default: {}
-                }
-                return vec4(vertex, 0.0, 1.0);
-            }
-            
-            @group(0) @binding(0)
-            var fine_output: texture_2d<f32>;
-            
-            @fragment
-            fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
-                let rgba_sep = textureLoad(fine_output, vec2<i32>(pos.xy), 0);
-                return vec4(rgba_sep.rgb * rgba_sep.a, rgba_sep.a);
+    use super::*;
+    use crate::gpu_test::pixel;
+
+    #[test]
+    fn draw_bbox_overlay_outlines_bounding_box() {
+        let Some(handle) = gpu_test::device() else {
+            eprintln!("skipping: no adapter");
+            return;
+        };
+        let mut scene = Scene::new();
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        let blue = Color::rgb8(0, 0, 255);
+        let rect = Rect::new(10.0, 12.0, 30.0, 26.0);
+        builder.fill(Fill::NonZero, Affine::IDENTITY, blue, None, &rect);
+        builder.finish();
+        let mut renderer = gpu_test::renderer(&handle);
+        renderer.set_debug_overlay(DebugOverlay::DrawBboxes);
+        let pixels = gpu_test::render(&handle, &mut renderer, &scene, 40, 40);
+        let magenta = [255, 0, 255, 255];
+        for y in 0..40 {
+            for x in 0..40 {
+                let inside = (10..30).contains(&x) && (12..26).contains(&y);
+                let edge = inside && (x == 10 || x == 29 || y == 12 || y == 25);
+                let expected = if edge {
+                    magenta
+                } else if inside {
+                    [0, 0, 255, 255]
+                } else {
+                    [0; 4]
+                };
+                assert_eq!(pixel(&pixels, 40, x, y), expected, "pixel {x}, {y}");
             }
-        "#;
+        }
+    }
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("blit shaders"),
-            source: wgpu::ShaderSource::Wgsl(SHADERS.into()),
-        });
-        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                binding: 0,
-                ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    multisampled: false,
-                },
-                count: None,
-            }],
-        });
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&bind_layout],
-            push_constant_ranges: &[],
-        });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
+    #[test]
+    fn region_renders_leave_the_rest_of_the_target_untouched() {
+        let Some(handle) = gpu_test::device() else {
+            eprintln!("skipping: no adapter");
+            return;
+        };
+        let texture = handle.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("sentinel target"),
+            size: wgpu::Extent3d {
+                width: 512,
+                height: 512,
+                depth_or_array_layers: 1,
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        });
+        let sentinel = [12, 34, 56, 78];
+        handle.queue.write_texture(
+            texture.as_image_copy(),
+            &sentinel.repeat(512 * 512),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(512 * 4),
+                rows_per_image: None,
             },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+            wgpu::Extent3d {
+                width: 512,
+                height: 512,
+                depth_or_array_layers: 1,
             },
-            multiview: None,
-        });
-        Self {
-            bind_layout,
-            pipeline,
+        );
+        let mut scene = Scene::new();
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        let rect = Rect::new(0.0, 0.0, 512.0, 512.0);
+        builder.fill(Fill::NonZero, Affine::IDENTITY, Color::WHITE, None, &rect);
+        builder.finish();
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let target = TargetView::new(&view, 512, 512);
+        let mut renderer = gpu_test::renderer(&handle);
+        renderer
+            .render_to_texture(&handle.device, &handle.queue, &scene, target, 200, 100)
+            .unwrap();
+        let pixels = gpu_test::read_texture(&handle, &texture, 512, 512);
+        for y in 0..512 {
+            for x in 0..512 {
+                let expected = if x < 200 && y < 100 {
+                    [255; 4]
+                } else {
+                    sentinel
+                };
+                assert_eq!(pixel(&pixels, 512, x, y), expected, "pixel {x}, {y}");
+            }
         }
+        let outside = renderer.render_to_texture_region(
+            &handle.device,
+            &handle.queue,
+            &scene,
+            target,
+            (400, 0),
+            (200, 100),
+        );
+        assert!(matches!(
+            outside,
+            Err(Error::Render(RenderError::RegionOutsideTarget { .. }))
+        ));
+    }
+
+    #[test]
+    fn sparse_fine_matches_dense_fine() {
+        let Some(handle) = gpu_test::device() else {
+            eprintln!("skipping: no adapter");
+            return;
+        };
+        // A single small shape on a large target, so that most tiles draw nothing
+        let mut scene = Scene::new();
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        let circle = peniko::kurbo::Circle::new((64.0, 64.0), 32.0);
+        let blue = Color::rgb8(0x40, 0x80, 0xff);
+        builder.fill(Fill::NonZero, Affine::IDENTITY, blue, None, &circle);
+        builder.finish();
+        let options = RendererOptions {
+            surface_blit_support: false,
+            deterministic: true,
+            ..Default::default()
+        };
+        let mut renderer = Renderer::new(&handle.device, &options).unwrap();
+        let sparse = gpu_test::render(&handle, &mut renderer, &scene, 4096, 4096);
+        assert_eq!(pixel(&sparse, 4096, 64, 64), [0x40, 0x80, 0xff, 0xff]);
+        assert_eq!(pixel(&sparse, 4096, 4000, 4000), [0; 4]);
+        renderer.shaders.dense_fine = Some(
+            shaders::dense_fine_shader(
+                &handle.device,
+                &mut renderer.engine,
+                true,
+                renderer.shaders.tile_size,
+            )
+            .unwrap(),
+        );
+        let dense = gpu_test::render(&handle, &mut renderer, &scene, 4096, 4096);
+        assert!(sparse == dense, "sparse and dense renders differ");
     }
 }
@@ -0,0 +1,126 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Descriptions of how a frame was rendered, as returned by
+//! [`Renderer::metadata`](crate::Renderer::metadata).
+
+use std::fmt::{Display, Write as _};
+
+use crate::{DebugOverlay, SceneStats, TileSize};
+
+/// Everything described by the metadata of a frame.
+pub(crate) struct Metadata<'a> {
+    pub adapter: &'a wgpu::AdapterInfo,
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: TileSize,
+    pub selected_tile_size: TileSize,
+    pub deterministic: bool,
+    pub overflow_readback: bool,
+    pub debug_overlay: DebugOverlay,
+    pub scene: SceneStats,
+}
+
+impl Metadata<'_> {
+    /// Writes the metadata as a compact JSON object. Strings only contain ASCII, so
+    /// that the object can be stored in a PNG `tEXt` chunk, which is Latin-1.
+    pub fn to_json(&self) -> String {
+        let adapter = self.adapter;
+        let mut adapter_json = Object::new();
+        adapter_json
+            .string("name", &adapter.name)
+            .field("vendor", adapter.vendor)
+            .field("device", adapter.device)
+            .string("device_type", &format!("{:?}", adapter.device_type))
+            .string("driver", &adapter.driver)
+            .string("driver_info", &adapter.driver_info)
+            .string("backend", &format!("{:?}", adapter.backend));
+        let mut options = Object::new();
+        options
+            .string("tile_size", &format!("{:?}", self.tile_size))
+            .string(
+                "selected_tile_size",
+                &format!("{:?}", self.selected_tile_size),
+            )
+            .field("deterministic", self.deterministic)
+            .field("overflow_readback", self.overflow_readback)
+            .string("debug_overlay", &format!("{:?}", self.debug_overlay))
+            // Coverage is always computed analytically from the area of each pixel
+            // which a path covers
+            .string("antialiasing", "area");
+        let stats = &self.scene;
+        let mut scene = Object::new();
+        scene
+            .field("path_tags", stats.path_tags)
+            .field("path_data_bytes", stats.path_data_bytes)
+            .field("draw_objects", stats.draw_objects)
+            .field("transforms", stats.transforms)
+            .field("color_stops", stats.color_stops)
+            .field("total_bytes", stats.total_bytes);
+        let mut metadata = Object::new();
+        metadata
+            .string("vello", env!("CARGO_PKG_VERSION"))
+            .field("width", self.width)
+            .field("height", self.height)
+            .field("adapter", adapter_json.finish())
+            .field("options", options.finish())
+            .field("scene", scene.finish());
+        metadata.finish()
+    }
+}
+
+/// Builder for a JSON object.
+struct Object(String);
+
+impl Object {
+    fn new() -> Self {
+        Self(String::from("{"))
+    }
+
+    /// Adds a field whose value is written as it is displayed, such as a number or
+    /// another object.
+    fn field(&mut self, name: &str, value: impl Display) -> &mut Self {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        let _ = write!(self.0, "\"{name}\":{value}");
+        self
+    }
+
+    fn string(&mut self, name: &str, value: &str) -> &mut Self {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                ' '..='~' => quoted.push(c),
+                _ => {
+                    let mut units = [0; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        let _ = write!(quoted, "\\u{unit:04x}");
+                    }
+                }
+            }
+        }
+        quoted.push('"');
+        self.field(name, quoted)
+    }
+
+    fn finish(&self) -> String {
+        format!("{}}}", self.0)
+    }
+}
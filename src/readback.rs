@@ -0,0 +1,122 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! A render target which can be copied back to the CPU.
+
+use std::num::NonZeroU32;
+
+use wgpu::{Buffer, Device, Queue, Texture, TextureView};
+
+/// Target texture and readback buffer, which are reused between renders of the same
+/// size.
+pub struct ImageReadback {
+    texture: Texture,
+    pub view: TextureView,
+    pub buffer: Buffer,
+    pub width: u32,
+    pub height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl ImageReadback {
+    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("readback target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Rows of a texture to buffer copy must be aligned to 256 bytes
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (width * 4 + align - 1) / align * align;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback buffer"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            texture,
+            view,
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Returns the size in bytes of the texture and buffer.
+    pub fn byte_size(&self) -> u64 {
+        let texture = self.width as u64 * self.height as u64 * 4;
+        texture + self.padded_bytes_per_row as u64 * self.height as u64
+    }
+
+    /// Submits a copy of the target texture into the readback buffer.
+    pub fn copy(&self, device: &Device, queue: &Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("readback copy"),
+        });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Returns the tightly packed RGBA pixels of the mapped readback buffer, and unmaps
+    /// it. The pixels are premultiplied by alpha if `premultiplied` is true.
+    pub fn take_pixels(&self, premultiplied: bool) -> Vec<u8> {
+        let row_bytes = self.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+        if premultiplied {
+            for pixel in pixels.chunks_exact_mut(4) {
+                let alpha = pixel[3] as u32;
+                for channel in &mut pixel[..3] {
+                    *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+                }
+            }
+        }
+        pixels
+    }
+}
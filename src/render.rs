@@ -1,52 +1,77 @@
 //! Take an encoded scene and create a graph to render it
 
-use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
 
 use crate::{
-    encoding::Encoding,
+    encoding::{
+        resource::ResourceCache, Config, DrawMonoid, Encoding, Layout, PathBbox, PathMonoid,
+        PathSegment, Transform,
+    },
     engine::{BufProxy, ImageFormat, ImageProxy, Recording, ResourceProxy},
-    shaders::{self, FullShaders, Shaders},
-    Scene,
+    shaders::{
+        self,
+        types::{Bic, BinHeader, BumpAllocators, ClipEl, ClipInp, Cubic, Path, TagMonoid, Tile},
+        FullShaders, Shaders,
+    },
+    Error, RenderError, Scene, TileSize,
 };
 
-const TAG_MONOID_SIZE: u64 = 12;
-const TAG_MONOID_FULL_SIZE: u64 = 20;
-const PATH_BBOX_SIZE: u64 = 24;
-const CUBIC_SIZE: u64 = 48;
-const DRAWMONOID_SIZE: u64 = 16;
-const MAX_DRAWINFO_SIZE: u64 = 44;
-const CLIP_BIC_SIZE: u64 = 8;
-const CLIP_EL_SIZE: u64 = 32;
-const CLIP_INP_SIZE: u64 = 8;
-const CLIP_BBOX_SIZE: u64 = 16;
-const PATH_SIZE: u64 = 32;
-const DRAW_BBOX_SIZE: u64 = 16;
-const BUMP_SIZE: u64 = std::mem::size_of::<BumpAllocators>() as u64;
-const BIN_HEADER_SIZE: u64 = 8;
-const TILE_SIZE: u64 = 8;
-const SEGMENT_SIZE: u64 = 24;
-
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
-struct Config {
-    width_in_tiles: u32,
-    height_in_tiles: u32,
-    target_width: u32,
-    target_height: u32,
-    n_drawobj: u32,
-    n_path: u32,
-    n_clip: u32,
-    bin_data_start: u32,
-    pathtag_base: u32,
-    pathdata_base: u32,
-    drawtag_base: u32,
-    drawdata_base: u32,
-    transform_base: u32,
-    linewidth_base: u32,
-}
+const TAG_MONOID_SIZE: u64 = size_of::<TagMonoid>() as u64;
+const TAG_MONOID_FULL_SIZE: u64 = size_of::<PathMonoid>() as u64;
+const PATH_BBOX_SIZE: u64 = size_of::<PathBbox>() as u64;
+const CUBIC_SIZE: u64 = size_of::<Cubic>() as u64;
+const DRAWMONOID_SIZE: u64 = size_of::<DrawMonoid>() as u64;
+const MAX_DRAWINFO_SIZE: u64 = 56;
+const CLIP_BIC_SIZE: u64 = size_of::<Bic>() as u64;
+const CLIP_EL_SIZE: u64 = size_of::<ClipEl>() as u64;
+const CLIP_INP_SIZE: u64 = size_of::<ClipInp>() as u64;
+// Clip and draw bounding boxes are stored as vec4<f32>
+const CLIP_BBOX_SIZE: u64 = size_of::<[f32; 4]>() as u64;
+const PATH_SIZE: u64 = size_of::<Path>() as u64;
+const DRAW_BBOX_SIZE: u64 = size_of::<[f32; 4]>() as u64;
+const BUMP_SIZE: u64 = size_of::<BumpAllocators>() as u64;
+const BIN_HEADER_SIZE: u64 = size_of::<BinHeader>() as u64;
+const TILE_SIZE: u64 = size_of::<Tile>() as u64;
+const SEGMENT_SIZE: u64 = size_of::<PathSegment>() as u64;
+
+// Sizes in bytes of the buffers whose size doesn't depend on the scene
+const INFO_BIN_DATA_BUF_SIZE: u64 = 1 << 20;
+const TILE_BUF_SIZE: u64 = 1 << 24;
+const SEGMENTS_BUF_SIZE: u64 = 1 << 26;
+const PTCL_BUF_SIZE: u64 = 1 << 25;
+
+// This must be kept in sync with the constant in shader/shared/ptcl.wgsl
+const PTCL_INITIAL_ALLOC: u32 = 64;
+
+// These must be kept in sync with the constants in shader/shared/ptcl.wgsl
+const TILE_QUEUE_HEADER: u32 = 2;
+const TILE_QUEUE_WIDTH: u32 = 256;
+
+// Every tile has an initial allocation in the per tile command lists, so the tile
+// queue holds as many tiles as fit in them
+const TILE_QUEUE_BUF_SIZE: u64 =
+    (TILE_QUEUE_HEADER as u64 + PTCL_BUF_SIZE / (4 * PTCL_INITIAL_ALLOC as u64)) * 4;
+
+/// Arguments of the indirect dispatches of fine and of the clear stage, before coarse
+/// adds a row of workgroups to each for every [`TILE_QUEUE_WIDTH`] tiles it queues.
+const FINE_ARGS: [u32; 6] = [TILE_QUEUE_WIDTH, 0, 1, TILE_QUEUE_WIDTH, 0, 1];
+const FINE_ARGS_SIZE: u64 = size_of::<[u32; 6]>() as u64;
+
+/// Signpost after the stages which can overflow their buffers, in recordings which
+/// read back the bump allocators.
+pub const OVERFLOW_SIGNPOST: &str = "overflow";
+
+/// Number of workgroups in a single dimension of a dispatch which every device
+/// supports.
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+/// Median area of the bounding boxes of paths, in square pixels, below which
+/// [`TileSize::Auto`] chooses small tiles. The median is only known to be below a
+/// power of two, so this is one.
+const SMALL_TILES_MAX_MEDIAN_AREA: f64 = 32.0 * 32.0;
 
 fn size_to_words(byte_size: usize) -> u32 {
-    (byte_size / std::mem::size_of::<u32>()) as u32
+    (byte_size / size_of::<u32>()) as u32
 }
 
 pub const fn next_multiple_of(val: u32, rhs: u32) -> u32 {
@@ -56,18 +81,89 @@ pub const fn next_multiple_of(val: u32, rhs: u32) -> u32 {
     }
 }
 
-// This must be kept in sync with the struct in shader/shared/bump.wgsl
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
-struct BumpAllocators {
-    failed: u32,
-    // Final needed dynamic size of the buffers. If any of these are larger than the corresponding `_size` element
-    // reallocation needs to occur
-    binning: u32,
-    ptcl: u32,
-    tile: u32,
-    segments: u32,
-    blend: u32,
+/// Usage of a buffer which is filled by a bump allocator on the GPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferUsage {
+    /// Number of elements the stage tried to allocate.
+    pub needed: u32,
+    /// Number of elements which fit in the buffer.
+    pub capacity: u32,
+}
+
+impl BufferUsage {
+    /// Whether the stage tried to allocate more than fits in the buffer.
+    pub fn overflowed(&self) -> bool {
+        self.needed > self.capacity
+    }
+}
+
+/// Usage of the dynamically sized buffers of a frame which overflowed at least one of
+/// them, as reported by [`Renderer::last_frame_overflow`](crate::Renderer::last_frame_overflow).
+///
+/// Writes past the end of a buffer are discarded and the stages after the first one
+/// which overflowed are skipped, so the usage reported for those stages is not
+/// meaningful. Rendering again with larger buffers may report further overflows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverflowInfo {
+    /// Bin data written by binning, in words.
+    pub bin_data: BufferUsage,
+    /// Tiles allocated by tile allocation.
+    pub tiles: BufferUsage,
+    /// Line segments written by path coarse rasterization.
+    pub segments: BufferUsage,
+    /// Per tile command list words written by coarse rasterization.
+    pub ptcl: BufferUsage,
+}
+
+/// Results which a recording made by [`render_encoding_full`] downloads.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Readback {
+    /// Download the bump allocators before fine, at [`OVERFLOW_SIGNPOST`].
+    pub overflow: bool,
+    /// Download every intermediate buffer at the end of the recording, see
+    /// [`BumpBuffer::captured`].
+    pub capture: bool,
+}
+
+/// The bump allocator buffer of a rendering, and the capacities it is checked against.
+pub struct BumpBuffer {
+    pub buf: BufProxy,
+    pub config: Config,
+    /// The intermediate buffers which are downloaded when capturing the frame, with
+    /// their contents at the end of the frame.
+    pub captured: Vec<BufProxy>,
+}
+
+impl BumpBuffer {
+    /// Interprets the downloaded contents of the buffer, returning `None` if no stage
+    /// overflowed.
+    pub fn overflow(&self, data: &[u8]) -> Option<OverflowInfo> {
+        let bump: BumpAllocators = bytemuck::pod_read_unaligned(data.get(..BUMP_SIZE as usize)?);
+        if bump.failed == 0 {
+            return None;
+        }
+        let config = &self.config;
+        let ptcl_dyn_start = config.width_in_tiles * config.height_in_tiles * PTCL_INITIAL_ALLOC;
+        Some(OverflowInfo {
+            bin_data: BufferUsage {
+                needed: bump.binning,
+                capacity: config.binning_size,
+            },
+            tiles: BufferUsage {
+                needed: bump.tile,
+                capacity: config.tiles_size,
+            },
+            // The first segment is reserved as the target of discarded writes
+            segments: BufferUsage {
+                needed: bump.segments.saturating_add(1),
+                capacity: config.segments_size,
+            },
+            ptcl: BufferUsage {
+                needed: ptcl_dyn_start.saturating_add(bump.ptcl),
+                capacity: config.ptcl_size,
+            },
+        })
+    }
 }
 
 #[allow(unused)]
@@ -89,8 +185,11 @@ fn render(scene: &Scene, shaders: &Shaders) -> (Recording, BufProxy) {
         height_in_tiles: 64,
         target_width: 64 * 16,
         target_height: 64 * 16,
-        pathtag_base,
-        pathdata_base,
+        layout: Layout {
+            path_tag_base: pathtag_base,
+            path_data_base: pathdata_base,
+            ..Default::default()
+        },
         ..Default::default()
     };
     let scene_buf = recording.upload("scene", scene);
@@ -148,29 +247,216 @@ fn render(scene: &Scene, shaders: &Shaders) -> (Recording, BufProxy) {
     (recording, out_buf)
 }
 
+/// Sizes in bytes of the buffers used to render an encoding.
+///
+/// The render graph, the device limit checks and
+/// [`Renderer::estimate_memory`](crate::Renderer::estimate_memory) all take their
+/// sizes from here, so they can't disagree. Buffers which are not needed for the
+/// encoding have a size of zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferSizes {
+    /// Packed scene data.
+    pub scene: u64,
+    /// Uniform configuration.
+    pub config: u64,
+    /// Path tag reduction.
+    pub path_reduced: u64,
+    /// Second level of the path tag reduction, for large scenes.
+    pub path_reduced2: u64,
+    /// Scan of the path tag reduction, for large scenes.
+    pub path_reduced_scan: u64,
+    /// Path tag monoids.
+    pub path_monoids: u64,
+    /// Path bounding boxes.
+    pub path_bboxes: u64,
+    /// Flattened cubics.
+    pub cubics: u64,
+    /// Draw tag reduction.
+    pub draw_reduced: u64,
+    /// Draw monoids.
+    pub draw_monoids: u64,
+    /// Clip inputs.
+    pub clip_inps: u64,
+    /// Clip reduction elements.
+    pub clip_els: u64,
+    /// Clip reduction bicyclic monoids.
+    pub clip_bics: u64,
+    /// Clip bounding boxes.
+    pub clip_bboxes: u64,
+    /// Draw object bounding boxes.
+    pub draw_bboxes: u64,
+    /// Bump allocators.
+    pub bump: u64,
+    /// Bin headers.
+    pub bin_headers: u64,
+    /// Paths.
+    pub paths: u64,
+    /// Draw info followed by bin data.
+    pub info_bin_data: u64,
+    /// Tiles.
+    pub tiles: u64,
+    /// Line segments.
+    pub segments: u64,
+    /// Per tile command lists.
+    pub ptcl: u64,
+    /// Queues of the tiles which fine draws and of those which are only cleared.
+    pub tile_queue: u64,
+    /// Indirect dispatch arguments of fine and of the clear stage.
+    pub fine_args: u64,
+}
+
+impl BufferSizes {
+    /// Computes the buffer sizes for an encoding.
+    pub fn new(encoding: &Encoding) -> Self {
+        let n_pathtag = encoding.path_tags.len();
+        let pathtag_padded = align_up(n_pathtag, 4 * shaders::PATHTAG_REDUCE_WG);
+        let pathtag_wgs = (pathtag_padded / (4 * shaders::PATHTAG_REDUCE_WG as usize)) as u64;
+        let pathtag_large = pathtag_wgs > shaders::PATHTAG_REDUCE_WG as u64;
+        let reduced_size = if pathtag_large {
+            align_up(pathtag_wgs as usize, shaders::PATHTAG_REDUCE_WG) as u64
+        } else {
+            pathtag_wgs
+        };
+        let n_paths = encoding.n_paths as u64;
+        let n_clips = encoding.n_clips as u64;
+        let drawobj_wgs =
+            (n_paths + shaders::PATH_BBOX_WG as u64 - 1) / shaders::PATH_BBOX_WG as u64;
+        let info_size = encoding
+            .draw_tags
+            .iter()
+            .map(|tag| tag.info_size())
+            .sum::<u32>();
+        let binning_size = (INFO_BIN_DATA_BUF_SIZE as u32 / 4).saturating_sub(info_size);
+        let scene = pathtag_padded as u64
+            + encoding.path_data.len() as u64
+            + encoding.draw_tags.len() as u64 * 4
+            + encoding.draw_data.len() as u64
+            + (encoding.transforms.len() * size_of::<Transform>()) as u64
+            + encoding.linewidths.len() as u64 * 4
+            + encoding.path_offsets_size() as u64;
+        Self {
+            scene,
+            config: size_of::<Config>() as u64,
+            path_reduced: reduced_size * TAG_MONOID_FULL_SIZE,
+            path_reduced2: if pathtag_large {
+                shaders::PATHTAG_REDUCE_WG as u64 * TAG_MONOID_FULL_SIZE
+            } else {
+                0
+            },
+            path_reduced_scan: if pathtag_large {
+                pathtag_wgs * TAG_MONOID_FULL_SIZE
+            } else {
+                0
+            },
+            path_monoids: pathtag_wgs * shaders::PATHTAG_REDUCE_WG as u64 * TAG_MONOID_FULL_SIZE,
+            path_bboxes: n_paths * PATH_BBOX_SIZE,
+            cubics: n_pathtag as u64 * CUBIC_SIZE,
+            draw_reduced: drawobj_wgs * DRAWMONOID_SIZE,
+            draw_monoids: n_paths * DRAWMONOID_SIZE,
+            clip_inps: n_clips * CLIP_INP_SIZE,
+            clip_els: n_clips * CLIP_EL_SIZE,
+            clip_bics: (n_clips / shaders::CLIP_REDUCE_WG as u64) * CLIP_BIC_SIZE,
+            clip_bboxes: n_clips * CLIP_BBOX_SIZE,
+            draw_bboxes: n_paths * DRAW_BBOX_SIZE,
+            bump: BUMP_SIZE,
+            bin_headers: 256 * drawobj_wgs * BIN_HEADER_SIZE,
+            // Note: this only needs to be rounded up because of the workaround to store the
+            // tile_offset in storage rather than workgroup memory.
+            paths: align_up(n_paths as usize, 256) as u64 * PATH_SIZE,
+            info_bin_data: (info_size + binning_size) as u64 * 4,
+            tiles: TILE_BUF_SIZE,
+            segments: SEGMENTS_BUF_SIZE,
+            ptcl: PTCL_BUF_SIZE,
+            tile_queue: TILE_QUEUE_BUF_SIZE,
+            fine_args: FINE_ARGS_SIZE,
+        }
+    }
+
+    /// Returns the sizes of every buffer.
+    pub fn all(&self) -> [u64; 24] {
+        [
+            self.scene,
+            self.config,
+            self.path_reduced,
+            self.path_reduced2,
+            self.path_reduced_scan,
+            self.path_monoids,
+            self.path_bboxes,
+            self.cubics,
+            self.draw_reduced,
+            self.draw_monoids,
+            self.clip_inps,
+            self.clip_els,
+            self.clip_bics,
+            self.clip_bboxes,
+            self.draw_bboxes,
+            self.bump,
+            self.bin_headers,
+            self.paths,
+            self.info_bin_data,
+            self.tiles,
+            self.segments,
+            self.ptcl,
+            self.tile_queue,
+            self.fine_args,
+        ]
+    }
+
+    /// Returns the sizes of the buffers whose size doesn't depend on the scene.
+    pub fn fixed(&self) -> [u64; 7] {
+        [
+            self.bump,
+            self.info_bin_data,
+            self.tiles,
+            self.segments,
+            self.ptcl,
+            self.tile_queue,
+            self.fine_args,
+        ]
+    }
+
+    /// Returns the size of the largest buffer.
+    pub fn max(&self) -> u64 {
+        self.all().into_iter().max().unwrap_or_default()
+    }
+}
+
 pub fn render_full(
     scene: &Scene,
+    resources: &mut ResourceCache,
     shaders: &FullShaders,
     width: u32,
     height: u32,
-) -> (Recording, ResourceProxy) {
-    render_encoding_full(scene.data(), shaders, width, height)
+    origin: (u32, u32),
+    readback: Readback,
+) -> (Recording, ResourceProxy, BumpBuffer) {
+    render_encoding_full(
+        scene.data(),
+        resources,
+        shaders,
+        width,
+        height,
+        origin,
+        readback,
+    )
 }
 
 pub fn render_encoding_full(
     encoding: &Encoding,
+    resources: &mut ResourceCache,
     shaders: &FullShaders,
     width: u32,
     height: u32,
-) -> (Recording, ResourceProxy) {
-    use crate::encoding::{resource::ResourceCache, PackedEncoding};
+    origin: (u32, u32),
+    readback: Readback,
+) -> (Recording, ResourceProxy, BumpBuffer) {
+    use crate::encoding::PackedEncoding;
     let mut recording = Recording::default();
-    let mut resources = ResourceCache::new();
     let mut packed = PackedEncoding::default();
-    packed.pack(encoding, &mut resources);
+    packed.pack(encoding, resources);
     let (ramp_data, ramps_width, ramps_height) = resources.ramps(packed.resources).unwrap();
     let gradient_image = if encoding.patches.is_empty() {
-        ResourceProxy::new_image(1, 1, ImageFormat::Rgba8)
+        ResourceProxy::Image(recording.upload_image(1, 1, ImageFormat::Rgba8, [0; 4]))
     } else {
         let data: &[u8] = bytemuck::cast_slice(ramp_data);
         ResourceProxy::Image(recording.upload_image(
@@ -187,33 +473,33 @@ pub fn render_encoding_full(
     let n_drawobj = n_paths;
     let n_clip = encoding.n_clips;
 
-    let new_width = next_multiple_of(width, 16);
-    let new_height = next_multiple_of(height, 16);
+    let tile_size = shaders.tile_size.pixels();
+    let new_width = next_multiple_of(width, tile_size);
+    let new_height = next_multiple_of(height, tile_size);
 
+    let sizes = BufferSizes::new(encoding);
     let info_size = packed.layout.bin_data_start;
-    let config = crate::encoding::Config {
-        width_in_tiles: new_width / 16,
-        height_in_tiles: new_height / 16,
+    let config = Config {
+        width_in_tiles: new_width / tile_size,
+        height_in_tiles: new_height / tile_size,
         target_width: width,
         target_height: height,
-        binning_size: ((1 << 20) / 4) - info_size,
-        tiles_size: (1 << 24) / TILE_SIZE as u32,
-        segments_size: (1 << 26) / SEGMENT_SIZE as u32,
-        ptcl_size: (1 << 25) / 4,
+        binning_size: (INFO_BIN_DATA_BUF_SIZE as u32 / 4).saturating_sub(info_size),
+        tiles_size: (TILE_BUF_SIZE / TILE_SIZE) as u32,
+        segments_size: (SEGMENTS_BUF_SIZE / SEGMENT_SIZE) as u32,
+        ptcl_size: (PTCL_BUF_SIZE / 4) as u32,
         layout: packed.layout,
+        target_x: origin.0,
+        target_y: origin.1,
     };
     // println!("{:?}", config);
     let scene_buf = ResourceProxy::Buf(recording.upload("scene", packed.data));
     let config_buf =
         ResourceProxy::Buf(recording.upload_uniform("config", bytemuck::bytes_of(&config)));
-    let info_bin_data_buf = ResourceProxy::new_buf(
-        (info_size + config.binning_size) as u64 * 4,
-        "info_bin_data_buf",
-    );
-    let tile_buf = ResourceProxy::new_buf(config.tiles_size as u64 * TILE_SIZE, "tile_buf");
-    let segments_buf =
-        ResourceProxy::new_buf(config.segments_size as u64 * SEGMENT_SIZE, "segments_buf");
-    let ptcl_buf = ResourceProxy::new_buf(config.ptcl_size as u64 * 4, "ptcl_buf");
+    let info_bin_data_buf = ResourceProxy::new_buf(sizes.info_bin_data, "info_bin_data_buf");
+    let tile_buf = ResourceProxy::new_buf(sizes.tiles, "tile_buf");
+    let segments_buf = ResourceProxy::new_buf(sizes.segments, "segments_buf");
+    let ptcl_buf = ResourceProxy::new_buf(sizes.ptcl, "ptcl_buf");
 
     let pathtag_wgs = pathtag_padded / (4 * shaders::PATHTAG_REDUCE_WG as usize);
     let pathtag_large = pathtag_wgs > shaders::PATHTAG_REDUCE_WG as usize;
@@ -222,8 +508,7 @@ pub fn render_encoding_full(
     } else {
         pathtag_wgs
     };
-    let reduced_buf =
-        ResourceProxy::new_buf(reduced_size as u64 * TAG_MONOID_FULL_SIZE, "reduced_buf");
+    let reduced_buf = ResourceProxy::new_buf(sizes.path_reduced, "reduced_buf");
     // TODO: really only need pathtag_wgs - 1
     recording.dispatch(
         shaders.pathtag_reduce,
@@ -231,31 +516,26 @@ pub fn render_encoding_full(
         [config_buf, scene_buf, reduced_buf],
     );
     let mut pathtag_parent = reduced_buf;
+    let mut large_bufs = vec![];
     if pathtag_large {
         let reduced2_size = shaders::PATHTAG_REDUCE_WG as usize;
-        let reduced2_buf =
-            ResourceProxy::new_buf(reduced2_size as u64 * TAG_MONOID_FULL_SIZE, "reduced2_buf");
+        let reduced2_buf = ResourceProxy::new_buf(sizes.path_reduced2, "reduced2_buf");
         recording.dispatch(
             shaders.pathtag_reduce2,
             (reduced2_size as u32, 1, 1),
             [reduced_buf, reduced2_buf],
         );
-        let reduced_scan_buf = ResourceProxy::new_buf(
-            pathtag_wgs as u64 * TAG_MONOID_FULL_SIZE,
-            "reduced_scan_buf",
-        );
+        let reduced_scan_buf = ResourceProxy::new_buf(sizes.path_reduced_scan, "reduced_scan_buf");
         recording.dispatch(
             shaders.pathtag_scan1,
             (reduced_size as u32 / shaders::PATHTAG_REDUCE_WG, 1, 1),
             [reduced_buf, reduced2_buf, reduced_scan_buf],
         );
         pathtag_parent = reduced_scan_buf;
+        large_bufs = vec![reduced2_buf, reduced_scan_buf];
     }
 
-    let tagmonoid_buf = ResourceProxy::new_buf(
-        pathtag_wgs as u64 * shaders::PATHTAG_REDUCE_WG as u64 * TAG_MONOID_FULL_SIZE,
-        "tagmonoid_buf",
-    );
+    let tagmonoid_buf = ResourceProxy::new_buf(sizes.path_monoids, "tagmonoid_buf");
     let pathtag_scan = if pathtag_large {
         shaders.pathtag_scan_large
     } else {
@@ -267,18 +547,18 @@ pub fn render_encoding_full(
         [config_buf, scene_buf, pathtag_parent, tagmonoid_buf],
     );
     let drawobj_wgs = (n_drawobj + shaders::PATH_BBOX_WG - 1) / shaders::PATH_BBOX_WG;
-    let path_bbox_buf = ResourceProxy::new_buf(n_paths as u64 * PATH_BBOX_SIZE, "path_bbox_buf");
+    let path_bbox_buf = ResourceProxy::new_buf(sizes.path_bboxes, "path_bbox_buf");
     recording.dispatch(
         shaders.bbox_clear,
         (drawobj_wgs, 1, 1),
         [config_buf, path_bbox_buf],
     );
-    let cubic_buf = ResourceProxy::new_buf(n_pathtag as u64 * CUBIC_SIZE, "cubic_buf");
+    let cubic_buf = ResourceProxy::new_buf(sizes.cubics, "cubic_buf");
     let path_coarse_wgs =
         (n_pathtag as u32 + shaders::PATH_COARSE_WG - 1) / shaders::PATH_COARSE_WG;
     recording.dispatch(
         shaders.pathseg,
-        (path_coarse_wgs, 1, 1),
+        split_dispatch(path_coarse_wgs),
         [
             config_buf,
             scene_buf,
@@ -287,17 +567,14 @@ pub fn render_encoding_full(
             cubic_buf,
         ],
     );
-    let draw_reduced_buf =
-        ResourceProxy::new_buf(drawobj_wgs as u64 * DRAWMONOID_SIZE, "draw_reduced_buf");
+    let draw_reduced_buf = ResourceProxy::new_buf(sizes.draw_reduced, "draw_reduced_buf");
     recording.dispatch(
         shaders.draw_reduce,
         (drawobj_wgs, 1, 1),
         [config_buf, scene_buf, draw_reduced_buf],
     );
-    let draw_monoid_buf =
-        ResourceProxy::new_buf(n_drawobj as u64 * DRAWMONOID_SIZE, "draw_monoid_buf");
-    let clip_inp_buf =
-        ResourceProxy::new_buf(encoding.n_clips as u64 * CLIP_INP_SIZE, "clip_inp_buf");
+    let draw_monoid_buf = ResourceProxy::new_buf(sizes.draw_monoids, "draw_monoid_buf");
+    let clip_inp_buf = ResourceProxy::new_buf(sizes.clip_inps, "clip_inp_buf");
     recording.dispatch(
         shaders.draw_leaf,
         (drawobj_wgs, 1, 1),
@@ -311,11 +588,8 @@ pub fn render_encoding_full(
             clip_inp_buf,
         ],
     );
-    let clip_el_buf = ResourceProxy::new_buf(encoding.n_clips as u64 * CLIP_EL_SIZE, "clip_el_buf");
-    let clip_bic_buf = ResourceProxy::new_buf(
-        (n_clip / shaders::CLIP_REDUCE_WG) as u64 * CLIP_BIC_SIZE,
-        "clip_bic_buf",
-    );
+    let clip_el_buf = ResourceProxy::new_buf(sizes.clip_els, "clip_el_buf");
+    let clip_bic_buf = ResourceProxy::new_buf(sizes.clip_bics, "clip_bic_buf");
     let clip_wg_reduce = n_clip.saturating_sub(1) / shaders::CLIP_REDUCE_WG;
     if clip_wg_reduce > 0 {
         recording.dispatch(
@@ -329,9 +603,13 @@ pub fn render_encoding_full(
                 clip_el_buf,
             ],
         );
+    } else {
+        // The buffers are still bound by the clip leaf stage, so must be initialized
+        recording.clear_all(*clip_bic_buf.as_buf().unwrap());
+        recording.clear_all(*clip_el_buf.as_buf().unwrap());
     }
     let clip_wg = (n_clip + shaders::CLIP_REDUCE_WG - 1) / shaders::CLIP_REDUCE_WG;
-    let clip_bbox_buf = ResourceProxy::new_buf(n_clip as u64 * CLIP_BBOX_SIZE, "clip_bbox_buf");
+    let clip_bbox_buf = ResourceProxy::new_buf(sizes.clip_bboxes, "clip_bbox_buf");
     if clip_wg > 0 {
         recording.dispatch(
             shaders.clip_leaf,
@@ -346,15 +624,15 @@ pub fn render_encoding_full(
                 clip_bbox_buf,
             ],
         );
+    } else {
+        // Binning reads the clip bounding boxes even if there are none
+        recording.clear_all(*clip_bbox_buf.as_buf().unwrap());
     }
-    let draw_bbox_buf = ResourceProxy::new_buf(n_paths as u64 * DRAW_BBOX_SIZE, "draw_bbox_buf");
-    let bump_buf = BufProxy::new(BUMP_SIZE, "bump_buf");
+    let draw_bbox_buf = ResourceProxy::new_buf(sizes.draw_bboxes, "draw_bbox_buf");
+    let bump_buf = BufProxy::new(sizes.bump, "bump_buf");
     let width_in_bins = (config.width_in_tiles + 15) / 16;
     let height_in_bins = (config.height_in_tiles + 15) / 16;
-    let bin_header_buf = ResourceProxy::new_buf(
-        (256 * drawobj_wgs) as u64 * BIN_HEADER_SIZE,
-        "bin_header_buf",
-    );
+    let bin_header_buf = ResourceProxy::new_buf(sizes.bin_headers, "bin_header_buf");
     recording.clear_all(bump_buf);
     let bump_buf = ResourceProxy::Buf(bump_buf);
     recording.dispatch(
@@ -371,10 +649,7 @@ pub fn render_encoding_full(
             bin_header_buf,
         ],
     );
-    // Note: this only needs to be rounded up because of the workaround to store the tile_offset
-    // in storage rather than workgroup memory.
-    let n_path_aligned = align_up(n_paths as usize, 256);
-    let path_buf = ResourceProxy::new_buf(n_path_aligned as u64 * PATH_SIZE, "path_buf");
+    let path_buf = ResourceProxy::new_buf(sizes.paths, "path_buf");
     let path_wgs = (n_paths + shaders::PATH_BBOX_WG - 1) / shaders::PATH_BBOX_WG;
     recording.dispatch(
         shaders.tile_alloc,
@@ -390,7 +665,7 @@ pub fn render_encoding_full(
     );
     recording.dispatch(
         shaders.path_coarse,
-        (path_coarse_wgs, 1, 1),
+        split_dispatch(path_coarse_wgs),
         [
             config_buf,
             scene_buf,
@@ -402,11 +677,22 @@ pub fn render_encoding_full(
             segments_buf,
         ],
     );
+    let backdrop_splits = if shaders::PARALLEL_BACKDROP {
+        let target_tiles = config.width_in_tiles * config.height_in_tiles;
+        ((target_tiles + shaders::BACKDROP_SPLIT_TILES - 1) / shaders::BACKDROP_SPLIT_TILES)
+            .clamp(1, shaders::BACKDROP_MAX_SPLITS)
+    } else {
+        1
+    };
     recording.dispatch(
         shaders.backdrop,
-        (path_wgs, 1, 1),
+        (path_wgs, backdrop_splits, 1),
         [config_buf, path_buf, tile_buf],
     );
+    let tile_queue_buf = BufProxy::new(sizes.tile_queue, "tile_queue_buf");
+    recording.clear_all(tile_queue_buf);
+    let tile_queue_buf = ResourceProxy::Buf(tile_queue_buf);
+    let fine_args_buf = recording.upload("fine_args_buf", bytemuck::bytes_of(&FINE_ARGS));
     recording.dispatch(
         shaders.coarse,
         (width_in_bins, height_in_bins, 1),
@@ -420,25 +706,252 @@ pub fn render_encoding_full(
             tile_buf,
             bump_buf,
             ptcl_buf,
+            tile_queue_buf,
+            ResourceProxy::Buf(fine_args_buf),
         ],
     );
+    if readback.overflow {
+        // Download the bump allocators before fine, which doesn't allocate
+        recording.download(*bump_buf.as_buf().unwrap());
+        recording.signpost(OVERFLOW_SIGNPOST);
+    }
     let out_image = ImageProxy::new(width, height, ImageFormat::Rgba8);
-    recording.dispatch(
-        shaders.fine,
-        (config.width_in_tiles, config.height_in_tiles, 1),
-        [
-            config_buf,
+    let fine_resources = [
+        config_buf,
+        tile_buf,
+        segments_buf,
+        ResourceProxy::Image(out_image),
+        ptcl_buf,
+        gradient_image,
+        info_bin_data_buf,
+    ];
+    let dense_wgs = (config.width_in_tiles, config.height_in_tiles, 1);
+    match (shaders.debug_fine, shaders.dense_fine) {
+        // The overlay is drawn over every tile, and the overlay of draw bounding boxes
+        // reads them from binning
+        (Some(debug_fine), _) => recording.dispatch(
+            debug_fine,
+            dense_wgs,
+            fine_resources.into_iter().chain([draw_bbox_buf]),
+        ),
+        (None, Some(dense_fine)) => recording.dispatch(dense_fine, dense_wgs, fine_resources),
+        // Fine only runs for the tiles coarse queued as drawing something, which are
+        // only known on the GPU, and the others are cleared
+        (None, None) => {
+            recording.dispatch_indirect(
+                shaders.fine_clear,
+                fine_args_buf,
+                FINE_ARGS_SIZE / 2,
+                [config_buf, tile_queue_buf, ResourceProxy::Image(out_image)],
+            );
+            recording.dispatch_indirect(
+                shaders.fine,
+                fine_args_buf,
+                0,
+                fine_resources.into_iter().chain([tile_queue_buf]),
+            );
+        }
+    }
+    let mut captured = vec![];
+    if readback.capture {
+        let bufs = [
+            scene_buf,
+            reduced_buf,
+            tagmonoid_buf,
+            path_bbox_buf,
+            cubic_buf,
+            draw_reduced_buf,
+            draw_monoid_buf,
+            clip_inp_buf,
+            clip_el_buf,
+            clip_bic_buf,
+            clip_bbox_buf,
+            draw_bbox_buf,
+            bump_buf,
+            bin_header_buf,
+            info_bin_data_buf,
+            path_buf,
             tile_buf,
             segments_buf,
-            ResourceProxy::Image(out_image),
             ptcl_buf,
-            gradient_image,
-            info_bin_data_buf,
-        ],
-    );
-    (recording, ResourceProxy::Image(out_image))
+            tile_queue_buf,
+            ResourceProxy::Buf(fine_args_buf),
+        ];
+        for buf in bufs.iter().chain(&large_bufs) {
+            let buf = *buf.as_buf().unwrap();
+            recording.download(buf);
+            captured.push(buf);
+        }
+    }
+    let bump = BumpBuffer {
+        buf: *bump_buf.as_buf().unwrap(),
+        config,
+        captured,
+    };
+    (recording, ResourceProxy::Image(out_image), bump)
+}
+
+/// Splits a dispatch of `n_wgs` workgroups across a second dimension if there are
+/// more than can be dispatched in one. Shaders dispatched this way must derive
+/// their index from both dimensions, and ignore invocations past the end.
+fn split_dispatch(n_wgs: u32) -> (u32, u32, u32) {
+    if n_wgs <= MAX_WORKGROUPS_PER_DIMENSION {
+        return (n_wgs, 1, 1);
+    }
+    let y = (n_wgs + MAX_WORKGROUPS_PER_DIMENSION - 1) / MAX_WORKGROUPS_PER_DIMENSION;
+    ((n_wgs + y - 1) / y, y, 1)
+}
+
+/// Returns the size in bytes of the largest buffer needed to render `encoding`.
+pub fn max_buffer_size(encoding: &Encoding) -> u64 {
+    BufferSizes::new(encoding).max()
+}
+
+/// Returns the tile size to render `encoding` into a target of `width` by `height`
+/// pixels with, for [`TileSize::Auto`].
+///
+/// Small tiles waste less of the fine stage on paths much smaller than a tile, such
+/// as the glyphs of text, while large tiles have less overhead per tile for large
+/// fills. Small tiles are chosen if the median area of the bounding boxes of the
+/// paths is below [`SMALL_TILES_MAX_MEDIAN_AREA`], so that a few large fills, such
+/// as a background, don't hide many small paths. They are only chosen if the commands
+/// initially allocated for every tile leave at least half of the command buffer for
+/// the rest.
+pub fn choose_tile_size(encoding: &Encoding, width: u32, height: u32) -> TileSize {
+    let small = TileSize::Small.pixels() as u64;
+    let n_small_tiles =
+        ((width as u64 + small - 1) / small) * ((height as u64 + small - 1) / small);
+    if n_small_tiles * PTCL_INITIAL_ALLOC as u64 * 4 > PTCL_BUF_SIZE / 2 {
+        return TileSize::Large;
+    }
+    match encoding.path_areas.median_bound() {
+        Some(bound) if bound <= SMALL_TILES_MAX_MEDIAN_AREA => TileSize::Small,
+        _ => TileSize::Large,
+    }
+}
+
+/// Checks that `encoding` can be rendered on a device with the given limits.
+pub fn check_limits(encoding: &Encoding, limits: &wgpu::Limits) -> Result<(), Error> {
+    let needed = max_buffer_size(encoding);
+    let limit = limits
+        .max_buffer_size
+        .min(limits.max_storage_buffer_binding_size as u64);
+    if needed > limit {
+        return Err(RenderError::SceneTooLarge { needed, limit }.into());
+    }
+    // Every clip has a begin and an end, so only count the depth if it could be too
+    // deep
+    if encoding.n_clips / 2 > Config::MAX_CLIP_DEPTH {
+        let depth = encoding.max_clip_depth();
+        if depth > Config::MAX_CLIP_DEPTH {
+            return Err(RenderError::ClipDepthExceeded {
+                depth,
+                limit: Config::MAX_CLIP_DEPTH,
+            }
+            .into());
+        }
+    }
+    // The path tag scan is at most two levels deep, so splitting its dispatches into
+    // two dimensions would only allow one more workgroup. The draw object stages are
+    // dispatched in one dimension. The per path tag stages are split instead.
+    let pathtag_wgs = (align_up(encoding.path_tags.len(), 4 * shaders::PATHTAG_REDUCE_WG)
+        / (4 * shaders::PATHTAG_REDUCE_WG as usize)) as u32;
+    let drawobj_wgs = (encoding.n_paths + shaders::PATH_BBOX_WG - 1) / shaders::PATH_BBOX_WG;
+    let dispatches = [
+        (
+            "pathtag_reduce",
+            pathtag_wgs,
+            shaders::PATHTAG_REDUCE_WG * shaders::PATHTAG_REDUCE_WG,
+        ),
+        ("bbox_clear", drawobj_wgs, u32::MAX),
+    ];
+    for (stage, workgroups, stage_limit) in dispatches {
+        let limit = limits.max_compute_workgroups_per_dimension.min(stage_limit);
+        if workgroups > limit {
+            return Err(Error::SceneTooComplex {
+                stage,
+                workgroups,
+                limit,
+            });
+        }
+    }
+    Ok(())
 }
 
 pub fn align_up(len: usize, alignment: u32) -> usize {
     len + (len.wrapping_neg() & (alignment as usize - 1))
 }
+
+#[cfg(test)]
+mod tests {
+    use peniko::kurbo::{Affine, Rect};
+    use peniko::{Color, Fill};
+
+    use super::*;
+    use crate::SceneBuilder;
+
+    #[test]
+    fn tile_size_follows_the_median_path() {
+        let scene = |n_small: usize| {
+            let mut scene = Scene::new();
+            let mut sb = SceneBuilder::for_scene(&mut scene);
+            // A background which would bring the mean area above the limit
+            let background = Rect::new(0.0, 0.0, 1024.0, 1024.0);
+            sb.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                Color::WHITE,
+                None,
+                &background,
+            );
+            for ix in 0..n_small {
+                let glyph = Rect::new(ix as f64 * 10.0, 0.0, ix as f64 * 10.0 + 8.0, 12.0);
+                sb.fill(Fill::NonZero, Affine::IDENTITY, Color::BLACK, None, &glyph);
+            }
+            sb.finish();
+            scene
+        };
+        let choose = |scene: Scene| choose_tile_size(scene.data(), 1024, 1024);
+        assert_eq!(choose(scene(20)), TileSize::Small);
+        assert_eq!(choose(scene(0)), TileSize::Large);
+        // The median of two paths is the smaller one
+        assert_eq!(choose(scene(1)), TileSize::Small);
+    }
+
+    #[test]
+    fn too_many_path_tags_name_the_stage() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        for ix in 0..1000 {
+            let rect = Rect::new(ix as f64, 0.0, ix as f64 + 1.0, 1.0);
+            sb.fill(Fill::NonZero, Affine::IDENTITY, Color::RED, None, &rect);
+        }
+        sb.finish();
+        let encoding = scene.data();
+        let workgroups = (align_up(encoding.path_tags.len(), 4 * shaders::PATHTAG_REDUCE_WG)
+            / (4 * shaders::PATHTAG_REDUCE_WG as usize)) as u32;
+        assert!(workgroups > 1);
+        // Just over the limit, and at it
+        let limits = wgpu::Limits {
+            max_compute_workgroups_per_dimension: workgroups - 1,
+            ..Default::default()
+        };
+        match check_limits(encoding, &limits) {
+            Err(Error::SceneTooComplex {
+                stage,
+                workgroups: needed,
+                limit,
+            }) => {
+                assert_eq!(stage, "pathtag_reduce");
+                assert_eq!(needed, workgroups);
+                assert_eq!(limit, workgroups - 1);
+            }
+            other => panic!("expected SceneTooComplex, got {other:?}"),
+        }
+        let limits = wgpu::Limits {
+            max_compute_workgroups_per_dimension: workgroups,
+            ..Default::default()
+        };
+        assert!(check_limits(encoding, &limits).is_ok());
+    }
+}
@@ -14,10 +14,37 @@
 //
 // Also licensed under MIT license, at your choice.
 
-use peniko::kurbo::{Affine, Rect, Shape};
-use peniko::{BlendMode, BrushRef, Fill, Stroke};
+use peniko::kurbo::{Affine, Point, Rect, Shape};
+use peniko::{BlendMode, BrushRef, Color, Compose, Fill, Mix, Stroke};
 
-use crate::encoding::{Encoding, Transform};
+use crate::encoding::{
+    Config, DecodeError, DrawBlurRect, DrawColor, Encoding, HitRegion, InterpolationSpace,
+    MaskKind, PathEncoder, PathTag, PathValidation, Precision, StreamLengths, Transform,
+    MAX_COORDINATE,
+};
+use crate::glyph::{Glyph, GlyphCache, GlyphFont};
+use crate::RenderError;
+
+/// Half the size of the clip of a layer pushed with
+/// [`SceneBuilder::push_opacity_layer`].
+const UNBOUNDED_LAYER_SIZE: f64 = 1.0e8;
+
+/// Error returned by [`SceneBuilder::fill_blended`] for a blend mode which can only
+/// be applied to a layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsupportedBlend(pub BlendMode);
+
+impl std::fmt::Display for UnsupportedBlend {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "blend mode {:?} with {:?} can't be applied without a layer; use push_layer",
+            self.0.mix, self.0.compose
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedBlend {}
 
 /// Encoded definition of a scene and associated resources.
 #[derive(Default)]
@@ -35,6 +62,112 @@ impl Scene {
     pub fn data(&self) -> &Encoding {
         &self.data
     }
+
+    /// Serializes the scene, for example to render it in another process.
+    ///
+    /// See [`Encoding::to_bytes`] for details of the format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data.to_bytes()
+    }
+
+    /// Deserializes a scene which was serialized with [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            data: Encoding::from_bytes(bytes, false)?,
+        })
+    }
+
+    /// Returns true if the scene does not contain any paths.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the tag of the topmost draw object drawn with a tag, set with
+    /// [`SceneBuilder::set_tag`], whose bounding box contains `point`.
+    ///
+    /// This is conservative like [`bounding_box`](Self::bounding_box): only the
+    /// bounding boxes of the draw objects and of the layers they are in are tested,
+    /// so a point outside of a shape but inside its bounding box still hits it, and
+    /// draw objects without a tag don't hide the draw objects below them.
+    pub fn hit_test(&self, point: Point) -> Option<u32> {
+        self.data.hit_test(point)
+    }
+
+    /// Returns the bounding box of everything filled or stroked in the scene, after
+    /// transforms, or `None` if nothing has been drawn.
+    ///
+    /// This is conservative: layers are not taken into account, so content which
+    /// is clipped away still contributes to the bounds.
+    pub fn bounding_box(&self) -> Option<Rect> {
+        self.data.bbox
+    }
+
+    /// Returns the size in bytes of the largest GPU buffer needed to render the scene.
+    ///
+    /// Rendering fails with [`RenderError::SceneTooLarge`](crate::RenderError) if
+    /// this is above the device's `max_buffer_size` or
+    /// `max_storage_buffer_binding_size` limits.
+    pub fn encoded_size_estimate(&self) -> u64 {
+        crate::render::max_buffer_size(&self.data)
+    }
+
+    /// Returns the number of encoded paths, including the paths of layer clips.
+    pub fn n_paths(&self) -> u32 {
+        self.data.n_paths
+    }
+
+    /// Returns the number of encoded draw objects, including the beginning and
+    /// end of each layer.
+    pub fn n_draw_objects(&self) -> usize {
+        self.data.draw_tags.len()
+    }
+
+    /// Returns the sizes of the encoded streams, for example to decide whether the
+    /// scene is worth caching.
+    pub fn stats(&self) -> SceneStats {
+        SceneStats {
+            path_tags: self.data.path_tags.len(),
+            path_data_bytes: self.data.path_data.len(),
+            draw_objects: self.data.draw_tags.len(),
+            transforms: self.data.transforms.len(),
+            color_stops: self.data.color_stops.len(),
+            total_bytes: self.data.byte_size(),
+            allocated_bytes: self.data.allocated_byte_size(),
+            path_data_saved_bytes: self.data.path_data_saved_bytes(),
+        }
+    }
+
+    /// Frees memory which was allocated while building the scene but is no longer
+    /// needed, for scenes which are kept for a long time.
+    ///
+    /// Building into the scene again will allocate again.
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+    }
+}
+
+/// Sizes of the encoded streams of a scene, as returned by [`Scene::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    /// Number of path tags, including changes of transform and line width.
+    pub path_tags: usize,
+    /// Size of the path data stream in bytes.
+    pub path_data_bytes: usize,
+    /// Number of draw objects, including the beginning and end of each layer.
+    pub draw_objects: usize,
+    /// Number of transforms, including the initial identity transform.
+    pub transforms: usize,
+    /// Number of gradient color stops.
+    pub color_stops: usize,
+    /// Total size in bytes of the encoded streams.
+    pub total_bytes: usize,
+    /// Size in bytes of the memory allocated for the encoded streams. This is larger
+    /// than `total_bytes` by any capacity left over from building the scene, which
+    /// [`Scene::shrink_to_fit`] frees.
+    pub allocated_bytes: usize,
+    /// Number of bytes by which the path data is smaller than it would be if every
+    /// point was stored in [`Precision::F32`].
+    pub path_data_saved_bytes: usize,
 }
 
 /// Encoded definition of a scene fragment and associated resources.
@@ -54,6 +187,24 @@ impl SceneFragment {
         self.data.is_empty()
     }
 
+    /// Serializes the fragment. See [`Scene::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data.to_bytes()
+    }
+
+    /// Deserializes a fragment which was serialized with [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            data: Encoding::from_bytes(bytes, true)?,
+        })
+    }
+
+    /// Returns the bounding box of everything filled or stroked in the fragment,
+    /// or `None` if nothing has been drawn. See [`Scene::bounding_box`].
+    pub fn bounding_box(&self) -> Option<Rect> {
+        self.data.bbox
+    }
+
     /// Returns the the entire sequence of points in the scene fragment.
     pub fn points(&self) -> &[[f32; 2]] {
         if self.is_empty() {
@@ -64,10 +215,210 @@ impl SceneFragment {
     }
 }
 
+/// Handle to a fragment of a [`SceneGraph`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FragmentId(usize);
+
+/// A scene assembled from fragments, any of which can be replaced without encoding
+/// the others again.
+///
+/// This is for scenes which mostly stay the same from frame to frame, such as
+/// documents where only a part is edited. A replaced fragment is spliced into the
+/// scene in place of the old one, so replacing it costs a copy of the encoded
+/// content after it, rather than building every fragment again.
+pub struct SceneGraph {
+    scene: Scene,
+    fragments: Vec<(SceneFragment, Option<Affine>)>,
+    /// Lengths of the streams of an empty scene.
+    base: StreamLengths,
+}
+
+impl SceneGraph {
+    /// Creates a scene graph with no fragments.
+    pub fn new() -> Self {
+        let mut scene = Scene::new();
+        scene.data.reset(false);
+        let base = StreamLengths::of(&scene.data);
+        Self {
+            scene,
+            fragments: Vec::new(),
+            base,
+        }
+    }
+
+    /// Returns the scene made of all of the fragments, in the order they were added.
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Adds a fragment on top of the others, with an optional transform.
+    pub fn push(&mut self, fragment: SceneFragment, transform: Option<Affine>) -> FragmentId {
+        self.scene.data.append(
+            &fragment.data,
+            &transform.map(|xform| Transform::from_kurbo(&xform)),
+        );
+        self.fragments.push((fragment, transform));
+        FragmentId(self.fragments.len() - 1)
+    }
+
+    /// Returns the fragment `id` and its transform.
+    pub fn fragment(&self, id: FragmentId) -> (&SceneFragment, Option<Affine>) {
+        let (fragment, transform) = &self.fragments[id.0];
+        (fragment, *transform)
+    }
+
+    /// Replaces the fragment `id` and its transform, returning the old fragment so
+    /// that it can be reused with [`SceneBuilder::for_fragment`].
+    pub fn replace(
+        &mut self,
+        id: FragmentId,
+        fragment: SceneFragment,
+        transform: Option<Affine>,
+    ) -> SceneFragment {
+        let mut start = self.base;
+        for (before, _) in &self.fragments[..id.0] {
+            start.add(&StreamLengths::of(&before.data));
+        }
+        let removed = StreamLengths::of(&self.fragments[id.0].0.data);
+        self.scene.data.splice(
+            &start,
+            &removed,
+            &fragment.data,
+            &transform.map(|xform| Transform::from_kurbo(&xform)),
+        );
+        let (old, _) = std::mem::replace(&mut self.fragments[id.0], (fragment, transform));
+        self.scene.data.bbox = None;
+        self.scene.data.path_areas = Default::default();
+        for (fragment, transform) in &self.fragments {
+            let transform = transform.unwrap_or(Affine::IDENTITY);
+            if let Some(bbox) = fragment.data.bbox {
+                self.scene
+                    .data
+                    .add_bbox(transform.transform_rect_bbox(bbox));
+            }
+            self.scene
+                .data
+                .path_areas
+                .merge(&fragment.data.path_areas, transform.determinant().abs());
+        }
+        old
+    }
+
+    /// Replaces the content of the fragment `id` with what `build` draws into a
+    /// builder, keeping its transform.
+    pub fn update(&mut self, id: FragmentId, build: impl FnOnce(&mut SceneBuilder)) {
+        let transform = self.fragments[id.0].1;
+        let mut fragment = SceneFragment::new();
+        let mut builder = SceneBuilder::for_fragment(&mut fragment);
+        build(&mut builder);
+        builder.finish();
+        self.replace(id, fragment, transform);
+    }
+}
+
+impl Default for SceneGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle to a path defined with [`SceneBuilder::define_path`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PathId(usize);
+
+/// Encoded segments of a path which can be drawn many times, in any scene, with
+/// [`SceneBuilder::fill_path`].
+#[derive(Clone, Default)]
+pub struct EncodedPath {
+    tags: Vec<PathTag>,
+    data: Vec<u8>,
+    n_segments: u32,
+    bbox: Rect,
+}
+
+impl EncodedPath {
+    /// Encodes a shape as a filled path, with the default [`PathValidation`].
+    pub fn new(shape: &impl Shape) -> Self {
+        Self::with_validation(shape, PathValidation::default())
+    }
+
+    fn with_validation(shape: &impl Shape, validation: PathValidation) -> Self {
+        let mut path = Self {
+            bbox: shape.bounding_box(),
+            ..Default::default()
+        };
+        let mut n_paths = 0;
+        let mut encoder = PathEncoder::new(
+            &mut path.tags,
+            &mut path.data,
+            &mut path.n_segments,
+            &mut n_paths,
+            true,
+        );
+        encoder.set_validation(validation);
+        encoder.shape(shape);
+        // Each instance is a separate path, so the marker is added when drawing
+        encoder.finish(false);
+        path
+    }
+
+    /// Returns the bounding box of the shape the path was encoded from.
+    pub fn bounding_box(&self) -> Rect {
+        self.bbox
+    }
+
+    /// Returns the size of the encoded segments in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.tags.len() + self.data.len()
+    }
+}
+
+/// A path defined with [`SceneBuilder::define_path`].
+struct DefinedPath {
+    path: EncodedPath,
+    /// Offset of the points of the path in the path data stream, once it has been
+    /// drawn.
+    data_offset: Option<usize>,
+}
+
+/// A layer pushed onto a [`SceneBuilder`].
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Layer {
+    /// The layer was encoded, and must be closed.
+    Encoded,
+    /// The layer is a mask layer with a clip shape, which was encoded as a mask
+    /// layer with a clip inside it, and both must be closed.
+    ClippedMask,
+    /// The layer has no effect, so it was not encoded.
+    Elided,
+    /// The layer is outside of the viewport hint, so its content is skipped.
+    Culled,
+    /// The layer is nested more deeply than [`Config::MAX_CLIP_DEPTH`], so it was
+    /// not encoded, and its content is only culled to its bounding box.
+    Flattened,
+}
+
 /// Builder for constructing a scene or scene fragment.
 pub struct SceneBuilder<'a> {
     scene: &'a mut Encoding,
-    layer_depth: u32,
+    layers: Vec<Layer>,
+    /// Number of culled layers in `layers`.
+    culled_layers: u32,
+    /// Number of encoded layers in `layers`.
+    clip_depth: u32,
+    /// Intersections of the bounding boxes of the flattened layers in `layers`, from
+    /// the outermost to each of them.
+    flattened: Vec<Rect>,
+    /// Intersections of the bounding boxes of the encoded layers in `layers`, from
+    /// the outermost to each of them, which clip the hit regions of tagged draws.
+    clip_bboxes: Vec<Rect>,
+    tag: Option<u32>,
+    strict_clip_depth: bool,
+    viewport: Option<Rect>,
+    paths: Vec<DefinedPath>,
+    validation: PathValidation,
+    interpolation: InterpolationSpace,
+    precision: Precision,
 }
 
 impl<'a> SceneBuilder<'a> {
@@ -88,12 +439,93 @@ impl<'a> SceneBuilder<'a> {
         scene.reset(is_fragment);
         Self {
             scene,
-            layer_depth: 0,
+            layers: Vec::new(),
+            culled_layers: 0,
+            clip_depth: 0,
+            flattened: Vec::new(),
+            clip_bboxes: Vec::new(),
+            tag: None,
+            strict_clip_depth: false,
+            viewport: None,
+            paths: Vec::new(),
+            validation: PathValidation::default(),
+            interpolation: InterpolationSpace::default(),
+            precision: Precision::default(),
         }
     }
 
+    /// Sets how the coordinates of shapes drawn after this call are validated. The
+    /// default is [`PathValidation::Sanitize`].
+    ///
+    /// Shapes which were already defined with [`define_path`](Self::define_path) keep
+    /// the validation they were defined with.
+    pub fn set_validation(&mut self, validation: PathValidation) {
+        self.validation = validation;
+    }
+
+    /// Sets the tag of the draw objects drawn after this call, so that the topmost
+    /// of them at a point can be found with [`Scene::hit_test`]. Draw objects in
+    /// appended fragments keep the tags they were drawn with.
+    pub fn set_tag(&mut self, tag: u32) {
+        self.tag = Some(tag);
+    }
+
+    /// Stops tagging the draw objects drawn after this call, so they are not found
+    /// by [`Scene::hit_test`].
+    pub fn clear_tag(&mut self) {
+        self.tag = None;
+    }
+
+    /// Sets the color space that gradients drawn after this call are interpolated in.
+    /// The default is [`InterpolationSpace::Srgb`].
+    pub fn set_interpolation_space(&mut self, space: InterpolationSpace) {
+        self.interpolation = space;
+    }
+
+    /// Sets how the points of shapes drawn with the builder are stored. The default
+    /// is [`Precision::F32`].
+    ///
+    /// With [`Precision::F16`], the points of each shape are stored relative to the
+    /// center of its bounding box, which is folded into its transform. Shapes which
+    /// are too large on screen to be stored to within a quarter of a pixel are still
+    /// stored in f32, as are paths defined with [`define_path`](Self::define_path)
+    /// and blurred rectangles.
+    pub fn with_coordinate_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the area of the scene which will be visible, in the coordinates of the
+    /// scene being built. Content drawn after this call is skipped if its bounding box
+    /// is outside of the area, which saves encoding and uploading it. The default of
+    /// `None` keeps all content.
+    ///
+    /// Bounding boxes are conservative, so some content outside of the area may still
+    /// be encoded. Layers outside of the area are skipped along with everything drawn
+    /// in them.
+    pub fn set_viewport_hint(&mut self, viewport: Option<Rect>) {
+        self.viewport = viewport;
+    }
+
+    /// Sets whether layers nested more deeply than [`Config::MAX_CLIP_DEPTH`] are
+    /// encoded anyway, so that rendering the scene returns
+    /// [`RenderError::ClipDepthExceeded`](crate::RenderError::ClipDepthExceeded)
+    /// rather than drawing it approximately. The default is `false`.
+    pub fn set_strict_clip_depth(&mut self, strict: bool) {
+        self.strict_clip_depth = strict;
+    }
+
     /// Pushes a new layer bound by the specifed shape and composed with
     /// previous layers using the specified blend mode.
+    ///
+    /// Layers can be nested up to [`Config::MAX_CLIP_DEPTH`] deep, including those
+    /// pushed with [`push_opacity_layer`](Self::push_opacity_layer). Layers beyond
+    /// that are flattened: they aren't encoded, so they don't blend or fade their
+    /// content, and it is only clipped by the layers within the limit, after
+    /// skipping any content outside of the intersection of the bounding boxes of the
+    /// flattened layers. [`set_strict_clip_depth`](Self::set_strict_clip_depth) makes
+    /// them an error instead. Layers in appended fragments count towards the depth
+    /// when the scene is rendered, but are never flattened.
     pub fn push_layer(
         &mut self,
         blend: impl Into<BlendMode>,
@@ -101,25 +533,175 @@ impl<'a> SceneBuilder<'a> {
         transform: Affine,
         shape: &impl Shape,
     ) {
+        let bbox = transform.transform_rect_bbox(shape.bounding_box());
+        if self.is_culled(bbox) {
+            // Nothing in the layer can be visible
+            self.layers.push(Layer::Culled);
+            self.culled_layers += 1;
+            return;
+        }
+        if self.is_too_deep() {
+            let bbox = match self.flattened.last() {
+                Some(outer) => outer.intersect(bbox),
+                None => bbox,
+            };
+            self.flattened.push(bbox);
+            self.layers.push(Layer::Flattened);
+            return;
+        }
         let blend = blend.into();
-        self.scene
-            .encode_transform(Transform::from_kurbo(&transform));
         self.scene.encode_linewidth(-1.0);
-        if !self.scene.encode_shape(shape, true) {
+        if !self.encode_shape(shape, true, &transform) {
             // If the layer shape is invalid, encode a valid empty path. This suppresses
             // all drawing until the layer is popped.
             self.scene
                 .encode_shape(&Rect::new(0.0, 0.0, 0.0, 0.0), true);
         }
         self.scene.encode_begin_clip(blend, alpha.clamp(0.0, 1.0));
-        self.layer_depth += 1;
+        let clip_bbox = match self.clip_bboxes.last() {
+            Some(outer) => outer.intersect(bbox),
+            None => bbox,
+        };
+        self.clip_bboxes.push(clip_bbox);
+        self.layers.push(Layer::Encoded);
+        self.clip_depth += 1;
+    }
+
+    /// Pushes a new layer with no bounds, which is composed with previous layers at
+    /// the specified alpha. This fades a group of draws as a whole, so draws in the
+    /// layer cover each other before they are faded.
+    ///
+    /// A layer with an alpha of 1 has no effect, so it is not encoded.
+    pub fn push_opacity_layer(&mut self, alpha: f32) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        if self.culled_layers > 0 {
+            self.layers.push(Layer::Culled);
+            self.culled_layers += 1;
+            return;
+        }
+        if alpha == 1.0 {
+            self.layers.push(Layer::Elided);
+            return;
+        }
+        if self.is_too_deep() {
+            // The layer has no bounds, so it doesn't cull anything more
+            let bbox = self.flattened.last().copied().unwrap_or(Rect::new(
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::INFINITY,
+            ));
+            self.flattened.push(bbox);
+            self.layers.push(Layer::Flattened);
+            return;
+        }
+        // The clip is a rectangle far larger than any target, which is clipped to the
+        // target like any other path
+        let bounds = Rect::new(
+            -UNBOUNDED_LAYER_SIZE,
+            -UNBOUNDED_LAYER_SIZE,
+            UNBOUNDED_LAYER_SIZE,
+            UNBOUNDED_LAYER_SIZE,
+        );
+        self.scene.encode_linewidth(-1.0);
+        self.encode_shape(&bounds, true, &Affine::IDENTITY);
+        self.scene.encode_begin_clip(Mix::Normal.into(), alpha);
+        self.clip_bboxes
+            .push(self.clip_bboxes.last().copied().unwrap_or(bounds));
+        self.layers.push(Layer::Encoded);
+        self.clip_depth += 1;
+    }
+
+    /// Pushes a layer which masks what was drawn before it in the enclosing layer.
+    /// When the layer is popped, that content is multiplied by the alpha or luminance
+    /// of what was drawn in the layer, so it is hidden wherever nothing was drawn in
+    /// the layer.
+    ///
+    /// If `clip_shape` is given, what is drawn in the layer is clipped to the shape,
+    /// transformed by `transform`, so the masked content is hidden outside of it.
+    ///
+    /// To mask a group of draws, push a layer, draw them, then push a mask layer, draw
+    /// the mask, and pop both layers.
+    ///
+    /// A mask layer counts once towards [`Config::MAX_CLIP_DEPTH`], or twice with a
+    /// clip shape. Unlike other layers, mask layers can't be flattened, as their
+    /// content would then be drawn instead of masking, so pushing one which would
+    /// be nested too deeply returns [`RenderError::ClipDepthExceeded`] without
+    /// pushing a layer. With [`set_strict_clip_depth`](Self::set_strict_clip_depth),
+    /// it is encoded, and rendering the scene returns that error instead.
+    pub fn push_mask_layer(
+        &mut self,
+        kind: MaskKind,
+        transform: Affine,
+        clip_shape: Option<&impl Shape>,
+    ) -> Result<(), RenderError> {
+        if self.culled_layers > 0 {
+            self.layers.push(Layer::Culled);
+            self.culled_layers += 1;
+            return Ok(());
+        }
+        let depth = self.clip_depth + 1 + clip_shape.is_some() as u32;
+        if !self.strict_clip_depth && depth > Config::MAX_CLIP_DEPTH {
+            return Err(RenderError::ClipDepthExceeded {
+                depth,
+                limit: Config::MAX_CLIP_DEPTH,
+            });
+        }
+        // The mask is unbounded, so that content outside of the clip shape is
+        // masked by nothing rather than left untouched
+        let bounds = Rect::new(
+            -UNBOUNDED_LAYER_SIZE,
+            -UNBOUNDED_LAYER_SIZE,
+            UNBOUNDED_LAYER_SIZE,
+            UNBOUNDED_LAYER_SIZE,
+        );
+        self.scene.encode_linewidth(-1.0);
+        self.encode_shape(&bounds, true, &Affine::IDENTITY);
+        self.scene.encode_begin_mask(kind);
+        let outer = self.clip_bboxes.last().copied().unwrap_or(bounds);
+        self.clip_bboxes.push(outer);
+        self.clip_depth += 1;
+        let shape = match clip_shape {
+            Some(shape) => shape,
+            None => {
+                self.layers.push(Layer::Encoded);
+                return Ok(());
+            }
+        };
+        self.scene.encode_linewidth(-1.0);
+        if !self.encode_shape(shape, true, &transform) {
+            // As for other layers, an invalid shape hides everything in the layer
+            self.scene
+                .encode_shape(&Rect::new(0.0, 0.0, 0.0, 0.0), true);
+        }
+        self.scene.encode_begin_clip(Mix::Clip.into(), 1.0);
+        let bbox = transform.transform_rect_bbox(shape.bounding_box());
+        self.clip_bboxes.push(outer.intersect(bbox));
+        self.clip_depth += 1;
+        self.layers.push(Layer::ClippedMask);
+        Ok(())
     }
 
     /// Pops the current layer.
     pub fn pop_layer(&mut self) {
-        if self.layer_depth > 0 {
-            self.scene.encode_end_clip();
-            self.layer_depth -= 1;
+        match self.layers.pop() {
+            Some(Layer::Encoded) => {
+                self.scene.encode_end_clip();
+                self.clip_bboxes.pop();
+                self.clip_depth -= 1;
+            }
+            Some(Layer::ClippedMask) => {
+                for _ in 0..2 {
+                    self.scene.encode_end_clip();
+                    self.clip_bboxes.pop();
+                }
+                self.clip_depth -= 2;
+            }
+            Some(Layer::Culled) => self.culled_layers -= 1,
+            Some(Layer::Flattened) => {
+                self.flattened.pop();
+            }
+            Some(Layer::Elided) | None => {}
         }
     }
 
@@ -132,25 +714,191 @@ impl<'a> SceneBuilder<'a> {
         brush_transform: Option<Affine>,
         shape: &impl Shape,
     ) {
+        let bbox = transform.transform_rect_bbox(shape.bounding_box());
+        if self.is_culled(bbox) {
+            return;
+        }
+        self.scene.encode_linewidth(match style {
+            Fill::NonZero => -1.0,
+            Fill::EvenOdd => -2.0,
+        });
+        if self.encode_shape(shape, true, &transform) {
+            self.add_bbox(bbox);
+            self.encode_brush(brush, &transform, brush_transform);
+        }
+    }
+
+    /// Fills a shape like [`fill`](Self::fill), compositing the brush with `blend`
+    /// against what is already drawn, without pushing a layer.
+    ///
+    /// Only separable mix modes, from [`Mix::Normal`] to [`Mix::Exclusion`], with
+    /// [`Compose::SrcOver`] can be applied this way. Other blend modes return an error,
+    /// and need a layer pushed with [`push_layer`](Self::push_layer) instead.
+    pub fn fill_blended<'b>(
+        &mut self,
+        style: Fill,
+        transform: Affine,
+        brush: impl Into<BrushRef<'b>>,
+        blend: impl Into<BlendMode>,
+        shape: &impl Shape,
+    ) -> Result<(), UnsupportedBlend> {
+        let blend = blend.into();
+        if blend.compose != Compose::SrcOver || blend.mix as u32 > Mix::Exclusion as u32 {
+            return Err(UnsupportedBlend(blend));
+        }
+        let bbox = transform.transform_rect_bbox(shape.bounding_box());
+        if self.is_culled(bbox) {
+            return Ok(());
+        }
+        self.scene.encode_linewidth(match style {
+            Fill::NonZero => -1.0,
+            Fill::EvenOdd => -2.0,
+        });
+        if self.encode_shape(shape, true, &transform) {
+            self.add_bbox(bbox);
+            self.encode_brush(brush, &transform, None);
+            if blend.mix != Mix::Normal {
+                self.scene.encode_blend(blend);
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes a shape once, so that it can be filled any number of times with
+    /// [`fill_instance`](Self::fill_instance) without encoding it again.
+    ///
+    /// The returned handle is only valid for this builder.
+    pub fn define_path(&mut self, shape: &impl Shape) -> PathId {
+        self.paths.push(DefinedPath {
+            path: EncodedPath::with_validation(shape, self.validation),
+            data_offset: None,
+        });
+        PathId(self.paths.len() - 1)
+    }
+
+    /// Fills a path defined with [`define_path`](Self::define_path) using the
+    /// specified style and brush.
+    ///
+    /// This is equivalent to calling [`fill`](Self::fill) with the original shape,
+    /// but the points of the path are only stored the first time it is drawn. Later
+    /// instances only add their path tags, transform and brush to the scene, and read
+    /// the same points.
+    pub fn fill_instance<'b>(
+        &mut self,
+        style: Fill,
+        transform: Affine,
+        brush: impl Into<BrushRef<'b>>,
+        path: PathId,
+    ) {
+        let bbox = transform.transform_rect_bbox(self.paths[path.0].path.bbox);
+        if self.is_culled(bbox) {
+            return;
+        }
         self.scene
             .encode_transform(Transform::from_kurbo(&transform));
         self.scene.encode_linewidth(match style {
             Fill::NonZero => -1.0,
             Fill::EvenOdd => -2.0,
         });
-        if self.scene.encode_shape(shape, true) {
-            if let Some(brush_transform) = brush_transform {
-                self.scene
-                    .encode_transform(Transform::from_kurbo(&(transform * brush_transform)));
-                self.scene.swap_last_path_tags();
-                self.scene.encode_brush(brush, 1.0);
-            } else {
-                self.scene.encode_brush(brush, 1.0);
+        let defined = &mut self.paths[path.0];
+        let encoded = match defined.data_offset {
+            Some(data_offset) => self.scene.encode_shared_path_segments(
+                &defined.path.tags,
+                data_offset,
+                defined.path.n_segments,
+            ),
+            None => {
+                let data_offset = self.scene.path_data.len();
+                let encoded = self.scene.encode_path_segments(
+                    &defined.path.tags,
+                    &defined.path.data,
+                    defined.path.n_segments,
+                );
+                if encoded {
+                    defined.data_offset = Some(data_offset);
+                }
+                encoded
             }
+        };
+        if encoded {
+            self.add_bbox(bbox);
+            self.encode_brush(brush, &transform, None);
+        }
+    }
+
+    /// Fills a path which was encoded ahead of time using the specified style and
+    /// brush.
+    ///
+    /// Unlike [`fill_instance`](Self::fill_instance), the path can be kept and drawn
+    /// into any number of scenes, such as successive frames.
+    pub fn fill_path<'b>(
+        &mut self,
+        style: Fill,
+        transform: Affine,
+        brush: impl Into<BrushRef<'b>>,
+        brush_transform: Option<Affine>,
+        path: &EncodedPath,
+    ) {
+        let bbox = transform.transform_rect_bbox(path.bbox);
+        if self.is_culled(bbox) {
+            return;
+        }
+        self.scene
+            .encode_transform(Transform::from_kurbo(&transform));
+        self.scene.encode_linewidth(match style {
+            Fill::NonZero => -1.0,
+            Fill::EvenOdd => -2.0,
+        });
+        if self
+            .scene
+            .encode_path_segments(&path.tags, &path.data, path.n_segments)
+        {
+            self.add_bbox(bbox);
+            self.encode_brush(brush, &transform, brush_transform);
+        }
+    }
+
+    /// Draws a run of glyphs from `font` at `size` pixels per em, filled with `brush`.
+    /// The glyph positions are in the coordinate space of `transform`, and so is the
+    /// brush.
+    ///
+    /// Outlines are taken from `cache`, which encodes each glyph once and keeps it for
+    /// later frames, so drawing the same text again only copies the encoded segments.
+    pub fn draw_glyphs<'b>(
+        &mut self,
+        cache: &mut GlyphCache,
+        font: &GlyphFont,
+        size: f32,
+        glyphs: impl IntoIterator<Item = Glyph>,
+        brush: impl Into<BrushRef<'b>>,
+        transform: Affine,
+    ) {
+        let brush = brush.into();
+        let mut outlines = cache.outlines(font, size);
+        for glyph in glyphs {
+            let (path, scale) = match outlines.get(glyph.id) {
+                Some(outline) => outline,
+                None => continue,
+            };
+            // Outlines are y up
+            let glyph_transform = Affine::translate((glyph.x as f64, glyph.y as f64))
+                * Affine::scale_non_uniform(scale, -scale);
+            self.fill_path(
+                Fill::NonZero,
+                transform * glyph_transform,
+                brush.clone(),
+                Some(glyph_transform.inverse()),
+                path,
+            );
         }
     }
 
     /// Strokes a shape using the specified style and brush.
+    ///
+    /// Strokes with round joins and caps are drawn directly. Other styles, and strokes
+    /// with transforms which don't scale uniformly, are expanded into fills, where
+    /// miter joins are beveled when the ratio of the length of the miter to the line
+    /// width is more than the miter limit, as in SVG.
     pub fn stroke<'b>(
         &mut self,
         style: &Stroke,
@@ -159,33 +907,764 @@ impl<'a> SceneBuilder<'a> {
         brush_transform: Option<Affine>,
         shape: &impl Shape,
     ) {
-        self.scene
-            .encode_transform(Transform::from_kurbo(&transform));
+        // The GPU scales line widths by the same amount in every direction
+        let expand = crate::stroke::needs_expansion(style)
+            || (style.width > 0.0 && !is_uniform_scale(&transform));
+        // Miter joins extend by at most the miter limit, square caps by the diagonal
+        // of a square, and round joins and caps by half the line width
+        let extent = if expand {
+            (style.miter_limit as f64).max(std::f64::consts::SQRT_2)
+        } else {
+            1.0
+        };
+        let extent = extent * style.width.abs() as f64 * 0.5;
+        let bbox = shape.bounding_box().inflate(extent, extent);
+        if self.is_culled(transform.transform_rect_bbox(bbox)) {
+            return;
+        }
+        let tolerance = tolerance(&transform);
+        let dashed = if style.dash_pattern.is_empty() {
+            None
+        } else {
+            let path = shape.path_elements(tolerance);
+            crate::dash::dash(path, style.dash_offset as f64, &style.dash_pattern)
+        };
+        if expand {
+            // Other joins and caps are drawn by filling the outline of the stroke
+            let outline = match &dashed {
+                Some(dashed) => crate::stroke::expand(dashed.iter(), style, tolerance),
+                None => crate::stroke::expand(shape.path_elements(tolerance), style, tolerance),
+            };
+            self.fill(Fill::NonZero, transform, brush, brush_transform, &outline);
+            return;
+        }
         self.scene.encode_linewidth(style.width);
-        if self.scene.encode_shape(shape, false) {
-            if let Some(brush_transform) = brush_transform {
-                self.scene
-                    .encode_transform(Transform::from_kurbo(&(transform * brush_transform)));
-                self.scene.swap_last_path_tags();
-                self.scene.encode_brush(brush, 1.0);
-            } else {
-                self.scene.encode_brush(brush, 1.0);
-            }
+        let encoded = match &dashed {
+            Some(dashed) => self.encode_shape(dashed, false, &transform),
+            None => self.encode_shape(shape, false, &transform),
+        };
+        if encoded {
+            // Round joins and caps don't extend further than half the line width from
+            // the path
+            let bbox = shape
+                .bounding_box()
+                .inflate(style.width as f64 * 0.5, style.width as f64 * 0.5);
+            self.add_bbox(transform.transform_rect_bbox(bbox));
+            self.encode_brush(brush, &transform, brush_transform);
+        }
+    }
+
+    /// Draws a rounded rectangle blurred with a gaussian of standard deviation
+    /// `std_dev`, as is commonly used for drop shadows.
+    ///
+    /// The blur is evaluated analytically, so this is much cheaper than blurring
+    /// a filled rectangle.
+    ///
+    /// The rectangle, radius and standard deviation are validated like the
+    /// coordinates of a shape, as set with [`set_validation`](Self::set_validation),
+    /// so with [`PathValidation::Sanitize`] nothing is drawn if any of them is not
+    /// finite.
+    pub fn draw_blurred_rounded_rect(
+        &mut self,
+        transform: Affine,
+        rect: Rect,
+        radius: f64,
+        std_dev: f64,
+        color: Color,
+    ) {
+        let (rect, radius, std_dev) = match self.validate_blur(rect, radius, std_dev) {
+            Some(params) => params,
+            None => return,
+        };
+        let rect = rect.abs();
+        if rect.width() <= 0.0 || rect.height() <= 0.0 {
+            return;
+        }
+        // Beyond three standard deviations the blur contributes nothing visible
+        let extent = 3.0 * std_dev.max(0.0);
+        let half_width = rect.width() * 0.5 + extent;
+        let half_height = rect.height() * 0.5 + extent;
+        let shape = Rect::new(-half_width, -half_height, half_width, half_height);
+        let transform = transform * Affine::translate(rect.center().to_vec2());
+        if self.is_culled(transform.transform_rect_bbox(shape)) {
+            return;
+        }
+        self.scene.encode_linewidth(-1.0);
+        // The blur is evaluated relative to the center of the rectangle, so its
+        // transform can't include an origin for f16 points
+        if self.encode_shape_with_precision(&shape, true, &transform, Precision::F32) {
+            self.add_bbox(transform.transform_rect_bbox(shape));
+            self.scene.encode_blurred_rounded_rect(DrawBlurRect {
+                rgba: DrawColor::new(color).rgba,
+                width: rect.width() as f32,
+                height: rect.height() as f32,
+                radius: radius as f32,
+                std_dev: std_dev as f32,
+            });
         }
     }
 
     /// Appends a fragment to the scene.
     pub fn append(&mut self, fragment: &SceneFragment, transform: Option<Affine>) {
+        if self.is_appended_culled(&fragment.data, transform) {
+            return;
+        }
+        let n_hit_regions = self.scene.hit_regions.len();
         self.scene.append(
             &fragment.data,
             &transform.map(|xform| Transform::from_kurbo(&xform)),
         );
+        self.clip_hit_regions(n_hit_regions);
+    }
+
+    /// Appends a scene to the scene.
+    ///
+    /// Unlike [`append`](Self::append), this copies content which was built with
+    /// [`for_scene`](Self::for_scene), so a full scene can be cached and reused as
+    /// part of another without being rebuilt.
+    pub fn append_scene(&mut self, scene: &Scene, transform: Option<Affine>) {
+        if self.is_appended_culled(&scene.data, transform) {
+            return;
+        }
+        let n_hit_regions = self.scene.hit_regions.len();
+        self.scene.append_scene(
+            &scene.data,
+            &transform.map(|xform| Transform::from_kurbo(&xform)),
+        );
+        self.clip_hit_regions(n_hit_regions);
     }
 
     /// Completes construction and finalizes the underlying scene.
     pub fn finish(self) {
-        for _ in 0..self.layer_depth {
-            self.scene.encode_end_clip();
+        for layer in &self.layers {
+            let clips = match layer {
+                Layer::Encoded => 1,
+                Layer::ClippedMask => 2,
+                _ => 0,
+            };
+            for _ in 0..clips {
+                self.scene.encode_end_clip();
+            }
+        }
+    }
+
+    /// Returns true if content with the bounding box `bbox` can be skipped, as it is
+    /// outside of the viewport hint or of a flattened layer, or in a layer which was
+    /// culled.
+    fn is_culled(&self, bbox: Rect) -> bool {
+        if self.culled_layers > 0 {
+            return true;
+        }
+        // Comparisons with NaN are false, so invalid boxes are kept
+        let is_outside = |area: Rect| {
+            bbox.x0 > area.x1 || bbox.y0 > area.y1 || bbox.x1 < area.x0 || bbox.y1 < area.y0
+        };
+        self.viewport.map_or(false, is_outside)
+            || self.flattened.last().copied().map_or(false, is_outside)
+    }
+
+    /// Returns true if a layer pushed now would be flattened.
+    fn is_too_deep(&self) -> bool {
+        !self.strict_clip_depth && self.clip_depth >= Config::MAX_CLIP_DEPTH
+    }
+
+    /// Returns true if an appended encoding can be skipped, like [`is_culled`](Self::is_culled).
+    /// Encodings with nothing drawn in them are kept, as they may still have layers.
+    fn is_appended_culled(&self, encoding: &Encoding, transform: Option<Affine>) -> bool {
+        if self.culled_layers > 0 {
+            return true;
+        }
+        match encoding.bbox {
+            Some(bbox) => self.is_culled(
+                transform
+                    .unwrap_or(Affine::IDENTITY)
+                    .transform_rect_bbox(bbox),
+            ),
+            None => false,
+        }
+    }
+
+    /// Returns the parameters of a blurred rounded rectangle validated like the
+    /// coordinates of a shape, or `None` if it should not be drawn.
+    fn validate_blur(&self, rect: Rect, radius: f64, std_dev: f64) -> Option<(Rect, f64, f64)> {
+        if self.validation == PathValidation::Off {
+            return Some((rect, radius, std_dev));
+        }
+        let params = [rect.x0, rect.y0, rect.x1, rect.y1, radius, std_dev];
+        let sanitized = params
+            .iter()
+            .all(|x| x.is_finite())
+            .then(|| params.map(|x| x.clamp(-MAX_COORDINATE, MAX_COORDINATE)));
+        if self.validation == PathValidation::Strict && sanitized != Some(params) {
+            panic!("blurred rounded rectangle {rect:?} with radius {radius} and standard deviation {std_dev} has an invalid parameter");
+        }
+        let [x0, y0, x1, y1, radius, std_dev] = sanitized?;
+        Some((Rect::new(x0, y0, x1, y1), radius, std_dev))
+    }
+
+    /// Encodes the transform of a shape, then the shape with the builder's validation
+    /// and precision. Returns true if a non-zero number of segments were encoded.
+    fn encode_shape(&mut self, shape: &impl Shape, is_fill: bool, transform: &Affine) -> bool {
+        self.encode_shape_with_precision(shape, is_fill, transform, self.precision)
+    }
+
+    /// Encodes a shape like [`encode_shape`](Self::encode_shape), storing its points
+    /// with `precision` rather than the builder's precision.
+    fn encode_shape_with_precision(
+        &mut self,
+        shape: &impl Shape,
+        is_fill: bool,
+        transform: &Affine,
+        precision: Precision,
+    ) -> bool {
+        let origin = match precision {
+            Precision::F32 => None,
+            Precision::F16 => f16_origin(shape.bounding_box(), transform),
+        };
+        let path_transform = match origin {
+            Some(origin) => *transform * Affine::translate(origin.to_vec2()),
+            None => *transform,
+        };
+        self.scene
+            .encode_transform(Transform::from_kurbo(&path_transform));
+        let mut encoder = self.scene.encode_path(is_fill);
+        encoder.set_validation(self.validation);
+        if let Some(origin) = origin {
+            encoder.set_precision(Precision::F16, [origin.x as f32, origin.y as f32]);
+        }
+        encoder.shape_with_tolerance(shape, tolerance(transform));
+        encoder.finish(true) != 0
+    }
+
+    /// Encodes the brush of the path which was just encoded with
+    /// [`encode_shape`](Self::encode_shape), drawn with `transform` followed by
+    /// `brush_transform`.
+    fn encode_brush<'b>(
+        &mut self,
+        brush: impl Into<BrushRef<'b>>,
+        transform: &Affine,
+        brush_transform: Option<Affine>,
+    ) {
+        let brush = brush.into();
+        // Solid colors don't use the transform. For other brushes, the transform of
+        // the path may include the origin of its coordinates, so the brush needs its
+        // own unless they are the same.
+        if !matches!(brush, BrushRef::Solid(_)) {
+            let brush_transform = *transform * brush_transform.unwrap_or(Affine::IDENTITY);
+            let brush_transform = Transform::from_kurbo(&brush_transform);
+            if self.scene.transforms.last() != Some(&brush_transform) {
+                self.scene.encode_transform(brush_transform);
+                self.scene.swap_last_path_tags();
+            }
+        }
+        self.scene
+            .encode_brush_in_space(brush, 1.0, self.interpolation);
+    }
+
+    /// Adds the bounding box of a draw object to the scene, along with a hit region
+    /// if the builder has a tag. Unless validation is off, bounding boxes of shapes
+    /// with invalid coordinates are clamped like the coordinates themselves, or
+    /// dropped if they contain a NaN.
+    fn add_bbox(&mut self, bbox: Rect) {
+        let bbox = if self.validation == PathValidation::Off {
+            bbox
+        } else {
+            let coords = [bbox.x0, bbox.y0, bbox.x1, bbox.y1];
+            if coords.iter().any(|x| x.is_nan()) {
+                return;
+            }
+            let [x0, y0, x1, y1] = coords.map(|x| x.clamp(-MAX_COORDINATE, MAX_COORDINATE));
+            Rect::new(x0, y0, x1, y1)
+        };
+        self.scene.add_path_bbox(bbox);
+        if let Some(tag) = self.tag {
+            self.scene.hit_regions.push(HitRegion { tag, bbox });
+            self.clip_hit_regions(self.scene.hit_regions.len() - 1);
+        }
+    }
+
+    /// Clips the hit regions of the scene from `start` to the bounding boxes of the
+    /// current layers.
+    fn clip_hit_regions(&mut self, start: usize) {
+        let clips = [self.clip_bboxes.last(), self.flattened.last()];
+        for clip in clips.into_iter().flatten() {
+            for region in &mut self.scene.hit_regions[start..] {
+                region.bbox = region.bbox.intersect(*clip);
+            }
+        }
+    }
+}
+
+/// Tolerance, in local units, for approximating shapes drawn with `transform` such
+/// that the error is at most 0.1 pixels after the transform is applied.
+fn tolerance(transform: &Affine) -> f64 {
+    const DEVICE_TOLERANCE: f64 = 0.1;
+    match max_scale(transform) {
+        Some(max_scale) => DEVICE_TOLERANCE / max_scale,
+        None => DEVICE_TOLERANCE,
+    }
+}
+
+/// Returns the most that `transform` can scale a distance by, or `None` if it is
+/// degenerate.
+fn max_scale(transform: &Affine) -> Option<f64> {
+    // This is the largest singular value of the linear part of the transform
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let sum_squares = a * a + b * b + c * c + d * d;
+    let det = a * d - b * c;
+    let discriminant = (sum_squares * sum_squares - 4.0 * det * det).max(0.0);
+    let max_scale = ((sum_squares + discriminant.sqrt()) * 0.5).sqrt();
+    (max_scale.is_finite() && max_scale > 0.0).then_some(max_scale)
+}
+
+/// Returns true if `transform` scales distances by the same amount in every direction,
+/// up to rounding.
+fn is_uniform_scale(transform: &Affine) -> bool {
+    // The linear part is a multiple of a rotation, or of a reflection
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let epsilon = 1e-6 * (a.abs() + b.abs() + c.abs() + d.abs());
+    (a - d).abs() + (b + c).abs() <= epsilon || (a + d).abs() + (b - c).abs() <= epsilon
+}
+
+/// Returns the origin that the points of a shape with bounding box `bbox` are stored
+/// relative to with [`Precision::F16`], or `None` if the shape should be stored in
+/// f32 because it is too large to be stored to within a quarter of a pixel after
+/// `transform` is applied.
+fn f16_origin(bbox: Rect, transform: &Affine) -> Option<Point> {
+    const MAX_DEVICE_ERROR: f64 = 0.25;
+    // Integers are exact in f32, so the origin is the same in the transform and
+    // the encoded points. Rounding moves it by up to half a unit.
+    let origin = bbox.center().round();
+    let max_distance = bbox.width().max(bbox.height()) * 0.5 + 1.0;
+    // The rounding error of a half precision float is at most 2^-11 of its value,
+    // and values above 2^15 lose all precision in the fraction
+    let max_error = max_distance / 2048.0 * max_scale(transform)?;
+    (max_error <= MAX_DEVICE_ERROR && max_distance < 32768.0).then_some(origin)
+}
+
+#[cfg(test)]
+mod tests {
+    use peniko::kurbo::Circle;
+    use peniko::Gradient;
+
+    use super::*;
+    use crate::encoding::resource::ResourceCache;
+    use crate::encoding::{DrawBeginClip, DrawTag, PackedEncoding};
+
+    /// Returns the path index and points of each segment of `encoding`, read from
+    /// the packed encoding the way the pathseg stage reads them.
+    fn segment_points(encoding: &Encoding) -> Vec<(u32, Vec<[f32; 2]>)> {
+        let mut packed = PackedEncoding::new();
+        packed.pack(encoding, &mut ResourceCache::new());
+        let layout = packed.layout;
+        let word = |ix: u32| {
+            let at = ix as usize * 4;
+            u32::from_le_bytes(packed.data[at..at + 4].try_into().unwrap())
+        };
+        let mut segments = Vec::new();
+        let mut counted = 0u32;
+        let mut path_ix = 0;
+        for tag in &encoding.path_tags {
+            if tag.is_path_segment() {
+                assert!(tag.is_f32());
+                let offset = match layout.path_offset_base {
+                    0 => 0,
+                    base => word(base + path_ix),
+                };
+                let base = layout.path_data_base + counted.wrapping_add(offset);
+                let n_points = tag.path_segment_type().0 as u32 + 1;
+                let points = (0..n_points)
+                    .map(|ix| {
+                        let x = f32::from_bits(word(base + ix * 2));
+                        let y = f32::from_bits(word(base + ix * 2 + 1));
+                        [x, y]
+                    })
+                    .collect();
+                segments.push((path_ix, points));
+            }
+            counted += tag.point_words();
+            if *tag == PathTag::PATH {
+                path_ix += 1;
+            }
+        }
+        segments
+    }
+
+    fn transforms() -> Vec<Affine> {
+        (0..8)
+            .map(|ix| Affine::translate((ix as f64 * 20.0, 10.0)) * Affine::rotate(ix as f64 * 0.3))
+            .collect()
+    }
+
+    fn draw_instanced(sb: &mut SceneBuilder) {
+        let gradient =
+            Gradient::new_linear((0.0, 0.0), (10.0, 0.0)).with_stops([Color::RED, Color::BLUE]);
+        let circle = sb.define_path(&Circle::new((0.0, 0.0), 5.0));
+        let square = sb.define_path(&Rect::new(-4.0, -4.0, 4.0, 4.0));
+        for (ix, transform) in transforms().into_iter().enumerate() {
+            sb.fill_instance(Fill::NonZero, transform, Color::GREEN, circle);
+            sb.fill_instance(Fill::EvenOdd, transform, &gradient, square);
+            if ix == 3 {
+                sb.fill(
+                    Fill::NonZero,
+                    transform,
+                    Color::BLACK,
+                    None,
+                    &Circle::new((1.0, 1.0), 2.0),
+                );
+            }
+        }
+    }
+
+    fn draw_naive(sb: &mut SceneBuilder) {
+        let gradient =
+            Gradient::new_linear((0.0, 0.0), (10.0, 0.0)).with_stops([Color::RED, Color::BLUE]);
+        let circle = Circle::new((0.0, 0.0), 5.0);
+        let square = Rect::new(-4.0, -4.0, 4.0, 4.0);
+        for (ix, transform) in transforms().into_iter().enumerate() {
+            sb.fill(Fill::NonZero, transform, Color::GREEN, None, &circle);
+            sb.fill(Fill::EvenOdd, transform, &gradient, None, &square);
+            if ix == 3 {
+                sb.fill(
+                    Fill::NonZero,
+                    transform,
+                    Color::BLACK,
+                    None,
+                    &Circle::new((1.0, 1.0), 2.0),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn instances_read_the_same_points_as_naive_fills() {
+        let mut instanced = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut instanced);
+        draw_instanced(&mut sb);
+        sb.finish();
+        let mut naive = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut naive);
+        draw_naive(&mut sb);
+        sb.finish();
+        let (instanced, naive) = (instanced.data(), naive.data());
+        assert_eq!(instanced.n_paths, naive.n_paths);
+        assert_eq!(instanced.n_path_segments, naive.n_path_segments);
+        // Each shape is stored once, rather than once per instance
+        assert_eq!(instanced.shared_paths.len(), 14);
+        assert!(instanced.path_data.len() * 4 < naive.path_data.len());
+        assert_eq!(segment_points(instanced), segment_points(naive));
+    }
+
+    #[test]
+    fn appended_instances_read_the_same_points_as_naive_fills() {
+        let mut instanced = SceneFragment::new();
+        let mut sb = SceneBuilder::for_fragment(&mut instanced);
+        draw_instanced(&mut sb);
+        sb.finish();
+        let mut naive = SceneFragment::new();
+        let mut sb = SceneBuilder::for_fragment(&mut naive);
+        draw_naive(&mut sb);
+        sb.finish();
+        let mut scenes = [Scene::new(), Scene::new()];
+        for (scene, fragment) in scenes.iter_mut().zip([&instanced, &naive]) {
+            let mut sb = SceneBuilder::for_scene(scene);
+            sb.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                Color::WHITE,
+                None,
+                &Circle::new((50.0, 50.0), 30.0),
+            );
+            sb.append(fragment, Some(Affine::translate((100.0, 0.0))));
+            sb.append(fragment, None);
+            sb.finish();
+        }
+        assert_eq!(scenes[0].data().shared_paths.len(), 28);
+        assert_eq!(
+            segment_points(scenes[0].data()),
+            segment_points(scenes[1].data())
+        );
+    }
+
+    #[test]
+    fn replaced_instances_read_the_same_points_as_naive_fills() {
+        let fragment = |draw: fn(&mut SceneBuilder)| {
+            let mut fragment = SceneFragment::new();
+            let mut sb = SceneBuilder::for_fragment(&mut fragment);
+            draw(&mut sb);
+            sb.finish();
+            fragment
+        };
+        let mut graph = SceneGraph::new();
+        let first = graph.push(fragment(draw_naive), None);
+        let second = graph.push(fragment(draw_instanced), None);
+        graph.push(fragment(draw_instanced), None);
+        graph.replace(first, fragment(draw_instanced), None);
+        graph.replace(second, fragment(draw_naive), None);
+        let mut naive = SceneGraph::new();
+        for _ in 0..3 {
+            naive.push(fragment(draw_naive), None);
+        }
+        assert_eq!(graph.scene().data().shared_paths.len(), 28);
+        assert_eq!(
+            segment_points(graph.scene().data()),
+            segment_points(naive.scene().data())
+        );
+    }
+
+    #[test]
+    fn instances_survive_serialization() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        draw_instanced(&mut sb);
+        sb.finish();
+        let decoded = Scene::from_bytes(&scene.to_bytes()).unwrap();
+        assert_eq!(decoded.data().shared_paths, scene.data().shared_paths);
+        assert_eq!(segment_points(decoded.data()), segment_points(scene.data()));
+    }
+
+    #[test]
+    fn blurred_rect_with_invalid_parameters_is_not_drawn() {
+        let square = Rect::new(0.0, 0.0, 10.0, 10.0);
+        for (rect, radius, std_dev) in [
+            (Rect::new(0.0, 0.0, f64::NAN, 10.0), 2.0, 3.0),
+            (Rect::new(f64::NEG_INFINITY, 0.0, 10.0, 10.0), 2.0, 3.0),
+            (square, f64::INFINITY, 3.0),
+            (square, 2.0, f64::NAN),
+        ] {
+            let mut scene = Scene::new();
+            let mut sb = SceneBuilder::for_scene(&mut scene);
+            sb.draw_blurred_rounded_rect(Affine::IDENTITY, rect, radius, std_dev, Color::BLACK);
+            sb.finish();
+            assert!(scene.is_empty());
+            assert_eq!(scene.bounding_box(), None);
+        }
+    }
+
+    #[test]
+    fn blurred_rect_bounds_are_clamped() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        let rect = Rect::new(0.0, 0.0, 1.0e12, 10.0);
+        sb.draw_blurred_rounded_rect(Affine::IDENTITY, rect, 2.0, 1.0e12, Color::BLACK);
+        sb.finish();
+        let bbox = scene.bounding_box().unwrap();
+        for x in [bbox.x0, bbox.y0, bbox.x1, bbox.y1] {
+            assert!(x.abs() <= MAX_COORDINATE);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn blurred_rect_with_invalid_parameters_panics_when_strict() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        sb.set_validation(PathValidation::Strict);
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        sb.draw_blurred_rounded_rect(Affine::IDENTITY, rect, 2.0, f64::NAN, Color::BLACK);
+    }
+
+    fn square(sb: &mut SceneBuilder, x0: f64, y0: f64, size: f64) {
+        let rect = Rect::new(x0, y0, x0 + size, y0 + size);
+        sb.fill(Fill::NonZero, Affine::IDENTITY, Color::RED, None, &rect);
+    }
+
+    #[test]
+    fn hit_test_finds_the_topmost_tagged_draw() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        sb.set_tag(1);
+        square(&mut sb, 0.0, 0.0, 100.0);
+        sb.set_tag(2);
+        square(&mut sb, 50.0, 50.0, 100.0);
+        sb.clear_tag();
+        square(&mut sb, 0.0, 0.0, 200.0);
+        sb.finish();
+        assert_eq!(scene.hit_test(Point::new(25.0, 25.0)), Some(1));
+        assert_eq!(scene.hit_test(Point::new(75.0, 75.0)), Some(2));
+        assert_eq!(scene.hit_test(Point::new(125.0, 125.0)), Some(2));
+        assert_eq!(scene.hit_test(Point::new(175.0, 175.0)), None);
+        assert_eq!(scene.hit_test(Point::new(-1.0, 25.0)), None);
+    }
+
+    #[test]
+    fn hit_regions_are_clipped_by_layers() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        sb.set_tag(1);
+        sb.push_layer(
+            Mix::Normal,
+            1.0,
+            Affine::IDENTITY,
+            &Rect::new(0.0, 0.0, 50.0, 50.0),
+        );
+        sb.push_opacity_layer(0.5);
+        square(&mut sb, 0.0, 0.0, 100.0);
+        sb.pop_layer();
+        sb.pop_layer();
+        square(&mut sb, 200.0, 200.0, 10.0);
+        sb.finish();
+        assert_eq!(scene.hit_test(Point::new(25.0, 25.0)), Some(1));
+        assert_eq!(scene.hit_test(Point::new(75.0, 75.0)), None);
+        assert_eq!(scene.hit_test(Point::new(205.0, 205.0)), Some(1));
+    }
+
+    #[test]
+    fn hit_regions_of_fragments_are_transformed_and_clipped() {
+        let mut fragment = SceneFragment::new();
+        let mut sb = SceneBuilder::for_fragment(&mut fragment);
+        sb.set_tag(7);
+        square(&mut sb, 0.0, 0.0, 10.0);
+        sb.finish();
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        sb.set_tag(1);
+        sb.append(&fragment, Some(Affine::translate((100.0, 0.0))));
+        sb.push_layer(
+            Mix::Normal,
+            1.0,
+            Affine::IDENTITY,
+            &Rect::new(0.0, 0.0, 5.0, 5.0),
+        );
+        sb.append(&fragment, Some(Affine::scale(2.0)));
+        sb.pop_layer();
+        sb.finish();
+        assert_eq!(scene.hit_test(Point::new(105.0, 5.0)), Some(7));
+        assert_eq!(scene.hit_test(Point::new(5.0, 5.0)), Some(7));
+        assert_eq!(scene.hit_test(Point::new(15.0, 15.0)), None);
+        let decoded = Scene::from_bytes(&scene.to_bytes()).unwrap();
+        assert_eq!(decoded.data().hit_regions, scene.data().hit_regions);
+    }
+
+    #[test]
+    fn strokes_with_non_uniform_scale_are_expanded() {
+        let shape = peniko::kurbo::RoundedRect::new(0.0, 0.0, 20.0, 10.0, 3.0);
+        let is_expanded = |transform: Affine| {
+            let mut scene = Scene::new();
+            let mut sb = SceneBuilder::for_scene(&mut scene);
+            sb.stroke(&Stroke::new(2.0), transform, Color::RED, None, &shape);
+            sb.finish();
+            assert!(!scene.is_empty());
+            !scene.data().linewidths.iter().any(|width| *width >= 0.0)
+        };
+        assert!(is_expanded(Affine::scale_non_uniform(30.0, 0.3)));
+        assert!(is_expanded(Affine::new([1.0, 0.0, 0.5, 1.0, 0.0, 0.0])));
+        assert!(!is_expanded(Affine::scale(64.0)));
+        assert!(!is_expanded(Affine::rotate(1.0) * Affine::scale(3.0)));
+        assert!(!is_expanded(Affine::new([-3.0, 0.0, 0.0, 3.0, 0.0, 0.0])));
+    }
+
+    #[test]
+    fn mask_layers_are_encoded_as_clips() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        square(&mut sb, 0.0, 0.0, 100.0);
+        sb.push_mask_layer(MaskKind::Luminance, Affine::IDENTITY, None::<&Rect>)
+            .unwrap();
+        square(&mut sb, 25.0, 25.0, 50.0);
+        sb.pop_layer();
+        sb.finish();
+        let data = scene.data();
+        assert_eq!(data.n_clips, 2);
+        let begin_clip = data
+            .draw_tags
+            .iter()
+            .position(|tag| *tag == DrawTag::BEGIN_CLIP)
+            .unwrap();
+        // The first square is encoded before the clip
+        assert_eq!(begin_clip, 1);
+        let offset = std::mem::size_of::<DrawColor>();
+        let clip = &data.draw_data[offset..offset + std::mem::size_of::<DrawBeginClip>()];
+        let clip: DrawBeginClip = bytemuck::pod_read_unaligned(clip);
+        assert_eq!(
+            clip.blend_mode,
+            DrawBeginClip::mask(MaskKind::Luminance).blend_mode
+        );
+        assert!(data.draw_tags.last() == Some(&DrawTag::END_CLIP));
+    }
+
+    #[test]
+    fn clipped_mask_layers_are_closed_together() {
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        square(&mut sb, 0.0, 0.0, 100.0);
+        let clip = Rect::new(0.0, 0.0, 50.0, 50.0);
+        sb.push_mask_layer(MaskKind::Alpha, Affine::translate((10.0, 0.0)), Some(&clip))
+            .unwrap();
+        square(&mut sb, 25.0, 25.0, 50.0);
+        sb.pop_layer();
+        square(&mut sb, 50.0, 50.0, 10.0);
+        sb.finish();
+        let data = scene.data();
+        assert_eq!(data.n_clips, 4);
+        let tags = data
+            .draw_tags
+            .iter()
+            .map(|tag| {
+                if *tag == DrawTag::BEGIN_CLIP {
+                    "begin"
+                } else if *tag == DrawTag::END_CLIP {
+                    "end"
+                } else {
+                    "draw"
+                }
+            })
+            .collect::<Vec<_>>();
+        // The mask, the clip inside it, and the squares outside of both
+        assert_eq!(
+            tags,
+            ["draw", "begin", "begin", "draw", "end", "end", "draw"]
+        );
+        let offset = std::mem::size_of::<DrawColor>() + std::mem::size_of::<DrawBeginClip>();
+        let clip = &data.draw_data[offset..offset + std::mem::size_of::<DrawBeginClip>()];
+        let clip: DrawBeginClip = bytemuck::pod_read_unaligned(clip);
+        assert_eq!(
+            clip.blend_mode,
+            DrawBeginClip::new(Mix::Clip.into(), 1.0).blend_mode
+        );
+    }
+
+    #[test]
+    fn mask_layers_return_an_error_when_too_deep() {
+        let depth = Config::MAX_CLIP_DEPTH;
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        for _ in 0..depth - 1 {
+            sb.push_opacity_layer(0.5);
+        }
+        square(&mut sb, 0.0, 0.0, 100.0);
+        let clip = Rect::new(0.0, 0.0, 50.0, 50.0);
+        // A clip shape needs a second level
+        let error = sb.push_mask_layer(MaskKind::Alpha, Affine::IDENTITY, Some(&clip));
+        assert!(matches!(
+            error,
+            Err(RenderError::ClipDepthExceeded { depth: got, limit }) if got == depth + 1 && limit == depth
+        ));
+        sb.push_mask_layer(MaskKind::Alpha, Affine::IDENTITY, None::<&Rect>)
+            .unwrap();
+        square(&mut sb, 25.0, 25.0, 50.0);
+        sb.pop_layer();
+        for _ in 0..depth - 1 {
+            sb.pop_layer();
+        }
+        sb.finish();
+        // The layers, the mask layer and both squares
+        assert_eq!(scene.n_draw_objects() as u32, depth * 2 + 2);
+        assert_eq!(scene.data().n_clips, depth * 2);
+    }
+
+    #[test]
+    fn mask_layers_are_encoded_beyond_the_limit_when_strict() {
+        let depth = Config::MAX_CLIP_DEPTH;
+        let mut scene = Scene::new();
+        let mut sb = SceneBuilder::for_scene(&mut scene);
+        sb.set_strict_clip_depth(true);
+        for _ in 0..depth {
+            sb.push_opacity_layer(0.5);
         }
+        sb.push_mask_layer(MaskKind::Luminance, Affine::IDENTITY, None::<&Rect>)
+            .unwrap();
+        sb.finish();
+        assert_eq!(scene.data().n_clips, (depth + 1) * 2);
     }
 }
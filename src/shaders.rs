@@ -16,13 +16,19 @@
 
 //! Load rendering shaders.
 
+mod layout;
 mod preprocess;
+pub mod types;
 
-use std::collections::HashSet;
+pub use preprocess::{preprocess, PreprocessError};
+
+use std::collections::{HashMap, HashSet};
 
 use wgpu::Device;
 
-use crate::engine::{BindType, Engine, Error, ImageFormat, ShaderId};
+use crate::encoding::Config;
+use crate::engine::{BindType, Engine, ImageFormat, ShaderId};
+use crate::{DebugOverlay, Error, TileSize};
 
 pub const PATHTAG_REDUCE_WG: u32 = 256;
 pub const PATH_BBOX_WG: u32 = 256;
@@ -30,6 +36,19 @@ pub const PATH_COARSE_WG: u32 = 256;
 pub const PATH_DRAWOBJ_WG: u32 = 256;
 pub const CLIP_REDUCE_WG: u32 = 256;
 
+/// Whether the backdrop stage of the full pipeline is built with the `parallel_backdrop`
+/// define, which sums the tiles of each row in parallel and shares the rows between
+/// several workgroups, rather than giving each row to a single thread. Wide targets
+/// with few paths have too few rows to keep the device busy otherwise. Clearing this
+/// selects the serial version, for comparison.
+pub const PARALLEL_BACKDROP: bool = true;
+/// Tiles of the target for each workgroup of the parallel backdrop stage, per
+/// workgroup of paths.
+pub const BACKDROP_SPLIT_TILES: u32 = 1024;
+/// Most workgroups the parallel backdrop stage shares the tiles of a workgroup of paths
+/// between.
+pub const BACKDROP_MAX_SPLITS: u32 = 64;
+
 macro_rules! shader {
     ($name:expr) => {&{
         let shader = include_str!(concat!(
@@ -66,6 +85,9 @@ pub struct Shaders {
 
 // Shaders for the full pipeline
 pub struct FullShaders {
+    /// Size of the tiles the shaders were built for, which is never
+    /// [`TileSize::Auto`].
+    pub tile_size: TileSize,
     pub pathtag_reduce: ShaderId,
     pub pathtag_reduce2: ShaderId,
     pub pathtag_scan1: ShaderId,
@@ -83,6 +105,15 @@ pub struct FullShaders {
     pub backdrop: ShaderId,
     pub coarse: ShaderId,
     pub fine: ShaderId,
+    /// Clears the tiles which fine is not dispatched over, as they draw nothing.
+    pub fine_clear: ShaderId,
+    /// Fine shader which also draws a debug overlay, used in place of `fine` when it
+    /// is set. It is built by [`debug_fine_shader`] when an overlay is enabled.
+    pub debug_fine: Option<ShaderId>,
+    /// Fine shader which is dispatched over every tile rather than over those coarse
+    /// queues, used in place of `fine` and `fine_clear` when it is set, for comparison.
+    /// It is built by [`dense_fine_shader`].
+    pub dense_fine: Option<ShaderId>,
 }
 
 pub fn init_shaders(device: &Device, engine: &mut Engine) -> Result<Shaders, Error> {
@@ -91,16 +122,19 @@ pub fn init_shaders(device: &Device, engine: &mut Engine) -> Result<Shaders, Err
         .copied()
         .collect::<std::collections::HashMap<_, _>>();
     let empty = HashSet::new();
+    if cfg!(debug_assertions) {
+        check_mirrors(&empty, &imports, types::SIMPLE_MIRRORS)?;
+    }
     let pathtag_reduce = engine.add_shader(
         device,
         "pathtag_reduce",
-        preprocess::preprocess(shader!("pathtag_reduce"), &empty, &imports).into(),
+        preprocess(shader!("pathtag_reduce"), &empty, &imports)?.into(),
         &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
     )?;
     let pathtag_scan = engine.add_shader(
         device,
         "pathtag_scan",
-        preprocess::preprocess(shader!("pathtag_scan"), &empty, &imports).into(),
+        preprocess(shader!("pathtag_scan"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -114,7 +148,7 @@ pub fn init_shaders(device: &Device, engine: &mut Engine) -> Result<Shaders, Err
     let path_coarse = engine.add_shader(
         device,
         "path_coarse",
-        preprocess::preprocess(shader!("path_coarse"), &path_coarse_config, &imports).into(),
+        preprocess(shader!("path_coarse"), &path_coarse_config, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -126,13 +160,13 @@ pub fn init_shaders(device: &Device, engine: &mut Engine) -> Result<Shaders, Err
     let backdrop = engine.add_shader(
         device,
         "backdrop",
-        preprocess::preprocess(shader!("backdrop"), &empty, &imports).into(),
+        preprocess(shader!("backdrop"), &empty, &imports)?.into(),
         &[BindType::Uniform, BindType::Buffer],
     )?;
     let fine = engine.add_shader(
         device,
         "fine",
-        preprocess::preprocess(shader!("fine"), &empty, &imports).into(),
+        preprocess(shader!("fine"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -140,6 +174,10 @@ pub fn init_shaders(device: &Device, engine: &mut Engine) -> Result<Shaders, Err
             BindType::Buffer,
         ],
     )?;
+    declare_config_size(
+        engine,
+        &[pathtag_reduce, pathtag_scan, path_coarse, backdrop, fine],
+    );
     Ok(Shaders {
         pathtag_reduce,
         pathtag_scan,
@@ -149,7 +187,12 @@ pub fn init_shaders(device: &Device, engine: &mut Engine) -> Result<Shaders, Err
     })
 }
 
-pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders, Error> {
+pub fn full_shaders(
+    device: &Device,
+    engine: &mut Engine,
+    deterministic: bool,
+    tile_size: TileSize,
+) -> Result<FullShaders, Error> {
     let imports = SHARED_SHADERS
         .iter()
         .copied()
@@ -160,22 +203,38 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let mut small_config = HashSet::new();
     small_config.insert("full".into());
     small_config.insert("small".into());
+    // Automatic selection is made per scene, between shaders built for each size
+    let tile_size = match tile_size {
+        TileSize::Small => {
+            full_config.insert("small_tiles".into());
+            small_config.insert("small_tiles".into());
+            TileSize::Small
+        }
+        TileSize::Large | TileSize::Auto => TileSize::Large,
+    };
+    let mut fine_config = full_config.clone();
+    if deterministic {
+        fine_config.insert("deterministic".into());
+    }
+    if cfg!(debug_assertions) {
+        check_mirrors(&full_config, &imports, types::MIRRORS)?;
+    }
     let pathtag_reduce = engine.add_shader(
         device,
         "pathtag_reduce",
-        preprocess::preprocess(shader!("pathtag_reduce"), &full_config, &imports).into(),
+        preprocess(shader!("pathtag_reduce"), &full_config, &imports)?.into(),
         &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
     )?;
     let pathtag_reduce2 = engine.add_shader(
         device,
         "pathtag_reduce2",
-        preprocess::preprocess(shader!("pathtag_reduce2"), &full_config, &imports).into(),
+        preprocess(shader!("pathtag_reduce2"), &full_config, &imports)?.into(),
         &[BindType::BufReadOnly, BindType::Buffer],
     )?;
     let pathtag_scan1 = engine.add_shader(
         device,
         "pathtag_scan1",
-        preprocess::preprocess(shader!("pathtag_scan1"), &full_config, &imports).into(),
+        preprocess(shader!("pathtag_scan1"), &full_config, &imports)?.into(),
         &[
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -185,7 +244,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let pathtag_scan = engine.add_shader(
         device,
         "pathtag_scan",
-        preprocess::preprocess(shader!("pathtag_scan"), &small_config, &imports).into(),
+        preprocess(shader!("pathtag_scan"), &small_config, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -196,7 +255,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let pathtag_scan_large = engine.add_shader(
         device,
         "pathtag_scan",
-        preprocess::preprocess(shader!("pathtag_scan"), &full_config, &imports).into(),
+        preprocess(shader!("pathtag_scan"), &full_config, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -207,13 +266,13 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let bbox_clear = engine.add_shader(
         device,
         "bbox_clear",
-        preprocess::preprocess(shader!("bbox_clear"), &empty, &imports).into(),
+        preprocess(shader!("bbox_clear"), &empty, &imports)?.into(),
         &[BindType::Uniform, BindType::Buffer],
     )?;
     let pathseg = engine.add_shader(
         device,
         "pathseg",
-        preprocess::preprocess(shader!("pathseg"), &full_config, &imports).into(),
+        preprocess(shader!("pathseg"), &full_config, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -225,13 +284,13 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let draw_reduce = engine.add_shader(
         device,
         "draw_reduce",
-        preprocess::preprocess(shader!("draw_reduce"), &empty, &imports).into(),
+        preprocess(shader!("draw_reduce"), &empty, &imports)?.into(),
         &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
     )?;
     let draw_leaf = engine.add_shader(
         device,
         "draw_leaf",
-        preprocess::preprocess(shader!("draw_leaf"), &empty, &imports).into(),
+        preprocess(shader!("draw_leaf"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -245,7 +304,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let clip_reduce = engine.add_shader(
         device,
         "clip_reduce",
-        preprocess::preprocess(shader!("clip_reduce"), &empty, &imports).into(),
+        preprocess(shader!("clip_reduce"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -257,7 +316,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let clip_leaf = engine.add_shader(
         device,
         "clip_leaf",
-        preprocess::preprocess(shader!("clip_leaf"), &empty, &imports).into(),
+        preprocess(shader!("clip_leaf"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -271,7 +330,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let binning = engine.add_shader(
         device,
         "binning",
-        preprocess::preprocess(shader!("binning"), &empty, &imports).into(),
+        preprocess(shader!("binning"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -286,7 +345,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let tile_alloc = engine.add_shader(
         device,
         "tile_alloc",
-        preprocess::preprocess(shader!("tile_alloc"), &empty, &imports).into(),
+        preprocess(shader!("tile_alloc"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -300,7 +359,7 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
     let path_coarse = engine.add_shader(
         device,
         "path_coarse_full",
-        preprocess::preprocess(shader!("path_coarse_full"), &full_config, &imports).into(),
+        preprocess(shader!("path_coarse_full"), &full_config, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -312,16 +371,20 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
             BindType::Buffer,
         ],
     )?;
+    let mut backdrop_config = HashSet::new();
+    if PARALLEL_BACKDROP {
+        backdrop_config.insert("parallel_backdrop".into());
+    }
     let backdrop = engine.add_shader(
         device,
         "backdrop_dyn",
-        preprocess::preprocess(shader!("backdrop_dyn"), &empty, &imports).into(),
+        preprocess(shader!("backdrop_dyn"), &backdrop_config, &imports)?.into(),
         &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
     )?;
     let coarse = engine.add_shader(
         device,
         "coarse",
-        preprocess::preprocess(shader!("coarse"), &empty, &imports).into(),
+        preprocess(shader!("coarse"), &empty, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -332,12 +395,14 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
             BindType::BufReadOnly,
             BindType::Buffer,
             BindType::Buffer,
+            BindType::Buffer,
+            BindType::Buffer,
         ],
     )?;
     let fine = engine.add_shader(
         device,
         "fine",
-        preprocess::preprocess(shader!("fine"), &full_config, &imports).into(),
+        preprocess(shader!("fine"), &fine_config, &imports)?.into(),
         &[
             BindType::Uniform,
             BindType::BufReadOnly,
@@ -346,9 +411,44 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
             BindType::BufReadOnly,
             BindType::ImageRead(ImageFormat::Rgba8),
             BindType::BufReadOnly,
+            BindType::BufReadOnly,
+        ],
+    )?;
+    let fine_clear = engine.add_shader(
+        device,
+        "fine_clear",
+        preprocess(shader!("fine_clear"), &full_config, &imports)?.into(),
+        &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::Image(ImageFormat::Rgba8),
         ],
     )?;
+    declare_config_size(
+        engine,
+        &[
+            pathtag_reduce,
+            pathtag_reduce2,
+            pathtag_scan,
+            pathtag_scan1,
+            pathtag_scan_large,
+            bbox_clear,
+            pathseg,
+            draw_reduce,
+            draw_leaf,
+            clip_reduce,
+            clip_leaf,
+            binning,
+            tile_alloc,
+            path_coarse,
+            backdrop,
+            coarse,
+            fine,
+            fine_clear,
+        ],
+    );
     Ok(FullShaders {
+        tile_size,
         pathtag_reduce,
         pathtag_reduce2,
         pathtag_scan,
@@ -366,9 +466,129 @@ pub fn full_shaders(device: &Device, engine: &mut Engine) -> Result<FullShaders,
         backdrop,
         coarse,
         fine,
+        fine_clear,
+        debug_fine: None,
+        dense_fine: None,
     })
 }
 
+/// Builds a fine shader for the full pipeline which draws `overlay` over the scene.
+/// Like [`dense_fine_shader`], it is dispatched over every tile, and its bindings are
+/// followed by the draw bounding boxes written by binning.
+pub fn debug_fine_shader(
+    device: &Device,
+    engine: &mut Engine,
+    deterministic: bool,
+    tile_size: TileSize,
+    overlay: DebugOverlay,
+) -> Result<ShaderId, Error> {
+    let imports = SHARED_SHADERS
+        .iter()
+        .copied()
+        .collect::<std::collections::HashMap<_, _>>();
+    let mut config = HashSet::new();
+    config.insert("full".into());
+    if tile_size == TileSize::Small {
+        config.insert("small_tiles".into());
+    }
+    if deterministic {
+        config.insert("deterministic".into());
+    }
+    config.insert("debug_overlay".into());
+    config.insert("dense".into());
+    let variant = match overlay {
+        DebugOverlay::None => return Err(Error::Internal("no debug overlay to build")),
+        DebugOverlay::DrawBboxes => "debug_draw_bboxes",
+        DebugOverlay::TileOccupancy => "debug_tile_occupancy",
+        DebugOverlay::SegmentDensity => "debug_segment_density",
+    };
+    config.insert(variant.into());
+    let fine_debug = engine.add_shader(
+        device,
+        "fine_debug",
+        preprocess(shader!("fine"), &config, &imports)?.into(),
+        &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::Image(ImageFormat::Rgba8),
+            BindType::BufReadOnly,
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+        ],
+    )?;
+    declare_config_size(engine, &[fine_debug]);
+    Ok(fine_debug)
+}
+
+/// Builds a fine shader for the full pipeline which is dispatched over every tile, with
+/// the bindings of the fine shader except for the tile queue.
+pub fn dense_fine_shader(
+    device: &Device,
+    engine: &mut Engine,
+    deterministic: bool,
+    tile_size: TileSize,
+) -> Result<ShaderId, Error> {
+    let imports = SHARED_SHADERS
+        .iter()
+        .copied()
+        .collect::<std::collections::HashMap<_, _>>();
+    let mut config = HashSet::new();
+    config.insert("full".into());
+    if tile_size == TileSize::Small {
+        config.insert("small_tiles".into());
+    }
+    if deterministic {
+        config.insert("deterministic".into());
+    }
+    config.insert("dense".into());
+    let fine_dense = engine.add_shader(
+        device,
+        "fine_dense",
+        preprocess(shader!("fine"), &config, &imports)?.into(),
+        &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::Image(ImageFormat::Rgba8),
+            BindType::BufReadOnly,
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::BufReadOnly,
+        ],
+    )?;
+    declare_config_size(engine, &[fine_dense]);
+    Ok(fine_dense)
+}
+
+/// Declares the size of the config to the engine for those of `shaders` which bind it
+/// in their first slot, so that debug builds reject recordings binding a smaller one.
+fn declare_config_size(engine: &mut Engine, shaders: &[ShaderId]) {
+    let size = std::mem::size_of::<Config>() as u64;
+    for &shader in shaders {
+        if engine.bind_types(shader).first() == Some(&BindType::Uniform) {
+            engine.set_min_binding_size(shader, 0, size);
+        }
+    }
+}
+
+/// Panics if a Rust mirror of a shader struct doesn't have the size of the struct in
+/// the shared shaders, or in the binning shader which declares `BinHeader`, when they
+/// are preprocessed with `defines`.
+fn check_mirrors(
+    defines: &HashSet<String>,
+    imports: &HashMap<&str, &str>,
+    mirrors: &[(&str, usize)],
+) -> Result<(), PreprocessError> {
+    let sources = SHARED_SHADERS
+        .iter()
+        .map(|(_, source)| preprocess(source, defines, imports))
+        .chain([preprocess(shader!("binning"), defines, imports)])
+        .collect::<Result<Vec<_>, _>>()?;
+    layout::check(&sources, mirrors);
+    Ok(())
+}
+
 macro_rules! shared_shader {
     ($name:expr) => {
         (
@@ -381,6 +601,7 @@ macro_rules! shared_shader {
 const SHARED_SHADERS: &[(&str, &str)] = &[
     shared_shader!("bbox"),
     shared_shader!("blend"),
+    shared_shader!("blur"),
     shared_shader!("bump"),
     shared_shader!("clip"),
     shared_shader!("config"),
@@ -0,0 +1,165 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Checking of the Rust mirrors of shader structs against the WGSL.
+//!
+//! The sizes of the structs are computed from their WGSL declarations with the
+//! layout rules for storage buffers, which also apply to the uniform structs used
+//! here, as they only have scalar and vector fields.
+
+use std::collections::HashMap;
+
+use crate::render::align_up;
+
+/// Size and alignment of a type, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Layout {
+    size: usize,
+    align: usize,
+}
+
+/// Panics if the size of any of the `mirrors`, given as the names of WGSL structs
+/// with the sizes of the Rust types, differs from the size of the struct declared in
+/// the preprocessed `sources`.
+pub fn check(sources: &[String], mirrors: &[(&str, usize)]) {
+    let mut structs = HashMap::new();
+    for source in sources {
+        parse_structs(source, &mut structs);
+    }
+    for (name, rust_size) in mirrors {
+        let layout = structs
+            .get(*name)
+            .unwrap_or_else(|| panic!("the shaders don't declare the struct {name}"));
+        assert_eq!(
+            layout.size, *rust_size,
+            "struct {name} is {} bytes in the shaders, but its Rust mirror is {rust_size} bytes",
+            layout.size
+        );
+    }
+}
+
+/// Adds the layouts of the structs declared in `source` to `structs`. Structs may
+/// refer to structs declared before them.
+fn parse_structs(source: &str, structs: &mut HashMap<String, Layout>) {
+    let source = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut rest = source.as_str();
+    while let Some(start) = find_keyword(rest, "struct") {
+        rest = &rest[start + "struct".len()..];
+        let (open, close) = match (rest.find('{'), rest.find('}')) {
+            (Some(open), Some(close)) if open < close => (open, close),
+            _ => panic!("malformed struct declaration in shader"),
+        };
+        let name = rest[..open].trim().to_owned();
+        let layout = struct_layout(&name, &rest[open + 1..close], structs);
+        structs.insert(name, layout);
+        rest = &rest[close + 1..];
+    }
+}
+
+/// Returns the offset of the first use of `keyword` as a whole word.
+fn find_keyword(text: &str, keyword: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut from = 0;
+    while let Some(ix) = text[from..].find(keyword) {
+        let ix = from + ix;
+        let before = text[..ix].chars().next_back();
+        let after = text[ix + keyword.len()..].chars().next();
+        if !before.map_or(false, is_ident) && !after.map_or(false, is_ident) {
+            return Some(ix);
+        }
+        from = ix + keyword.len();
+    }
+    None
+}
+
+fn struct_layout(name: &str, body: &str, structs: &HashMap<String, Layout>) -> Layout {
+    let mut offset = 0;
+    let mut align = 1;
+    for field in split_top_level(body) {
+        let ty = match field.split_once(':') {
+            Some((_, ty)) => ty.trim(),
+            None => panic!("malformed field `{field}` in struct {name}"),
+        };
+        let field = type_layout(ty, structs)
+            .unwrap_or_else(|| panic!("unknown type {ty} in struct {name}"));
+        offset = align_up(offset, field.align as u32) + field.size;
+        align = align.max(field.align);
+    }
+    Layout {
+        size: align_up(offset, align as u32),
+        align,
+    }
+}
+
+fn type_layout(ty: &str, structs: &HashMap<String, Layout>) -> Option<Layout> {
+    let scalar = Layout { size: 4, align: 4 };
+    let (base, param) = match ty.split_once('<') {
+        Some((base, param)) => (base.trim(), Some(param.strip_suffix('>')?.trim())),
+        None => (ty, None),
+    };
+    match (base, param) {
+        ("u32" | "i32" | "f32", None) => Some(scalar),
+        ("atomic", Some("u32" | "i32")) => Some(scalar),
+        ("vec2", Some(_)) => Some(Layout { size: 8, align: 8 }),
+        ("vec3", Some(_)) => Some(Layout {
+            size: 12,
+            align: 16,
+        }),
+        ("vec4", Some(_)) => Some(Layout {
+            size: 16,
+            align: 16,
+        }),
+        ("array", Some(param)) => {
+            let (element, count) = param.rsplit_once(',')?;
+            let element = type_layout(element.trim(), structs)?;
+            let count = count.trim().trim_end_matches('u').parse::<usize>().ok()?;
+            Some(Layout {
+                size: align_up(element.size, element.align as u32) * count,
+                align: element.align,
+            })
+        }
+        (name, None) => structs.get(name).copied(),
+        _ => None,
+    }
+}
+
+/// Splits the fields of a struct body at the commas which aren't inside a type.
+fn split_top_level(body: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (ix, c) in body.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(&body[start..ix]);
+                start = ix + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(&body[start..]);
+    fields
+        .into_iter()
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect()
+}
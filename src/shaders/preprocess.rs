@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs,
+    fmt, fs,
     path::Path,
     vec,
 };
@@ -31,12 +31,130 @@ pub fn get_imports(shader_dir: &Path) -> HashMap<String, String> {
 pub struct StackItem {
     active: bool,
     else_passed: bool,
+    /// Line of the `#ifdef` or `#ifndef`.
+    line: usize,
 }
 
-pub fn preprocess(input: &str, defines: &HashSet<String>, imports: &HashMap<&str, &str>) -> String {
+/// Error produced when a shader has malformed preprocessor directives.
+///
+/// Line numbers start at 1 and refer to the source containing the directive, which
+/// may be an import.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PreprocessError {
+    /// An `#ifdef` or `#ifndef` has no matching `#endif`.
+    UnclosedConditional { line: usize },
+    /// An `#else` or `#endif` has no matching `#ifdef` or `#ifndef`.
+    UnmatchedDirective { directive: String, line: usize },
+    /// A conditional has a second `#else`.
+    DuplicateElse { line: usize },
+    /// An `#import` has no name.
+    MissingImportName { line: usize },
+    /// An `#import` names a source which isn't in the imports.
+    UnknownImport { name: String, line: usize },
+    /// A source imports itself, directly or through other imports.
+    RecursiveImport { name: String },
+    /// A directive isn't one of those supported.
+    UnknownDirective { directive: String, line: usize },
+    /// A conditional directive isn't the first item on its line.
+    MisplacedDirective { directive: String, line: usize },
+    /// An `#else` or `#endif` is followed by something other than a comment.
+    UnexpectedArgument {
+        directive: String,
+        argument: String,
+        line: usize,
+    },
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnclosedConditional { line } => {
+                write!(f, "conditional on line {line} has no #endif")
+            }
+            Self::UnmatchedDirective { directive, line } => {
+                write!(
+                    f,
+                    "#{directive} on line {line} has no matching #ifdef or #ifndef"
+                )
+            }
+            Self::DuplicateElse { line } => {
+                write!(f, "second #else for the same conditional on line {line}")
+            }
+            Self::MissingImportName { line } => write!(f, "#import on line {line} has no name"),
+            Self::UnknownImport { name, line } => {
+                write!(f, "unknown import `{name}` on line {line}")
+            }
+            Self::RecursiveImport { name } => write!(f, "`{name}` imports itself"),
+            Self::UnknownDirective { directive, line } => {
+                write!(
+                    f,
+                    "unknown preprocessor directive `{directive}` on line {line}"
+                )
+            }
+            Self::MisplacedDirective { directive, line } => {
+                write!(
+                    f,
+                    "#{directive} on line {line} is not the first item on its line"
+                )
+            }
+            Self::UnexpectedArgument {
+                directive,
+                argument,
+                line,
+            } => {
+                write!(
+                    f,
+                    "#{directive} on line {line} takes no argument, but is followed by `{argument}`"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Expands the `#import` directives of a WGSL `input` with the sources in `imports`,
+/// and keeps the lines of `#ifdef`, `#ifndef` and `#else` blocks whose conditions
+/// hold for `defines`.
+///
+/// This never panics, so arbitrary sources can be preprocessed.
+pub fn preprocess(
+    input: &str,
+    defines: &HashSet<String>,
+    imports: &HashMap<&str, &str>,
+) -> Result<String, PreprocessError> {
     let mut output = String::with_capacity(input.len());
+    preprocess_into(&mut output, input, defines, imports, &mut vec![])?;
+    Ok(output)
+}
+
+/// Checks that the `remainder` of the line of a directive which takes no argument is
+/// empty or a comment.
+fn check_no_argument(directive: &str, remainder: &str, line: usize) -> Result<(), PreprocessError> {
+    let remainder = remainder.trim();
+    if remainder.is_empty() || remainder.starts_with("//") {
+        Ok(())
+    } else {
+        Err(PreprocessError::UnexpectedArgument {
+            directive: directive.into(),
+            argument: remainder.into(),
+            line,
+        })
+    }
+}
+
+/// Appends the preprocessed `input` to `output`. `importing` holds the names of the
+/// imports being expanded, so that cycles can be detected.
+fn preprocess_into<'a>(
+    output: &mut String,
+    input: &str,
+    defines: &HashSet<String>,
+    imports: &HashMap<&'a str, &'a str>,
+    importing: &mut Vec<&'a str>,
+) -> Result<(), PreprocessError> {
     let mut stack = vec![];
-    'all_lines: for (line_number, mut line) in input.lines().enumerate() {
+    'all_lines: for (line_index, mut line) in input.lines().enumerate() {
+        let line_number = line_index + 1;
         loop {
             if line.is_empty() {
                 break;
@@ -61,8 +179,10 @@ pub fn preprocess(input: &str, defines: &HashSet<String>, imports: &HashMap<&str
 
             match directive {
                 if_item @ ("ifdef" | "ifndef" | "else" | "endif") if !directive_is_at_start => {
-                    eprintln!("#{if_item} directives must be the first non_whitespace items on their line, ignoring (line {line_number})");
-                    break;
+                    return Err(PreprocessError::MisplacedDirective {
+                        directive: if_item.into(),
+                        line: line_number,
+                    });
                 }
                 def_test @ ("ifdef" | "ifndef") => {
                     let def = directive_start[directive_len..].trim();
@@ -71,48 +191,44 @@ pub fn preprocess(input: &str, defines: &HashSet<String>, imports: &HashMap<&str
                     stack.push(StackItem {
                         active: mode == exists,
                         else_passed: false,
+                        line: line_number,
                     });
                     // Don't add this line to the output; instead process the next line
                     continue 'all_lines;
                 }
                 "else" => {
-                    let item = stack.last_mut();
-                    if let Some(item) = item {
-                        if item.else_passed {
-                            eprintln!("Second else for same ifdef/ifndef (line {line_number}); ignoring second else")
-                        } else {
-                            item.else_passed = true;
-                            item.active = !item.active;
-                        }
-                    }
-                    let remainder = directive_start[directive_len..].trim();
-                    if !remainder.is_empty() {
-                        eprintln!("#else directives don't take an argument. `{remainder}` will not be in output (line {line_number})");
+                    let Some(item) = stack.last_mut() else {
+                        return Err(PreprocessError::UnmatchedDirective {
+                            directive: "else".into(),
+                            line: line_number,
+                        });
+                    };
+                    if item.else_passed {
+                        return Err(PreprocessError::DuplicateElse { line: line_number });
                     }
-                    // Don't add this line to the output; it should be empty (see warning above)
+                    item.else_passed = true;
+                    item.active = !item.active;
+                    check_no_argument("else", &directive_start[directive_len..], line_number)?;
+                    // Don't add this line to the output
                     continue 'all_lines;
                 }
                 "endif" => {
                     if stack.pop().is_none() {
-                        eprintln!("Mismatched endif (line {line_number})");
-                    }
-                    let remainder = directive_start[directive_len..].trim();
-                    if !remainder.is_empty() {
-                        eprintln!("#endif directives don't take an argument. `{remainder}` will not be in output (line {line_number})");
+                        return Err(PreprocessError::UnmatchedDirective {
+                            directive: "endif".into(),
+                            line: line_number,
+                        });
                     }
-                    // Don't add this line to the output; it should be empty (see warning above)
+                    check_no_argument("endif", &directive_start[directive_len..], line_number)?;
+                    // Don't add this line to the output
                     continue 'all_lines;
                 }
                 "import" => {
                     output.push_str(&line[..hash_index]);
                     let directive_end = &directive_start[directive_len..];
-                    let import_name_start = if let Some(import_name_start) =
-                        directive_end.find(|c: char| !c.is_whitespace())
-                    {
-                        import_name_start
-                    } else {
-                        eprintln!("#import needs a non_whitespace argument (line {line_number})");
-                        continue 'all_lines;
+                    let Some(import_name_start) = directive_end.find(|c: char| !c.is_whitespace())
+                    else {
+                        return Err(PreprocessError::MissingImportName { line: line_number });
                     };
                     let import_name_start = &directive_end[import_name_start..];
                     let import_name_end_index = import_name_start
@@ -121,20 +237,29 @@ pub fn preprocess(input: &str, defines: &HashSet<String>, imports: &HashMap<&str
                         .unwrap_or(import_name_start.len());
                     let import_name = &import_name_start[..import_name_end_index];
                     line = &import_name_start[import_name_end_index..];
-                    let import = imports.get(import_name);
-                    if let Some(import) = import {
-                        // In theory, we can cache this until the top item of the stack changes
-                        // However, in practise there will only ever be at most 2 stack items, so it's reasonable to just recompute it every time
-                        if stack.iter().all(|item| item.active) {
-                            output.push_str(&preprocess(import, defines, imports));
+                    let Some((&name, &import)) = imports.get_key_value(import_name) else {
+                        return Err(PreprocessError::UnknownImport {
+                            name: import_name.into(),
+                            line: line_number,
+                        });
+                    };
+                    // In theory, we can cache this until the top item of the stack changes
+                    // However, in practise there will only ever be at most 2 stack items, so it's reasonable to just recompute it every time
+                    if stack.iter().all(|item| item.active) {
+                        if importing.contains(&name) {
+                            return Err(PreprocessError::RecursiveImport { name: name.into() });
                         }
-                    } else {
-                        eprintln!("Unknown import `{import_name}` (line {line_number})");
+                        importing.push(name);
+                        preprocess_into(output, import, defines, imports, importing)?;
+                        importing.pop();
                     }
                     continue;
                 }
                 val => {
-                    eprintln!("Unknown preprocessor directive `{val}` (line {line_number})");
+                    return Err(PreprocessError::UnknownDirective {
+                        directive: val.into(),
+                        line: line_number,
+                    });
                 }
             }
         }
@@ -151,5 +276,55 @@ pub fn preprocess(input: &str, defines: &HashSet<String>, imports: &HashMap<&str
             output.push('\n');
         }
     }
-    output
+    match stack.last() {
+        Some(item) => Err(PreprocessError::UnclosedConditional { line: item.line }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> Result<String, PreprocessError> {
+        let defines = ["full".to_string()].into_iter().collect();
+        preprocess(input, &defines, &HashMap::new())
+    }
+
+    #[test]
+    fn conditionals_keep_active_lines() {
+        let input = "a\n#ifdef full\nb\n#else // not full\nc\n#endif\n#ifndef full\nd\n#endif\n";
+        assert_eq!(run(input).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn misplaced_directives_are_errors() {
+        assert_eq!(
+            run("a\nlet x = 1; #ifdef full\n#endif\n"),
+            Err(PreprocessError::MisplacedDirective {
+                directive: "ifdef".into(),
+                line: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn arguments_to_else_and_endif_are_errors() {
+        assert_eq!(
+            run("#ifdef full\n#else full\n#endif\n"),
+            Err(PreprocessError::UnexpectedArgument {
+                directive: "else".into(),
+                argument: "full".into(),
+                line: 2,
+            })
+        );
+        assert_eq!(
+            run("#ifdef full\n#endif full\n"),
+            Err(PreprocessError::UnexpectedArgument {
+                directive: "endif".into(),
+                argument: "full".into(),
+                line: 2,
+            })
+        );
+    }
 }
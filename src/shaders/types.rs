@@ -0,0 +1,146 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Rust mirrors of the structs which the shaders store in buffers.
+//!
+//! The sizes of the buffers are computed from the sizes of these types, and
+//! [`MIRRORS`] and [`SIMPLE_MIRRORS`] list them by the names of the WGSL structs, so
+//! that [`layout::check`](super::layout::check) can compare them with the shaders in
+//! debug builds. Padding fields stand in for the gaps which WGSL leaves before fields
+//! with a larger alignment, so every type has the size of its struct in a buffer.
+
+// Most of the fields are only written and read by the shaders
+#![allow(dead_code)]
+
+use std::mem::size_of;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::encoding::{Config, DrawMonoid, PathBbox, PathMonoid, PathSegment};
+
+/// Mirror of `TagMonoid` in shader/shared/pathtag.wgsl without the `full` define.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct TagMonoid {
+    pub trans_ix: u32,
+    pub pathseg_ix: u32,
+    pub pathseg_offset: u32,
+}
+
+/// Mirror of `Cubic` in shader/shared/cubic.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Cubic {
+    pub p0: [f32; 2],
+    pub p1: [f32; 2],
+    pub p2: [f32; 2],
+    pub p3: [f32; 2],
+    pub stroke: [f32; 2],
+    pub path_ix: u32,
+    pub flags: u32,
+}
+
+/// Mirror of `Bic` in shader/shared/clip.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Bic {
+    pub a: u32,
+    pub b: u32,
+}
+
+/// Mirror of `ClipInp` in shader/shared/clip.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct ClipInp {
+    pub ix: u32,
+    pub path_ix: i32,
+}
+
+/// Mirror of `ClipEl` in shader/shared/clip.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct ClipEl {
+    pub parent_ix: u32,
+    pub _padding: [u32; 3],
+    pub bbox: [f32; 4],
+}
+
+/// Mirror of `BumpAllocators` in shader/shared/bump.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct BumpAllocators {
+    pub failed: u32,
+    // Final needed dynamic size of the buffers. If any of these are larger than the corresponding `_size` element
+    // reallocation needs to occur
+    pub binning: u32,
+    pub ptcl: u32,
+    pub tile: u32,
+    pub segments: u32,
+    pub blend: u32,
+}
+
+/// Mirror of `BinHeader` in shader/binning.wgsl and shader/coarse.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct BinHeader {
+    pub element_count: u32,
+    pub chunk_offset: u32,
+}
+
+/// Mirror of `Path` in shader/shared/tile.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Path {
+    /// Bounding box in tiles.
+    pub bbox: [u32; 4],
+    /// Offset of the tiles of the path in the tile buffer.
+    pub tiles: u32,
+    pub _padding: [u32; 3],
+}
+
+/// Mirror of `Tile` in shader/shared/tile.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Tile {
+    pub backdrop: i32,
+    pub segments: u32,
+}
+
+/// The structs used by the full pipeline, as the names of the WGSL structs with the
+/// sizes of their mirrors.
+pub const MIRRORS: &[(&str, usize)] = &[
+    ("Config", size_of::<Config>()),
+    ("TagMonoid", size_of::<PathMonoid>()),
+    ("PathBbox", size_of::<PathBbox>()),
+    ("Cubic", size_of::<Cubic>()),
+    ("DrawMonoid", size_of::<DrawMonoid>()),
+    ("Bic", size_of::<Bic>()),
+    ("ClipInp", size_of::<ClipInp>()),
+    ("ClipEl", size_of::<ClipEl>()),
+    ("BumpAllocators", size_of::<BumpAllocators>()),
+    ("BinHeader", size_of::<BinHeader>()),
+    ("Path", size_of::<Path>()),
+    ("Tile", size_of::<Tile>()),
+    ("Segment", size_of::<PathSegment>()),
+];
+
+/// The structs used by the simple pipeline, which is built without the `full` define.
+pub const SIMPLE_MIRRORS: &[(&str, usize)] = &[
+    ("Config", size_of::<Config>()),
+    ("TagMonoid", size_of::<TagMonoid>()),
+    ("Tile", size_of::<Tile>()),
+    ("Segment", size_of::<PathSegment>()),
+];
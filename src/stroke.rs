@@ -0,0 +1,568 @@
+// Copyright 2023 The vello authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Also licensed under MIT license, at your choice.
+
+//! Expansion of strokes with joins and caps which the GPU can't draw into fills.
+//!
+//! The GPU draws every stroke with round joins and caps. Other styles are drawn by
+//! expanding the stroke into a union of polygons on the CPU, one for each segment,
+//! join and cap of the flattened path, which is filled with the non-zero rule. All
+//! of the polygons wind the same way, so their overlaps add up instead of cancelling.
+//! Joins and caps start and end at exactly the same points as the sides of the
+//! segments they meet, so no cracks open up between them after a transform.
+
+use peniko::kurbo::{self, BezPath, PathEl, Point, Vec2};
+use peniko::{Cap, Join, Stroke};
+
+/// Returns true if strokes of `style` need to be expanded on the CPU.
+pub fn needs_expansion(style: &Stroke) -> bool {
+    let round =
+        style.join == Join::Round && style.start_cap == Cap::Round && style.end_cap == Cap::Round;
+    !round && style.width > 0.0
+}
+
+/// Expands a stroke of `path` into a path to fill with the non-zero rule. `tolerance`
+/// is the accuracy of the flattening of curves and round joins and caps.
+pub fn expand(path: impl IntoIterator<Item = PathEl>, style: &Stroke, tolerance: f64) -> BezPath {
+    let mut stroker = Stroker {
+        style,
+        half_width: style.width as f64 * 0.5,
+        tolerance,
+        out: BezPath::new(),
+    };
+    let mut subpath = Vec::new();
+    let mut start_point = Point::ZERO;
+    let mut last_point = Point::ZERO;
+    for el in path {
+        if subpath.is_empty() && !matches!(el, PathEl::MoveTo(_)) {
+            // Segments after a close start a new subpath from the same point
+            subpath.push(Vertex::corner(last_point));
+        }
+        match el {
+            PathEl::MoveTo(p) => {
+                stroker.subpath(&subpath, false);
+                subpath.clear();
+                subpath.push(Vertex::corner(p));
+                start_point = p;
+                last_point = p;
+            }
+            PathEl::LineTo(p) => {
+                subpath.push(Vertex::corner(p));
+                last_point = p;
+            }
+            PathEl::QuadTo(..) | PathEl::CurveTo(..) => {
+                // Flattening vertices inside a curve are joined smoothly, as the curve
+                // has no corner there
+                let mut points = Vec::new();
+                kurbo::flatten([PathEl::MoveTo(last_point), el], tolerance, |el| {
+                    if let PathEl::LineTo(p) = el {
+                        points.push(p);
+                    }
+                });
+                let n_points = points.len();
+                for (ix, p) in points.into_iter().enumerate() {
+                    subpath.push(Vertex {
+                        point: p,
+                        smooth: ix + 1 < n_points,
+                    });
+                }
+                if let Some(vertex) = subpath.last() {
+                    last_point = vertex.point;
+                }
+            }
+            PathEl::ClosePath => {
+                stroker.subpath(&subpath, true);
+                subpath.clear();
+                last_point = start_point;
+            }
+        }
+    }
+    stroker.subpath(&subpath, false);
+    stroker.out
+}
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    point: Point,
+    /// Whether the vertex is inside a flattened curve rather than at a corner.
+    smooth: bool,
+}
+
+impl Vertex {
+    fn corner(point: Point) -> Self {
+        Self {
+            point,
+            smooth: false,
+        }
+    }
+}
+
+struct Stroker<'a> {
+    style: &'a Stroke,
+    half_width: f64,
+    tolerance: f64,
+    out: BezPath,
+}
+
+impl<'a> Stroker<'a> {
+    fn subpath(&mut self, vertices: &[Vertex], closed: bool) {
+        if vertices.len() < 2 && !closed {
+            // A lone move has nothing to stroke
+            return;
+        }
+        let mut vertices = vertices.to_vec();
+        vertices.dedup_by(|b, a| a.point == b.point);
+        if closed && vertices.len() > 1 && vertices[0].point == vertices[vertices.len() - 1].point {
+            vertices.pop();
+        }
+        match vertices.len() {
+            0 => {}
+            1 => {
+                // A subpath with no length is drawn as its caps, where SVG aligns square
+                // caps with the axes
+                let p = vertices[0].point;
+                self.cap(p, Vec2::new(1.0, 0.0), self.style.end_cap);
+                self.cap(p, Vec2::new(-1.0, 0.0), self.style.start_cap);
+            }
+            n => {
+                let n_segments = if closed { n } else { n - 1 };
+                for ix in 0..n_segments {
+                    let p0 = vertices[ix].point;
+                    let p1 = vertices[(ix + 1) % n].point;
+                    let normal = normal(p1 - p0) * self.half_width;
+                    self.polygon(&[p0 + normal, p1 + normal, p1 - normal, p0 - normal]);
+                }
+                let joins = if closed { 0..n } else { 1..n - 1 };
+                for ix in joins {
+                    let prev = vertices[(ix + n - 1) % n].point;
+                    let vertex = vertices[ix];
+                    let next = vertices[(ix + 1) % n].point;
+                    let join = if vertex.smooth {
+                        Join::Round
+                    } else {
+                        self.style.join
+                    };
+                    self.join(vertex.point, vertex.point - prev, next - vertex.point, join);
+                }
+                if !closed {
+                    let start = vertices[0].point;
+                    let end = vertices[n - 1].point;
+                    self.cap(start, start - vertices[1].point, self.style.start_cap);
+                    self.cap(end, end - vertices[n - 2].point, self.style.end_cap);
+                }
+            }
+        }
+    }
+
+    /// Adds the join at `p` between segments in the directions `d0` and `d1`.
+    fn join(&mut self, p: Point, d0: Vec2, d1: Vec2, join: Join) {
+        // The normals are computed from the directions like those of the segments, so
+        // that the join meets their corners exactly
+        let (n0, n1) = (normal(d0) * self.half_width, normal(d1) * self.half_width);
+        let (d0, d1) = (d0.normalize(), d1.normalize());
+        let cross = d0.cross(d1);
+        let cos_turn = d0.dot(d1);
+        if cross.abs() < 1e-9 && cos_turn > 0.0 {
+            // The segments continue straight on, so they meet without a gap
+            return;
+        }
+        // The join fills the gap on the outside of the turn
+        let (a, b) = if cross > 0.0 {
+            (p - n0, p - n1)
+        } else {
+            (p + n0, p + n1)
+        };
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        match join {
+            Join::Bevel => self.polygon(&[p, a, b]),
+            Join::Miter => {
+                // The ratio of the length of the miter to the width of the stroke is
+                // 1 / sin(θ / 2) for an angle θ between the segments, which is
+                // 1 / cos(φ / 2) for the turning angle φ. A reversal has an unbounded
+                // miter, so it is always beveled.
+                let cos_half_turn = ((1.0 + cos_turn) * 0.5).max(0.0).sqrt();
+                let miter_limit = self.style.miter_limit as f64;
+                if cos_half_turn < 1e-9 || cos_half_turn * miter_limit < 1.0 {
+                    self.polygon(&[p, a, b]);
+                } else {
+                    let bisector = (a - p + (b - p)).normalize();
+                    let tip = p + bisector * (self.half_width / cos_half_turn);
+                    self.polygon(&[p, a, tip, b]);
+                }
+            }
+            Join::Round => {
+                let start = (a - p).atan2();
+                let sweep = cross.atan2(cos_turn).abs() * -side;
+                let mut points = vec![p];
+                self.arc(&mut points, p, start, sweep, a, b);
+                self.polygon(&points);
+            }
+        }
+    }
+
+    /// Adds a cap at `p`, the end of a segment in the direction `d`.
+    fn cap(&mut self, p: Point, d: Vec2, cap: Cap) {
+        // The normal of the reversed direction of a segment is exactly the negated
+        // normal of the segment, so the cap meets the corners of the segment at either
+        // end of the subpath
+        let n = normal(d) * self.half_width;
+        let d = d.normalize() * self.half_width;
+        match cap {
+            Cap::Butt => {}
+            Cap::Square => self.polygon(&[p + n, p + n + d, p - n + d, p - n]),
+            Cap::Round => {
+                let mut points = Vec::new();
+                self.arc(
+                    &mut points,
+                    p,
+                    n.atan2(),
+                    -std::f64::consts::PI,
+                    p + n,
+                    p - n,
+                );
+                self.polygon(&points);
+            }
+        }
+    }
+
+    /// Appends the points of an arc around `center` with a radius of half the line
+    /// width, from the angle `start` through `sweep` radians. The first and last
+    /// points are replaced by `from` and `to`, which are on the arc up to rounding.
+    fn arc(
+        &self,
+        points: &mut Vec<Point>,
+        center: Point,
+        start: f64,
+        sweep: f64,
+        from: Point,
+        to: Point,
+    ) {
+        let radius = self.half_width;
+        // Each step deviates from the arc by at most the tolerance
+        let max_step = 2.0 * (1.0 - (self.tolerance / radius).min(1.0)).acos();
+        let n_steps = (sweep.abs() / max_step.max(1e-3)).ceil().clamp(1.0, 1000.0) as usize;
+        points.push(from);
+        for ix in 1..n_steps {
+            let angle = start + sweep * (ix as f64 / n_steps as f64);
+            points.push(center + Vec2::from_angle(angle) * radius);
+        }
+        points.push(to);
+    }
+
+    /// Adds a polygon, reversed if needed so that it winds the same way as the others.
+    /// Polygons with no area are dropped.
+    fn polygon(&mut self, points: &[Point]) {
+        let mut area = 0.0;
+        for (ix, p0) in points.iter().enumerate() {
+            let p1 = points[(ix + 1) % points.len()];
+            area += p0.to_vec2().cross(p1.to_vec2());
+        }
+        if !area.is_finite() || area == 0.0 {
+            return;
+        }
+        let mut ordered = points.iter().copied();
+        let mut reversed = points.iter().rev().copied();
+        let points: &mut dyn Iterator<Item = Point> = if area > 0.0 {
+            &mut ordered
+        } else {
+            &mut reversed
+        };
+        if let Some(first) = points.next() {
+            self.out.move_to(first);
+            for p in points {
+                self.out.line_to(p);
+            }
+            self.out.close_path();
+        }
+    }
+}
+
+/// Returns the unit vector a quarter turn from `d`.
+fn normal(d: Vec2) -> Vec2 {
+    Vec2::new(-d.y, d.x).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use peniko::kurbo::{Circle, RoundedRect, Shape};
+
+    use super::*;
+
+    /// Returns the points of each polygon of an expanded stroke.
+    fn polygons(path: &BezPath) -> Vec<Vec<Point>> {
+        let mut polygons = Vec::new();
+        for el in path.elements() {
+            match el {
+                PathEl::MoveTo(p) => polygons.push(vec![*p]),
+                PathEl::LineTo(p) => polygons.last_mut().unwrap().push(*p),
+                PathEl::ClosePath => {}
+                _ => panic!("expanded strokes only have lines"),
+            }
+        }
+        polygons
+    }
+
+    /// Returns the distance from `p` to the closest point of `path`, flattened densely.
+    fn distance(p: Point, path: &BezPath) -> f64 {
+        let mut distance = f64::INFINITY;
+        let mut last = Point::ZERO;
+        kurbo::flatten(path.iter(), 1e-6, |el| match el {
+            PathEl::MoveTo(p0) => last = p0,
+            PathEl::LineTo(p1) => {
+                let d = p1 - last;
+                let t = ((p - last).dot(d) / d.hypot2()).clamp(0.0, 1.0);
+                if t.is_finite() {
+                    distance = distance.min((last + d * t).distance(p));
+                }
+                last = p1;
+            }
+            _ => {}
+        });
+        distance
+    }
+
+    /// Asserts that every point of the outline of a stroke of `shape` which isn't on
+    /// the path is half the line width from it, to within the tolerance.
+    fn assert_matches_reference(shape: &impl Shape, style: &Stroke, tolerance: f64) {
+        let path = shape.path_elements(tolerance).collect::<BezPath>();
+        let outline = expand(path.iter(), style, tolerance);
+        let half_width = style.width as f64 * 0.5;
+        let mut n_points = 0;
+        for p in polygons(&outline).into_iter().flatten() {
+            let distance = distance(p, &path);
+            if distance > half_width * 0.5 {
+                assert!(
+                    (distance - half_width).abs() <= 2.0 * tolerance,
+                    "{p:?} is {distance} from the path"
+                );
+                n_points += 1;
+            }
+        }
+        assert!(n_points > 0);
+    }
+
+    #[test]
+    fn joins_meet_segment_corners_exactly() {
+        let vertices = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (3.0, 17.0),
+            (3.0, 30.0),
+        ];
+        let mut path = BezPath::new();
+        path.move_to(vertices[0]);
+        for p in &vertices[1..] {
+            path.line_to(*p);
+        }
+        for join in [Join::Bevel, Join::Miter, Join::Round] {
+            let style = Stroke::new(3.0).with_join(join).with_caps(Cap::Butt);
+            let polygons = polygons(&expand(path.iter(), &style, 0.01));
+            for vertex in vertices[1..vertices.len() - 1]
+                .iter()
+                .map(|p| Point::from(*p))
+            {
+                let joins = polygons.iter().filter(|polygon| polygon.contains(&vertex));
+                let mut n_joins = 0;
+                for polygon in joins {
+                    let ix = polygon.iter().position(|p| *p == vertex).unwrap();
+                    let n = polygon.len();
+                    for corner in [polygon[(ix + 1) % n], polygon[(ix + n - 1) % n]] {
+                        assert!(
+                            polygons
+                                .iter()
+                                .any(|other| !other.contains(&vertex) && other.contains(&corner)),
+                            "{join:?} join at {vertex:?} doesn't meet a segment at {corner:?}"
+                        );
+                    }
+                    n_joins += 1;
+                }
+                assert_eq!(n_joins, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn caps_meet_segment_corners_exactly() {
+        let mut path = BezPath::new();
+        path.move_to((1.0, 2.0));
+        path.line_to((8.0, 5.0));
+        for cap in [Cap::Square, Cap::Round] {
+            let style = Stroke::new(3.0).with_caps(cap).with_join(Join::Bevel);
+            let polygons = polygons(&expand(path.iter(), &style, 0.01));
+            // The segment is followed by its start and end caps
+            assert_eq!(polygons.len(), 3);
+            for polygon in &polygons[1..] {
+                let shared = polygon.iter().filter(|p| polygons[0].contains(p)).count();
+                assert_eq!(shared, 2, "{cap:?} cap doesn't meet the segment");
+            }
+        }
+    }
+
+    #[test]
+    fn stroke_under_non_uniform_scale_matches_reference() {
+        // The tolerance the scene builder uses under `Affine::scale_non_uniform(30.0, 0.3)`
+        let tolerance = 0.1 / 30.0;
+        let shape = RoundedRect::new(0.0, 0.0, 20.0, 10.0, 3.0);
+        assert_matches_reference(&shape, &Stroke::new(2.0), tolerance);
+        let style = Stroke::new(2.0).with_join(Join::Miter);
+        assert_matches_reference(&shape, &style, tolerance);
+    }
+
+    #[test]
+    fn stroke_at_high_zoom_matches_reference() {
+        // The tolerance the scene builder uses under `Affine::scale(64.0)`
+        let tolerance = 0.1 / 64.0;
+        let shape = Circle::new((0.0, 0.0), 5.0);
+        assert_matches_reference(&shape, &Stroke::new(2.0).with_join(Join::Bevel), tolerance);
+    }
+
+    /// Returns twice the signed area of a polygon.
+    fn area(polygon: &[Point]) -> f64 {
+        let mut area = 0.0;
+        for (ix, p0) in polygon.iter().enumerate() {
+            let p1 = polygon[(ix + 1) % polygon.len()];
+            area += p0.to_vec2().cross(p1.to_vec2());
+        }
+        area
+    }
+
+    /// Returns the polygons of the joins of a stroke, which are the only ones that
+    /// contain the vertex they join at.
+    fn joins(path: &BezPath, style: &Stroke, vertex: Point) -> Vec<Vec<Point>> {
+        polygons(&expand(path.iter(), style, 0.01))
+            .into_iter()
+            .filter(|polygon| polygon.contains(&vertex))
+            .collect()
+    }
+
+    fn polyline(points: &[(f64, f64)]) -> BezPath {
+        let mut path = BezPath::new();
+        path.move_to(points[0]);
+        for p in &points[1..] {
+            path.line_to(*p);
+        }
+        path
+    }
+
+    fn assert_points_near(points: &[Point], expected: &[(f64, f64)]) {
+        assert_eq!(points.len(), expected.len(), "{points:?}");
+        for (p, q) in points.iter().zip(expected) {
+            assert!(p.distance(Point::from(*q)) < 1e-9, "{points:?}");
+        }
+    }
+
+    #[test]
+    fn right_angle_joins_have_expected_outline() {
+        let path = polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let vertex = Point::new(10.0, 0.0);
+        let style = Stroke::new(2.0).with_caps(Cap::Butt);
+        let bevel = joins(&path, &style.clone().with_join(Join::Bevel), vertex);
+        assert_eq!(bevel.len(), 1);
+        assert_points_near(&bevel[0], &[(10.0, 0.0), (10.0, -1.0), (11.0, 0.0)]);
+        let miter = joins(&path, &style.with_join(Join::Miter), vertex);
+        assert_eq!(miter.len(), 1);
+        assert_points_near(
+            &miter[0],
+            &[(10.0, 0.0), (10.0, -1.0), (11.0, -1.0), (11.0, 0.0)],
+        );
+    }
+
+    #[test]
+    fn miter_limit_bounds_miter_length() {
+        // Turning by 90° and 120° gives miters of √2 and 2 times the width
+        let right_angle = polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let sharp = polyline(&[(0.0, 0.0), (10.0, 0.0), (5.0, 75f64.sqrt())]);
+        let vertex = Point::new(10.0, 0.0);
+        for (path, ratio) in [(right_angle, std::f32::consts::SQRT_2), (sharp, 2.0)] {
+            for (miter_limit, n_points) in [(ratio * 1.01, 4), (ratio * 0.99, 3)] {
+                let style = Stroke::new(2.0)
+                    .with_caps(Cap::Butt)
+                    .with_join(Join::Miter)
+                    .with_miter_limit(miter_limit);
+                let joins = joins(&path, &style, vertex);
+                assert_eq!(joins.len(), 1);
+                assert_eq!(joins[0].len(), n_points, "miter limit {miter_limit}");
+            }
+        }
+    }
+
+    #[test]
+    fn reversals_are_beveled() {
+        let path = polyline(&[(0.0, 0.0), (10.0, 0.0), (3.0, 0.0)]);
+        let max_x = |style: &Stroke| {
+            polygons(&expand(path.iter(), style, 0.01))
+                .iter()
+                .flatten()
+                .map(|p| p.x)
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        let style = Stroke::new(2.0).with_caps(Cap::Butt);
+        // However large the miter limit, the miter of a reversal is unbounded
+        let miter = style.clone().with_join(Join::Miter).with_miter_limit(1e6);
+        assert!((max_x(&miter) - 10.0).abs() < 1e-9);
+        assert!((max_x(&style.clone().with_join(Join::Bevel)) - 10.0).abs() < 1e-9);
+        // A round join reaches the far side of the reversal, up to flattening
+        assert!((max_x(&style.with_join(Join::Round)) - 11.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn polygons_wind_the_same_way() {
+        let style = Stroke::new(2.0);
+        let mut stroker = Stroker {
+            style: &style,
+            half_width: 1.0,
+            tolerance: 0.01,
+            out: BezPath::new(),
+        };
+        stroker.polygon(&[
+            Point::new(0.0, 0.0),
+            Point::new(0.0, 1.0),
+            Point::new(1.0, 0.0),
+        ]);
+        stroker.polygon(&[
+            Point::new(0.0, 0.0),
+            Point::new(1.0, 0.0),
+            Point::new(0.0, 1.0),
+        ]);
+        // Polygons with no area are dropped
+        stroker.polygon(&[
+            Point::new(0.0, 0.0),
+            Point::new(1.0, 1.0),
+            Point::new(2.0, 2.0),
+        ]);
+        let out = polygons(&stroker.out);
+        assert_eq!(out.len(), 2);
+        assert_points_near(&out[0], &[(1.0, 0.0), (0.0, 1.0), (0.0, 0.0)]);
+        assert_points_near(&out[1], &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+
+        // Turning both ways gives segments, joins and caps that all wind the same way
+        let path = polyline(&[
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (3.0, 17.0),
+            (3.0, 30.0),
+        ]);
+        for join in [Join::Bevel, Join::Miter, Join::Round] {
+            for cap in [Cap::Butt, Cap::Square, Cap::Round] {
+                let style = Stroke::new(3.0).with_join(join).with_caps(cap);
+                for polygon in polygons(&expand(path.iter(), &style, 0.01)) {
+                    assert!(area(&polygon) > 0.0, "{join:?} join, {cap:?} cap");
+                }
+            }
+        }
+    }
+}
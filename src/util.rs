@@ -16,17 +16,23 @@
 
 //! Simple helpers for managing wgpu state and surfaces.
 
-use super::Result;
+use super::{Error, Result};
+
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{collections::VecDeque, time::Instant};
 
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use wgpu::{
-    Adapter, Device, Instance, Limits, Queue, RequestAdapterOptions, Surface, SurfaceConfiguration,
+    Adapter, Backends, CommandEncoder, CompositeAlphaMode, Device, Instance, Limits, Queue,
+    RequestAdapterOptions, Surface, SurfaceConfiguration, TextureFormat, TextureView,
 };
 
 /// Simple render context that maintains wgpu state for rendering the pipeline.
 pub struct RenderContext {
     pub instance: Instance,
     pub devices: Vec<DeviceHandle>,
+    backends: Backends,
 }
 
 pub struct DeviceHandle {
@@ -35,37 +41,87 @@ pub struct DeviceHandle {
     pub queue: Queue,
 }
 
+impl DeviceHandle {
+    /// Returns the adapter which the device was created from.
+    pub fn adapter(&self) -> &Adapter {
+        &self.adapter
+    }
+}
+
 impl RenderContext {
     pub fn new() -> Result<Self> {
-        let instance = Instance::new(wgpu::Backends::PRIMARY);
+        Self::with_backends(Backends::PRIMARY)
+    }
+
+    /// Creates a new render context which only uses adapters from the given backends.
+    pub fn with_backends(backends: Backends) -> Result<Self> {
+        let instance = Instance::new(backends);
         Ok(Self {
             instance,
             devices: Vec::new(),
+            backends,
         })
     }
 
+    /// Returns all adapters available to this context, for when the default adapter
+    /// selection is not appropriate.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn enumerate_adapters(&self) -> Vec<Adapter> {
+        self.instance.enumerate_adapters(self.backends).collect()
+    }
+
     /// Creates a new surface for the specified window and dimensions.
-    pub async fn create_surface<W>(&mut self, window: &W, width: u32, height: u32) -> RenderSurface
+    ///
+    /// Returns [`Error::NoCompatibleDevice`] if no adapter can present to the window,
+    /// and [`Error::SurfaceCreation`] if the surface can't be configured with a
+    /// format vello renders to.
+    pub async fn create_surface<W>(
+        &mut self,
+        window: &W,
+        width: u32,
+        height: u32,
+    ) -> Result<RenderSurface>
+    where
+        W: HasRawWindowHandle + HasRawDisplayHandle,
+    {
+        self.create_surface_with_alpha_mode(window, width, height, CompositeAlphaMode::Auto)
+            .await
+    }
+
+    /// Creates a new surface for the specified window and dimensions, which is
+    /// composited with the desktop using `alpha_mode` if the surface supports it.
+    ///
+    /// A transparent mode which isn't supported falls back to the other transparent
+    /// mode, and then to [`CompositeAlphaMode::Auto`], which is usually opaque. The
+    /// mode which was chosen is in the [`config`](RenderSurface::config) of the
+    /// surface, and [`RenderSurface::is_transparent`] tells whether the surface shows
+    /// what is behind the window.
+    pub async fn create_surface_with_alpha_mode<W>(
+        &mut self,
+        window: &W,
+        width: u32,
+        height: u32,
+        alpha_mode: CompositeAlphaMode,
+    ) -> Result<RenderSurface>
     where
         W: HasRawWindowHandle + HasRawDisplayHandle,
     {
         let surface = unsafe { self.instance.create_surface(window) };
+        let dev_id = self.device(Some(&surface)).await?;
+        let device_handle = &self.devices[dev_id];
+        let supported = surface.get_supported_alpha_modes(&device_handle.adapter);
         let format = wgpu::TextureFormat::Bgra8Unorm;
+        check_surface_format(&surface, &device_handle.adapter, format)?;
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width,
             height,
             present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            alpha_mode: select_alpha_mode(alpha_mode, &supported),
         };
-        let dev_id = self.device(Some(&surface)).await.unwrap();
-        surface.configure(&self.devices[dev_id].device, &config);
-        RenderSurface {
-            surface,
-            config,
-            dev_id,
-        }
+        surface.configure(&device_handle.device, &config);
+        Ok(RenderSurface::new(surface, config, dev_id))
     }
 
     /// Resizes the surface to the new dimensions.
@@ -77,8 +133,45 @@ impl RenderContext {
             .configure(&self.devices[surface.dev_id].device, &surface.config);
     }
 
+    /// Drops a surface while keeping its device, for when the platform destroys the
+    /// window's surface, such as when an Android app is suspended.
+    pub fn suspend_surface(&mut self, surface: RenderSurface) -> SuspendedSurface {
+        SuspendedSurface {
+            config: surface.config,
+            dev_id: surface.dev_id,
+        }
+    }
+
+    /// Recreates a suspended surface for the window, with the configuration and
+    /// device it had before. If the size of the window has changed, the surface should
+    /// then be resized with [`resize_surface`](Self::resize_surface).
+    ///
+    /// Returns [`Error::NoCompatibleDevice`] if the device can no longer present to the
+    /// window.
+    pub fn resume_surface<W>(
+        &mut self,
+        suspended: SuspendedSurface,
+        window: &W,
+    ) -> Result<RenderSurface>
+    where
+        W: HasRawWindowHandle + HasRawDisplayHandle,
+    {
+        let surface = unsafe { self.instance.create_surface(window) };
+        let device_handle = &self.devices[suspended.dev_id];
+        if !device_handle.adapter.is_surface_supported(&surface) {
+            return Err(Error::NoCompatibleDevice);
+        }
+        check_surface_format(&surface, &device_handle.adapter, suspended.config.format)?;
+        surface.configure(&device_handle.device, &suspended.config);
+        Ok(RenderSurface::new(
+            surface,
+            suspended.config,
+            suspended.dev_id,
+        ))
+    }
+
     /// Finds or creates a compatible device handle id.
-    async fn device(&mut self, compatible_surface: Option<&Surface>) -> Option<usize> {
+    pub async fn device(&mut self, compatible_surface: Option<&Surface>) -> Result<usize> {
         let compatible = match compatible_surface {
             Some(s) => self
                 .devices
@@ -88,20 +181,27 @@ impl RenderContext {
                 .map(|(i, _)| i),
             None => (!self.devices.is_empty()).then_some(0),
         };
-        if compatible.is_none() {
-            return self.new_device(compatible_surface).await;
+        match compatible {
+            Some(dev_id) => Ok(dev_id),
+            None => self.new_device(compatible_surface).await,
         }
-        compatible
     }
 
     /// Creates a compatible device handle id.
-    async fn new_device(&mut self, compatible_surface: Option<&Surface>) -> Option<usize> {
+    async fn new_device(&mut self, compatible_surface: Option<&Surface>) -> Result<usize> {
         let adapter = wgpu::util::initialize_adapter_from_env_or_default(
             &self.instance,
-            wgpu::Backends::PRIMARY,
+            self.backends,
             compatible_surface,
         )
-        .await?;
+        .await
+        .ok_or(Error::NoCompatibleDevice)?;
+        self.device_from_adapter(adapter).await
+    }
+
+    /// Creates a device handle id for an explicitly chosen adapter, such as one
+    /// returned by [`enumerate_adapters`](Self::enumerate_adapters).
+    pub async fn device_from_adapter(&mut self, adapter: Adapter) -> Result<usize> {
         let features = adapter.features();
         let limits = Limits::default();
         let (device, queue) = adapter
@@ -114,21 +214,627 @@ impl RenderContext {
                 },
                 None,
             )
-            .await
-            .ok()?;
+            .await?;
         let device_handle = DeviceHandle {
             adapter,
             device,
             queue,
         };
         self.devices.push(device_handle);
-        Some(self.devices.len() - 1)
+        Ok(self.devices.len() - 1)
+    }
+}
+
+/// Returns [`Error::SurfaceCreation`] if `surface` can't be configured with `format`
+/// on `adapter`.
+fn check_surface_format(surface: &Surface, adapter: &Adapter, format: TextureFormat) -> Result<()> {
+    let supported = surface.get_supported_formats(adapter);
+    if supported.contains(&format) {
+        Ok(())
+    } else {
+        Err(Error::SurfaceCreation {
+            detail: format!("{format:?} is not one of the supported formats {supported:?}"),
+        })
     }
 }
 
+/// Returns `preferred` if it is one of the `supported` alpha modes, or the closest
+/// supported mode otherwise.
+fn select_alpha_mode(
+    preferred: CompositeAlphaMode,
+    supported: &[CompositeAlphaMode],
+) -> CompositeAlphaMode {
+    let fallbacks: &[CompositeAlphaMode] = match preferred {
+        CompositeAlphaMode::PreMultiplied => &[CompositeAlphaMode::PostMultiplied],
+        CompositeAlphaMode::PostMultiplied => &[CompositeAlphaMode::PreMultiplied],
+        _ => &[],
+    };
+    [preferred]
+        .iter()
+        .chain(fallbacks)
+        .find(|mode| supported.contains(mode))
+        .copied()
+        .unwrap_or(CompositeAlphaMode::Auto)
+}
+
 /// Combination of surface and its configuration.
 pub struct RenderSurface {
     pub surface: Surface,
     pub config: SurfaceConfiguration,
     pub dev_id: usize,
+    stats: SurfaceFrameStats,
+    #[cfg(not(target_arch = "wasm32"))]
+    acquired_at: Option<Instant>,
+}
+
+/// Timings of the latest frame of a [`RenderSurface`], as acquired with
+/// [`RenderSurface::acquire`] and presented with [`RenderSurface::present`].
+///
+/// The timings are not measured on the web, where they are always zero.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SurfaceFrameStats {
+    /// Time spent waiting for the surface to provide the texture.
+    pub acquire_wait: Duration,
+    /// Time from when the texture was acquired until it was presented.
+    pub acquire_to_present: Duration,
+    /// Time spent in the call to present the texture.
+    pub present_time: Duration,
+    /// Number of frames presented since the surface was created.
+    pub frames: u64,
+    /// Number of times since the surface was created that acquiring a texture failed
+    /// because the surface was outdated or lost, and the surface was reconfigured.
+    pub reconfigurations: u64,
+}
+
+/// The state of a surface between [`RenderContext::suspend_surface`] and
+/// [`RenderContext::resume_surface`].
+pub struct SuspendedSurface {
+    pub config: SurfaceConfiguration,
+    pub dev_id: usize,
+}
+
+impl RenderSurface {
+    fn new(surface: Surface, config: SurfaceConfiguration, dev_id: usize) -> Self {
+        Self {
+            surface,
+            config,
+            dev_id,
+            stats: SurfaceFrameStats::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            acquired_at: None,
+        }
+    }
+
+    /// Acquires the next texture of the surface.
+    ///
+    /// If the surface is outdated or lost, such as while the window is being resized,
+    /// it is reconfigured on `device`, which must be the device of the surface, and
+    /// the texture is acquired again.
+    pub fn acquire(
+        &mut self,
+        device: &Device,
+    ) -> std::result::Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = Instant::now();
+        let texture = match self.surface.get_current_texture() {
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                self.stats.reconfigurations += 1;
+                self.surface.configure(device, &self.config);
+                self.surface.get_current_texture()?
+            }
+            result => result?,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let now = Instant::now();
+            self.stats.acquire_wait = now - start;
+            self.acquired_at = Some(now);
+        }
+        Ok(texture)
+    }
+
+    /// Presents a texture acquired with [`acquire`](Self::acquire).
+    pub fn present(&mut self, texture: wgpu::SurfaceTexture) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let start = Instant::now();
+            if let Some(acquired_at) = self.acquired_at.take() {
+                self.stats.acquire_to_present = start - acquired_at;
+            }
+            texture.present();
+            self.stats.present_time = start.elapsed();
+        }
+        #[cfg(target_arch = "wasm32")]
+        texture.present();
+        self.stats.frames += 1;
+    }
+
+    /// Returns the timings of the latest frame.
+    pub fn frame_stats(&self) -> SurfaceFrameStats {
+        self.stats
+    }
+
+    /// Returns true if the alpha of the surface is used when compositing it with the
+    /// desktop, so that transparent parts of the scene show what is behind the window.
+    pub fn is_transparent(&self) -> bool {
+        matches!(
+            self.config.alpha_mode,
+            CompositeAlphaMode::PreMultiplied | CompositeAlphaMode::PostMultiplied
+        )
+    }
+
+    /// Acquires the next texture of the surface like [`acquire`](Self::acquire), after
+    /// waiting until the pacer's next frame is due.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn acquire_with_pacer(
+        &mut self,
+        device: &Device,
+        pacer: &mut FramePacer,
+    ) -> std::result::Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        pacer.wait();
+        let texture = self.acquire(device)?;
+        push_sample(&mut pacer.acquire_times, self.stats.acquire_wait);
+        Ok(texture)
+    }
+}
+
+/// Number of frames the statistics of a [`FramePacer`] are taken over.
+#[cfg(not(target_arch = "wasm32"))]
+const PACER_WINDOW: usize = 120;
+
+/// How long before a frame is due the pacer stops sleeping and spins instead, as a
+/// sleep can overshoot by about a scheduler tick.
+#[cfg(not(target_arch = "wasm32"))]
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+/// Limits the rate at which a surface is presented, and measures frame timings.
+///
+/// Textures should be acquired with [`RenderSurface::acquire_with_pacer`] and presented
+/// with [`present`](Self::present).
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FramePacer {
+    /// Maximum rate to present at, or `None` to present as fast as the surface allows.
+    pub target_fps: Option<f32>,
+    last_present: Option<Instant>,
+    frame_times: VecDeque<Duration>,
+    acquire_times: VecDeque<Duration>,
+    missed_frames: u64,
+}
+
+/// Timings measured by a [`FramePacer`] over its recent frames.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// Mean time between presents.
+    pub mean_frame_time: Duration,
+    /// Longest time between presents.
+    pub max_frame_time: Duration,
+    /// Mean time spent waiting for the surface to provide a texture.
+    pub mean_acquire_time: Duration,
+    /// Number of frames since the pacer was created which took more than one and a half
+    /// frame intervals. Without a target rate, the interval is assumed to be 60Hz.
+    pub missed_frames: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FramePacer {
+    pub fn new(target_fps: Option<f32>) -> Self {
+        Self {
+            target_fps,
+            last_present: None,
+            frame_times: VecDeque::with_capacity(PACER_WINDOW),
+            acquire_times: VecDeque::with_capacity(PACER_WINDOW),
+            missed_frames: 0,
+        }
+    }
+
+    /// Presents the texture to the surface it was acquired from, and records the time
+    /// since the last present.
+    pub fn present(&mut self, surface: &mut RenderSurface, texture: wgpu::SurfaceTexture) {
+        surface.present(texture);
+        let now = Instant::now();
+        if let Some(last_present) = self.last_present {
+            let frame_time = now - last_present;
+            if frame_time > self.frame_interval().mul_f32(1.5) {
+                self.missed_frames += 1;
+            }
+            push_sample(&mut self.frame_times, frame_time);
+        }
+        self.last_present = Some(now);
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        fn mean(samples: &VecDeque<Duration>) -> Duration {
+            let sum = samples.iter().sum::<Duration>();
+            sum / (samples.len().max(1) as u32)
+        }
+        FrameStats {
+            mean_frame_time: mean(&self.frame_times),
+            max_frame_time: self.frame_times.iter().max().copied().unwrap_or_default(),
+            mean_acquire_time: mean(&self.acquire_times),
+            missed_frames: self.missed_frames,
+        }
+    }
+
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.target_fps.unwrap_or(60.0))
+    }
+
+    /// Blocks until the next frame is due, if there is a target rate.
+    fn wait(&self) {
+        let (Some(_), Some(last_present)) = (self.target_fps, self.last_present) else {
+            return;
+        };
+        let deadline = last_present + self.frame_interval();
+        let now = Instant::now();
+        if deadline > now + SPIN_MARGIN {
+            std::thread::sleep(deadline - now - SPIN_MARGIN);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn push_sample(samples: &mut VecDeque<Duration>, sample: Duration) {
+    if samples.len() == PACER_WINDOW {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+/// Draws the output of the renderer onto a texture of any color format.
+///
+/// The output is read with straight alpha and written premultiplied. Swizzling for
+/// formats such as [`TextureFormat::Bgra8Unorm`] is handled by the render target, and
+/// for sRGB formats the colors are decoded first, so that the stored values match the
+/// output of the renderer.
+pub struct TextureBlitter {
+    bind_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl TextureBlitter {
+    /// Creates a blitter which draws to textures of `target_format`.
+    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
+        Self::with_alpha_mode(device, target_format, CompositeAlphaMode::Auto)
+    }
+
+    /// Creates a blitter which draws to textures of `target_format`, with alpha
+    /// encoded for surfaces composited with `alpha_mode`.
+    ///
+    /// Colors are written premultiplied by alpha, except for
+    /// [`PostMultiplied`](CompositeAlphaMode::PostMultiplied), where they are written
+    /// unpremultiplied, and [`Opaque`](CompositeAlphaMode::Opaque), where alpha is
+    /// written as 1, so the scene appears composited over black.
+    pub fn with_alpha_mode(
+        device: &Device,
+        target_format: TextureFormat,
+        alpha_mode: CompositeAlphaMode,
+    ) -> Self {
+        // `rgba_sep` is the unpremultiplied color written by fine
+        let encode_alpha = match alpha_mode {
+            CompositeAlphaMode::PostMultiplied => {
+                "fn encode_alpha(rgba_sep: vec4<f32>) -> vec4<f32> { return rgba_sep; }"
+            }
+            CompositeAlphaMode::Opaque => {
+                "fn encode_alpha(rgba_sep: vec4<f32>) -> vec4<f32> { return vec4(rgba_sep.rgb * rgba_sep.a, 1.0); }"
+            }
+            _ => {
+                "fn encode_alpha(rgba_sep: vec4<f32>) -> vec4<f32> { return vec4(rgba_sep.rgb * rgba_sep.a, rgba_sep.a); }"
+            }
+        };
+        const SHADERS: &str = r#"
+            @vertex
+            fn vs_main(@builtin(vertex_index) ix: u32) -> @builtin(position) vec4<f32> {
+                // Generate a full screen quad in NDCs
+                var vertex = vec2(-1.0, 1.0);
+                switch ix {
+                    case 1u: {
+                        vertex = vec2(-1.0, -1.0);
+                    }
+                    case 2u, 4u: {
+                        vertex = vec2(1.0, -1.0);
+                    }
+                    case 5u: {
+                        vertex = vec2(1.0, 1.0);
+                    }
+                    default: {}
+                }
+                return vec4(vertex, 0.0, 1.0);
+            }
+
+            @group(0) @binding(0)
+            var fine_output: texture_2d<f32>;
+
+            fn load(pos: vec4<f32>) -> vec4<f32> {
+                let rgba_sep = textureLoad(fine_output, vec2<i32>(pos.xy), 0);
+                return encode_alpha(rgba_sep);
+            }
+
+            @fragment
+            fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+                return load(pos);
+            }
+
+            fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+                let low = c / 12.92;
+                let high = pow((c + 0.055) / 1.055, vec3(2.4));
+                return select(high, low, c <= vec3(0.04045));
+            }
+
+            // The target encodes to sRGB on store, so undo that here
+            @fragment
+            fn fs_main_srgb(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+                let rgba = load(pos);
+                return vec4(srgb_to_linear(rgba.rgb), rgba.a);
+            }
+        "#;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("blit shaders"),
+            source: wgpu::ShaderSource::Wgsl(format!("{encode_alpha}\n{SHADERS}").into()),
+        });
+        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                binding: 0,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_layout],
+            push_constant_ranges: &[],
+        });
+        let fs_entry_point = if target_format.describe().srgb {
+            "fs_main_srgb"
+        } else {
+            "fs_main"
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: fs_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+        Self {
+            bind_layout,
+            pipeline,
+        }
+    }
+
+    /// Records a render pass which clears `target` and draws `source` onto it.
+    ///
+    /// `source` must be an [`Rgba8Unorm`](TextureFormat::Rgba8Unorm) texture rendered by
+    /// the renderer, of the same size as `target`. `target` must have the format the
+    /// blitter was created for.
+    pub fn blit(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        source: &TextureView,
+        target: &TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            }],
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::default()),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Fine's output store and the blit shaders, checked by rendering on the GPU.
+
+    use peniko::kurbo::{Affine, Rect};
+    use peniko::{Color, Fill};
+    use wgpu::{Texture, TextureUsages};
+
+    use super::*;
+    use crate::gpu_test::{self, pixel};
+    use crate::{Renderer, Scene, SceneBuilder, TargetView};
+
+    const SIZE: u32 = 16;
+
+    fn texture(handle: &DeviceHandle, format: TextureFormat, usage: TextureUsages) -> Texture {
+        handle.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: usage | TextureUsages::COPY_SRC,
+        })
+    }
+
+    /// Renders half transparent red, over opaque green if `base` is set, into a texture
+    /// which can also be blitted from.
+    fn render_half_red(handle: &DeviceHandle, renderer: &mut Renderer, base: bool) -> Texture {
+        let mut scene = Scene::new();
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        let rect = Rect::new(0.0, 0.0, SIZE as f64, SIZE as f64);
+        if base {
+            let green = Color::rgb8(0, 255, 0);
+            builder.fill(Fill::NonZero, Affine::IDENTITY, green, None, &rect);
+        }
+        let red = Color::rgba8(255, 0, 0, 128);
+        builder.fill(Fill::NonZero, Affine::IDENTITY, red, None, &rect);
+        builder.finish();
+        let texture = texture(
+            handle,
+            TextureFormat::Rgba8Unorm,
+            TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let target = TargetView::new(&view, SIZE, SIZE);
+        renderer
+            .render_to_texture(&handle.device, &handle.queue, &scene, target, SIZE, SIZE)
+            .unwrap();
+        texture
+    }
+
+    /// Blits `source` into a texture of `format` and returns its first pixel as RGBA.
+    fn blit(
+        handle: &DeviceHandle,
+        source: &Texture,
+        format: TextureFormat,
+        alpha_mode: CompositeAlphaMode,
+    ) -> [u8; 4] {
+        let blitter = TextureBlitter::with_alpha_mode(&handle.device, format, alpha_mode);
+        let target = texture(handle, format, TextureUsages::RENDER_ATTACHMENT);
+        let mut encoder = handle
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        blitter.blit(
+            &handle.device,
+            &mut encoder,
+            &source.create_view(&wgpu::TextureViewDescriptor::default()),
+            &target.create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+        handle.queue.submit(Some(encoder.finish()));
+        let [x, y, z, a] = pixel(
+            &gpu_test::read_texture(handle, &target, SIZE, SIZE),
+            SIZE,
+            0,
+            0,
+        );
+        match format {
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => [z, y, x, a],
+            _ => [x, y, z, a],
+        }
+    }
+
+    fn assert_close(actual: [u8; 4], expected: [f32; 4]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (*actual as f32 - expected * 255.0).abs() <= 1.0,
+                "{actual:?} is not within 1/255 of {expected:?}"
+            );
+        }
+    }
+
+    const ALPHA_MODES: [CompositeAlphaMode; 4] = [
+        CompositeAlphaMode::Auto,
+        CompositeAlphaMode::PreMultiplied,
+        CompositeAlphaMode::PostMultiplied,
+        CompositeAlphaMode::Opaque,
+    ];
+
+    const FORMATS: [TextureFormat; 4] = [
+        TextureFormat::Rgba8Unorm,
+        TextureFormat::Rgba8UnormSrgb,
+        TextureFormat::Bgra8Unorm,
+        TextureFormat::Bgra8UnormSrgb,
+    ];
+
+    #[test]
+    fn texture_output_matches_srgb_composite() {
+        let Some(handle) = gpu_test::device() else {
+            eprintln!("skipping: no adapter");
+            return;
+        };
+        let mut renderer = gpu_test::renderer(&handle);
+        let texture = render_half_red(&handle, &mut renderer, true);
+        let pixels = gpu_test::read_texture(&handle, &texture, SIZE, SIZE);
+        // Colors are composited in sRGB, so half red over green is halfway between them
+        assert_close(pixel(&pixels, SIZE, 0, 0), [0.5, 0.5, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn surface_output_matches_srgb_composite() {
+        let Some(handle) = gpu_test::device() else {
+            eprintln!("skipping: no adapter");
+            return;
+        };
+        let mut renderer = gpu_test::renderer(&handle);
+        let texture = render_half_red(&handle, &mut renderer, true);
+        for alpha_mode in ALPHA_MODES {
+            for format in FORMATS {
+                let actual = blit(&handle, &texture, format, alpha_mode);
+                assert_close(actual, [0.5, 0.5, 0.0, 1.0]);
+            }
+        }
+    }
+
+    #[test]
+    fn translucent_surface_output_is_premultiplied_once() {
+        let Some(handle) = gpu_test::device() else {
+            eprintln!("skipping: no adapter");
+            return;
+        };
+        let mut renderer = gpu_test::renderer(&handle);
+        let texture = render_half_red(&handle, &mut renderer, false);
+        let pixels = gpu_test::read_texture(&handle, &texture, SIZE, SIZE);
+        assert_close(pixel(&pixels, SIZE, 0, 0), [1.0, 0.0, 0.0, 0.5]);
+        for (alpha_mode, expected) in ALPHA_MODES.into_iter().zip([
+            [0.5, 0.0, 0.0, 0.5],
+            [0.5, 0.0, 0.0, 0.5],
+            [1.0, 0.0, 0.0, 0.5],
+            [0.5, 0.0, 0.0, 1.0],
+        ]) {
+            for format in FORMATS {
+                assert_close(blit(&handle, &texture, format, alpha_mode), expected);
+            }
+        }
+    }
 }